@@ -0,0 +1,125 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use webrtc_audio_processing::*;
+
+const CHANNEL_COUNTS: &[usize] = &[1, 2, 8];
+
+/// Microphone arrays, where the cost of the interleave conversion grows the most.
+const ARRAY_CHANNEL_COUNTS: &[usize] = &[16];
+
+fn create_processor(num_channels: usize, config: &Config) -> Processor {
+    let init_config = InitializationConfig {
+        num_capture_channels: num_channels as i32,
+        num_render_channels: num_channels as i32,
+        ..InitializationConfig::default()
+    };
+    let mut processor = Processor::new(&init_config).unwrap();
+    processor.set_config(config.clone());
+    processor
+}
+
+/// All processing disabled, measuring only the wrapper and FFI overhead.
+fn passthrough_config() -> Config {
+    Config::default()
+}
+
+/// The typical voice chat setup with AEC, NS and AGC enabled.
+fn full_config() -> Config {
+    Config {
+        echo_cancellation: Some(EchoCancellation {
+            suppression_level: EchoCancellationSuppressionLevel::High,
+            stream_delay_ms: None,
+            enable_delay_agnostic: true,
+            enable_extended_filter: true,
+        }),
+        gain_control: Some(GainControl {
+            mode: GainControlMode::AdaptiveDigital,
+            target_level_dbfs: 3,
+            compression_gain_db: 9,
+            enable_limiter: true,
+        }),
+        noise_suppression: Some(NoiseSuppression {
+            suppression_level: NoiseSuppressionLevel::High,
+        }),
+        ..Config::default()
+    }
+}
+
+/// Generates an interleaved frame of a sine wave, with a different phase per channel.
+fn sample_frame(num_channels: usize) -> Vec<f32> {
+    let num_samples_per_frame = NUM_SAMPLES_PER_FRAME as usize;
+    let mut frame = Vec::with_capacity(num_samples_per_frame * num_channels);
+    for i in 0..num_samples_per_frame {
+        for channel in 0..num_channels {
+            frame.push(((i + channel * 7) as f32 / 20.0).sin() * 0.3);
+        }
+    }
+    frame
+}
+
+fn bench_process(c: &mut Criterion) {
+    let configs = [("passthrough", passthrough_config()), ("aec_ns_agc", full_config())];
+
+    for (config_name, config) in configs.iter() {
+        let mut group = c.benchmark_group(format!("process_frame/{}", config_name));
+        for &num_channels in CHANNEL_COUNTS {
+            group.throughput(Throughput::Elements(
+                (NUM_SAMPLES_PER_FRAME as usize * num_channels) as u64,
+            ));
+
+            let mut processor = create_processor(num_channels, config);
+            let frame = sample_frame(num_channels);
+            let mut buffer = frame.clone();
+            group.bench_with_input(
+                BenchmarkId::new("capture", num_channels),
+                &frame,
+                |b, frame| {
+                    b.iter(|| {
+                        buffer.copy_from_slice(frame);
+                        processor.process_capture_frame(black_box(&mut buffer)).unwrap();
+                    })
+                },
+            );
+
+            let mut processor = create_processor(num_channels, config);
+            let mut buffer = frame.clone();
+            group.bench_with_input(BenchmarkId::new("render", num_channels), &frame, |b, frame| {
+                b.iter(|| {
+                    buffer.copy_from_slice(frame);
+                    processor.process_render_frame(black_box(&mut buffer)).unwrap();
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+/// Compares the interleaved and non-interleaved capture APIs with all processing disabled, so
+/// the difference between the two isolates the cost of the interleave conversion.
+fn bench_interleave(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interleave");
+    for &num_channels in CHANNEL_COUNTS.iter().chain(ARRAY_CHANNEL_COUNTS) {
+        group.throughput(Throughput::Elements(
+            (NUM_SAMPLES_PER_FRAME as usize * num_channels) as u64,
+        ));
+
+        let mut processor = create_processor(num_channels, &passthrough_config());
+        let mut interleaved = sample_frame(num_channels);
+        group.bench_function(BenchmarkId::new("interleaved", num_channels), |b| {
+            b.iter(|| processor.process_capture_frame(black_box(&mut interleaved)).unwrap())
+        });
+
+        let mut processor = create_processor(num_channels, &passthrough_config());
+        let mut noninterleaved = vec![vec![0f32; NUM_SAMPLES_PER_FRAME as usize]; num_channels];
+        group.bench_function(BenchmarkId::new("noninterleaved", num_channels), |b| {
+            b.iter(|| {
+                processor
+                    .process_capture_frame_noninterleaved(black_box(&mut noninterleaved))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_process, bench_interleave);
+criterion_main!(benches);
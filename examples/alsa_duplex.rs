@@ -0,0 +1,53 @@
+// This example loops the microphone input back to the speakers while applying echo
+// cancellation, like `karaoke.rs`, but talks to ALSA directly, for headless embedded Linux
+// boards (e.g. a Raspberry Pi with a USB sound card) that don't run PulseAudio or JACK.
+//
+// Pass the capture and playback devices as arguments, e.g.
+// `cargo run --example alsa_duplex --features alsa -- plughw:1,0 plughw:1,0`.
+use failure::Error;
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use webrtc_audio_processing::{integrations::alsa::AlsaDuplex, *};
+
+fn main() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let capture_device = args.next().unwrap_or_else(|| "default".to_string());
+    let playback_device = args.next().unwrap_or_else(|| "default".to_string());
+
+    // Monoral microphone and speaker.
+    let mut duplex = AlsaDuplex::open(&capture_device, &playback_device, 1, 1)?;
+
+    let mut processor = Processor::new(&duplex.initialization_config())?;
+    // High pass filter is a prerequisite to running echo cancellation.
+    processor.set_config(Config {
+        echo_cancellation: Some(EchoCancellation {
+            suppression_level: EchoCancellationSuppressionLevel::Low,
+            stream_delay_ms: Some(0),
+            enable_delay_agnostic: true,
+            enable_extended_filter: true,
+        }),
+        enable_high_pass_filter: true,
+        ..Config::default()
+    });
+
+    let running = Arc::new(AtomicBool::new(true));
+    ctrlc::set_handler({
+        let running = running.clone();
+        move || {
+            running.store(false, Ordering::SeqCst);
+        }
+    })?;
+
+    duplex.run(&mut processor, |capture, playback| {
+        // Play back the processed audio capture.
+        playback.copy_from_slice(capture);
+        running.load(Ordering::SeqCst)
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+// This example measures the delay between the speakers and the microphone by playing a chirp,
+// and prints the `stream_delay_ms` to put in the echo cancellation config. Turn the volume up,
+// and keep the room quiet while it runs.
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use failure::{format_err, Error};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use webrtc_audio_processing::{integrations::cpal as wap_cpal, *};
+
+fn main() -> Result<(), Error> {
+    let host = cpal::default_host();
+    let input_device =
+        host.default_input_device().ok_or_else(|| format_err!("no input device available"))?;
+    let output_device =
+        host.default_output_device().ok_or_else(|| format_err!("no output device available"))?;
+
+    let config = wap_cpal::stream_config(1);
+    let calibrator = Arc::new(Mutex::new(DelayCalibrator::new(Duration::from_millis(500))));
+
+    let input_stream = input_device.build_input_stream(
+        &config,
+        {
+            let calibrator = calibrator.clone();
+            move |data: &[f32], _: &_| calibrator.lock().unwrap().capture(data, 1)
+        },
+        |err| eprintln!("Input stream error: {}", err),
+    )?;
+    let output_stream = output_device.build_output_stream(
+        &config,
+        {
+            let calibrator = calibrator.clone();
+            move |data: &mut [f32], _: &_| calibrator.lock().unwrap().render(data, 1)
+        },
+        |err| eprintln!("Output stream error: {}", err),
+    )?;
+
+    // Back to back, so that both streams count samples from the same instant.
+    output_stream.play()?;
+    input_stream.play()?;
+    while !calibrator.lock().unwrap().is_complete() {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    match calibrator.lock().unwrap().stream_delay_ms() {
+        Some(delay_ms) => println!("stream_delay_ms: {}", delay_ms),
+        None => return Err(format_err!("chirp not detected, try a higher volume")),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+// This example loops the microphone input back to the speakers while applying echo
+// cancellation, like `karaoke.rs`, but uses cpal as the interface to the audio devices. cpal
+// streams come with arbitrary buffer sizes, which the `integrations::cpal` helpers take care of.
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use failure::{format_err, Error};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use webrtc_audio_processing::{integrations::cpal as wap_cpal, *};
+
+fn wait_ctrlc() -> Result<(), Error> {
+    let running = Arc::new(AtomicBool::new(true));
+
+    ctrlc::set_handler({
+        let running = running.clone();
+        move || {
+            running.store(false, Ordering::SeqCst);
+        }
+    })?;
+
+    while running.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let host = cpal::default_host();
+    let input_device =
+        host.default_input_device().ok_or_else(|| format_err!("no input device available"))?;
+    let output_device =
+        host.default_output_device().ok_or_else(|| format_err!("no output device available"))?;
+
+    // Monoral microphone and speaker.
+    let input_config = wap_cpal::stream_config(1);
+    let output_config = wap_cpal::stream_config(1);
+
+    let mut processor =
+        Processor::new(&wap_cpal::initialization_config(&input_config, &output_config)?)?;
+    // High pass filter is a prerequisite to running echo cancellation.
+    processor.set_config(Config {
+        echo_cancellation: Some(EchoCancellation {
+            suppression_level: EchoCancellationSuppressionLevel::Low,
+            stream_delay_ms: Some(0),
+            enable_delay_agnostic: true,
+            enable_extended_filter: true,
+        }),
+        enable_high_pass_filter: true,
+        ..Config::default()
+    });
+
+    // Hands the processed capture over to the output stream, with up to one second of slack.
+    let (sender, receiver) = crossbeam_channel::bounded(wap_cpal::SAMPLE_RATE as usize);
+
+    let input_stream = input_device.build_input_stream(
+        &input_config,
+        wap_cpal::capture_callback(processor.clone(), input_config.channels, move |processed| {
+            match processed {
+                Ok(samples) => samples.iter().for_each(|&sample| {
+                    let _ = sender.try_send(sample);
+                }),
+                Err(err) => eprintln!("Failed to process a capture frame: {}", err),
+            }
+        }),
+        |err| eprintln!("Input stream error: {}", err),
+    )?;
+
+    let output_stream = output_device.build_output_stream(
+        &output_config,
+        wap_cpal::render_callback(processor, output_config.channels, move |data| {
+            // Play back the processed audio capture.
+            for sample in data.iter_mut() {
+                *sample = receiver.try_recv().unwrap_or(0.0);
+            }
+        }),
+        |err| eprintln!("Output stream error: {}", err),
+    )?;
+
+    input_stream.play()?;
+    output_stream.play()?;
+
+    wait_ctrlc()?;
+
+    Ok(())
+}
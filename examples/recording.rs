@@ -8,7 +8,7 @@
 /// input as a WAV file.
 ///
 /// ```
-/// $ cargo run --example recording --features bundled --features derive_serde -- --config-file \
+/// $ cargo run --example recording --features bundled,derive_serde,offline -- --config-file \
 ///     examples/recording-configs/record-sample.json5
 /// ```
 ///
@@ -18,17 +18,15 @@
 /// analyze the capture-processed.wav to understand the effect produced by the pipeline.
 ///
 /// ```
-/// $ cargo run --example recording --features bundled --features derive_serde -- --config-file \
+/// $ cargo run --example recording --features bundled,derive_serde,offline -- --config-file \
 ///     examples/recording-configs/record-pipeline.json5
 /// ```
 use failure::{format_err, Error};
-use hound::{WavIntoSamples, WavReader, WavWriter};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{self, File},
-    io::{BufReader, BufWriter},
-    path::{Path, PathBuf},
+    fs,
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -37,7 +35,10 @@ use std::{
     time::Duration,
 };
 use structopt::StructOpt;
-use webrtc_audio_processing::*;
+use webrtc_audio_processing::{
+    offline::{WavFrameReader, WavFrameWriter},
+    *,
+};
 
 const AUDIO_SAMPLE_RATE: u32 = 48_000;
 const AUDIO_INTERLEAVED: bool = true;
@@ -131,49 +132,6 @@ fn create_stream_settings(
     ))
 }
 
-fn open_wav_writer(path: &Path, channels: u16) -> Result<WavWriter<BufWriter<File>>, Error> {
-    let sink = hound::WavWriter::<BufWriter<File>>::create(
-        path,
-        hound::WavSpec {
-            channels,
-            sample_rate: AUDIO_SAMPLE_RATE,
-            bits_per_sample: 32,
-            sample_format: hound::SampleFormat::Float,
-        },
-    )?;
-
-    Ok(sink)
-}
-
-fn open_wav_reader(path: &Path) -> Result<WavIntoSamples<BufReader<File>, f32>, Error> {
-    let reader = WavReader::<BufReader<File>>::open(path)?;
-    Ok(reader.into_samples())
-}
-
-// The destination array is an interleaved audio stream.
-// Returns false if there are no more entries to read from the source.
-fn copy_stream(source: &mut WavIntoSamples<BufReader<File>, f32>, dest: &mut [f32]) -> bool {
-    let mut dest_iter = dest.iter_mut();
-    'outer: for sample in source {
-        for channel in &sample {
-            *dest_iter.next().unwrap() = *channel;
-            if dest_iter.len() == 0 {
-                break 'outer;
-            }
-        }
-    }
-
-    let source_eof = dest_iter.len() > 0;
-
-    // Zero-fill the remainder of the destination array if we finish consuming
-    // the source.
-    for sample in dest_iter {
-        *sample = 0.0;
-    }
-
-    !source_eof
-}
-
 fn main() -> Result<(), Error> {
     let args = Args::from_args();
     let opt: Options = json5::from_str(&fs::read_to_string(&args.config_file)?)?;
@@ -190,20 +148,23 @@ fn main() -> Result<(), Error> {
 
     let running = Arc::new(AtomicBool::new(true));
 
+    let num_capture_channels = opt.capture.num_channels as usize;
     let mut capture_source =
-        if let Some(path) = &opt.capture.source_path { Some(open_wav_reader(path)?) } else { None };
-    let mut capture_preprocess_sink = if let Some(path) = &opt.capture.preprocess_sink_path {
-        Some(open_wav_writer(path, opt.capture.num_channels)?)
-    } else {
-        None
-    };
-    let mut capture_postprocess_sink = if let Some(path) = &opt.capture.postprocess_sink_path {
-        Some(open_wav_writer(path, opt.capture.num_channels)?)
-    } else {
-        None
-    };
+        opt.capture.source_path.as_deref().map(WavFrameReader::open).transpose()?;
+    let mut capture_preprocess_sink = opt
+        .capture
+        .preprocess_sink_path
+        .as_deref()
+        .map(|path| WavFrameWriter::create(path, num_capture_channels))
+        .transpose()?;
+    let mut capture_postprocess_sink = opt
+        .capture
+        .postprocess_sink_path
+        .as_deref()
+        .map(|path| WavFrameWriter::create(path, num_capture_channels))
+        .transpose()?;
     let mut render_source =
-        if let Some(path) = &opt.render.source_path { Some(open_wav_reader(path)?) } else { None };
+        opt.render.source_path.as_deref().map(WavFrameReader::open).transpose()?;
 
     let audio_callback = {
         // Allocate buffers outside the performance-sensitive audio loop.
@@ -219,7 +180,7 @@ fn main() -> Result<(), Error> {
             let mut should_continue = true;
 
             if let Some(source) = &mut capture_source {
-                if !copy_stream(source, &mut input_mut) {
+                if !source.read_frame(&mut input_mut).unwrap() {
                     should_continue = false;
                 }
             } else {
@@ -227,21 +188,17 @@ fn main() -> Result<(), Error> {
             }
 
             if let Some(sink) = &mut capture_preprocess_sink {
-                for sample in &input_mut {
-                    sink.write_sample(*sample).unwrap();
-                }
+                sink.write_frame(&input_mut).unwrap();
             }
 
             processor.process_capture_frame(&mut input_mut).unwrap();
 
             if let Some(sink) = &mut capture_postprocess_sink {
-                for sample in &input_mut {
-                    sink.write_sample(*sample).unwrap();
-                }
+                sink.write_frame(&input_mut).unwrap();
             }
 
             if let Some(source) = &mut render_source {
-                if !copy_stream(source, out_buffer) {
+                if !source.read_frame(out_buffer).unwrap() {
                     should_continue = false;
                 }
             } else {
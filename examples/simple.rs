@@ -1,4 +1,4 @@
-use webrtc_audio_processing::*;
+use webrtc_audio_processing::{signal::Signal, *};
 
 fn main() {
     let config = InitializationConfig {
@@ -44,16 +44,9 @@ fn main() {
 /// Generate example stereo frames that simulates a situation where the
 /// microphone (capture) would be picking up the speaker (render) output.
 fn sample_stereo_frames() -> (Vec<f32>, Vec<f32>) {
-    let num_samples_per_frame = NUM_SAMPLES_PER_FRAME as usize;
-
-    let mut render_frame = Vec::with_capacity(num_samples_per_frame * 2);
-    let mut capture_frame = Vec::with_capacity(num_samples_per_frame * 2);
-    for i in 0..num_samples_per_frame {
-        render_frame.push((i as f32 / 40.0).cos() * 0.4);
-        render_frame.push((i as f32 / 40.0).cos() * 0.2);
-        capture_frame.push((i as f32 / 20.0).sin() * 0.4 + render_frame[i * 2] * 0.2);
-        capture_frame.push((i as f32 / 20.0).sin() * 0.2 + render_frame[i * 2 + 1] * 0.2);
-    }
+    let render_frame = signal::Sine::new(190.0, 0.4).frame(2);
+    let near_end = signal::Sine::new(380.0, 0.4).frame(2);
+    let capture_frame = signal::mix_echo(&near_end, &render_frame, 0.2);
 
     (render_frame, capture_frame)
 }
@@ -0,0 +1,25 @@
+//! Applies a sequence of configs mid-stream, each followed by a few frames of a plain signal,
+//! as reconfiguring a running processor is where valid-looking configs have crashed.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use webrtc_audio_processing::{signal::*, *};
+use webrtc_audio_processing_fuzz::{ValidConfig, ValidInitializationConfig};
+
+fuzz_target!(|input: (ValidInitializationConfig, Vec<(ValidConfig, u8)>)| {
+    let (ValidInitializationConfig(init_config), configs) = input;
+    let mut processor = Processor::new(&init_config).unwrap();
+    let mut render_signal = Sine::new(440.0, 0.5);
+    let mut capture_signal = Sine::new(1_000.0, 0.3);
+    for (ValidConfig(config), num_frames) in configs {
+        processor.set_config(config);
+        for _ in 0..num_frames % 8 {
+            let mut render_frame = render_signal.frame(init_config.num_render_channels as usize);
+            let mut capture_frame = capture_signal.frame(init_config.num_capture_channels as usize);
+            processor.process_render_frame(&mut render_frame).unwrap();
+            processor.process_capture_frame(&mut capture_frame).unwrap();
+            processor.get_stats();
+        }
+    }
+});
@@ -0,0 +1,25 @@
+//! Feeds arbitrary sample data, including NaN, infinities, denormals and wrong-sized frames,
+//! through a processor under an arbitrary config.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use webrtc_audio_processing::*;
+use webrtc_audio_processing_fuzz::{Frame, ValidConfig, ValidInitializationConfig};
+
+fuzz_target!(|input: (ValidInitializationConfig, ValidConfig, Vec<Frame>)| {
+    let (ValidInitializationConfig(init_config), ValidConfig(config), frames) = input;
+    let mut processor = Processor::new(&init_config).unwrap();
+    processor.set_config(config);
+    for frame in frames {
+        // Errors are fine, e.g. for wrong-sized frames; crashes and panics aren't.
+        let _ = if frame.render {
+            processor.process_render_frame(&mut frame.samples(init_config.num_render_channels))
+        } else {
+            let mut samples = frame.samples(init_config.num_capture_channels);
+            let result = processor.process_capture_frame(&mut samples);
+            processor.get_stats();
+            result
+        };
+    }
+});
@@ -0,0 +1,159 @@
+//! Arbitrary inputs for the fuzz targets. They stay within the ranges documented on `Config`
+//! and `InitializationConfig`, so that a crash points at a combination users can run into,
+//! rather than at a value the native module is documented to reject.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use webrtc_audio_processing::*;
+
+/// The most channels tried, to keep the frames small enough for the fuzzer to get through.
+const MAX_NUM_CHANNELS: i32 = 4;
+
+/// An `InitializationConfig` with a supported number of channels.
+#[derive(Debug)]
+pub struct ValidInitializationConfig(pub InitializationConfig);
+
+impl<'a> Arbitrary<'a> for ValidInitializationConfig {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self(InitializationConfig {
+            num_capture_channels: u.int_in_range(1..=MAX_NUM_CHANNELS)?,
+            num_render_channels: u.int_in_range(1..=MAX_NUM_CHANNELS)?,
+            enable_experimental_agc: u.arbitrary()?,
+            enable_intelligibility_enhancer: u.arbitrary()?,
+        }))
+    }
+}
+
+/// A `Config` with every value within its documented range.
+#[derive(Debug)]
+pub struct ValidConfig(pub Config);
+
+impl<'a> Arbitrary<'a> for ValidConfig {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let echo_cancellation = if u.arbitrary()? {
+            Some(EchoCancellation {
+                suppression_level: *u.choose(&[
+                    EchoCancellationSuppressionLevel::Lowest,
+                    EchoCancellationSuppressionLevel::Lower,
+                    EchoCancellationSuppressionLevel::Low,
+                    EchoCancellationSuppressionLevel::Moderate,
+                    EchoCancellationSuppressionLevel::High,
+                ])?,
+                enable_extended_filter: u.arbitrary()?,
+                enable_delay_agnostic: u.arbitrary()?,
+                stream_delay_ms: if u.arbitrary()? { Some(u.int_in_range(0..=500)?) } else { None },
+            })
+        } else {
+            None
+        };
+        let echo_control_mobile = if u.arbitrary()? {
+            Some(EchoControlMobile {
+                routing_mode: *u.choose(&[
+                    EchoControlMobileRoutingMode::QuietEarpieceOrHeadset,
+                    EchoControlMobileRoutingMode::Earpiece,
+                    EchoControlMobileRoutingMode::LoudEarpiece,
+                    EchoControlMobileRoutingMode::Speakerphone,
+                    EchoControlMobileRoutingMode::LoudSpeakerphone,
+                ])?,
+                enable_comfort_noise: u.arbitrary()?,
+                stream_delay_ms: if u.arbitrary()? { Some(u.int_in_range(0..=500)?) } else { None },
+            })
+        } else {
+            None
+        };
+        let gain_control = if u.arbitrary()? {
+            Some(GainControl {
+                mode: *u.choose(&[
+                    GainControlMode::AdaptiveAnalog,
+                    GainControlMode::AdaptiveDigital,
+                    GainControlMode::FixedDigital,
+                ])?,
+                target_level_dbfs: u.int_in_range(0..=31)?,
+                compression_gain_db: u.int_in_range(0..=90)?,
+                enable_limiter: u.arbitrary()?,
+            })
+        } else {
+            None
+        };
+        let noise_suppression = if u.arbitrary()? {
+            Some(NoiseSuppression {
+                suppression_level: *u.choose(&[
+                    NoiseSuppressionLevel::Low,
+                    NoiseSuppressionLevel::Moderate,
+                    NoiseSuppressionLevel::High,
+                    NoiseSuppressionLevel::VeryHigh,
+                ])?,
+            })
+        } else {
+            None
+        };
+        let voice_detection = if u.arbitrary()? {
+            Some(VoiceDetection {
+                detection_likelihood: *u.choose(&[
+                    VoiceDetectionLikelihood::VeryLow,
+                    VoiceDetectionLikelihood::Low,
+                    VoiceDetectionLikelihood::Moderate,
+                    VoiceDetectionLikelihood::High,
+                ])?,
+            })
+        } else {
+            None
+        };
+        let high_pass_filter = if u.arbitrary()? {
+            Some(HighPassFilter {
+                cutoff_hz: u.int_in_range(0..=24_000)? as f32,
+                order: u.int_in_range(0..=10)?,
+            })
+        } else {
+            None
+        };
+        let voice_gate = if u.arbitrary()? {
+            Some(VoiceGate {
+                attack_ms: u.int_in_range(0..=1000)?,
+                hold_ms: u.int_in_range(0..=5000)?,
+                attenuation_db: if u.arbitrary()? {
+                    Some(u.int_in_range(0..=120)? as f32)
+                } else {
+                    None
+                },
+            })
+        } else {
+            None
+        };
+        Ok(Self(Config {
+            echo_cancellation,
+            echo_control_mobile,
+            gain_control,
+            noise_suppression,
+            noise_suppression_backend: *u
+                .choose(&[NoiseSuppressionBackend::Builtin, NoiseSuppressionBackend::External])?,
+            voice_detection,
+            enable_transient_suppressor: u.arbitrary()?,
+            enable_high_pass_filter: u.arbitrary()?,
+            high_pass_filter,
+            sanitize_non_finite_samples: u.arbitrary()?,
+            deterministic: u.arbitrary()?,
+            voice_gate,
+            enable_comfort_noise: u.arbitrary()?,
+        }))
+    }
+}
+
+/// A frame for either direction. Unless `wrong_size` is set, `samples` is padded or truncated
+/// to the size the processor expects, so that most frames make it to the native module.
+#[derive(Debug, Arbitrary)]
+pub struct Frame {
+    pub render: bool,
+    pub wrong_size: bool,
+    pub samples: Vec<f32>,
+}
+
+impl Frame {
+    /// Returns the samples to pass for `num_channels` channels.
+    pub fn samples(&self, num_channels: i32) -> Vec<f32> {
+        let mut samples = self.samples.clone();
+        if !self.wrong_size {
+            samples.resize(NUM_SAMPLES_PER_FRAME as usize * num_channels as usize, 0.0);
+        }
+        samples
+    }
+}
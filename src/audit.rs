@@ -0,0 +1,237 @@
+use crate::Config;
+#[cfg(feature = "derive_serde")]
+use serde::Serialize;
+use std::{collections::VecDeque, time::SystemTime};
+
+/// The call that changed the config, or another setting of the processing, in a
+/// [`ConfigChange`]. The calls that don't change the config carry their arguments.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize))]
+pub enum ConfigChangeSource {
+    /// `Processor::set_config()`.
+    SetConfig,
+    /// `Processor::set_noise_suppression_level()`.
+    SetNoiseSuppressionLevel,
+    /// `Processor::set_agc1_target_level_dbfs()`.
+    SetAgc1TargetLevelDbfs,
+    /// `Processor::set_agc1_enable_limiter()`.
+    SetAgc1EnableLimiter,
+    /// `Processor::set_headphones_connected()`.
+    SetHeadphonesConnected(bool),
+    /// `Processor::notify_playout_device_changed()`.
+    NotifyPlayoutDeviceChanged,
+    /// `Processor::notify_capture_device_changed()`.
+    NotifyCaptureDeviceChanged,
+    /// `Processor::set_stream_delay_ms()`. Only recorded when the delay differs from the last
+    /// one since the last `set_config()`, as it's typically set on every frame.
+    SetStreamDelayMs(i32),
+    /// `Processor::set_echo_reference_weights()`.
+    SetEchoReferenceWeights(Option<Vec<f32>>),
+    /// `Processor::set_capture_channel_map()`.
+    SetCaptureChannelMap(Option<Vec<usize>>),
+    /// `Processor::set_render_channel_map()`.
+    SetRenderChannelMap(Option<Vec<usize>>),
+}
+
+/// A field of `Config` that differs from the previous config, in a [`ConfigChange`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize))]
+pub struct ConfigFieldChange {
+    /// The name of the field, e.g. `"gain_control"`.
+    pub field: &'static str,
+    /// The `Debug` representation of the previous value.
+    pub previous: String,
+    /// The `Debug` representation of the new value.
+    pub new: String,
+}
+
+/// An entry of the config audit log, see `Processor::enable_config_audit_log()`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize))]
+pub struct ConfigChange {
+    /// When the change was made, by the wall clock, to line it up with other logs.
+    pub time: SystemTime,
+    /// The call that made the change.
+    pub source: ConfigChangeSource,
+    /// The fields that differ from the previous config, or from `Config::default()` for the
+    /// first one. Empty if the same config was applied again, or if `source` doesn't change
+    /// the config.
+    pub changes: Vec<ConfigFieldChange>,
+    /// The config after the change, `Config::default()` before the first `set_config()`.
+    pub config: Config,
+}
+
+/// The last changes of the config, up to a capacity.
+#[derive(Debug, Default)]
+pub(crate) struct ConfigAuditLog {
+    capacity: usize,
+    entries: VecDeque<ConfigChange>,
+    // The delay of the last `SetStreamDelayMs` entry since the last `SetConfig` one.
+    last_stream_delay_ms: Option<i32>,
+}
+
+impl ConfigAuditLog {
+    /// Keeps the last `capacity` changes from now on, or stops recording and forgets them at 0.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub(crate) fn entries(&self) -> Vec<ConfigChange> {
+        self.entries.iter().cloned().collect()
+    }
+
+    /// Records the change from `previous`, if enabled.
+    pub(crate) fn record(
+        &mut self,
+        source: ConfigChangeSource,
+        previous: Option<&Config>,
+        config: &Config,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        if source == ConfigChangeSource::SetConfig {
+            self.last_stream_delay_ms = None;
+        }
+        let changes = match previous {
+            Some(previous) => diff(previous, config),
+            None => diff(&Config::default(), config),
+        };
+        self.push(source, changes, config);
+    }
+
+    /// Records a call that doesn't change `config`, if enabled.
+    pub(crate) fn record_call(&mut self, source: ConfigChangeSource, config: &Config) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let ConfigChangeSource::SetStreamDelayMs(delay_ms) = source {
+            if self.last_stream_delay_ms.replace(delay_ms) == Some(delay_ms) {
+                return;
+            }
+        }
+        self.push(source, Vec::new(), config);
+    }
+
+    fn push(
+        &mut self,
+        source: ConfigChangeSource,
+        changes: Vec<ConfigFieldChange>,
+        config: &Config,
+    ) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(ConfigChange {
+            time: SystemTime::now(),
+            source,
+            changes,
+            config: config.clone(),
+        });
+    }
+}
+
+/// Lists the fields of `config` that differ from `previous`.
+fn diff(previous: &Config, config: &Config) -> Vec<ConfigFieldChange> {
+    macro_rules! diff_fields {
+        ($($field:ident),* $(,)?) => {{
+            // Destructured without `..`, so that new fields can't be left out.
+            let Config { $($field),* } = config;
+            let mut changes = Vec::new();
+            $(
+                if previous.$field != *$field {
+                    changes.push(ConfigFieldChange {
+                        field: stringify!($field),
+                        previous: format!("{:?}", previous.$field),
+                        new: format!("{:?}", $field),
+                    });
+                }
+            )*
+            changes
+        }};
+    }
+
+    diff_fields!(
+        echo_cancellation,
+        echo_control_mobile,
+        gain_control,
+        noise_suppression,
+        noise_suppression_backend,
+        voice_detection,
+        enable_transient_suppressor,
+        enable_high_pass_filter,
+        high_pass_filter,
+        sanitize_non_finite_samples,
+        deterministic,
+        voice_gate,
+        enable_comfort_noise,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_audit_log() {
+        let mut log = ConfigAuditLog::default();
+        log.record(ConfigChangeSource::SetConfig, None, &Config::default());
+        assert!(log.entries().is_empty());
+
+        log.set_capacity(2);
+        let config = Config { enable_high_pass_filter: true, ..Config::default() };
+        log.record(ConfigChangeSource::SetConfig, None, &config);
+        log.record(ConfigChangeSource::SetConfig, Some(&config), &config);
+        let deterministic = Config { deterministic: true, ..config.clone() };
+        log.record(ConfigChangeSource::SetConfig, Some(&config), &deterministic);
+
+        let entries = log.entries();
+        assert_eq!(2, entries.len());
+        assert!(entries[0].changes.is_empty());
+        assert_eq!(
+            vec![ConfigFieldChange {
+                field: "deterministic",
+                previous: "false".to_string(),
+                new: "true".to_string(),
+            }],
+            entries[1].changes
+        );
+        assert_eq!(deterministic, entries[1].config);
+
+        log.set_capacity(0);
+        assert!(log.entries().is_empty());
+    }
+
+    #[test]
+    fn test_record_call() {
+        let mut log = ConfigAuditLog::default();
+        log.set_capacity(10);
+        let config = Config::default();
+        log.record_call(ConfigChangeSource::SetHeadphonesConnected(true), &config);
+        log.record_call(ConfigChangeSource::SetStreamDelayMs(40), &config);
+        log.record_call(ConfigChangeSource::SetStreamDelayMs(40), &config);
+        log.record_call(ConfigChangeSource::SetStreamDelayMs(50), &config);
+        log.record(ConfigChangeSource::SetConfig, Some(&config), &config);
+        log.record_call(ConfigChangeSource::SetStreamDelayMs(50), &config);
+
+        let sources: Vec<_> = log.entries().into_iter().map(|entry| entry.source).collect();
+        assert_eq!(
+            vec![
+                ConfigChangeSource::SetHeadphonesConnected(true),
+                ConfigChangeSource::SetStreamDelayMs(40),
+                ConfigChangeSource::SetStreamDelayMs(50),
+                ConfigChangeSource::SetConfig,
+                ConfigChangeSource::SetStreamDelayMs(50),
+            ],
+            sources
+        );
+        assert!(log.entries()[0].changes.is_empty());
+    }
+}
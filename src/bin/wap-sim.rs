@@ -0,0 +1,206 @@
+//! Runs recorded capture and render WAV files through the processing pipeline offline, faster
+//! than real time, like `audioproc_f` of upstream webrtc. Writes the processed capture and a
+//! JSON report of the statistics.
+//!
+//! ```
+//! $ cargo run --bin wap-sim --features bundled,sim -- --capture capture.wav \
+//!     --render render.wav --config config.json5 --report report.json
+//! ```
+//!
+//! The config file holds a JSON5 serialization of [`Config`]. Omitted fields take their default
+//! values.
+//!
+//! `--series stats.csv` writes the statistics of every frame for plotting, and `--plot
+//! stats.png` (with the `plot` feature) renders them.
+//!
+//! With `--compare a.json5 b.json5 ...` instead of `--config`, the session is processed under
+//! each config in parallel, and a table of metrics ranked by attenuation is printed instead of
+//! writing the processed capture.
+
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use structopt::StructOpt;
+use webrtc_audio_processing::{
+    offline::{self, WavFrameReader, WavFrameWriter},
+    *,
+};
+
+/// The number of frames in a second, i.e. between two entries of `Report::stats`.
+const FRAMES_PER_SECOND: usize = 100;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "wap-sim")]
+struct Args {
+    /// The capture (near-end) WAV file, at 48 kHz.
+    #[structopt(short, long)]
+    capture: PathBuf,
+    /// The render (far-end) WAV file, at 48 kHz. Padded with silence, or truncated, to the
+    /// length of the capture.
+    #[structopt(short, long)]
+    render: Option<PathBuf>,
+    /// A JSON5 file with the `Config` to run. The default config otherwise.
+    #[structopt(long)]
+    config: Option<PathBuf>,
+    /// JSON5 files with `Config`s to compare, named after their file names.
+    #[structopt(long, conflicts_with = "config")]
+    compare: Vec<PathBuf>,
+    /// Where to write the processed capture. Next to the capture, with a `-processed` suffix,
+    /// otherwise.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+    /// Where to write the JSON report. Printed to stdout otherwise.
+    #[structopt(long)]
+    report: Option<PathBuf>,
+    /// Where to write the statistics of every frame as CSV, for plotting.
+    #[structopt(long)]
+    series: Option<PathBuf>,
+    /// Where to render a PNG plot of the statistics over time.
+    #[cfg(feature = "plot")]
+    #[structopt(long)]
+    plot: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    /// The number of 10 ms frames processed.
+    num_frames: usize,
+    /// The duration of the processed audio, in seconds.
+    audio_duration_secs: f64,
+    /// How long the processing took, in seconds.
+    processing_duration_secs: f64,
+    /// How many times faster than real time the processing ran.
+    realtime_factor: f64,
+    /// The statistics at the end of every second of audio.
+    stats: Vec<Stats>,
+    /// The statistics after the last frame.
+    final_stats: Stats,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::from_args();
+    if !args.compare.is_empty() {
+        return compare(&args);
+    }
+
+    let config = match &args.config {
+        Some(path) => read_config(path)?,
+        None => Config::default(),
+    };
+    let output = args.output.clone().unwrap_or_else(|| {
+        let mut file_name = args.capture.file_stem().unwrap_or_default().to_os_string();
+        file_name.push("-processed.wav");
+        args.capture.with_file_name(file_name)
+    });
+
+    let mut capture = WavFrameReader::open(&args.capture)?;
+    let mut render = args.render.as_deref().map(WavFrameReader::open).transpose()?;
+    let mut processor = Processor::new(&InitializationConfig {
+        num_capture_channels: capture.num_channels() as i32,
+        num_render_channels: render.as_ref().map_or(1, |render| render.num_channels()) as i32,
+        ..InitializationConfig::default()
+    })?;
+    processor.set_config(config);
+
+    let mut writer = WavFrameWriter::create(&output, capture.num_channels())?;
+    let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * capture.num_channels()];
+    let mut render_frame = render
+        .as_ref()
+        .map(|render| vec![0f32; NUM_SAMPLES_PER_FRAME as usize * render.num_channels()]);
+
+    #[cfg(feature = "plot")]
+    let collect_rows = args.series.is_some() || args.plot.is_some();
+    #[cfg(not(feature = "plot"))]
+    let collect_rows = args.series.is_some();
+    let mut rows = Vec::new();
+    let mut input_frame = capture_frame.clone();
+
+    let start = Instant::now();
+    let mut num_frames = 0;
+    let mut stats = Vec::new();
+    let mut frames_until_stats = FRAMES_PER_SECOND;
+    while capture.read_frame(&mut capture_frame)? {
+        if let (Some(render), Some(render_frame)) = (&mut render, &mut render_frame) {
+            render.read_frame(render_frame)?;
+            processor.process_render_frame(render_frame)?;
+        }
+        if collect_rows {
+            input_frame.copy_from_slice(&capture_frame);
+        }
+        processor.process_capture_frame(&mut capture_frame)?;
+        writer.write_frame(&capture_frame)?;
+        if collect_rows {
+            let stats = processor.get_stats();
+            rows.push(offline::StatsRow::new(num_frames, &input_frame, &capture_frame, stats));
+        }
+        num_frames += 1;
+        frames_until_stats -= 1;
+        if frames_until_stats == 0 {
+            stats.push(processor.get_stats());
+            frames_until_stats = FRAMES_PER_SECOND;
+        }
+    }
+    writer.finalize()?;
+    let processing_duration_secs = start.elapsed().as_secs_f64();
+
+    let audio_duration_secs = num_frames as f64 / FRAMES_PER_SECOND as f64;
+    let report = Report {
+        num_frames,
+        audio_duration_secs,
+        processing_duration_secs,
+        realtime_factor: audio_duration_secs / processing_duration_secs,
+        stats,
+        final_stats: processor.get_stats(),
+    };
+    if let Some(path) = &args.series {
+        offline::write_stats_csv(&rows, BufWriter::new(File::create(path)?))?;
+    }
+    #[cfg(feature = "plot")]
+    if let Some(path) = &args.plot {
+        webrtc_audio_processing::plot::plot_stats_png(&rows, path)?;
+    }
+
+    let report_json = serde_json::to_string_pretty(&report)?;
+    match &args.report {
+        Some(path) => fs::write(path, report_json)?,
+        None => println!("{}", report_json),
+    }
+    eprintln!(
+        "Processed {:.1} s of audio in {:.2} s ({:.0}x real time), written to {}",
+        audio_duration_secs,
+        processing_duration_secs,
+        report.realtime_factor,
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn read_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    Ok(json5::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Processes the session under each of the `--compare` configs, and prints the ranking.
+fn compare(args: &Args) -> Result<(), Box<dyn Error>> {
+    let configs = args
+        .compare
+        .iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            Ok((name, read_config(path)?))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    let results = offline::compare_configs(&args.capture, args.render.as_deref(), &configs)?;
+    print!("{}", offline::comparison_table(&results));
+    if let Some(path) = &args.report {
+        fs::write(path, serde_json::to_string_pretty(&results)?)?;
+    }
+
+    Ok(())
+}
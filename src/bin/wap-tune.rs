@@ -0,0 +1,391 @@
+//! Runs live duplex processing on the default audio devices, and lets the operator adjust the
+//! config from the terminal while watching level, voice and echo meters, instead of editing a
+//! config file and restarting.
+//!
+//! ```
+//! $ cargo run --bin wap-tune --features bundled,tune -- --render far-end.wav --config config.json5
+//! ```
+//!
+//! The far end is simulated by playing the render WAV file in a loop. Type `help` for the
+//! commands; `save <path>` writes the current config as JSON, which `wap-sim` and `--config`
+//! read back.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use structopt::StructOpt;
+use webrtc_audio_processing::{
+    integrations::cpal as wap_cpal,
+    metrics::power_db,
+    offline::{OfflineError, WavFrameReader},
+    *,
+};
+
+const HELP: &str = "\
+Commands:
+  aec off|lowest|lower|low|moderate|high    echo suppression level
+  aec-delay <ms>|agnostic                   fixed stream delay, or delay-agnostic mode
+  ns off|low|moderate|high|very-high        noise suppression level
+  agc off|adaptive|fixed                    gain control mode
+  agc-target <dbfs>                         gain control target level, 0 to 31
+  agc-gain <db>                             gain control compression gain, 0 to 90
+  agc-limiter on|off                        gain control limiter
+  vad off|very-low|low|moderate|high        voice detection likelihood
+  hpf on|off                                high-pass filter
+  show                                      print the current config
+  save <path>                               write the current config as JSON
+  quit";
+
+/// How often the meters are redrawn.
+const METER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of render frames read ahead of the output stream.
+const NUM_RENDER_FRAMES_AHEAD: usize = 10;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "wap-tune")]
+struct Args {
+    /// A WAV file at 48 kHz, played in a loop as the far end.
+    #[structopt(short, long)]
+    render: Option<PathBuf>,
+    /// A JSON5 file with the `Config` to start with. Echo cancellation, noise suppression, gain
+    /// control and voice detection with their defaults otherwise.
+    #[structopt(long)]
+    config: Option<PathBuf>,
+}
+
+/// A level in dBFS, shared with the audio threads.
+#[derive(Default)]
+struct Level(AtomicU32);
+
+impl Level {
+    fn set(&self, samples: &[f32]) {
+        self.0.store(power_db(samples).to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::from_args();
+    let mut config = match &args.config {
+        Some(path) => json5::from_str(&fs::read_to_string(path)?)?,
+        None => default_config(),
+    };
+
+    let host = cpal::default_host();
+    let input_device = host.default_input_device().ok_or("no input device available")?;
+    let output_device = host.default_output_device().ok_or("no output device available")?;
+
+    let mut render = match &args.render {
+        Some(path) => Some(LoopedRender::spawn(WavFrameReader::open(path)?, path.clone())),
+        None => None,
+    };
+    let input_config = wap_cpal::stream_config(1);
+    let output_config =
+        wap_cpal::stream_config(render.as_ref().map_or(1, LoopedRender::num_channels) as u16);
+    let mut processor =
+        Processor::new(&wap_cpal::initialization_config(&input_config, &output_config)?)?;
+    processor.set_config(config.clone());
+
+    let input_level = Arc::new(Level::default());
+    let output_level = Arc::new(Level::default());
+    let mut capture = wap_cpal::capture_callback(processor.clone(), input_config.channels, {
+        let output_level = output_level.clone();
+        move |processed| match processed {
+            Ok(samples) => output_level.set(samples),
+            Err(err) => eprintln!("Failed to process a capture frame: {}", err),
+        }
+    });
+    let input_stream = input_device.build_input_stream(
+        &input_config,
+        {
+            let input_level = input_level.clone();
+            move |data: &[f32], info: &_| {
+                input_level.set(data);
+                capture(data, info);
+            }
+        },
+        |err| eprintln!("Input stream error: {}", err),
+    )?;
+
+    let output_stream = output_device.build_output_stream(
+        &output_config,
+        wap_cpal::render_callback(processor.clone(), output_config.channels, move |data| {
+            for sample in data.iter_mut() {
+                *sample = render.as_mut().map_or(0.0, LoopedRender::next_sample);
+            }
+        }),
+        |err| eprintln!("Output stream error: {}", err),
+    )?;
+    output_stream.play()?;
+    input_stream.play()?;
+
+    thread::spawn({
+        let processor = processor.clone();
+        move || loop {
+            draw_meters(&processor.get_stats(), input_level.get(), output_level.get());
+            thread::sleep(METER_INTERVAL);
+        }
+    });
+
+    println!("{}\n", HELP);
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        match line.trim() {
+            "" => {},
+            "quit" | "exit" => break,
+            "help" => println!("{}", HELP),
+            "show" => println!("{}", serde_json::to_string_pretty(&config)?),
+            command => match apply_command(&mut config, command) {
+                Ok(()) => processor.set_config(config.clone()),
+                Err(err) => println!("{}", err),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays a WAV file in a loop, read on a thread of its own so that the output callback never
+/// touches the file. The frame buffers are passed back and forth between the threads, so that
+/// the callback doesn't allocate or free either.
+struct LoopedRender {
+    frames: Receiver<Vec<f32>>,
+    free_frames: SyncSender<Vec<f32>>,
+    frame: Vec<f32>,
+    position: usize,
+}
+
+impl LoopedRender {
+    fn spawn(mut reader: WavFrameReader, path: PathBuf) -> Self {
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * reader.num_channels();
+        let (frames_sender, frames) = mpsc::sync_channel(NUM_RENDER_FRAMES_AHEAD);
+        // Room for every buffer but the one being played, so that returning one never fails.
+        let (free_frames, free_frames_receiver) = mpsc::sync_channel(NUM_RENDER_FRAMES_AHEAD);
+        for _ in 0..NUM_RENDER_FRAMES_AHEAD {
+            free_frames.send(vec![0f32; frame_len]).unwrap();
+        }
+        thread::spawn(move || {
+            for mut frame in free_frames_receiver {
+                if let Err(err) = read_looped(&mut reader, &path, &mut frame) {
+                    eprintln!("Failed to read the render file: {}", err);
+                    return;
+                }
+                if frames_sender.send(frame).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { frames, free_frames, frame: vec![0f32; frame_len], position: frame_len }
+    }
+
+    fn num_channels(&self) -> usize {
+        self.frame.len() / NUM_SAMPLES_PER_FRAME as usize
+    }
+
+    /// Returns the next sample, or silence while the reader thread is behind.
+    fn next_sample(&mut self) -> f32 {
+        if self.position == self.frame.len() {
+            self.position = 0;
+            match self.frames.try_recv() {
+                Ok(frame) => {
+                    let played = mem::replace(&mut self.frame, frame);
+                    let _ = self.free_frames.try_send(played);
+                },
+                Err(_) => self.frame.iter_mut().for_each(|sample| *sample = 0.0),
+            }
+        }
+        let sample = self.frame[self.position];
+        self.position += 1;
+        sample
+    }
+}
+
+/// Reads the next frame of `reader`, starting over from `path` at the end of the file, without
+/// a gap.
+fn read_looped(
+    reader: &mut WavFrameReader,
+    path: &Path,
+    frame: &mut [f32],
+) -> Result<(), OfflineError> {
+    if !reader.read_frame(frame)? {
+        *reader = WavFrameReader::open(path)?;
+        reader.read_frame(frame)?;
+    }
+    Ok(())
+}
+
+fn default_config() -> Config {
+    Config {
+        echo_cancellation: Some(EchoCancellation {
+            suppression_level: EchoCancellationSuppressionLevel::Moderate,
+            enable_extended_filter: true,
+            enable_delay_agnostic: true,
+            stream_delay_ms: None,
+        }),
+        gain_control: Some(GainControl {
+            mode: GainControlMode::AdaptiveDigital,
+            target_level_dbfs: 3,
+            compression_gain_db: 9,
+            enable_limiter: true,
+        }),
+        noise_suppression: Some(NoiseSuppression {
+            suppression_level: NoiseSuppressionLevel::Moderate,
+        }),
+        voice_detection: Some(VoiceDetection {
+            detection_likelihood: VoiceDetectionLikelihood::Moderate,
+        }),
+        enable_high_pass_filter: true,
+        ..Config::default()
+    }
+}
+
+/// Draws the meters on the line above the prompt, leaving what is being typed alone.
+fn draw_meters(stats: &Stats, input_db: f32, output_db: f32) {
+    let optional = |value: Option<f64>, unit| match value {
+        Some(value) => format!("{:5.1} {}", value, unit),
+        None => "    -   ".to_string(),
+    };
+    let meters = format!(
+        "in {} {:6.1} dB | out {} {:6.1} dB | voice {} | ERLE {} | delay {}",
+        bar(input_db),
+        input_db,
+        bar(output_db),
+        output_db,
+        match stats.has_voice {
+            Some(true) => "yes",
+            Some(false) => "no ",
+            None => " - ",
+        },
+        optional(stats.echo_return_loss_enhancement, "dB"),
+        optional(stats.delay_median_ms.map(f64::from), "ms"),
+    );
+    // Save the cursor, go up a line, clear it, draw, and restore the cursor.
+    print!("\x1b7\x1b[1A\r\x1b[2K{}\x1b8", meters);
+    let _ = io::stdout().flush();
+}
+
+/// Returns a bar of the level from -60 to 0 dBFS.
+fn bar(level_db: f32) -> String {
+    const WIDTH: usize = 20;
+    let filled = (((level_db + 60.0) / 60.0).clamp(0.0, 1.0) * WIDTH as f32).round() as usize;
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(WIDTH - filled))
+}
+
+/// Applies a command typed by the operator to `config`.
+fn apply_command(config: &mut Config, command: &str) -> Result<(), String> {
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let argument = words.next().ok_or_else(|| format!("`{}` takes an argument", name))?;
+    let on_off = |argument| match argument {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got `{}`", argument)),
+    };
+    let number = |argument: &str, range: std::ops::RangeInclusive<i32>| match argument.parse() {
+        Ok(value) if range.contains(&value) => Ok(value),
+        _ => Err(format!("expected a number in {:?}, got `{}`", range, argument)),
+    };
+
+    match name {
+        "aec" => {
+            let suppression_level = match argument {
+                "off" => {
+                    config.echo_cancellation = None;
+                    return Ok(());
+                },
+                "lowest" => EchoCancellationSuppressionLevel::Lowest,
+                "lower" => EchoCancellationSuppressionLevel::Lower,
+                "low" => EchoCancellationSuppressionLevel::Low,
+                "moderate" => EchoCancellationSuppressionLevel::Moderate,
+                "high" => EchoCancellationSuppressionLevel::High,
+                _ => return Err(format!("unknown echo suppression level `{}`", argument)),
+            };
+            let default = default_config().echo_cancellation.unwrap();
+            config.echo_cancellation.get_or_insert(default).suppression_level = suppression_level;
+        },
+        "aec-delay" => {
+            let echo_cancellation =
+                config.echo_cancellation.as_mut().ok_or("echo cancellation is off")?;
+            if argument == "agnostic" {
+                echo_cancellation.enable_delay_agnostic = true;
+                echo_cancellation.stream_delay_ms = None;
+            } else {
+                echo_cancellation.enable_delay_agnostic = false;
+                echo_cancellation.stream_delay_ms = Some(number(argument, 0..=500)?);
+            }
+        },
+        "ns" => {
+            let suppression_level = match argument {
+                "off" => {
+                    config.noise_suppression = None;
+                    return Ok(());
+                },
+                "low" => NoiseSuppressionLevel::Low,
+                "moderate" => NoiseSuppressionLevel::Moderate,
+                "high" => NoiseSuppressionLevel::High,
+                "very-high" => NoiseSuppressionLevel::VeryHigh,
+                _ => return Err(format!("unknown noise suppression level `{}`", argument)),
+            };
+            config.noise_suppression = Some(NoiseSuppression { suppression_level });
+        },
+        "agc" => {
+            let mode = match argument {
+                "off" => {
+                    config.gain_control = None;
+                    return Ok(());
+                },
+                "adaptive" => GainControlMode::AdaptiveDigital,
+                "fixed" => GainControlMode::FixedDigital,
+                _ => return Err(format!("unknown gain control mode `{}`", argument)),
+            };
+            let default = default_config().gain_control.unwrap();
+            config.gain_control.get_or_insert(default).mode = mode;
+        },
+        "agc-target" | "agc-gain" | "agc-limiter" => {
+            let gain_control = config.gain_control.as_mut().ok_or("gain control is off")?;
+            match name {
+                "agc-target" => gain_control.target_level_dbfs = number(argument, 0..=31)?,
+                "agc-gain" => gain_control.compression_gain_db = number(argument, 0..=90)?,
+                _ => gain_control.enable_limiter = on_off(argument)?,
+            }
+        },
+        "vad" => {
+            let detection_likelihood = match argument {
+                "off" => {
+                    config.voice_detection = None;
+                    return Ok(());
+                },
+                "very-low" => VoiceDetectionLikelihood::VeryLow,
+                "low" => VoiceDetectionLikelihood::Low,
+                "moderate" => VoiceDetectionLikelihood::Moderate,
+                "high" => VoiceDetectionLikelihood::High,
+                _ => return Err(format!("unknown voice detection likelihood `{}`", argument)),
+            };
+            config.voice_detection = Some(VoiceDetection { detection_likelihood });
+        },
+        "hpf" => config.enable_high_pass_filter = on_off(argument)?,
+        "save" => {
+            let json = serde_json::to_string_pretty(config).map_err(|err| err.to_string())?;
+            fs::write(argument, json).map_err(|err| err.to_string())?;
+            println!("Saved to {}", argument);
+        },
+        _ => return Err(format!("unknown command `{}`, type `help` for the list", name)),
+    }
+    Ok(())
+}
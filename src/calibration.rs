@@ -0,0 +1,161 @@
+use crate::{
+    signal::{Signal, Sweep},
+    SAMPLE_RATE,
+};
+use std::time::Duration;
+
+/// The chirp, long enough to stand out of room noise, short enough to correlate quickly.
+const CHIRP_DURATION: Duration = Duration::from_millis(100);
+const CHIRP_START_FREQUENCY: f32 = 500.0;
+const CHIRP_END_FREQUENCY: f32 = 5_000.0;
+const CHIRP_AMPLITUDE: f32 = 0.5;
+
+/// The lowest normalized correlation between the chirp and the capture to count as detected.
+const DETECTION_THRESHOLD: f32 = 0.3;
+
+/// Measures the delay between the render and capture streams, to use as
+/// `EchoCancellation::stream_delay_ms`, by playing a chirp and detecting it in the capture.
+///
+/// Call [`render()`](Self::render) on each buffer about to be played and
+/// [`capture()`](Self::capture) on each captured buffer, where the processor would otherwise
+/// see them, until [`is_complete()`](Self::is_complete). Buffers may be of any length. The
+/// delay is measured in samples from the start of each stream, so both streams must start
+/// together, e.g. with their `play()` calls back to back, and run at 48 kHz.
+#[derive(Debug, Clone)]
+pub struct DelayCalibrator {
+    chirp: Vec<f32>,
+    num_rendered: usize,
+    captured: Vec<f32>,
+    capture_len: usize,
+}
+
+impl DelayCalibrator {
+    /// Creates a calibrator that looks for delays of up to `max_delay`.
+    pub fn new(max_delay: Duration) -> Self {
+        let mut sweep = Sweep::linear(
+            CHIRP_START_FREQUENCY,
+            CHIRP_END_FREQUENCY,
+            CHIRP_DURATION,
+            CHIRP_AMPLITUDE,
+        );
+        let chirp: Vec<f32> = (0..sweep.num_samples()).map(|_| sweep.next_sample()).collect();
+        let max_delay_samples = (max_delay.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+        Self {
+            capture_len: chirp.len() + max_delay_samples,
+            chirp,
+            num_rendered: 0,
+            captured: Vec::new(),
+        }
+    }
+
+    /// Overwrites the interleaved `samples` of `num_channels` channels with the chirp, on every
+    /// channel, then with silence once it has been played.
+    pub fn render(&mut self, samples: &mut [f32], num_channels: usize) {
+        for frame in samples.chunks_exact_mut(num_channels) {
+            let sample = self.chirp.get(self.num_rendered).copied().unwrap_or(0.0);
+            frame.iter_mut().for_each(|s| *s = sample);
+            self.num_rendered += 1;
+        }
+    }
+
+    /// Records the interleaved `samples` of `num_channels` channels, mixed down to mono.
+    pub fn capture(&mut self, samples: &[f32], num_channels: usize) {
+        for frame in samples.chunks_exact(num_channels) {
+            if self.is_complete() {
+                return;
+            }
+            self.captured.push(frame.iter().sum::<f32>() / num_channels as f32);
+        }
+    }
+
+    /// Returns true once enough has been captured to cover the maximum delay.
+    pub fn is_complete(&self) -> bool {
+        self.captured.len() >= self.capture_len
+    }
+
+    /// Returns the measured delay, or `None` if the capture isn't complete yet or the chirp
+    /// couldn't be told apart from the noise, e.g. because the volume is too low.
+    pub fn delay(&self) -> Option<Duration> {
+        if !self.is_complete() {
+            return None;
+        }
+        let (lag, correlation) = self.best_lag();
+        if correlation < DETECTION_THRESHOLD {
+            return None;
+        }
+        Some(Duration::from_secs_f64(lag as f64 / SAMPLE_RATE as f64))
+    }
+
+    /// Returns the measured delay in ms, ready for `EchoCancellation::stream_delay_ms`.
+    pub fn stream_delay_ms(&self) -> Option<i32> {
+        self.delay().map(|delay| delay.as_millis() as i32)
+    }
+
+    /// Returns the lag of the capture that correlates best with the chirp, along with its
+    /// correlation normalized to [0, 1].
+    fn best_lag(&self) -> (usize, f32) {
+        let chirp_energy = self.chirp.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+        let mut window_energy =
+            self.captured[..self.chirp.len()].iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+
+        let mut best = (0, 0.0);
+        for lag in 0..=self.captured.len() - self.chirp.len() {
+            if lag > 0 {
+                let removed = self.captured[lag - 1] as f64;
+                let added = self.captured[lag + self.chirp.len() - 1] as f64;
+                window_energy = (window_energy - removed * removed + added * added).max(0.0);
+            }
+            if window_energy <= 0.0 {
+                continue;
+            }
+            let window = &self.captured[lag..lag + self.chirp.len()];
+            let dot =
+                self.chirp.iter().zip(window).map(|(&c, &s)| c as f64 * s as f64).sum::<f64>();
+            let correlation = (dot.abs() / (chirp_energy * window_energy).sqrt()) as f32;
+            if correlation > best.1 {
+                best = (lag, correlation);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{signal::WhiteNoise, NUM_SAMPLES_PER_FRAME};
+
+    /// Simulates an echo path of `delay_samples` with `gain`, in lockstep 10 ms frames.
+    fn calibrate(delay_samples: usize, gain: f32, noise: f32) -> DelayCalibrator {
+        let mut calibrator = DelayCalibrator::new(Duration::from_millis(200));
+        let mut noise = WhiteNoise::new(noise, 0);
+        let mut echo_path = vec![0.0; delay_samples];
+        let mut frame = vec![0.0; NUM_SAMPLES_PER_FRAME as usize * 2];
+        while !calibrator.is_complete() {
+            calibrator.render(&mut frame, 2);
+            echo_path.extend(frame.iter().step_by(2).map(|&sample| sample * gain));
+            let captured: Vec<f32> = echo_path
+                .drain(..NUM_SAMPLES_PER_FRAME as usize)
+                .map(|sample| sample + noise.next_sample())
+                .collect();
+            calibrator.capture(&captured, 1);
+        }
+        calibrator
+    }
+
+    #[test]
+    fn test_delay_calibration() {
+        let calibrator = calibrate(4_800 + 24, 0.1, 0.01);
+        assert_eq!(Some(Duration::from_micros(100_500)), calibrator.delay());
+        assert_eq!(Some(100), calibrator.stream_delay_ms());
+
+        assert_eq!(Some(Duration::ZERO), calibrate(0, 0.5, 0.0).delay());
+    }
+
+    #[test]
+    fn test_delay_calibration_failure() {
+        assert_eq!(None, DelayCalibrator::new(Duration::from_millis(200)).delay());
+        // Muted speakers.
+        assert_eq!(None, calibrate(480, 0.0, 0.1).delay());
+    }
+}
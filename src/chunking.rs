@@ -0,0 +1,392 @@
+use crate::{Error, NUM_SAMPLES_PER_FRAME};
+use std::{collections::VecDeque, time::Duration};
+
+/// Adapts interleaved buffers of arbitrary length to the fixed 10 ms frames `Processor` works
+/// on. Audio APIs rarely hand out buffers of exactly `NUM_SAMPLES_PER_FRAME` samples, so this
+/// queues the incoming samples, calls back for every complete frame, and hands the processed
+/// samples back in buffers of the caller's size.
+///
+/// Use one `FrameChunker` per direction, with either `process()` or `consume()` but not both.
+///
+/// ```no_run
+/// # use webrtc_audio_processing::*;
+/// # let mut processor = Processor::new(&InitializationConfig::default()).unwrap();
+/// let mut chunker = FrameChunker::new(1);
+/// let mut buffer = vec![0f32; 256]; // e.g. from the audio callback.
+/// chunker.process(&mut buffer, |frame| processor.process_capture_frame(frame)).unwrap();
+/// ```
+pub struct FrameChunker {
+    num_channels: usize,
+    frame: Vec<f32>,
+    input: VecDeque<f32>,
+    output: VecDeque<f32>,
+    // Set while fed by `consume()`, which never hands back the frame of silence in `output`.
+    consuming: bool,
+    processing_delay: Duration,
+}
+
+impl FrameChunker {
+    /// Creates a new `FrameChunker` for interleaved buffers of `num_channels` channels.
+    ///
+    /// Panics if `num_channels` is 0.
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "a FrameChunker needs at least one channel");
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * num_channels;
+        let mut output = VecDeque::with_capacity(frame_len * 2);
+        // One frame of silence up front guarantees that there are always enough processed
+        // samples to hand back, whatever the buffer sizes.
+        output.resize(frame_len, 0.0);
+        Self {
+            num_channels,
+            frame: vec![0.0; frame_len],
+            input: VecDeque::with_capacity(frame_len * 2),
+            output,
+            consuming: false,
+            processing_delay: Duration::ZERO,
+        }
+    }
+
+    /// Sets the delay the processing itself adds on top of the buffering, included in
+    /// `current_latency()`. Usually the result of `Processor::measure_algorithmic_delay()`.
+    pub fn set_processing_delay(&mut self, delay: Duration) {
+        self.processing_delay = delay;
+    }
+
+    /// Returns how long the samples handed back by the last `process()` call have spent in the
+    /// chunker, plus the processing delay. With `process()` that's always 10 ms of buffering;
+    /// with `consume()` it's the samples waiting for a complete frame.
+    pub fn current_latency(&self) -> Duration {
+        let num_output_samples = if self.consuming { 0 } else { self.output.len() };
+        let num_samples = (self.input.len() + num_output_samples) / self.num_channels;
+        // A frame is 10 ms.
+        let buffered =
+            Duration::from_micros(num_samples as u64 * 10_000 / NUM_SAMPLES_PER_FRAME as u64);
+        buffered + self.processing_delay
+    }
+
+    /// Replaces the samples in `buffer` with processed samples, calling `process_frame` for
+    /// each complete frame. The output lags the input by exactly one frame (10 ms); the first
+    /// frame of output is silence. `buffer` must hold a whole number of samples per channel.
+    pub fn process<F>(&mut self, buffer: &mut [f32], mut process_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut [f32]) -> Result<(), Error>,
+    {
+        let mut result = Ok(());
+        self.consuming = false;
+        self.input.extend(buffer.iter());
+        while self.input.len() >= self.frame.len() {
+            self.pop_frame();
+            // Keep the samples flowing even when processing fails, so that the latency stays
+            // the same. Only the first error is returned.
+            if let Err(err) = process_frame(&mut self.frame) {
+                result = result.and(Err(err));
+            }
+            self.output.extend(self.frame.iter());
+        }
+        let len = buffer.len();
+        for (sample, processed) in buffer.iter_mut().zip(self.output.drain(..len)) {
+            *sample = processed;
+        }
+        result
+    }
+
+    /// Feeds the samples in `buffer` without producing any output, calling `process_frame` for
+    /// each complete frame. Meant for the render direction, where the processor only needs to
+    /// see the played back audio and it shouldn't be delayed.
+    pub fn consume<F>(&mut self, buffer: &[f32], mut process_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut [f32]) -> Result<(), Error>,
+    {
+        // No output is handed back, so the frame of silence `process()` starts with doesn't
+        // count towards the latency. It stays, so that `process()` can't run short after all.
+        self.consuming = true;
+        self.input.extend(buffer.iter());
+        while self.input.len() >= self.frame.len() {
+            self.pop_frame();
+            process_frame(&mut self.frame)?;
+        }
+        Ok(())
+    }
+
+    fn pop_frame(&mut self) {
+        let len = self.frame.len();
+        for (sample, input) in self.frame.iter_mut().zip(self.input.drain(..len)) {
+            *sample = input;
+        }
+    }
+}
+
+/// Adapts the fixed size frames of voice codecs (e.g. 20, 40 or 60 ms for Opus) to the 10 ms
+/// frames `Processor` works on, without manual slicing.
+///
+/// When the codec frame is a whole number of 10 ms frames, each one is processed in place and
+/// no latency is added. Otherwise (e.g. 2.5 or 5 ms Opus frames) the samples go through a
+/// [`FrameChunker`] and lag by 10 ms; see [`CodecFrameAdapter::latency_samples()`]. Either way
+/// the output stays sample aligned with the input.
+pub struct CodecFrameAdapter {
+    codec_frame_len: usize,
+    frame_len: usize,
+    frame: Vec<f32>,
+    chunker: Option<FrameChunker>,
+}
+
+impl CodecFrameAdapter {
+    /// Creates a new adapter for interleaved codec frames of `samples_per_channel` samples per
+    /// channel at 48 kHz, e.g. 960 for 20 ms frames.
+    ///
+    /// Panics if `num_channels` is 0.
+    pub fn new(num_channels: usize, samples_per_channel: usize) -> Self {
+        assert!(num_channels > 0, "a CodecFrameAdapter needs at least one channel");
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * num_channels;
+        let chunker = match samples_per_channel % NUM_SAMPLES_PER_FRAME as usize {
+            0 => None,
+            _ => Some(FrameChunker::new(num_channels)),
+        };
+        Self {
+            codec_frame_len: samples_per_channel * num_channels,
+            frame_len,
+            frame: vec![0.0; frame_len],
+            chunker,
+        }
+    }
+
+    /// Replaces the samples of the codec frame in `buffer` with processed samples, calling
+    /// `process_frame` for each 10 ms frame. Use it on the encoder (capture) side.
+    ///
+    /// Panics if `buffer` doesn't hold exactly one codec frame.
+    pub fn process<F>(&mut self, buffer: &mut [f32], mut process_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut [f32]) -> Result<(), Error>,
+    {
+        assert_eq!(self.codec_frame_len, buffer.len(), "buffer must hold one codec frame");
+        if let Some(chunker) = &mut self.chunker {
+            return chunker.process(buffer, process_frame);
+        }
+        // Like `FrameChunker::process()`, carry on with the rest of the frames after an error.
+        let mut result = Ok(());
+        for frame in buffer.chunks_exact_mut(self.frame_len) {
+            if let Err(err) = process_frame(frame) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    /// Feeds the codec frame in `buffer` without modifying it, calling `process_frame` for each
+    /// 10 ms frame. Use it on the decoder (render) side, after decoding.
+    ///
+    /// Panics if `buffer` doesn't hold exactly one codec frame.
+    pub fn consume<F>(&mut self, buffer: &[f32], mut process_frame: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut [f32]) -> Result<(), Error>,
+    {
+        assert_eq!(self.codec_frame_len, buffer.len(), "buffer must hold one codec frame");
+        match &mut self.chunker {
+            Some(chunker) => chunker.consume(buffer, process_frame),
+            None => buffer.chunks_exact(self.frame_len).try_for_each(|samples| {
+                // The processor may modify the frame, which must not leak into the caller's
+                // buffer.
+                self.frame.copy_from_slice(samples);
+                process_frame(&mut self.frame)
+            }),
+        }
+    }
+
+    /// Returns the number of samples per channel by which `process()` delays the audio.
+    pub fn latency_samples(&self) -> usize {
+        if self.chunker.is_some() {
+            NUM_SAMPLES_PER_FRAME as usize
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{collection::vec, prelude::*};
+
+    #[test]
+    fn test_process_delays_by_one_frame() {
+        let num_channels = 2;
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * num_channels;
+        let mut chunker = FrameChunker::new(num_channels);
+
+        let input = (0..frame_len * 3).map(|v| v as f32).collect::<Vec<f32>>();
+        let mut output = Vec::new();
+        let mut num_frames = 0;
+        // Odd buffer sizes, which don't line up with the frame boundaries.
+        for chunk in input.chunks(2 * 157) {
+            let mut buffer = chunk.to_vec();
+            chunker
+                .process(&mut buffer, |frame| {
+                    assert_eq!(frame_len, frame.len());
+                    num_frames += 1;
+                    Ok(())
+                })
+                .unwrap();
+            output.extend(buffer);
+        }
+
+        assert_eq!(3, num_frames);
+        assert_eq!(input.len(), output.len());
+        assert!(output[..frame_len].iter().all(|&sample| sample == 0.0));
+        assert_eq!(input[..frame_len * 2], output[frame_len..]);
+    }
+
+    #[test]
+    fn test_current_latency() {
+        let num_channels = 2;
+        let mut chunker = FrameChunker::new(num_channels);
+        for _ in 0..5 {
+            chunker.process(&mut [0.0; 2 * 157], |_| Ok(())).unwrap();
+            assert_eq!(Duration::from_millis(10), chunker.current_latency());
+        }
+        chunker.set_processing_delay(Duration::from_millis(2));
+        assert_eq!(Duration::from_millis(12), chunker.current_latency());
+
+        let mut chunker = FrameChunker::new(num_channels);
+        chunker.consume(&[0.0; 2 * 600], |_| Ok(())).unwrap();
+        // 120 samples left over, 2.5 ms.
+        assert_eq!(Duration::from_micros(2_500), chunker.current_latency());
+    }
+
+    #[test]
+    fn test_consume() {
+        let mut chunker = FrameChunker::new(1);
+        let mut num_frames = 0;
+        let buffer = vec![0.5f32; 100];
+        for _ in 0..10 {
+            chunker
+                .consume(&buffer, |_| {
+                    num_frames += 1;
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert_eq!(1000 / NUM_SAMPLES_PER_FRAME as usize, num_frames);
+
+        // Even though they're not meant to be mixed, `process()` still has enough samples.
+        chunker.process(&mut [0.0; 100], |_| Ok(())).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one channel")]
+    fn test_no_channels() {
+        FrameChunker::new(0);
+    }
+
+    #[test]
+    fn test_codec_frame_adapter() {
+        let samples_per_channel = NUM_SAMPLES_PER_FRAME as usize * 2;
+        let mut adapter = CodecFrameAdapter::new(1, samples_per_channel);
+        assert_eq!(0, adapter.latency_samples());
+
+        let input = (0..samples_per_channel).map(|v| v as f32).collect::<Vec<f32>>();
+        let mut buffer = input.clone();
+        let mut num_frames = 0;
+        adapter
+            .process(&mut buffer, |frame| {
+                assert_eq!(NUM_SAMPLES_PER_FRAME as usize, frame.len());
+                num_frames += 1;
+                frame.iter_mut().for_each(|sample| *sample *= 2.0);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(2, num_frames);
+        assert_eq!(input.iter().map(|sample| sample * 2.0).collect::<Vec<f32>>(), buffer);
+
+        adapter
+            .consume(&input, |frame| {
+                frame.iter_mut().for_each(|sample| *sample = 0.0);
+                Ok(())
+            })
+            .unwrap();
+
+        // 5 ms frames don't line up with the 10 ms frames.
+        let adapter = CodecFrameAdapter::new(2, NUM_SAMPLES_PER_FRAME as usize / 2);
+        assert_eq!(NUM_SAMPLES_PER_FRAME as usize, adapter.latency_samples());
+    }
+
+    /// Returns `input` delayed by `delay` samples of silence, at the same length.
+    fn delayed(input: &[f32], delay: usize) -> Vec<f32> {
+        let mut expected = vec![0.0; delay.min(input.len())];
+        expected.extend_from_slice(&input[..input.len() - expected.len()]);
+        expected
+    }
+
+    proptest! {
+        #[test]
+        fn test_process_conserves_samples(
+            num_channels in 1..=8usize,
+            buffer_lens in vec(0..=1_500usize, 0..20),
+        ) {
+            let frame_len = NUM_SAMPLES_PER_FRAME as usize * num_channels;
+            let mut chunker = FrameChunker::new(num_channels);
+            let mut input = Vec::new();
+            let mut output = Vec::new();
+            let mut num_frames = 0;
+            for buffer_len in buffer_lens {
+                let start = input.len();
+                let mut buffer: Vec<f32> =
+                    (start..start + buffer_len * num_channels).map(|v| v as f32).collect();
+                input.extend_from_slice(&buffer);
+                chunker
+                    .process(&mut buffer, |frame| {
+                        assert_eq!(frame_len, frame.len());
+                        num_frames += 1;
+                        Ok(())
+                    })
+                    .unwrap();
+                output.extend(buffer);
+            }
+            prop_assert_eq!(input.len() / frame_len, num_frames);
+            prop_assert_eq!(delayed(&input, frame_len), output);
+        }
+
+        #[test]
+        fn test_consume_conserves_samples(
+            num_channels in 1..=8usize,
+            buffer_lens in vec(0..=1_500usize, 0..20),
+        ) {
+            let frame_len = NUM_SAMPLES_PER_FRAME as usize * num_channels;
+            let mut chunker = FrameChunker::new(num_channels);
+            let mut input = Vec::new();
+            let mut consumed = Vec::new();
+            for buffer_len in buffer_lens {
+                let start = input.len();
+                let buffer: Vec<f32> =
+                    (start..start + buffer_len * num_channels).map(|v| v as f32).collect();
+                input.extend_from_slice(&buffer);
+                chunker
+                    .consume(&buffer, |frame| {
+                        consumed.extend_from_slice(frame);
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+            // Whole frames only; the rest waits for the next buffer.
+            prop_assert_eq!(&input[..input.len() / frame_len * frame_len], &consumed[..]);
+        }
+
+        #[test]
+        fn test_codec_frame_adapter_conserves_samples(
+            num_channels in 1..=4usize,
+            samples_per_channel in 1..=2_880usize,
+            num_codec_frames in 0..10usize,
+        ) {
+            let mut adapter = CodecFrameAdapter::new(num_channels, samples_per_channel);
+            let codec_frame_len = samples_per_channel * num_channels;
+            let input: Vec<f32> =
+                (0..codec_frame_len * num_codec_frames).map(|v| v as f32).collect();
+            let mut output = Vec::new();
+            for codec_frame in input.chunks_exact(codec_frame_len) {
+                let mut buffer = codec_frame.to_vec();
+                adapter.process(&mut buffer, |_| Ok(())).unwrap();
+                output.extend(buffer);
+            }
+            prop_assert_eq!(delayed(&input, adapter.latency_samples() * num_channels), output);
+        }
+    }
+}
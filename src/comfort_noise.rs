@@ -0,0 +1,96 @@
+/// The noise floor the estimate never falls below, as power.
+const MIN_NOISE_FLOOR: f32 = 1e-10;
+
+/// How fast the noise floor estimate rises when the level goes up, per frame. About 2 dB/s.
+const NOISE_FLOOR_RISE: f32 = 1.0046;
+
+/// Tracks the noise floor of the processed capture stream, and fills muted frames with white
+/// noise of the same level.
+pub(crate) struct ComfortNoiseGenerator {
+    // Unknown until the first unmuted frame, in which case muted frames stay silent.
+    noise_floor: Option<f32>,
+    rng_state: u32,
+}
+
+impl ComfortNoiseGenerator {
+    pub(crate) fn new() -> Self {
+        // A constant seed, so that `Config::deterministic` holds.
+        Self { noise_floor: None, rng_state: 0x9e37_79b9 }
+    }
+
+    /// Creates a generator of noise at a fixed `power`, as long as no frame is analyzed.
+    pub(crate) fn with_noise_floor(power: f32) -> Self {
+        Self { noise_floor: Some(power), ..Self::new() }
+    }
+
+    /// Updates the noise floor estimate from an unmuted frame. It follows the level down
+    /// immediately, and up slowly, so that speech barely moves it.
+    pub(crate) fn analyze_frame(&mut self, frame: &[Vec<f32>]) {
+        let num_samples = frame.iter().map(Vec::len).sum::<usize>().max(1);
+        let power =
+            frame.iter().flatten().map(|&sample| sample * sample).sum::<f32>() / num_samples as f32;
+        let noise_floor = match self.noise_floor {
+            Some(noise_floor) => (noise_floor * NOISE_FLOOR_RISE).min(power),
+            None => power,
+        };
+        self.noise_floor = Some(noise_floor.max(MIN_NOISE_FLOOR));
+    }
+
+    /// Replaces a muted frame with noise at the estimated noise floor.
+    pub(crate) fn fill_frame(&mut self, frame: &mut [Vec<f32>]) {
+        // Uniform noise in [-a, a] has a power of a² / 3.
+        let amplitude = (3.0 * self.noise_floor.unwrap_or(0.0)).sqrt();
+        for sample in frame.iter_mut().flat_map(|channel| channel.iter_mut()) {
+            *sample = amplitude * self.next_uniform();
+        }
+    }
+
+    /// Returns a uniformly distributed value in [-1, 1), from a xorshift32 generator.
+    fn next_uniform(&mut self) -> f32 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+        x as f32 / 2_147_483_648.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NUM_SAMPLES_PER_FRAME;
+
+    fn power(frame: &[Vec<f32>]) -> f32 {
+        frame.iter().flatten().map(|&sample| sample * sample).sum::<f32>()
+            / (frame.len() * frame[0].len()) as f32
+    }
+
+    #[test]
+    fn test_comfort_noise() {
+        let mut generator = ComfortNoiseGenerator::new();
+        let mut muted = vec![vec![1f32; NUM_SAMPLES_PER_FRAME as usize]; 2];
+        generator.fill_frame(&mut muted);
+        assert_eq!(0.0, power(&muted));
+
+        let mut rng = ComfortNoiseGenerator::new();
+        let noise = |rng: &mut ComfortNoiseGenerator, amplitude: f32| {
+            vec![(0..NUM_SAMPLES_PER_FRAME).map(|_| amplitude * rng.next_uniform()).collect(); 2]
+        };
+
+        // A second of speech over a noise floor of 0.01 power raises the estimate by 2 dB only.
+        for _ in 0..500 {
+            generator.analyze_frame(&noise(&mut rng, (3.0f32 * 0.01).sqrt()));
+        }
+        for _ in 0..100 {
+            generator.analyze_frame(&noise(&mut rng, 0.5));
+        }
+        let noise_floor = generator.noise_floor.unwrap();
+        assert!(noise_floor > 0.008 && noise_floor < 0.02);
+
+        generator.fill_frame(&mut muted);
+        let power = power(&muted);
+        assert!((power - noise_floor).abs() < noise_floor * 0.2);
+        assert_ne!(muted[0], muted[1]);
+    }
+}
@@ -83,10 +83,82 @@ impl From<EchoCancellation> for ffi::EchoCancellation {
     }
 }
 
+/// The audio routing of the device, which mobile echo control tunes its suppression for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub enum EchoControlMobileRoutingMode {
+    /// A headset, or an earpiece at low volume.
+    QuietEarpieceOrHeadset,
+    /// An earpiece.
+    Earpiece,
+    /// An earpiece at high volume.
+    LoudEarpiece,
+    /// A speakerphone.
+    Speakerphone,
+    /// A speakerphone at high volume.
+    LoudSpeakerphone,
+}
+
+impl From<EchoControlMobileRoutingMode> for ffi::EchoControlMobile_RoutingMode {
+    fn from(other: EchoControlMobileRoutingMode) -> ffi::EchoControlMobile_RoutingMode {
+        match other {
+            EchoControlMobileRoutingMode::QuietEarpieceOrHeadset => {
+                ffi::EchoControlMobile_RoutingMode::QUIET_EARPIECE_OR_HEADSET
+            },
+            EchoControlMobileRoutingMode::Earpiece => ffi::EchoControlMobile_RoutingMode::EARPIECE,
+            EchoControlMobileRoutingMode::LoudEarpiece => {
+                ffi::EchoControlMobile_RoutingMode::LOUD_EARPIECE
+            },
+            EchoControlMobileRoutingMode::Speakerphone => {
+                ffi::EchoControlMobile_RoutingMode::SPEAKERPHONE
+            },
+            EchoControlMobileRoutingMode::LoudSpeakerphone => {
+                ffi::EchoControlMobile_RoutingMode::LOUD_SPEAKERPHONE
+            },
+        }
+    }
+}
+
+/// Mobile echo control (AECM) configuration. A lighter echo canceller than
+/// `EchoCancellation`, e.g. for battery-powered devices, at the cost of quality: it processes the
+/// capture stream at 16 kHz, and reports no echo metrics.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct EchoControlMobile {
+    /// Sets the echo path the suppression is tuned for. Louder routes suppress more.
+    pub routing_mode: EchoControlMobileRoutingMode,
+
+    /// Fills the suppressed parts of the capture stream with comfort noise.
+    pub enable_comfort_noise: bool,
+
+    /// Sets the delay in ms between process_render_frame() receiving a far-end
+    /// frame and process_capture_frame() receiving a near-end frame containing
+    /// the corresponding echo. Unlike `EchoCancellation`, it can't estimate the delay on its
+    /// own, so 0 is used if unset.
+    pub stream_delay_ms: Option<i32>,
+}
+
+impl From<EchoControlMobile> for ffi::EchoControlMobile {
+    fn from(other: EchoControlMobile) -> ffi::EchoControlMobile {
+        ffi::EchoControlMobile {
+            enable: true,
+            routing_mode: other.routing_mode.into(),
+            enable_comfort_noise: other.enable_comfort_noise,
+            stream_delay_ms: other.stream_delay_ms.into(),
+        }
+    }
+}
+
 /// Mode of gain control.
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
 pub enum GainControlMode {
+    /// Adapts the analog volume of the microphone through the
+    /// [`MicVolumeBackend`](crate::MicVolumeBackend) passed to
+    /// `Processor::set_mic_volume_backend()`, in addition to the digital gain, so that the
+    /// signal is brought into range before it's quantized.
+    AdaptiveAnalog,
+
     /// Bring the signal to an appropriate range by applying an adaptive gain
     /// control. The volume is dynamically amplified with a microphone with
     /// small pickup and vice versa.
@@ -102,6 +174,7 @@ pub enum GainControlMode {
 impl From<GainControlMode> for ffi::GainControl_Mode {
     fn from(other: GainControlMode) -> ffi::GainControl_Mode {
         match other {
+            GainControlMode::AdaptiveAnalog => ffi::GainControl_Mode::ADAPTIVE_ANALOG,
             GainControlMode::AdaptiveDigital => ffi::GainControl_Mode::ADAPTIVE_DIGITAL,
             GainControlMode::FixedDigital => ffi::GainControl_Mode::FIXED_DIGITAL,
         }
@@ -184,6 +257,19 @@ impl From<NoiseSuppression> for ffi::NoiseSuppression {
     }
 }
 
+/// Selects what suppresses the noise on the capture stream.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub enum NoiseSuppressionBackend {
+    /// WebRTC's noise suppressor, configured by `Config::noise_suppression`.
+    #[default]
+    Builtin,
+    /// The [`NoiseSuppressorBackend`](crate::NoiseSuppressorBackend) passed to
+    /// `Processor::set_noise_suppressor()`, e.g. RNNoise. WebRTC's own noise suppressor is
+    /// disabled and `Config::noise_suppression` is ignored.
+    External,
+}
+
 /// The sensitivity of the noise detector.
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
@@ -228,6 +314,44 @@ impl From<VoiceDetection> for ffi::VoiceDetection {
     }
 }
 
+/// Voice gate configuration, see `Config::voice_gate`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct VoiceGate {
+    /// How long the gain takes to ramp up to unity once voice is detected, in ms. Short attack
+    /// times clip less of the first syllable, at the risk of an audible click.
+    pub attack_ms: u32,
+    /// How long the gate stays open after the last frame with voice, in ms, so that it doesn't
+    /// close on the short pauses within speech.
+    pub hold_ms: u32,
+    /// How much the capture stream is attenuated while the gate is closed, in dB, or `None`
+    /// for silence.
+    pub attenuation_db: Option<f32>,
+}
+
+impl Default for VoiceGate {
+    fn default() -> Self {
+        Self { attack_ms: 10, hold_ms: 300, attenuation_db: None }
+    }
+}
+
+/// Supplementary high-pass filter configuration, see `Config::high_pass_filter`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct HighPassFilter {
+    /// The frequency below which the capture stream is attenuated, at -3 dB, in Hz.
+    pub cutoff_hz: f32,
+    /// The order of the Butterworth filter, from 1 to 8. Each order steepens the roll-off below
+    /// the cutoff by 6 dB per octave.
+    pub order: u32,
+}
+
+impl Default for HighPassFilter {
+    fn default() -> Self {
+        Self { cutoff_hz: 150.0, order: 2 }
+    }
+}
+
 /// Config that can be used mid-processing.
 #[derive(Debug, Default, Clone, PartialEq)]
 #[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
@@ -235,12 +359,23 @@ pub struct Config {
     /// Enable and configure AEC (acoustic echo cancellation).
     pub echo_cancellation: Option<EchoCancellation>,
 
+    /// Enable and configure AECM (mobile echo control) instead of AEC. Ignored while
+    /// `echo_cancellation` is set, as the two can't run together. Switching between them with
+    /// `set_config()` is fine mid-processing, but reinitializes the native module, which resets
+    /// the state of the other components too, e.g. the adapted gain of the AGC.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub echo_control_mobile: Option<EchoControlMobile>,
+
     /// Enable and configure AGC (automatic gain control).
     pub gain_control: Option<GainControl>,
 
     /// Enable and configure noise suppression.
     pub noise_suppression: Option<NoiseSuppression>,
 
+    /// Selects between WebRTC's noise suppressor and an external one.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub noise_suppression_backend: NoiseSuppressionBackend,
+
     /// Enable and configure voice detection.
     pub voice_detection: Option<VoiceDetection>,
 
@@ -252,10 +387,51 @@ pub struct Config {
     /// low-frequency noise.
     #[cfg_attr(feature = "derive_serde", serde(default))]
     pub enable_high_pass_filter: bool,
+
+    /// Runs the capture stream through a high-pass filter of configurable cutoff and order
+    /// before it reaches the native module, e.g. to remove HVAC rumble above the fixed, low
+    /// cutoff of `enable_high_pass_filter`. Independent of it, and done in Rust.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub high_pass_filter: Option<HighPassFilter>,
+
+    /// Replaces NaN samples with silence and clamps infinite ones to full scale before they
+    /// reach the native module, where a single one would corrupt the filter states for good.
+    /// The replaced samples are counted in `Stats::num_non_finite_samples`.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub sanitize_non_finite_samples: bool,
+
+    /// Makes the output depend only on the frames and configs passed in, so that processing the
+    /// same session again gives bit-identical output, e.g. for regression tests. Denormals are
+    /// flushed to zero while processing, whatever the floating point state of the calling thread
+    /// (see `DenormalGuard`); the native components are otherwise seeded with constants and
+    /// don't dither. Frames must still be passed in the same order, e.g. in lockstep from a
+    /// single thread as the `offline` module does, and the output may differ between
+    /// architectures and compilers.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub deterministic: bool,
+
+    /// Attenuates the processed capture stream while voice detection reports no voice, like a
+    /// push-to-talk button driven by the voice. Runs after all the other components, and needs
+    /// `voice_detection` to be enabled; the gate stays open without it. Whether the gate is open
+    /// is reported in `Stats::voice_gate_open`.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub voice_gate: Option<VoiceGate>,
+
+    /// Fills the capture stream with noise at the level of its noise floor while the output is
+    /// muted with `set_output_will_be_muted()`, instead of passing it through, so that remote
+    /// listeners don't hear a dead line. The noise floor is estimated from the processed
+    /// capture stream while it isn't muted, i.e. after noise suppression.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub enable_comfort_noise: bool,
 }
 
 impl From<Config> for ffi::Config {
     fn from(other: Config) -> ffi::Config {
+        let echo_control_mobile = match (other.echo_control_mobile, &other.echo_cancellation) {
+            (Some(enabled_value), None) => enabled_value.into(),
+            _ => ffi::EchoControlMobile { enable: false, ..ffi::EchoControlMobile::default() },
+        };
+
         let echo_cancellation = if let Some(enabled_value) = other.echo_cancellation {
             enabled_value.into()
         } else {
@@ -268,7 +444,9 @@ impl From<Config> for ffi::Config {
             ffi::GainControl { enable: false, ..ffi::GainControl::default() }
         };
 
-        let noise_suppression = if let Some(enabled_value) = other.noise_suppression {
+        let noise_suppression = if let (Some(enabled_value), NoiseSuppressionBackend::Builtin) =
+            (other.noise_suppression, other.noise_suppression_backend)
+        {
             enabled_value.into()
         } else {
             ffi::NoiseSuppression { enable: false, ..ffi::NoiseSuppression::default() }
@@ -282,6 +460,7 @@ impl From<Config> for ffi::Config {
 
         ffi::Config {
             echo_cancellation,
+            echo_control_mobile,
             gain_control,
             noise_suppression,
             voice_detection,
@@ -337,6 +516,27 @@ pub struct Stats {
     /// The fraction of delay estimates that can make the echo cancellation perform
     /// poorly.
     pub delay_fraction_poor_delays: Option<f64>,
+
+    /// The number of non-finite samples replaced in the capture and render streams since the
+    /// processor was created, while `Config::sanitize_non_finite_samples` is enabled.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub num_non_finite_samples: Option<u64>,
+
+    /// True if the voice gate let the current frame through, while `Config::voice_gate` is set.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub voice_gate_open: Option<bool>,
+
+    /// The microphone volume applied for the current frame, as an analog level in [0, 255],
+    /// while the gain control is in `GainControlMode::AdaptiveAnalog`. Together with
+    /// `recommended_input_volume`, it shows whether the volume changes come from the gain
+    /// control or from elsewhere, e.g. the user or the OS.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub applied_input_volume: Option<i32>,
+
+    /// The microphone volume recommended by the gain control after the current frame, as an
+    /// analog level in [0, 255], while it's in `GainControlMode::AdaptiveAnalog`.
+    #[cfg_attr(feature = "derive_serde", serde(default))]
+    pub recommended_input_volume: Option<i32>,
 }
 
 impl From<ffi::Stats> for Stats {
@@ -353,6 +553,289 @@ impl From<ffi::Stats> for Stats {
             delay_median_ms: other.delay_median_ms.into(),
             delay_standard_deviation_ms: other.delay_standard_deviation_ms.into(),
             delay_fraction_poor_delays: other.delay_fraction_poor_delays.into(),
+            num_non_finite_samples: None,
+            voice_gate_open: None,
+            applied_input_volume: other.applied_input_volume.into(),
+            recommended_input_volume: other.recommended_input_volume.into(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{option, prelude::*};
+    use std::fmt;
+
+    fn echo_cancellation() -> impl Strategy<Value = EchoCancellation> {
+        (
+            prop_oneof![
+                Just(EchoCancellationSuppressionLevel::Lowest),
+                Just(EchoCancellationSuppressionLevel::Lower),
+                Just(EchoCancellationSuppressionLevel::Low),
+                Just(EchoCancellationSuppressionLevel::Moderate),
+                Just(EchoCancellationSuppressionLevel::High),
+            ],
+            any::<bool>(),
+            any::<bool>(),
+            option::of(any::<i32>()),
+        )
+            .prop_map(
+                |(
+                    suppression_level,
+                    enable_extended_filter,
+                    enable_delay_agnostic,
+                    stream_delay_ms,
+                )| {
+                    EchoCancellation {
+                        suppression_level,
+                        enable_extended_filter,
+                        enable_delay_agnostic,
+                        stream_delay_ms,
+                    }
+                },
+            )
+    }
+
+    fn echo_control_mobile() -> impl Strategy<Value = EchoControlMobile> {
+        (
+            prop_oneof![
+                Just(EchoControlMobileRoutingMode::QuietEarpieceOrHeadset),
+                Just(EchoControlMobileRoutingMode::Earpiece),
+                Just(EchoControlMobileRoutingMode::LoudEarpiece),
+                Just(EchoControlMobileRoutingMode::Speakerphone),
+                Just(EchoControlMobileRoutingMode::LoudSpeakerphone),
+            ],
+            any::<bool>(),
+            option::of(any::<i32>()),
+        )
+            .prop_map(|(routing_mode, enable_comfort_noise, stream_delay_ms)| {
+                EchoControlMobile { routing_mode, enable_comfort_noise, stream_delay_ms }
+            })
+    }
+
+    fn gain_control() -> impl Strategy<Value = GainControl> {
+        (
+            prop_oneof![
+                Just(GainControlMode::AdaptiveAnalog),
+                Just(GainControlMode::AdaptiveDigital),
+                Just(GainControlMode::FixedDigital)
+            ],
+            any::<i32>(),
+            any::<i32>(),
+            any::<bool>(),
+        )
+            .prop_map(|(mode, target_level_dbfs, compression_gain_db, enable_limiter)| {
+                GainControl { mode, target_level_dbfs, compression_gain_db, enable_limiter }
+            })
+    }
+
+    fn noise_suppression() -> impl Strategy<Value = NoiseSuppression> {
+        prop_oneof![
+            Just(NoiseSuppressionLevel::Low),
+            Just(NoiseSuppressionLevel::Moderate),
+            Just(NoiseSuppressionLevel::High),
+            Just(NoiseSuppressionLevel::VeryHigh),
+        ]
+        .prop_map(|suppression_level| NoiseSuppression { suppression_level })
+    }
+
+    fn voice_detection() -> impl Strategy<Value = VoiceDetection> {
+        prop_oneof![
+            Just(VoiceDetectionLikelihood::VeryLow),
+            Just(VoiceDetectionLikelihood::Low),
+            Just(VoiceDetectionLikelihood::Moderate),
+            Just(VoiceDetectionLikelihood::High),
+        ]
+        .prop_map(|detection_likelihood| VoiceDetection { detection_likelihood })
+    }
+
+    fn voice_gate() -> impl Strategy<Value = VoiceGate> {
+        (any::<u32>(), any::<u32>(), option::of(any::<f32>())).prop_map(
+            |(attack_ms, hold_ms, attenuation_db)| VoiceGate { attack_ms, hold_ms, attenuation_db },
+        )
+    }
+
+    fn high_pass_filter() -> impl Strategy<Value = HighPassFilter> {
+        (any::<f32>(), any::<u32>())
+            .prop_map(|(cutoff_hz, order)| HighPassFilter { cutoff_hz, order })
+    }
+
+    fn config() -> impl Strategy<Value = Config> {
+        (
+            option::of(echo_cancellation()),
+            option::of(echo_control_mobile()),
+            option::of(gain_control()),
+            option::of(noise_suppression()),
+            prop_oneof![
+                Just(NoiseSuppressionBackend::Builtin),
+                Just(NoiseSuppressionBackend::External)
+            ],
+            option::of(voice_detection()),
+            any::<bool>(),
+            any::<bool>(),
+            any::<bool>(),
+            any::<bool>(),
+            // Nested, as tuple strategies only go up to 12 elements.
+            (option::of(high_pass_filter()), option::of(voice_gate()), any::<bool>()),
+        )
+            .prop_map(
+                |(
+                    echo_cancellation,
+                    echo_control_mobile,
+                    gain_control,
+                    noise_suppression,
+                    noise_suppression_backend,
+                    voice_detection,
+                    enable_transient_suppressor,
+                    enable_high_pass_filter,
+                    sanitize_non_finite_samples,
+                    deterministic,
+                    (high_pass_filter, voice_gate, enable_comfort_noise),
+                )| {
+                    // No `..Config::default()`, so that a new field doesn't compile until it's
+                    // generated here, and checked by `test_config_conversion()`.
+                    Config {
+                        echo_cancellation,
+                        echo_control_mobile,
+                        gain_control,
+                        noise_suppression,
+                        noise_suppression_backend,
+                        voice_detection,
+                        enable_transient_suppressor,
+                        enable_high_pass_filter,
+                        high_pass_filter,
+                        sanitize_non_finite_samples,
+                        deterministic,
+                        voice_gate,
+                        enable_comfort_noise,
+                    }
+                },
+            )
+    }
+
+    /// Compares enum variants by name, e.g. `VeryHigh` with `VERY_HIGH`, so that a mapping to
+    /// the wrong native variant is caught.
+    fn same_variant(variant: impl fmt::Debug, ffi_variant: impl fmt::Debug) -> bool {
+        format!("{:?}", variant).to_uppercase()
+            == format!("{:?}", ffi_variant).replace('_', "").to_uppercase()
+    }
+
+    proptest! {
+        #[test]
+        fn test_config_conversion(config in config()) {
+            let converted = ffi::Config::from(config.clone());
+
+            let echo_cancellation = converted.echo_cancellation;
+            prop_assert_eq!(config.echo_cancellation.is_some(), echo_cancellation.enable);
+            if let Some(expected) = &config.echo_cancellation {
+                prop_assert!(same_variant(
+                    expected.suppression_level,
+                    echo_cancellation.suppression_level
+                ));
+                prop_assert_eq!(
+                    expected.enable_extended_filter,
+                    echo_cancellation.enable_extended_filter
+                );
+                prop_assert_eq!(
+                    expected.enable_delay_agnostic,
+                    echo_cancellation.enable_delay_agnostic
+                );
+                let stream_delay_ms: Option<i32> = echo_cancellation.stream_delay_ms.into();
+                prop_assert_eq!(expected.stream_delay_ms, stream_delay_ms);
+            }
+
+            // AECM is off while AEC is on.
+            let echo_control_mobile = converted.echo_control_mobile;
+            let expected_echo_control_mobile =
+                config.echo_control_mobile.as_ref().filter(|_| config.echo_cancellation.is_none());
+            prop_assert_eq!(expected_echo_control_mobile.is_some(), echo_control_mobile.enable);
+            if let Some(expected) = expected_echo_control_mobile {
+                prop_assert!(same_variant(expected.routing_mode, echo_control_mobile.routing_mode));
+                prop_assert_eq!(
+                    expected.enable_comfort_noise,
+                    echo_control_mobile.enable_comfort_noise
+                );
+                let stream_delay_ms: Option<i32> = echo_control_mobile.stream_delay_ms.into();
+                prop_assert_eq!(expected.stream_delay_ms, stream_delay_ms);
+            }
+
+            let gain_control = converted.gain_control;
+            prop_assert_eq!(config.gain_control.is_some(), gain_control.enable);
+            if let Some(expected) = &config.gain_control {
+                prop_assert!(same_variant(expected.mode, gain_control.mode));
+                prop_assert_eq!(expected.target_level_dbfs, gain_control.target_level_dbfs);
+                prop_assert_eq!(expected.compression_gain_db, gain_control.compression_gain_db);
+                prop_assert_eq!(expected.enable_limiter, gain_control.enable_limiter);
+            }
+
+            // The builtin suppressor is off while an external one is in use.
+            let noise_suppression = converted.noise_suppression;
+            let builtin = config.noise_suppression_backend == NoiseSuppressionBackend::Builtin;
+            prop_assert_eq!(
+                config.noise_suppression.is_some() && builtin,
+                noise_suppression.enable
+            );
+            if let (Some(expected), true) = (&config.noise_suppression, builtin) {
+                prop_assert!(same_variant(
+                    expected.suppression_level,
+                    noise_suppression.suppression_level
+                ));
+            }
+
+            let voice_detection = converted.voice_detection;
+            prop_assert_eq!(config.voice_detection.is_some(), voice_detection.enable);
+            if let Some(expected) = &config.voice_detection {
+                prop_assert!(same_variant(
+                    expected.detection_likelihood,
+                    voice_detection.detection_likelihood
+                ));
+            }
+
+            prop_assert_eq!(
+                config.enable_transient_suppressor,
+                converted.enable_transient_suppressor
+            );
+            prop_assert_eq!(config.enable_high_pass_filter, converted.enable_high_pass_filter);
+            // `sanitize_non_finite_samples`, `deterministic`, `voice_gate` and
+            // `enable_comfort_noise` are handled on the Rust side.
+        }
+    }
+
+    #[cfg(feature = "derive_serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        // Every optional section set, the voice gate and high-pass filter with their defaults,
+        // which JSON must be able to represent.
+        let config = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::High,
+                enable_extended_filter: true,
+                enable_delay_agnostic: false,
+                stream_delay_ms: Some(40),
+            }),
+            echo_control_mobile: Some(EchoControlMobile {
+                routing_mode: EchoControlMobileRoutingMode::Speakerphone,
+                enable_comfort_noise: true,
+                stream_delay_ms: None,
+            }),
+            gain_control: Some(GainControl {
+                mode: GainControlMode::AdaptiveDigital,
+                target_level_dbfs: 3,
+                compression_gain_db: 9,
+                enable_limiter: true,
+            }),
+            noise_suppression: Some(NoiseSuppression {
+                suppression_level: NoiseSuppressionLevel::High,
+            }),
+            voice_detection: Some(VoiceDetection {
+                detection_likelihood: VoiceDetectionLikelihood::Moderate,
+            }),
+            high_pass_filter: Some(HighPassFilter::default()),
+            voice_gate: Some(VoiceGate::default()),
+            ..Config::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(config, serde_json::from_str(&json).unwrap());
+    }
+}
@@ -0,0 +1,304 @@
+//! Conversions between interleaved signed 16-bit (S16) and `f32` sample buffers.
+//!
+//! `Processor` works on `f32` samples in the range of [-1.0, 1.0], while most audio devices and
+//! codecs deal in S16. The inner loops are written over fixed-size chunks without branches so
+//! that the compiler vectorizes them, which keeps the conversion cheap at high channel counts.
+//! The layout of the buffers doesn't matter as the conversion is sample-wise.
+//!
+//! [`F32ToS16Converter`] adds dithering and soft clipping to the `f32` to S16 conversion, for
+//! quiet or hot processed audio. [`bytes_to_f32()`] and [`f32_to_bytes()`] convert raw byte
+//! buffers tagged with a [`SampleFormat`], as received from network and IPC transports.
+
+use crate::signal::{Signal, WhiteNoise};
+
+/// The number of samples converted per iteration of the inner loops.
+const CHUNK_SIZE: usize = 16;
+
+/// The scale between full-scale `f32` and S16 samples.
+const S16_SCALE: f32 = 32768.0;
+
+/// The scale between full-scale `f32` and S24 samples.
+const S24_SCALE: f32 = 8_388_608.0;
+
+/// The level above which `Clipping::Soft` starts to compress.
+const SOFT_CLIPPING_KNEE: f32 = 0.8;
+
+/// Converts S16 samples in `src` to `f32` samples in the range of [-1.0, 1.0) in `dst`.
+/// Panics if the lengths of `src` and `dst` differ.
+pub fn s16_to_f32(src: &[i16], dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len());
+
+    let mut src_chunks = src.chunks_exact(CHUNK_SIZE);
+    let mut dst_chunks = dst.chunks_exact_mut(CHUNK_SIZE);
+    for (src_chunk, dst_chunk) in (&mut src_chunks).zip(&mut dst_chunks) {
+        for (src_sample, dst_sample) in src_chunk.iter().zip(dst_chunk.iter_mut()) {
+            *dst_sample = s16_sample_to_f32(*src_sample);
+        }
+    }
+    for (src_sample, dst_sample) in
+        src_chunks.remainder().iter().zip(dst_chunks.into_remainder().iter_mut())
+    {
+        *dst_sample = s16_sample_to_f32(*src_sample);
+    }
+}
+
+/// Converts `f32` samples in `src` to S16 samples in `dst`, rounding to the nearest value and
+/// clamping anything outside of [-1.0, 1.0] to the S16 range instead of wrapping around.
+/// Panics if the lengths of `src` and `dst` differ.
+pub fn f32_to_s16(src: &[f32], dst: &mut [i16]) {
+    assert_eq!(src.len(), dst.len());
+
+    let mut src_chunks = src.chunks_exact(CHUNK_SIZE);
+    let mut dst_chunks = dst.chunks_exact_mut(CHUNK_SIZE);
+    for (src_chunk, dst_chunk) in (&mut src_chunks).zip(&mut dst_chunks) {
+        for (src_sample, dst_sample) in src_chunk.iter().zip(dst_chunk.iter_mut()) {
+            *dst_sample = f32_sample_to_s16(*src_sample);
+        }
+    }
+    for (src_sample, dst_sample) in
+        src_chunks.remainder().iter().zip(dst_chunks.into_remainder().iter_mut())
+    {
+        *dst_sample = f32_sample_to_s16(*src_sample);
+    }
+}
+
+/// The encoding of the samples in a raw byte buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Signed 16-bit, little endian.
+    S16Le,
+    /// Signed 24-bit packed in 3 bytes, little endian.
+    S24Le,
+    /// 32-bit float in the range of [-1.0, 1.0], little endian.
+    F32Le,
+}
+
+impl SampleFormat {
+    /// Returns the size of a sample in bytes.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::S16Le => 2,
+            SampleFormat::S24Le => 3,
+            SampleFormat::F32Le => 4,
+        }
+    }
+
+    /// Decodes the sample in the first `bytes_per_sample()` bytes of `bytes`.
+    #[inline(always)]
+    pub(crate) fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::S16Le => s16_sample_to_f32(i16::from_le_bytes([bytes[0], bytes[1]])),
+            SampleFormat::S24Le => {
+                // Sign-extended by the arithmetic shift.
+                let sample = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                sample as f32 / S24_SCALE
+            },
+            SampleFormat::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Encodes `sample` into the first `bytes_per_sample()` bytes of `bytes`, rounding and
+    /// clamping integer formats like `f32_to_s16()`.
+    #[inline(always)]
+    pub(crate) fn encode(self, sample: f32, bytes: &mut [u8]) {
+        match self {
+            SampleFormat::S16Le => {
+                bytes[..2].copy_from_slice(&f32_sample_to_s16(sample).to_le_bytes())
+            },
+            SampleFormat::S24Le => {
+                let scaled = sample * S24_SCALE;
+                let rounded = (scaled + 0.5f32.copysign(scaled)) as i32;
+                let clamped = rounded.clamp(-(S24_SCALE as i32), S24_SCALE as i32 - 1);
+                bytes[..3].copy_from_slice(&clamped.to_le_bytes()[..3]);
+            },
+            SampleFormat::F32Le => bytes[..4].copy_from_slice(&sample.to_le_bytes()),
+        }
+    }
+}
+
+/// Converts the samples encoded as `format` in `src` to `f32` samples in `dst`. Panics unless
+/// `src` holds exactly `dst.len()` samples.
+pub fn bytes_to_f32(src: &[u8], format: SampleFormat, dst: &mut [f32]) {
+    let bytes_per_sample = format.bytes_per_sample();
+    assert_eq!(src.len(), dst.len() * bytes_per_sample);
+
+    for (src_sample, dst_sample) in src.chunks_exact(bytes_per_sample).zip(dst.iter_mut()) {
+        *dst_sample = format.decode(src_sample);
+    }
+}
+
+/// Converts the `f32` samples in `src` to samples encoded as `format` in `dst`. Integer
+/// formats are rounded and clamped like in [`f32_to_s16()`]. Panics unless `dst` holds exactly
+/// `src.len()` samples.
+pub fn f32_to_bytes(src: &[f32], format: SampleFormat, dst: &mut [u8]) {
+    let bytes_per_sample = format.bytes_per_sample();
+    assert_eq!(dst.len(), src.len() * bytes_per_sample);
+
+    for (src_sample, dst_sample) in src.iter().zip(dst.chunks_exact_mut(bytes_per_sample)) {
+        format.encode(*src_sample, dst_sample);
+    }
+}
+
+/// The noise added before rounding to S16 by [`F32ToS16Converter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// No dither. Quiet signals are truncated to a few steps, which sounds distorted, and
+    /// signals below half a step vanish.
+    #[default]
+    None,
+    /// Triangular probability density function dither of ±1 step, which turns the rounding
+    /// error into a constant noise floor of about -98 dBFS, independent of the signal.
+    Tpdf,
+}
+
+/// How [`F32ToS16Converter`] treats samples outside of [-1.0, 1.0].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clipping {
+    /// Clamps them to the S16 range, like [`f32_to_s16()`].
+    #[default]
+    Hard,
+    /// Compresses everything above 0.8 smoothly towards full scale, which is reached only
+    /// asymptotically. Overshoots then sound less harsh, at the cost of slightly bending the
+    /// peaks of signals that would have fit.
+    Soft,
+}
+
+/// Converts `f32` samples to S16 like [`f32_to_s16()`], with optional dithering and soft
+/// clipping. It keeps the state of the dither between buffers, so use one per stream.
+#[derive(Debug, Clone)]
+pub struct F32ToS16Converter {
+    dither: Dither,
+    clipping: Clipping,
+    // Uniform in [-0.5, 0.5) steps; two samples add up to the triangular dither.
+    noise: WhiteNoise,
+}
+
+impl F32ToS16Converter {
+    /// Creates a new converter. With `Dither::None` and `Clipping::Hard`, it converts exactly
+    /// like `f32_to_s16()`.
+    pub fn new(dither: Dither, clipping: Clipping) -> Self {
+        Self { dither, clipping, noise: WhiteNoise::new(0.5, 0) }
+    }
+
+    /// Converts `f32` samples in `src` to S16 samples in `dst`. Unlike `f32_to_s16()`, it isn't
+    /// vectorized while dithering or soft clipping. Panics if the lengths of `src` and `dst`
+    /// differ.
+    pub fn convert(&mut self, src: &[f32], dst: &mut [i16]) {
+        if self.dither == Dither::None && self.clipping == Clipping::Hard {
+            return f32_to_s16(src, dst);
+        }
+        assert_eq!(src.len(), dst.len());
+
+        for (src_sample, dst_sample) in src.iter().zip(dst.iter_mut()) {
+            let sample = match self.clipping {
+                Clipping::Hard => *src_sample,
+                Clipping::Soft => soft_clip(*src_sample),
+            };
+            let dither = match self.dither {
+                Dither::None => 0.0,
+                Dither::Tpdf => (self.noise.next_sample() + self.noise.next_sample()) / S16_SCALE,
+            };
+            *dst_sample = f32_sample_to_s16(sample + dither);
+        }
+    }
+}
+
+/// Compresses `sample` above `SOFT_CLIPPING_KNEE` with a tanh curve, which meets the straight
+/// line below it without a kink.
+fn soft_clip(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= SOFT_CLIPPING_KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - SOFT_CLIPPING_KNEE;
+    let compressed =
+        SOFT_CLIPPING_KNEE + headroom * ((magnitude - SOFT_CLIPPING_KNEE) / headroom).tanh();
+    compressed.copysign(sample)
+}
+
+#[inline(always)]
+fn s16_sample_to_f32(sample: i16) -> f32 {
+    f32::from(sample) / S16_SCALE
+}
+
+#[inline(always)]
+fn f32_sample_to_s16(sample: f32) -> i16 {
+    let scaled = sample * S16_SCALE;
+    // Rounds half away from zero, as `as` truncates towards zero. It also saturates at the i32
+    // range and maps NaN to 0.
+    let rounded = (scaled + 0.5f32.copysign(scaled)) as i32;
+    rounded.max(i32::from(i16::MIN)).min(i32::from(i16::MAX)) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s16_to_f32() {
+        let src = [0i16, 16384, -16384, i16::MAX, i16::MIN];
+        let mut dst = [0f32; 5];
+        s16_to_f32(&src, &mut dst);
+        assert_eq!([0.0, 0.5, -0.5, 32767.0 / 32768.0, -1.0], dst);
+    }
+
+    #[test]
+    fn test_f32_to_s16_clamps() {
+        let src = [0.0f32, 0.5, -0.5, 1.0, -1.0, 1.5, -1.5, f32::NAN];
+        let mut dst = [0i16; 8];
+        f32_to_s16(&src, &mut dst);
+        assert_eq!([0, 16384, -16384, i16::MAX, i16::MIN, i16::MAX, i16::MIN, 0], dst);
+    }
+
+    #[test]
+    fn test_f32_to_s16_converter() {
+        // A quarter of a step vanishes without dither, and survives on average with it.
+        let src = vec![0.25 / S16_SCALE; 10_000];
+        let mut dst = vec![0i16; src.len()];
+        F32ToS16Converter::new(Dither::None, Clipping::Hard).convert(&src, &mut dst);
+        assert!(dst.iter().all(|&sample| sample == 0));
+        F32ToS16Converter::new(Dither::Tpdf, Clipping::Hard).convert(&src, &mut dst);
+        assert!(dst.iter().all(|&sample| (-1..=2).contains(&sample)));
+        let mean = dst.iter().map(|&sample| sample as f32).sum::<f32>() / dst.len() as f32;
+        assert!((mean - 0.25).abs() < 0.05, "{}", mean);
+
+        let src = [0.5f32, -0.5, 0.9, 1.5, -1.5, f32::NAN];
+        let mut dst = [0i16; 6];
+        F32ToS16Converter::new(Dither::None, Clipping::Soft).convert(&src, &mut dst);
+        assert_eq!([16384, -16384], dst[..2]);
+        assert!(dst[2] < 29491 && dst[2] > 26214);
+        assert!(dst[3] > dst[2] && dst[3] < i16::MAX);
+        assert_eq!(-dst[3], dst[4]);
+        assert_eq!(0, dst[5]);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let src = [0.0f32, 0.5, -0.5, -1.0, 0.25];
+        for format in [SampleFormat::S16Le, SampleFormat::S24Le, SampleFormat::F32Le] {
+            let mut bytes = vec![0u8; src.len() * format.bytes_per_sample()];
+            f32_to_bytes(&src, format, &mut bytes);
+            let mut dst = [1f32; 5];
+            bytes_to_f32(&bytes, format, &mut dst);
+            assert_eq!(src, dst, "{:?}", format);
+        }
+
+        let mut bytes = [0u8; 9];
+        f32_to_bytes(&[1.5, -1.5, 0.5], SampleFormat::S24Le, &mut bytes);
+        assert_eq!([0xff, 0xff, 0x7f, 0x00, 0x00, 0x80, 0x00, 0x00, 0x40], bytes);
+        let mut bytes = [0u8; 2];
+        f32_to_bytes(&[-0.5], SampleFormat::S16Le, &mut bytes);
+        assert_eq!([0x00, 0xc0], bytes);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Longer than a chunk, and not a multiple of it, to cover the remainder too.
+        let src = (0..1000).map(|i| (i * 65 - 32768) as i16).collect::<Vec<i16>>();
+        let mut float = vec![0f32; src.len()];
+        let mut dst = vec![0i16; src.len()];
+        s16_to_f32(&src, &mut float);
+        f32_to_s16(&float, &mut dst);
+        assert_eq!(src, dst);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::sync_policy::{AtomicCell, Shared, SyncPolicy};
+#[cfg(feature = "derive_serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// The real time available to process a frame.
+const FRAME_DURATION: Duration = Duration::from_millis(10);
+
+/// The weight of a new frame in the moving average, for a time constant of about a second.
+const SMOOTHING: f32 = 0.01;
+
+/// The processing cost of each direction as a fraction of real time, e.g. 0.29 when a 10 ms
+/// frame takes 2.9 ms to process. See [`Processor::cpu_load()`](crate::Processor::cpu_load).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct CpuLoad {
+    /// The load of the capture stream, 0 until a capture frame was processed.
+    pub capture: f32,
+
+    /// The load of the render stream, 0 until a render frame was processed.
+    pub render: f32,
+}
+
+impl CpuLoad {
+    /// Returns the load of both directions together, i.e. of a thread processing both.
+    pub fn total(&self) -> f32 {
+        self.capture + self.render
+    }
+}
+
+/// A moving average of the processing time per frame, updated without locking from the
+/// processing thread.
+pub(crate) struct CpuLoadMeter<S: SyncPolicy = Shared> {
+    // The bits of the `f32` average, 0 until the first frame.
+    load: S::AtomicU32,
+}
+
+impl<S: SyncPolicy> Default for CpuLoadMeter<S> {
+    fn default() -> Self {
+        Self { load: AtomicCell::new(0) }
+    }
+}
+
+impl<S: SyncPolicy> CpuLoadMeter<S> {
+    /// Starts timing a frame, until the returned guard is dropped.
+    pub(crate) fn start(&self) -> CpuLoadTimer<'_, S> {
+        CpuLoadTimer { meter: self, start: Instant::now() }
+    }
+
+    pub(crate) fn load(&self) -> f32 {
+        f32::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// Adds a frame that took `elapsed` to the average. Frames processed concurrently by
+    /// clones may lose an update, which doesn't matter for an estimate.
+    fn record(&self, elapsed: Duration) {
+        let frame_load = elapsed.as_secs_f32() / FRAME_DURATION.as_secs_f32();
+        let load = self.load();
+        let load = if load == 0.0 { frame_load } else { load + SMOOTHING * (frame_load - load) };
+        self.load.store(load.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Records the time since its creation into a [`CpuLoadMeter`] when dropped.
+pub(crate) struct CpuLoadTimer<'a, S: SyncPolicy> {
+    meter: &'a CpuLoadMeter<S>,
+    start: Instant,
+}
+
+impl<S: SyncPolicy> Drop for CpuLoadTimer<'_, S> {
+    fn drop(&mut self) {
+        self.meter.record(self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_load_meter() {
+        let meter: CpuLoadMeter = CpuLoadMeter::default();
+        assert_eq!(0.0, meter.load());
+        meter.record(Duration::from_micros(2900));
+        assert!((meter.load() - 0.29).abs() < 1e-6);
+        for _ in 0..1000 {
+            meter.record(Duration::from_millis(5));
+        }
+        assert!((meter.load() - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{Error, Processor, Stats};
+
+/// Drives both directions of a `Processor` in the order the echo cancellation expects: each
+/// 10 ms capture frame is processed right after the render frame played out at the same time.
+/// With two independent clones, one per direction, it's easy to get this wrong, e.g. by
+/// processing the render stream in bursts, and the echo cancellation degrades without any
+/// error.
+///
+/// `process()` takes the render and capture frames of one tick, e.g. from a duplex audio
+/// callback, and processes them in the right order. When the two directions come from
+/// separate callbacks, `process_render_frame()` and `process_capture_frame()` can be called as
+/// the frames come instead, and the ticks that break the alternation are counted in
+/// `num_missing_render_frames()` and `num_excess_render_frames()`, to warn about a cadence
+/// problem.
+pub struct DuplexProcessor {
+    processor: Processor,
+    // The render frames processed since the last capture frame, or `None` before the first
+    // render frame, as the playback usually starts after the capture.
+    num_pending_render_frames: Option<usize>,
+    num_missing_render_frames: usize,
+    num_excess_render_frames: usize,
+}
+
+impl DuplexProcessor {
+    /// Creates a new `DuplexProcessor` driving `processor`.
+    pub fn new(processor: Processor) -> Self {
+        Self {
+            processor,
+            num_pending_render_frames: None,
+            num_missing_render_frames: 0,
+            num_excess_render_frames: 0,
+        }
+    }
+
+    /// Processes the interleaved render and capture frames of one tick, in that order. Like
+    /// `FrameChunker::process()`, the capture frame is still processed if the render frame
+    /// fails, and only the first error is returned.
+    pub fn process(
+        &mut self,
+        render_frame: &mut [f32],
+        capture_frame: &mut [f32],
+    ) -> Result<(), Error> {
+        let render_result = self.process_render_frame(render_frame);
+        let capture_result = self.process_capture_frame(capture_frame);
+        render_result.and(capture_result)
+    }
+
+    /// Processes an interleaved render frame, on its own. See
+    /// `Processor::process_render_frame()`.
+    pub fn process_render_frame(&mut self, frame: &mut [f32]) -> Result<(), Error> {
+        let num_pending_render_frames = self.num_pending_render_frames.get_or_insert(0);
+        *num_pending_render_frames += 1;
+        if *num_pending_render_frames > 1 {
+            self.num_excess_render_frames += 1;
+        }
+        self.processor.process_render_frame(frame)
+    }
+
+    /// Processes an interleaved capture frame, on its own. See
+    /// `Processor::process_capture_frame()`.
+    pub fn process_capture_frame(&mut self, frame: &mut [f32]) -> Result<(), Error> {
+        if let Some(num_pending_render_frames) = &mut self.num_pending_render_frames {
+            if *num_pending_render_frames == 0 {
+                self.num_missing_render_frames += 1;
+            }
+            *num_pending_render_frames = 0;
+        }
+        self.processor.process_capture_frame(frame)
+    }
+
+    /// Returns how many capture frames have been processed without a render frame since the
+    /// previous one, once the render stream started.
+    pub fn num_missing_render_frames(&self) -> usize {
+        self.num_missing_render_frames
+    }
+
+    /// Returns how many render frames have been processed beyond the first one between two
+    /// capture frames.
+    pub fn num_excess_render_frames(&self) -> usize {
+        self.num_excess_render_frames
+    }
+
+    /// Returns statistics from the last capture frame. See `Processor::get_stats()`.
+    pub fn get_stats(&self) -> Stats {
+        self.processor.get_stats()
+    }
+
+    /// Returns the driven processor, e.g. to change its config.
+    pub fn processor_mut(&mut self) -> &mut Processor {
+        &mut self.processor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InitializationConfig, NUM_SAMPLES_PER_FRAME};
+
+    #[test]
+    fn test_duplex_processor() {
+        let processor = Processor::new(&InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let mut duplex = DuplexProcessor::new(processor);
+        let mut render_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
+        let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
+
+        // The capture may start first.
+        duplex.process_capture_frame(&mut capture_frame).unwrap();
+        for _ in 0..10 {
+            duplex.process(&mut render_frame, &mut capture_frame).unwrap();
+        }
+        assert_eq!(0, duplex.num_missing_render_frames());
+        assert_eq!(0, duplex.num_excess_render_frames());
+
+        // A render burst.
+        duplex.process_capture_frame(&mut capture_frame).unwrap();
+        duplex.process_render_frame(&mut render_frame).unwrap();
+        duplex.process_render_frame(&mut render_frame).unwrap();
+        duplex.process_capture_frame(&mut capture_frame).unwrap();
+        assert_eq!(1, duplex.num_missing_render_frames());
+        assert_eq!(1, duplex.num_excess_render_frames());
+        assert_eq!(13, duplex.processor_mut().inspect().num_processed_capture_frames);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::NUM_SAMPLES_PER_FRAME;
+
+/// Mixes the render channels into the far-end reference of the echo cancellation.
+pub(crate) struct EchoReference {
+    weights: Vec<f32>,
+    // The reference passed to the native module in place of the render frame.
+    frame: Vec<Vec<f32>>,
+}
+
+impl EchoReference {
+    pub(crate) fn new(weights: Vec<f32>) -> Self {
+        let frame = vec![vec![0.0; NUM_SAMPLES_PER_FRAME as usize]; weights.len()];
+        Self { weights, frame }
+    }
+
+    pub(crate) fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Returns the reference for the render `frame`: its channels mixed with the weights into
+    /// the first channel, the other channels silent. The native echo cancellation models one
+    /// echo path per render channel, so silent channels leave only the mixed path to model.
+    pub(crate) fn mix(&mut self, frame: &[Vec<f32>]) -> &mut [Vec<f32>] {
+        let (mixed, silent) = self.frame.split_first_mut().unwrap();
+        mixed.iter_mut().for_each(|sample| *sample = 0.0);
+        for (channel, &weight) in frame.iter().zip(&self.weights) {
+            if weight != 0.0 {
+                for (mixed, sample) in mixed.iter_mut().zip(channel) {
+                    *mixed += weight * sample;
+                }
+            }
+        }
+        for channel in silent {
+            channel.iter_mut().for_each(|sample| *sample = 0.0);
+        }
+        &mut self.frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let len = NUM_SAMPLES_PER_FRAME as usize;
+        // 5.1, with only the front left and right reaching the microphone.
+        let frame: Vec<_> = (0..6).map(|channel| vec![channel as f32 + 1.0; len]).collect();
+        let mut reference = EchoReference::new(vec![0.5, 0.5, 0.0, 0.0, 0.0, 0.0]);
+        let mixed = reference.mix(&frame);
+        assert_eq!(vec![1.5; len], mixed[0]);
+        assert!(mixed[1..].iter().all(|channel| channel.iter().all(|&sample| sample == 0.0)));
+    }
+}
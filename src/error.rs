@@ -0,0 +1,180 @@
+use std::{error, fmt};
+
+/// Which stream of the processor a frame was passed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    /// The near-end stream, passed to `process_capture_frame()`.
+    Capture,
+    /// The far-end stream, passed to `process_render_frame()`.
+    Render,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StreamKind::Capture => "capture",
+            StreamKind::Render => "render",
+        })
+    }
+}
+
+/// An error inside webrtc::AudioProcessing, mapped from its error codes.
+/// See the documentation of [`webrtc::AudioProcessing::Error`](https://cgit.freedesktop.org/pulseaudio/webrtc-audio-processing/tree/webrtc/modules/audio_processing/include/audio_processing.h?id=9def8cf10d3c97640d32f1328535e881288f700f)
+/// for further details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingError {
+    /// The native module couldn't be created.
+    CreationFailed,
+    /// A component isn't supported by this build of the native library.
+    UnsupportedComponent,
+    /// A function isn't supported by this build of the native library.
+    UnsupportedFunction,
+    /// A null pointer was passed to the native module.
+    NullPointer,
+    /// A parameter is out of range.
+    BadParameter,
+    /// The sample rate isn't supported.
+    BadSampleRate,
+    /// A frame isn't of the expected length.
+    BadDataLength,
+    /// The number of channels isn't supported, or doesn't match the initialization.
+    BadNumChannels,
+    /// A stream parameter required by an enabled component, e.g. the stream delay for echo
+    /// cancellation, hasn't been set.
+    StreamParameterNotSet,
+    /// The component isn't enabled.
+    NotEnabled,
+    /// A stream parameter was out of range and has been clamped; processing went on.
+    BadStreamParameterWarning,
+    /// A frame passed to the processor holds a different number of channels than the stream was
+    /// initialized with in `InitializationConfig`. Reported before anything reaches the native
+    /// module.
+    ChannelMismatch {
+        /// The stream the frame was passed to.
+        stream: StreamKind,
+        /// The number of channels the stream was initialized with.
+        expected: usize,
+        /// The number of channels of the frame.
+        got: usize,
+    },
+    /// A frame passed to the processor doesn't hold 10 ms of the channels it was initialized
+    /// with, or for interleaved buffers, a multiple of it. Reported before anything reaches the
+    /// native module.
+    FrameSizeMismatch {
+        /// The expected number of samples, over all channels.
+        expected: usize,
+        /// The number of samples passed.
+        got: usize,
+    },
+    /// The processor has been closed with `Processor::close()`, and its native module released.
+    Closed,
+    /// Any other error, with its code.
+    Unspecified(i32),
+}
+
+/// The error type of the processor, kept under its historical name.
+pub type Error = ProcessingError;
+
+impl ProcessingError {
+    /// Maps a webrtc::AudioProcessing::Error code.
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            -2 => ProcessingError::CreationFailed,
+            -3 => ProcessingError::UnsupportedComponent,
+            -4 => ProcessingError::UnsupportedFunction,
+            -5 => ProcessingError::NullPointer,
+            -6 => ProcessingError::BadParameter,
+            -7 => ProcessingError::BadSampleRate,
+            -8 => ProcessingError::BadDataLength,
+            -9 => ProcessingError::BadNumChannels,
+            -11 => ProcessingError::StreamParameterNotSet,
+            -12 => ProcessingError::NotEnabled,
+            -13 => ProcessingError::BadStreamParameterWarning,
+            code => ProcessingError::Unspecified(code),
+        }
+    }
+
+    /// Returns the webrtc::AudioProcessing::Error code.
+    pub fn code(&self) -> i32 {
+        match self {
+            ProcessingError::CreationFailed => -2,
+            ProcessingError::UnsupportedComponent => -3,
+            ProcessingError::UnsupportedFunction => -4,
+            ProcessingError::NullPointer => -5,
+            ProcessingError::BadParameter => -6,
+            ProcessingError::BadSampleRate => -7,
+            ProcessingError::BadDataLength | ProcessingError::FrameSizeMismatch { .. } => -8,
+            ProcessingError::BadNumChannels | ProcessingError::ChannelMismatch { .. } => -9,
+            ProcessingError::StreamParameterNotSet => -11,
+            ProcessingError::NotEnabled => -12,
+            ProcessingError::BadStreamParameterWarning => -13,
+            // webrtc::AudioProcessing::kUnspecifiedError, as the native module has no such state.
+            ProcessingError::Closed => -1,
+            ProcessingError::Unspecified(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            ProcessingError::ChannelMismatch { stream, expected, got } => {
+                return write!(
+                    f,
+                    "{} frame has {} channels, but the {} stream was initialized with {}",
+                    stream, got, stream, expected
+                );
+            },
+            ProcessingError::FrameSizeMismatch { expected, got } => {
+                return write!(
+                    f,
+                    "frame size mismatch: expected {} samples, got {}",
+                    expected, got
+                );
+            },
+            ProcessingError::CreationFailed => "creation failed",
+            ProcessingError::UnsupportedComponent => "unsupported component",
+            ProcessingError::UnsupportedFunction => "unsupported function",
+            ProcessingError::NullPointer => "null pointer",
+            ProcessingError::BadParameter => "bad parameter",
+            ProcessingError::BadSampleRate => "bad sample rate",
+            ProcessingError::BadDataLength => "bad data length",
+            ProcessingError::BadNumChannels => "bad number of channels",
+            ProcessingError::StreamParameterNotSet => "stream parameter not set",
+            ProcessingError::NotEnabled => "component not enabled",
+            ProcessingError::BadStreamParameterWarning => "bad stream parameter",
+            ProcessingError::Closed => "processor closed",
+            ProcessingError::Unspecified(_) => "unspecified error",
+        };
+        write!(f, "audio processing error: {} (code {})", description, self.code())
+    }
+}
+
+impl error::Error for ProcessingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        for code in -14..0 {
+            assert_eq!(code, ProcessingError::from_code(code).code());
+        }
+        assert_eq!(ProcessingError::BadSampleRate, ProcessingError::from_code(-7));
+        assert_eq!(ProcessingError::Unspecified(-1), ProcessingError::from_code(-1));
+        assert_eq!(
+            "audio processing error: bad number of channels (code -9)",
+            ProcessingError::BadNumChannels.to_string()
+        );
+        assert_eq!(
+            "frame size mismatch: expected 960 samples, got 480",
+            ProcessingError::FrameSizeMismatch { expected: 960, got: 480 }.to_string()
+        );
+        assert_eq!(
+            "render frame has 1 channels, but the render stream was initialized with 2",
+            ProcessingError::ChannelMismatch { stream: StreamKind::Render, expected: 2, got: 1 }
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Regression tests against golden metrics: runs recorded samples through the pipeline and
+//! checks that objective metrics of the output stay within tolerances of stored values, to
+//! catch behavior changes, e.g. when the bundled native library is upgraded.
+//!
+//! ```no_run
+//! use webrtc_audio_processing::{golden::*, Config};
+//!
+//! let harness = GoldenHarness::new("tests/golden");
+//! harness
+//!     .check_all(&[GoldenCase {
+//!         name: "double_talk".to_string(),
+//!         capture: "tests/samples/double_talk-capture.wav".into(),
+//!         render: Some("tests/samples/double_talk-render.wav".into()),
+//!         config: Config::default(),
+//!     }])
+//!     .unwrap();
+//! ```
+//!
+//! Golden values are stored as one JSON file per case in the golden directory. Run with the
+//! `WAP_UPDATE_GOLDEN` environment variable set to record them, or to accept a change.
+
+use crate::{
+    offline::{self, OfflineError},
+    Config,
+};
+use serde::{Deserialize, Serialize};
+use std::{env, error, fmt, fs, io, path::PathBuf};
+
+/// The environment variable that makes [`GoldenHarness::new()`] record golden values instead
+/// of checking them.
+pub const UPDATE_GOLDEN_ENV_VAR: &str = "WAP_UPDATE_GOLDEN";
+
+/// An error while running golden tests.
+#[derive(Debug)]
+pub enum GoldenError {
+    /// A sample couldn't be processed.
+    Offline(OfflineError),
+    /// A golden file couldn't be read or written.
+    Io(PathBuf, io::Error),
+    /// A golden file isn't valid.
+    Json(PathBuf, serde_json::Error),
+    /// Metrics drifted beyond the tolerances.
+    Mismatch(Vec<Mismatch>),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Offline(err) => write!(f, "{}", err),
+            GoldenError::Io(path, err) => {
+                write!(f, "failed to access {}: {}", path.display(), err)?;
+                if err.kind() == io::ErrorKind::NotFound {
+                    write!(f, " (set {} to record it)", UPDATE_GOLDEN_ENV_VAR)?;
+                }
+                Ok(())
+            },
+            GoldenError::Json(path, err) => write!(f, "invalid {}: {}", path.display(), err),
+            GoldenError::Mismatch(mismatches) => {
+                write!(f, "{} metrics drifted from golden values:", mismatches.len())?;
+                for mismatch in mismatches {
+                    write!(f, "\n  {}", mismatch)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl error::Error for GoldenError {}
+
+impl From<OfflineError> for GoldenError {
+    fn from(err: OfflineError) -> Self {
+        GoldenError::Offline(err)
+    }
+}
+
+/// A metric that drifted beyond its tolerance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// The name of the case.
+    pub case: String,
+    /// The name of the metric.
+    pub metric: &'static str,
+    /// The golden value.
+    pub expected: f64,
+    /// The measured value.
+    pub actual: f64,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} is {:.3}, expected {:.3}",
+            self.case, self.metric, self.actual, self.expected
+        )
+    }
+}
+
+/// A sample to run through the pipeline.
+#[derive(Debug, Clone)]
+pub struct GoldenCase {
+    /// Names the golden file, so must be unique within the golden directory.
+    pub name: String,
+    /// The capture (near-end) WAV file, at 48 kHz.
+    pub capture: PathBuf,
+    /// The render (far-end) WAV file, at 48 kHz, if any.
+    pub render: Option<PathBuf>,
+    /// The config to process with.
+    pub config: Config,
+}
+
+/// The metrics compared against golden values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenMetrics {
+    /// The number of 10 ms frames processed. Must match exactly.
+    pub num_frames: usize,
+    /// The mean power of the processed capture, in dBFS.
+    pub output_power_db: f32,
+    /// How much weaker the processed capture is than the unprocessed one, in dB.
+    pub attenuation_db: f32,
+    /// The share of frames in which voice was detected, if voice detection is enabled.
+    pub voice_ratio: Option<f32>,
+}
+
+/// How far metrics may drift from the golden values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerances {
+    /// For the metrics in dB.
+    pub db: f32,
+    /// For the ratios.
+    pub ratio: f32,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Self { db: 0.5, ratio: 0.02 }
+    }
+}
+
+/// Runs [`GoldenCase`]s and compares their metrics against the golden files in a directory.
+#[derive(Debug, Clone)]
+pub struct GoldenHarness {
+    golden_dir: PathBuf,
+    tolerances: Tolerances,
+    update: bool,
+}
+
+impl GoldenHarness {
+    /// Creates a harness with golden files in `golden_dir`, which records them instead of
+    /// checking them if [`UPDATE_GOLDEN_ENV_VAR`] is set.
+    pub fn new(golden_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            golden_dir: golden_dir.into(),
+            tolerances: Tolerances::default(),
+            update: env::var_os(UPDATE_GOLDEN_ENV_VAR).is_some(),
+        }
+    }
+
+    /// Sets the tolerances, [`Tolerances::default()`] otherwise.
+    pub fn tolerances(mut self, tolerances: Tolerances) -> Self {
+        self.tolerances = tolerances;
+        self
+    }
+
+    /// Sets whether to record golden values instead of checking them, overriding
+    /// [`UPDATE_GOLDEN_ENV_VAR`].
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Runs `case` and checks its metrics, or records them. Returns the measured metrics.
+    pub fn check(&self, case: &GoldenCase) -> Result<GoldenMetrics, GoldenError> {
+        let metrics = measure(case)?;
+        let path = self.golden_dir.join(format!("{}.json", case.name));
+        if self.update {
+            fs::create_dir_all(&self.golden_dir)
+                .map_err(|err| GoldenError::Io(self.golden_dir.clone(), err))?;
+            let json = serde_json::to_string_pretty(&metrics)
+                .map_err(|err| GoldenError::Json(path.clone(), err))?;
+            fs::write(&path, json).map_err(|err| GoldenError::Io(path, err))?;
+            return Ok(metrics);
+        }
+
+        let json = fs::read_to_string(&path).map_err(|err| GoldenError::Io(path.clone(), err))?;
+        let golden: GoldenMetrics =
+            serde_json::from_str(&json).map_err(|err| GoldenError::Json(path, err))?;
+        let mismatches = self.compare(&case.name, &golden, &metrics);
+        if mismatches.is_empty() {
+            Ok(metrics)
+        } else {
+            Err(GoldenError::Mismatch(mismatches))
+        }
+    }
+
+    /// Runs all `cases`, reporting the mismatches of all of them rather than stopping at the
+    /// first one.
+    pub fn check_all(&self, cases: &[GoldenCase]) -> Result<(), GoldenError> {
+        let mut mismatches = Vec::new();
+        for case in cases {
+            match self.check(case) {
+                Ok(_) => {},
+                Err(GoldenError::Mismatch(case_mismatches)) => mismatches.extend(case_mismatches),
+                Err(err) => return Err(err),
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(GoldenError::Mismatch(mismatches))
+        }
+    }
+
+    fn compare(&self, case: &str, golden: &GoldenMetrics, actual: &GoldenMetrics) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut compare = |metric, expected: f64, actual: f64, tolerance: f64| {
+            if (expected - actual).abs() > tolerance {
+                mismatches.push(Mismatch { case: case.to_string(), metric, expected, actual });
+            }
+        };
+        let db = self.tolerances.db as f64;
+        compare("num_frames", golden.num_frames as f64, actual.num_frames as f64, 0.0);
+        compare(
+            "output_power_db",
+            golden.output_power_db as f64,
+            actual.output_power_db as f64,
+            db,
+        );
+        compare("attenuation_db", golden.attenuation_db as f64, actual.attenuation_db as f64, db);
+        // A missing ratio on either side means voice detection was toggled.
+        let ratio = |value: Option<f32>| value.map_or(-1.0, |value| value as f64);
+        compare(
+            "voice_ratio",
+            ratio(golden.voice_ratio),
+            ratio(actual.voice_ratio),
+            self.tolerances.ratio as f64,
+        );
+        mismatches
+    }
+}
+
+/// Runs `case` through a processor and measures the output.
+fn measure(case: &GoldenCase) -> Result<GoldenMetrics, GoldenError> {
+    let metrics = offline::measure_wav(&case.capture, case.render.as_deref(), &case.config)?;
+    Ok(GoldenMetrics {
+        num_frames: metrics.num_frames,
+        output_power_db: metrics.output_power_db,
+        attenuation_db: metrics.attenuation_db,
+        voice_ratio: metrics.voice_ratio,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        offline::WavFrameWriter,
+        signal::{Signal, SpeechShapedNoise},
+        NoiseSuppression, NoiseSuppressionLevel,
+    };
+
+    #[test]
+    fn test_golden_harness() {
+        let dir = env::temp_dir().join("webrtc-audio-processing-golden");
+        let capture = dir.join("capture.wav");
+        fs::create_dir_all(&dir).unwrap();
+        let mut writer = WavFrameWriter::create(&capture, 1).unwrap();
+        writer.write_frame(&SpeechShapedNoise::new(0.3, 0).frames(1, 100)).unwrap();
+        writer.finalize().unwrap();
+
+        let harness = GoldenHarness::new(&dir);
+        let case = GoldenCase {
+            name: "noise_suppression".to_string(),
+            capture,
+            render: None,
+            config: Config {
+                noise_suppression: Some(NoiseSuppression {
+                    suppression_level: NoiseSuppressionLevel::Low,
+                }),
+                ..Config::default()
+            },
+        };
+        let recorded = harness.clone().update(true).check(&case).unwrap();
+        assert_eq!(100, recorded.num_frames);
+        assert_eq!(None, recorded.voice_ratio);
+        assert_eq!(recorded, harness.clone().update(false).check(&case).unwrap());
+
+        let mut golden = recorded;
+        golden.output_power_db += 1.0;
+        fs::write(dir.join("noise_suppression.json"), serde_json::to_string(&golden).unwrap())
+            .unwrap();
+        match harness.update(false).check_all(&[case]) {
+            Err(GoldenError::Mismatch(mismatches)) => {
+                assert_eq!(1, mismatches.len());
+                assert_eq!("output_power_db", mismatches[0].metric);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
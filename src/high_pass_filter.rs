@@ -0,0 +1,143 @@
+use crate::{HighPassFilter, SAMPLE_RATE};
+use std::f64::consts::PI;
+
+/// The highest order supported, i.e. a cascade of 4 second order sections.
+const MAX_ORDER: u32 = 8;
+
+/// The coefficients of a second order section, normalized so that a0 is 1. First order sections
+/// have `b2` and `a2` at 0.
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    fn first_order(cutoff_hz: f64) -> Self {
+        let k = (PI * cutoff_hz / SAMPLE_RATE as f64).tan();
+        let b0 = 1.0 / (1.0 + k);
+        Self { b0, b1: -b0, b2: 0.0, a1: (k - 1.0) / (k + 1.0), a2: 0.0 }
+    }
+
+    fn second_order(cutoff_hz: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / SAMPLE_RATE as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+}
+
+/// Returns the sections of a Butterworth high-pass filter, one of first order for odd orders
+/// and the rest of second order.
+fn butterworth(config: &HighPassFilter) -> Vec<Coefficients> {
+    let order = config.order.clamp(1, MAX_ORDER);
+    // Stay clear of Nyquist, where the bilinear transform breaks down.
+    let cutoff_hz = (config.cutoff_hz as f64).clamp(1.0, SAMPLE_RATE as f64 * 0.45);
+    let mut sections: Vec<_> = (0..order / 2)
+        .map(|k| {
+            // The angle of the pole pair from the negative real axis.
+            let angle = match order % 2 {
+                0 => PI * (2 * k + 1) as f64 / (2 * order) as f64,
+                _ => PI * (k + 1) as f64 / order as f64,
+            };
+            Coefficients::second_order(cutoff_hz, 1.0 / (2.0 * angle.cos()))
+        })
+        .collect();
+    if order % 2 == 1 {
+        sections.push(Coefficients::first_order(cutoff_hz));
+    }
+    sections
+}
+
+/// The state of the supplementary high-pass filter between capture frames.
+pub(crate) struct HighPassFilterState {
+    sections: Vec<Coefficients>,
+    // The two delay elements of each section, per channel, in transposed direct form II.
+    states: Vec<Vec<[f64; 2]>>,
+}
+
+impl HighPassFilterState {
+    pub(crate) fn new(config: &HighPassFilter, num_channels: usize) -> Self {
+        let sections = butterworth(config);
+        let states = vec![vec![[0.0; 2]; sections.len()]; num_channels];
+        Self { sections, states }
+    }
+
+    /// Applies a new config. The filter states are kept as long as the order stays the same, so
+    /// that moving the cutoff doesn't click.
+    pub(crate) fn set_config(&mut self, config: &HighPassFilter) {
+        self.sections = butterworth(config);
+        for states in &mut self.states {
+            states.resize(self.sections.len(), [0.0; 2]);
+        }
+    }
+
+    pub(crate) fn process_frame(&mut self, frame: &mut [Vec<f32>]) {
+        for (channel, states) in frame.iter_mut().zip(&mut self.states) {
+            for sample in channel.iter_mut() {
+                let mut x = *sample as f64;
+                for (c, state) in self.sections.iter().zip(states.iter_mut()) {
+                    let y = c.b0 * x + state[0];
+                    state[0] = c.b1 * x - c.a1 * y + state[1];
+                    state[1] = c.b2 * x - c.a2 * y;
+                    x = y;
+                }
+                *sample = x as f32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NUM_SAMPLES_PER_FRAME;
+
+    /// Returns the gain of the filter for a sine of `frequency_hz`, once settled.
+    fn gain(config: &HighPassFilter, frequency_hz: f64) -> f64 {
+        let mut filter = HighPassFilterState::new(config, 1);
+        let mut rms = 0.0;
+        for i in 0..20 {
+            let mut frame = vec![(0..NUM_SAMPLES_PER_FRAME as usize)
+                .map(|j| {
+                    let t = (i * NUM_SAMPLES_PER_FRAME as usize + j) as f64 / SAMPLE_RATE as f64;
+                    (2.0 * PI * frequency_hz * t).sin() as f32
+                })
+                .collect::<Vec<_>>()];
+            filter.process_frame(&mut frame);
+            rms = (frame[0].iter().map(|&s| (s * s) as f64).sum::<f64>() / frame[0].len() as f64)
+                .sqrt();
+        }
+        rms * 2f64.sqrt()
+    }
+
+    #[test]
+    fn test_high_pass_filter() {
+        for order in 1..=MAX_ORDER {
+            let config = HighPassFilter { cutoff_hz: 200.0, order };
+            // -3 dB at the cutoff, whatever the order.
+            assert!((gain(&config, 200.0) - 0.5f64.sqrt()).abs() < 0.02, "order {}", order);
+            assert!((gain(&config, 4_000.0) - 1.0).abs() < 0.01, "order {}", order);
+            // Butterworth filters fall by 6 dB per octave and order.
+            let expected = 2f64.powi(-(order as i32) * 2);
+            let stopband = gain(&config, 50.0);
+            assert!(stopband < expected * 1.5 && stopband > expected * 0.5, "order {}", order);
+        }
+
+        // Out of range orders are clamped.
+        let config = HighPassFilter { cutoff_hz: 200.0, order: 0 };
+        assert_eq!(1, HighPassFilterState::new(&config, 2).sections.len());
+        let config = HighPassFilter { cutoff_hz: 200.0, order: 100 };
+        assert_eq!(4, HighPassFilterState::new(&config, 2).sections.len());
+    }
+}
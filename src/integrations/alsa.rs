@@ -0,0 +1,267 @@
+//! Helpers to drive a [`Processor`] directly from ALSA PCMs, for headless embedded Linux
+//! without a sound server. See `examples/alsa_duplex.rs` for a complete example.
+//!
+//! The PCMs are opened with 16-bit samples, which any hardware supports, and a period of
+//! 10 ms where the device allows it, so that each frame is read and written in a single
+//! blocking call. Otherwise the nearest period size the device offers is used.
+
+use crate::{
+    conversion::{f32_to_s16, s16_to_f32},
+    InitializationConfig, MicVolumeBackend, Processor, NUM_SAMPLES_PER_FRAME,
+};
+use alsa::{
+    mixer::{Mixer, SelemChannelId, SelemId},
+    pcm::{Access, Format, HwParams, IO, PCM},
+    Direction, ValueOr,
+};
+use std::{error, fmt, time::Duration};
+
+pub use super::SAMPLE_RATE;
+
+/// The number of 10 ms frames requested for the ALSA ring buffers. Higher values are more
+/// robust to scheduling hiccups, at the cost of playback latency.
+const PERIODS_PER_BUFFER: i64 = 4;
+
+/// The number of silent periods written ahead on playback, so that it doesn't underrun while
+/// the first capture frame is being read.
+const PLAYBACK_PREFILL_PERIODS: usize = 2;
+
+/// An error while running a duplex stream.
+#[derive(Debug)]
+pub enum DuplexError {
+    /// An ALSA call failed, and couldn't be recovered from.
+    Alsa(alsa::Error),
+    /// The processor failed.
+    Processing(crate::Error),
+}
+
+impl fmt::Display for DuplexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DuplexError::Alsa(err) => write!(f, "ALSA error: {}", err),
+            DuplexError::Processing(err) => write!(f, "failed to process: {}", err),
+        }
+    }
+}
+
+impl error::Error for DuplexError {}
+
+impl From<alsa::Error> for DuplexError {
+    fn from(err: alsa::Error) -> Self {
+        DuplexError::Alsa(err)
+    }
+}
+
+impl From<crate::Error> for DuplexError {
+    fn from(err: crate::Error) -> Self {
+        DuplexError::Processing(err)
+    }
+}
+
+/// A pair of ALSA capture and playback PCMs, configured for 10 ms frames at [`SAMPLE_RATE`].
+pub struct AlsaDuplex {
+    capture: PCM,
+    playback: PCM,
+    num_capture_channels: usize,
+    num_playback_channels: usize,
+    // The size of the playback ring buffer granted by the device, in samples per channel.
+    playback_buffer_size: i64,
+}
+
+impl AlsaDuplex {
+    /// Opens the capture and playback devices, e.g. `"default"` or `"hw:1,0"` (or `"plughw:1,0"`
+    /// when the hardware doesn't support 48 kHz).
+    pub fn open(
+        capture_device: &str,
+        playback_device: &str,
+        num_capture_channels: u16,
+        num_playback_channels: u16,
+    ) -> Result<Self, DuplexError> {
+        let (capture, _) = open_pcm(capture_device, Direction::Capture, num_capture_channels)?;
+        let (playback, playback_buffer_size) =
+            open_pcm(playback_device, Direction::Playback, num_playback_channels)?;
+        Ok(Self {
+            capture,
+            playback,
+            num_capture_channels: num_capture_channels as usize,
+            num_playback_channels: num_playback_channels as usize,
+            playback_buffer_size,
+        })
+    }
+
+    /// Returns the length of the playback ring buffer the device granted, which the played
+    /// audio is delayed by at most, e.g. to estimate the stream delay of the echo cancellation.
+    pub fn playback_buffer_duration(&self) -> Duration {
+        Duration::from_micros(self.playback_buffer_size as u64 * 1_000_000 / SAMPLE_RATE as u64)
+    }
+
+    /// Returns the `InitializationConfig` matching the opened devices.
+    pub fn initialization_config(&self) -> InitializationConfig {
+        InitializationConfig {
+            num_capture_channels: self.num_capture_channels as i32,
+            num_render_channels: self.num_playback_channels as i32,
+            ..InitializationConfig::default()
+        }
+    }
+
+    /// Runs the duplex stream on the current thread until `callback` returns false.
+    ///
+    /// Every 10 ms, a captured frame is processed and passed to `callback` together with a
+    /// playback frame to fill, both interleaved. The playback frame is then fed to the processor
+    /// as the render stream and played. Under- and overruns are recovered from silently.
+    pub fn run<F>(&mut self, processor: &mut Processor, mut callback: F) -> Result<(), DuplexError>
+    where
+        F: FnMut(&[f32], &mut [f32]) -> bool,
+    {
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize;
+        let mut capture_samples = vec![0i16; frame_len * self.num_capture_channels];
+        let mut capture_frame = vec![0f32; frame_len * self.num_capture_channels];
+        let mut playback_samples = vec![0i16; frame_len * self.num_playback_channels];
+        let mut playback_frame = vec![0f32; frame_len * self.num_playback_channels];
+
+        let capture_io = self.capture.io_i16()?;
+        let playback_io = self.playback.io_i16()?;
+        self.prefill_playback(&playback_io)?;
+        self.capture.start()?;
+
+        loop {
+            read_frame(&self.capture, &capture_io, &mut capture_samples)?;
+            s16_to_f32(&capture_samples, &mut capture_frame);
+            processor.process_capture_frame(&mut capture_frame)?;
+
+            if !callback(&capture_frame, &mut playback_frame) {
+                return Ok(());
+            }
+
+            f32_to_s16(&playback_frame, &mut playback_samples);
+            processor.process_render_frame(&mut playback_frame)?;
+            if !write_frame(&self.playback, &playback_io, &playback_samples)? {
+                self.prefill_playback(&playback_io)?;
+            }
+        }
+    }
+
+    fn prefill_playback(&self, playback_io: &IO<i16>) -> Result<(), DuplexError> {
+        let silence = vec![0i16; NUM_SAMPLES_PER_FRAME as usize * self.num_playback_channels];
+        // Leaves room for the first frame, if the device granted a smaller buffer.
+        let num_frames = (self.playback_buffer_size / NUM_SAMPLES_PER_FRAME as i64 - 1)
+            .clamp(0, PLAYBACK_PREFILL_PERIODS as i64);
+        for _ in 0..num_frames {
+            playback_io.writei(&silence)?;
+        }
+        Ok(())
+    }
+}
+
+/// The capture volume of an ALSA mixer control, to pass to
+/// [`Processor::set_mic_volume_backend()`] for the adaptive analog gain control.
+pub struct AlsaMicVolume {
+    mixer: Mixer,
+    selem_id: SelemId,
+    min_value: i64,
+    max_value: i64,
+    volume: f32,
+}
+
+impl AlsaMicVolume {
+    /// Opens the mixer control named `control` of `card`, e.g. `"Capture"` of `"hw:0"`. See
+    /// `amixer -c 0 scontrols` for the controls of a card.
+    pub fn open(card: &str, control: &str) -> Result<Self, alsa::Error> {
+        let mixer = Mixer::new(card, false)?;
+        let selem_id = SelemId::new(control, 0);
+        let (min_value, max_value) = mixer
+            .find_selem(&selem_id)
+            .filter(|selem| selem.has_capture_volume())
+            .ok_or_else(|| alsa::Error::unsupported("mixer control without capture volume"))?
+            .get_capture_volume_range();
+        let mut mic_volume = Self { mixer, selem_id, min_value, max_value, volume: 0.0 };
+        mic_volume.volume();
+        Ok(mic_volume)
+    }
+}
+
+impl MicVolumeBackend for AlsaMicVolume {
+    fn volume(&mut self) -> f32 {
+        // Picks up the changes made by other applications since the last call. Errors leave
+        // the last known volume in place.
+        let _ = self.mixer.handle_events();
+        if let Some(selem) = self.mixer.find_selem(&self.selem_id) {
+            if let Ok(value) = selem.get_capture_volume(SelemChannelId::mono()) {
+                let range = (self.max_value - self.min_value).max(1);
+                self.volume = (value - self.min_value) as f32 / range as f32;
+            }
+        }
+        self.volume
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        let range = self.max_value - self.min_value;
+        let value = self.min_value + (volume.clamp(0.0, 1.0) * range as f32).round() as i64;
+        if let Some(selem) = self.mixer.find_selem(&self.selem_id) {
+            for &channel in SelemChannelId::all() {
+                if selem.has_capture_channel(channel) {
+                    let _ = selem.set_capture_volume(channel, value);
+                }
+            }
+        }
+    }
+}
+
+/// Opens `device`, and returns it with the size of its ring buffer in samples per channel.
+fn open_pcm(
+    device: &str,
+    direction: Direction,
+    num_channels: u16,
+) -> Result<(PCM, i64), alsa::Error> {
+    let pcm = PCM::new(device, direction, false)?;
+    let buffer_size = {
+        let params = HwParams::any(&pcm)?;
+        params.set_channels(num_channels as u32)?;
+        params.set_rate(SAMPLE_RATE, ValueOr::Nearest)?;
+        params.set_format(Format::s16())?;
+        params.set_access(Access::RWInterleaved)?;
+        // Not every device can do exactly these, so the nearest sizes it offers are taken.
+        // `read_frame()` and `write_frame()` work with any period size.
+        params.set_period_size_near(NUM_SAMPLES_PER_FRAME as i64, ValueOr::Nearest)?;
+        params.set_buffer_size_near(NUM_SAMPLES_PER_FRAME as i64 * PERIODS_PER_BUFFER)?;
+        pcm.hw_params(&params)?;
+
+        let rate = params.get_rate()?;
+        if rate != SAMPLE_RATE {
+            // The device can't do 48 kHz. Use its plughw: device to have ALSA resample.
+            return Err(alsa::Error::unsupported("48 kHz sample rate"));
+        }
+        params.get_buffer_size()?
+    };
+    Ok((pcm, buffer_size))
+}
+
+/// Reads exactly one frame, recovering from overruns.
+fn read_frame(pcm: &PCM, io: &IO<i16>, samples: &mut [i16]) -> Result<(), alsa::Error> {
+    let num_channels = samples.len() / NUM_SAMPLES_PER_FRAME as usize;
+    let mut position = 0;
+    while position < samples.len() {
+        match io.readi(&mut samples[position..]) {
+            Ok(num_frames) => position += num_frames * num_channels,
+            Err(err) => pcm.try_recover(err, true)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes exactly one frame, recovering from underruns. Returns false if it had to recover.
+fn write_frame(pcm: &PCM, io: &IO<i16>, samples: &[i16]) -> Result<bool, alsa::Error> {
+    let num_channels = samples.len() / NUM_SAMPLES_PER_FRAME as usize;
+    let mut position = 0;
+    let mut recovered = false;
+    while position < samples.len() {
+        match io.writei(&samples[position..]) {
+            Ok(num_frames) => position += num_frames * num_channels,
+            Err(err) => {
+                pcm.try_recover(err, true)?;
+                recovered = true;
+            },
+        }
+    }
+    Ok(!recovered)
+}
@@ -0,0 +1,128 @@
+//! Helpers to run a [`Processor`] inside [cpal](https://docs.rs/cpal) streams.
+//!
+//! cpal hands out buffers of whatever size the device prefers, so the callbacks built here go
+//! through a [`FrameChunker`] to split them into the 10 ms frames the processor needs. See
+//! `examples/cpal_duplex.rs` for a complete example.
+
+use crate::{Error, FrameChunker, InitializationConfig, Processor, NUM_SAMPLES_PER_FRAME};
+use std::{error, fmt};
+
+pub use super::SAMPLE_RATE;
+
+/// Returned when a cpal `StreamConfig` can't be processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedStreamConfig {
+    /// The sample rate of the rejected config.
+    pub sample_rate: u32,
+}
+
+impl fmt::Display for UnsupportedStreamConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported sample rate {} Hz, expected {} Hz", self.sample_rate, SAMPLE_RATE)
+    }
+}
+
+impl error::Error for UnsupportedStreamConfig {}
+
+/// Returns a `StreamConfig` with `num_channels` channels at the supported sample rate.
+pub fn stream_config(num_channels: u16) -> cpal::StreamConfig {
+    cpal::StreamConfig {
+        channels: num_channels,
+        sample_rate: cpal::SampleRate(SAMPLE_RATE),
+        buffer_size: cpal::BufferSize::Default,
+    }
+}
+
+/// Builds the `InitializationConfig` matching the given capture (input) and render (output)
+/// stream configs.
+pub fn initialization_config(
+    capture: &cpal::StreamConfig,
+    render: &cpal::StreamConfig,
+) -> Result<InitializationConfig, UnsupportedStreamConfig> {
+    for config in &[capture, render] {
+        if config.sample_rate.0 != SAMPLE_RATE {
+            return Err(UnsupportedStreamConfig { sample_rate: config.sample_rate.0 });
+        }
+    }
+    Ok(InitializationConfig {
+        num_capture_channels: capture.channels as i32,
+        num_render_channels: render.channels as i32,
+        ..InitializationConfig::default()
+    })
+}
+
+/// Returns a data callback for `build_input_stream()`, which processes the captured samples
+/// and passes them on to `on_processed`. The processed samples lag the input by one frame.
+pub fn capture_callback<F>(
+    mut processor: Processor,
+    num_channels: u16,
+    mut on_processed: F,
+) -> impl FnMut(&[f32], &cpal::InputCallbackInfo) + Send + 'static
+where
+    F: FnMut(Result<&[f32], Error>) + Send + 'static,
+{
+    let mut chunker = FrameChunker::new(num_channels as usize);
+    // Only allocates when the device hands out a larger buffer than ever before.
+    let mut buffer = Vec::with_capacity(NUM_SAMPLES_PER_FRAME as usize * num_channels as usize);
+    move |data, _info| {
+        buffer.clear();
+        buffer.extend_from_slice(data);
+        match chunker.process(&mut buffer, |frame| processor.process_capture_frame(frame)) {
+            Ok(()) => on_processed(Ok(&buffer)),
+            Err(err) => on_processed(Err(err)),
+        }
+    }
+}
+
+/// Returns a data callback for `build_output_stream()`, which lets `fill` write the samples to
+/// play back and then feeds them to the processor as the echo reference. Playback isn't delayed.
+pub fn render_callback<F>(
+    mut processor: Processor,
+    num_channels: u16,
+    mut fill: F,
+) -> impl FnMut(&mut [f32], &cpal::OutputCallbackInfo) + Send + 'static
+where
+    F: FnMut(&mut [f32]) + Send + 'static,
+{
+    let mut chunker = FrameChunker::new(num_channels as usize);
+    move |data, _info| {
+        fill(data);
+        // The render frames are only used as the echo reference, so there is nobody to report
+        // the error to.
+        let _ = chunker.consume(data, |frame| processor.process_render_frame(frame));
+    }
+}
+
+/// Returns a data callback for `build_input_stream()` on a loopback device (e.g. WASAPI
+/// loopback of the output device, or a PulseAudio monitor source), which feeds what is being
+/// played back to the processor as the echo reference. Use it when the playback isn't under
+/// your control, instead of [`render_callback()`].
+pub fn loopback_callback(
+    mut processor: Processor,
+    num_channels: u16,
+) -> impl FnMut(&[f32], &cpal::InputCallbackInfo) + Send + 'static {
+    let mut chunker = FrameChunker::new(num_channels as usize);
+    move |data, _info| {
+        // As in `render_callback()`, there is nobody to report the error to.
+        let _ = chunker.consume(data, |frame| processor.process_render_frame(frame));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initialization_config() {
+        let config = initialization_config(&stream_config(2), &stream_config(1)).unwrap();
+        assert_eq!(2, config.num_capture_channels);
+        assert_eq!(1, config.num_render_channels);
+
+        let mut unsupported = stream_config(1);
+        unsupported.sample_rate = cpal::SampleRate(44_100);
+        assert_eq!(
+            Err(UnsupportedStreamConfig { sample_rate: 44_100 }),
+            initialization_config(&unsupported, &stream_config(1))
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Interop with [dasp](https://docs.rs/dasp) frames and signals.
+//!
+//! dasp represents audio as a sequence of frames (e.g. `[f32; 2]` for stereo), while the
+//! processor works on 10 ms blocks of either interleaved samples or one `Vec<f32>` per channel.
+//! The functions here convert between the two, and [`ProcessedSignal`] pulls a dasp `Signal`
+//! through the processor so that it can be composed with other dasp combinators.
+
+use crate::{Processor, NUM_SAMPLES_PER_FRAME};
+use dasp::{Frame, Signal};
+
+/// Writes `frames` to `samples` as interleaved samples. Stops at whichever ends first.
+pub fn frames_to_interleaved<F>(frames: &[F], samples: &mut [f32])
+where
+    F: Frame<Sample = f32>,
+{
+    for (frame, chunk) in frames.iter().zip(samples.chunks_exact_mut(F::CHANNELS)) {
+        for (sample, value) in chunk.iter_mut().zip(frame.channels()) {
+            *sample = value;
+        }
+    }
+}
+
+/// Reads interleaved `samples` into `frames`. Stops at whichever ends first.
+pub fn interleaved_to_frames<F>(samples: &[f32], frames: &mut [F])
+where
+    F: Frame<Sample = f32>,
+{
+    for (frame, chunk) in frames.iter_mut().zip(samples.chunks_exact(F::CHANNELS)) {
+        *frame = F::from_fn(|channel| chunk[channel]);
+    }
+}
+
+/// Writes `frames` to `channels`, one `Vec<f32>` per channel as taken by e.g.
+/// `Processor::process_capture_frame_noninterleaved()`.
+pub fn frames_to_noninterleaved<F>(frames: &[F], channels: &mut [Vec<f32>])
+where
+    F: Frame<Sample = f32>,
+{
+    for (index, frame) in frames.iter().enumerate() {
+        for (channel, value) in channels.iter_mut().zip(frame.channels()) {
+            channel[index] = value;
+        }
+    }
+}
+
+/// Reads `channels`, one `Vec<f32>` per channel, into `frames`.
+pub fn noninterleaved_to_frames<F>(channels: &[Vec<f32>], frames: &mut [F])
+where
+    F: Frame<Sample = f32>,
+{
+    for (index, frame) in frames.iter_mut().enumerate() {
+        *frame = F::from_fn(|channel| channels[channel][index]);
+    }
+}
+
+/// A `Signal` which runs the frames of another signal through a `Processor`, 10 ms at a time.
+/// The number of channels of the frame type must match the processor's stream.
+pub struct ProcessedSignal<S: Signal> {
+    inner: S,
+    processor: Processor,
+    is_capture: bool,
+    frames: Vec<S::Frame>,
+    samples: Vec<f32>,
+    position: usize,
+}
+
+impl<S> ProcessedSignal<S>
+where
+    S: Signal,
+    S::Frame: Frame<Sample = f32>,
+{
+    /// Treats `inner` as the capture stream and yields the processed frames. Each 10 ms block is
+    /// pulled from `inner` as a whole when its first frame is requested.
+    pub fn capture(inner: S, processor: Processor) -> Self {
+        Self::new(inner, processor, true)
+    }
+
+    /// Treats `inner` as the render stream, feeding it to the processor as the echo reference.
+    /// Yields the frames of `inner` unchanged.
+    pub fn render(inner: S, processor: Processor) -> Self {
+        Self::new(inner, processor, false)
+    }
+
+    fn new(inner: S, processor: Processor, is_capture: bool) -> Self {
+        let num_frames = NUM_SAMPLES_PER_FRAME as usize;
+        Self {
+            inner,
+            processor,
+            is_capture,
+            frames: vec![S::Frame::EQUILIBRIUM; num_frames],
+            samples: vec![0.0; num_frames * S::Frame::CHANNELS],
+            position: num_frames,
+        }
+    }
+
+    fn process_block(&mut self) {
+        for frame in self.frames.iter_mut() {
+            *frame = self.inner.next();
+        }
+        frames_to_interleaved(&self.frames, &mut self.samples);
+        // Frames that fail to process are passed through unchanged. Render errors can't be
+        // reported anyway, as the render stream is only used as the echo reference.
+        if self.is_capture {
+            if self.processor.process_capture_frame(&mut self.samples).is_ok() {
+                interleaved_to_frames(&self.samples, &mut self.frames);
+            }
+        } else {
+            let _ = self.processor.process_render_frame(&mut self.samples);
+        }
+        self.position = 0;
+    }
+}
+
+impl<S> Signal for ProcessedSignal<S>
+where
+    S: Signal,
+    S::Frame: Frame<Sample = f32>,
+{
+    type Frame = S::Frame;
+
+    fn next(&mut self) -> Self::Frame {
+        if self.position == self.frames.len() {
+            self.process_block();
+        }
+        let frame = self.frames[self.position];
+        self.position += 1;
+        frame
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.position == self.frames.len() && self.inner.is_exhausted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InitializationConfig;
+
+    #[test]
+    fn test_conversions() {
+        let frames = [[1.0f32, 2.0], [3.0, 4.0]];
+
+        let mut samples = vec![0f32; 4];
+        frames_to_interleaved(&frames, &mut samples);
+        assert_eq!(vec![1.0, 2.0, 3.0, 4.0], samples);
+        let mut converted = [[0f32; 2]; 2];
+        interleaved_to_frames(&samples, &mut converted);
+        assert_eq!(frames, converted);
+
+        let mut channels = vec![vec![0f32; 2]; 2];
+        frames_to_noninterleaved(&frames, &mut channels);
+        assert_eq!(vec![vec![1.0, 3.0], vec![2.0, 4.0]], channels);
+        let mut converted = [[0f32; 2]; 2];
+        noninterleaved_to_frames(&channels, &mut converted);
+        assert_eq!(frames, converted);
+    }
+
+    #[test]
+    fn test_processed_signal() {
+        let processor = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+
+        let render = dasp::signal::rate(48_000.0).const_hz(440.0).sine().map(|s| s as f32);
+        let expected = render.clone().take(1000).collect::<Vec<f32>>();
+        let render = ProcessedSignal::render(render, processor.clone());
+        assert_eq!(expected, render.take(1000).collect::<Vec<f32>>());
+
+        let capture = dasp::signal::equilibrium::<[f32; 2]>();
+        let mut capture = ProcessedSignal::capture(capture, processor);
+        assert_eq!([0.0, 0.0], capture.next());
+    }
+}
@@ -0,0 +1,17 @@
+//! Helpers to plug a [`Processor`](crate::Processor) into third-party audio libraries. Each
+//! integration lives behind a feature flag of the same name.
+
+#[cfg(feature = "alsa")]
+pub mod alsa;
+#[cfg(feature = "cpal")]
+pub mod cpal;
+#[cfg(feature = "dasp")]
+pub mod dasp;
+#[cfg(feature = "rodio")]
+pub mod rodio;
+#[cfg(feature = "symphonia")]
+pub mod symphonia;
+#[cfg(all(feature = "wasapi", windows))]
+pub mod wasapi;
+
+pub use crate::SAMPLE_RATE;
@@ -0,0 +1,208 @@
+//! Adapters to drop a [`Processor`] into a [rodio](https://docs.rs/rodio) playback graph.
+//!
+//! Wrap whatever is played back in a [`ProcessedSource`] so that it's fed to the processor as
+//! the echo reference, and pass the captured samples through [`ProcessedCapture`] to get them
+//! back with the echo removed. Both expect audio at [`SAMPLE_RATE`]; convert other sources with
+//! rodio's `UniformSourceIterator` first.
+
+use crate::{Processor, NUM_SAMPLES_PER_FRAME};
+use rodio::Source;
+use std::{error, fmt, time::Duration};
+
+pub use super::SAMPLE_RATE;
+
+/// Returned when a rodio `Source` has a sample rate the processor doesn't support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedSampleRate {
+    /// The sample rate of the rejected source.
+    pub sample_rate: u32,
+}
+
+impl fmt::Display for UnsupportedSampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported sample rate {} Hz, expected {} Hz", self.sample_rate, SAMPLE_RATE)
+    }
+}
+
+impl error::Error for UnsupportedSampleRate {}
+
+/// A `Source` that plays back the wrapped source unchanged, while feeding it to the processor
+/// as the render (echo reference) stream. The source must have as many channels as the
+/// processor's render stream.
+pub struct ProcessedSource<S> {
+    inner: S,
+    processor: Processor,
+    frame: Vec<f32>,
+    frame_len: usize,
+}
+
+impl<S> ProcessedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    /// Wraps `inner`, feeding its samples to `processor`.
+    pub fn new(inner: S, processor: Processor) -> Result<Self, UnsupportedSampleRate> {
+        if inner.sample_rate() != SAMPLE_RATE {
+            return Err(UnsupportedSampleRate { sample_rate: inner.sample_rate() });
+        }
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * inner.channels() as usize;
+        Ok(Self { inner, processor, frame: Vec::with_capacity(frame_len), frame_len })
+    }
+}
+
+impl<S> Iterator for ProcessedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        self.frame.push(sample);
+        if self.frame.len() == self.frame_len {
+            // The render frames are only used as the echo reference, so there is nobody to
+            // report the error to.
+            let _ = self.processor.process_render_frame(&mut self.frame);
+            self.frame.clear();
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Source for ProcessedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Runs interleaved captured samples through the processor 10 ms at a time, yielding the
+/// processed samples. Frames that fail to process are passed through unchanged. It's also a
+/// `Source`, so the processed capture can be played back or mixed directly.
+pub struct ProcessedCapture<I> {
+    inner: I,
+    processor: Processor,
+    num_channels: u16,
+    frame: Vec<f32>,
+    position: usize,
+}
+
+impl<I> ProcessedCapture<I>
+where
+    I: Iterator<Item = f32>,
+{
+    /// Wraps `inner`, which yields interleaved samples of `num_channels` channels captured at
+    /// [`SAMPLE_RATE`].
+    pub fn new(inner: I, processor: Processor, num_channels: u16) -> Self {
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * num_channels as usize;
+        Self { inner, processor, num_channels, frame: Vec::with_capacity(frame_len), position: 0 }
+    }
+
+    fn fill_frame(&mut self) {
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * self.num_channels as usize;
+        self.frame.clear();
+        self.frame.extend(self.inner.by_ref().take(frame_len));
+        self.position = 0;
+        let num_samples = self.frame.len();
+        if num_samples > 0 {
+            // A partial frame at the end of the input is padded with silence for processing.
+            self.frame.resize(frame_len, 0.0);
+            let _ = self.processor.process_capture_frame(&mut self.frame);
+            self.frame.truncate(num_samples);
+        }
+    }
+}
+
+impl<I> Iterator for ProcessedCapture<I>
+where
+    I: Iterator<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.frame.len() {
+            self.fill_frame();
+        }
+        let sample = *self.frame.get(self.position)?;
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl<I> Source for ProcessedCapture<I>
+where
+    I: Iterator<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.num_channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InitializationConfig;
+    use rodio::source::SineWave;
+
+    fn create_processor() -> Processor {
+        Processor::new(&InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_processed_source_plays_unchanged() {
+        let processor = create_processor();
+        assert_eq!(
+            UnsupportedSampleRate { sample_rate: SAMPLE_RATE / 2 },
+            ProcessedSource::new(SineWave::new(440).speed(0.5), processor.clone()).err().unwrap()
+        );
+
+        let expected = SineWave::new(440).take(1000).collect::<Vec<f32>>();
+        let processed = ProcessedSource::new(SineWave::new(440), processor)
+            .unwrap()
+            .take(1000)
+            .collect::<Vec<f32>>();
+        assert_eq!(expected, processed);
+    }
+
+    #[test]
+    fn test_processed_capture_keeps_length() {
+        let samples = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * 2 + 100];
+        let capture = ProcessedCapture::new(samples.clone().into_iter(), create_processor(), 1);
+        assert_eq!(samples.len(), capture.count());
+    }
+}
@@ -0,0 +1,351 @@
+//! Batch processing of recorded audio files, e.g. to clean up the recording of a meeting.
+//!
+//! Files are decoded with [symphonia](https://docs.rs/symphonia), so any container and codec it
+//! supports can be read, and resampled to [`SAMPLE_RATE`] with [rubato](https://docs.rs/rubato).
+//! The result is written as a 32-bit float WAV file. Everything is streamed 10 ms at a time, so
+//! the files don't need to fit in memory.
+
+use crate::{Config, InitializationConfig, Processor, NUM_SAMPLES_PER_FRAME};
+use rubato::{FftFixedInOut, Resampler};
+use std::{collections::VecDeque, error, fmt, fs::File, io, path::Path};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+pub use super::SAMPLE_RATE;
+
+/// The number of frames per channel fed to the resampler at once.
+const RESAMPLER_CHUNK_SIZE: usize = 1024;
+
+/// An error while processing audio files.
+#[derive(Debug)]
+pub enum FileProcessingError {
+    /// The input file couldn't be opened.
+    Io(io::Error),
+    /// The input file couldn't be demuxed or decoded.
+    Decode(symphonia::core::errors::Error),
+    /// The input file has no audio track, or lacks its sample rate or channel layout.
+    NoAudioTrack,
+    /// The input file couldn't be resampled to 48 kHz.
+    Resample(Box<dyn error::Error + Send + Sync>),
+    /// The output file couldn't be written.
+    Write(hound::Error),
+    /// The processor failed.
+    Processing(crate::Error),
+}
+
+impl fmt::Display for FileProcessingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileProcessingError::Io(err) => write!(f, "failed to open the input file: {}", err),
+            FileProcessingError::Decode(err) => write!(f, "failed to decode the input: {}", err),
+            FileProcessingError::NoAudioTrack => write!(f, "no usable audio track found"),
+            FileProcessingError::Resample(err) => write!(f, "failed to resample: {}", err),
+            FileProcessingError::Write(err) => write!(f, "failed to write the output: {}", err),
+            FileProcessingError::Processing(err) => write!(f, "failed to process: {}", err),
+        }
+    }
+}
+
+impl error::Error for FileProcessingError {}
+
+impl From<crate::Error> for FileProcessingError {
+    fn from(err: crate::Error) -> Self {
+        FileProcessingError::Processing(err)
+    }
+}
+
+/// Decodes an audio file and hands it out as 10 ms frames of interleaved samples at
+/// [`SAMPLE_RATE`].
+pub struct AudioFileReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    num_channels: usize,
+    resampler: Option<FftFixedInOut<f32>>,
+    /// Decoded samples waiting to be resampled, one `Vec` per channel.
+    resampler_input: Vec<Vec<f32>>,
+    /// The number of leading resampled frames per channel to drop to compensate for the delay of
+    /// the resampler, so that the output stays aligned with the input.
+    resampler_delay: usize,
+    sample_rate: usize,
+    /// The number of frames per channel fed to the resampler so far.
+    num_input_frames: usize,
+    /// The number of resampled frames per channel handed out so far.
+    num_output_frames: usize,
+    /// Interleaved samples at `SAMPLE_RATE` ready to be handed out.
+    samples: VecDeque<f32>,
+    end_of_stream: bool,
+}
+
+impl AudioFileReader {
+    /// Opens the audio file at `path`, using the first audio track in it.
+    pub fn open(path: &Path) -> Result<Self, FileProcessingError> {
+        let file = File::open(path).map_err(FileProcessingError::Io)?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(FileProcessingError::Decode)?;
+
+        let track = probed
+            .format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(FileProcessingError::NoAudioTrack)?;
+        let sample_rate =
+            track.codec_params.sample_rate.ok_or(FileProcessingError::NoAudioTrack)? as usize;
+        let num_channels =
+            track.codec_params.channels.ok_or(FileProcessingError::NoAudioTrack)?.count();
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(FileProcessingError::Decode)?;
+        let track_id = track.id;
+
+        let resampler = if sample_rate == SAMPLE_RATE as usize {
+            None
+        } else {
+            Some(
+                FftFixedInOut::new(
+                    sample_rate,
+                    SAMPLE_RATE as usize,
+                    RESAMPLER_CHUNK_SIZE,
+                    num_channels,
+                )
+                .map_err(|err| FileProcessingError::Resample(Box::new(err)))?,
+            )
+        };
+        let resampler_delay = resampler.as_ref().map_or(0, |resampler| resampler.output_delay());
+
+        Ok(Self {
+            format: probed.format,
+            decoder,
+            track_id,
+            num_channels,
+            resampler,
+            resampler_input: vec![Vec::new(); num_channels],
+            resampler_delay,
+            sample_rate,
+            num_input_frames: 0,
+            num_output_frames: 0,
+            samples: VecDeque::new(),
+            end_of_stream: false,
+        })
+    }
+
+    /// Returns the number of channels of the audio track.
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// Fills `frame` with the next 10 ms of interleaved samples. Returns false once the file
+    /// has been read to the end. A partial frame at the end is padded with silence.
+    pub fn read_frame(&mut self, frame: &mut [f32]) -> Result<bool, FileProcessingError> {
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * self.num_channels;
+        assert_eq!(frame_len, frame.len(), "frame must hold 10 ms of samples");
+
+        while self.samples.len() < frame_len && !self.end_of_stream {
+            self.decode_packet()?;
+        }
+        if self.samples.is_empty() {
+            return Ok(false);
+        }
+
+        let num_samples = self.samples.len().min(frame_len);
+        for (sample, decoded) in frame.iter_mut().zip(self.samples.drain(..num_samples)) {
+            *sample = decoded;
+        }
+        for sample in &mut frame[num_samples..] {
+            *sample = 0.0;
+        }
+        Ok(true)
+    }
+
+    fn decode_packet(&mut self) -> Result<(), FileProcessingError> {
+        use symphonia::core::errors::Error;
+
+        let packet = match self.format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                self.end_of_stream = true;
+                return self.flush_resampler();
+            },
+            Err(err) => return Err(FileProcessingError::Decode(err)),
+        };
+        if packet.track_id() != self.track_id {
+            return Ok(());
+        }
+
+        let decoded = match self.decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupted packet is skipped, like players do.
+            Err(Error::DecodeError(_)) => return Ok(()),
+            Err(err) => return Err(FileProcessingError::Decode(err)),
+        };
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+
+        if self.resampler.is_none() {
+            self.samples.extend(buffer.samples());
+            return Ok(());
+        }
+        for frame in buffer.samples().chunks_exact(self.num_channels) {
+            for (channel, &sample) in self.resampler_input.iter_mut().zip(frame) {
+                channel.push(sample);
+            }
+            self.num_input_frames += 1;
+        }
+        self.resample(false)
+    }
+
+    fn flush_resampler(&mut self) -> Result<(), FileProcessingError> {
+        if self.resampler.is_some() {
+            self.resample(true)?;
+        }
+        Ok(())
+    }
+
+    fn resample(&mut self, flush: bool) -> Result<(), FileProcessingError> {
+        let resampler = match &mut self.resampler {
+            Some(resampler) => resampler,
+            None => return Ok(()),
+        };
+        // How many frames the whole input amounts to at `SAMPLE_RATE`. Only final when flushing.
+        let scaled_input_frames = self.num_input_frames * SAMPLE_RATE as usize;
+        let total_output_frames = (scaled_input_frames + self.sample_rate - 1) / self.sample_rate;
+
+        loop {
+            let num_frames = resampler.input_frames_next();
+            let available = self.resampler_input[0].len();
+            let output = if available >= num_frames {
+                let output = resampler.process(&self.resampler_input, None);
+                self.resampler_input.iter_mut().for_each(|channel| {
+                    channel.drain(..num_frames);
+                });
+                output
+            } else if flush && self.num_output_frames < total_output_frames {
+                // Pushes the rest of the input, and then silence, through the resampler until
+                // its delayed tail has come out.
+                let output = if available > 0 {
+                    resampler.process_partial(Some(&self.resampler_input), None)
+                } else {
+                    resampler.process_partial::<Vec<f32>>(None, None)
+                };
+                self.resampler_input.iter_mut().for_each(Vec::clear);
+                output
+            } else {
+                return Ok(());
+            }
+            .map_err(|err| FileProcessingError::Resample(Box::new(err)))?;
+
+            let start = self.resampler_delay.min(output[0].len());
+            self.resampler_delay -= start;
+            let mut end = output[0].len();
+            if flush {
+                end = end.min(start + total_output_frames - self.num_output_frames);
+            }
+            for index in start..end {
+                self.samples.extend(output.iter().map(|channel| channel[index]));
+            }
+            self.num_output_frames += end - start;
+        }
+    }
+}
+
+/// Runs the capture file at `capture_path` through a processor configured with `config`, and
+/// writes the result to `output_path` as a 32-bit float WAV file at [`SAMPLE_RATE`].
+///
+/// If given, `render_path` is fed as the render (far-end) stream in sync with the capture, for
+/// echo cancellation. It is padded with silence if shorter than the capture, and truncated if
+/// longer. Returns the number of 10 ms frames processed.
+pub fn process_file(
+    capture_path: &Path,
+    render_path: Option<&Path>,
+    output_path: &Path,
+    config: &Config,
+) -> Result<usize, FileProcessingError> {
+    let mut capture = AudioFileReader::open(capture_path)?;
+    let mut render = render_path.map(AudioFileReader::open).transpose()?;
+
+    let mut processor = Processor::new(&InitializationConfig {
+        num_capture_channels: capture.num_channels() as i32,
+        num_render_channels: render.as_ref().map_or(1, |render| render.num_channels()) as i32,
+        ..InitializationConfig::default()
+    })?;
+    processor.set_config(config.clone());
+
+    let mut writer = hound::WavWriter::create(
+        output_path,
+        hound::WavSpec {
+            channels: capture.num_channels() as u16,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        },
+    )
+    .map_err(FileProcessingError::Write)?;
+
+    let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * capture.num_channels()];
+    let mut render_frame = render
+        .as_ref()
+        .map(|render| vec![0f32; NUM_SAMPLES_PER_FRAME as usize * render.num_channels()]);
+    let mut num_frames = 0;
+    while capture.read_frame(&mut capture_frame)? {
+        if let (Some(render), Some(render_frame)) = (&mut render, &mut render_frame) {
+            if !render.read_frame(render_frame)? {
+                render_frame.iter_mut().for_each(|sample| *sample = 0.0);
+            }
+            processor.process_render_frame(render_frame)?;
+        }
+        processor.process_capture_frame(&mut capture_frame)?;
+        for &sample in &capture_frame {
+            writer.write_sample(sample).map_err(FileProcessingError::Write)?;
+        }
+        num_frames += 1;
+    }
+    writer.finalize().map_err(FileProcessingError::Write)?;
+
+    Ok(num_frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_file_resamples() {
+        let dir = std::env::temp_dir();
+        let input_path = dir.join("webrtc-audio-processing-symphonia-input.wav");
+        let output_path = dir.join("webrtc-audio-processing-symphonia-output.wav");
+
+        // One second of a 440 Hz sine at 44.1 kHz.
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&input_path, spec).unwrap();
+        for i in 0..44_100 {
+            let phase = 2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44_100.0;
+            writer.write_sample((phase.sin() * 8_000.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let num_frames = process_file(&input_path, None, &output_path, &Config::default()).unwrap();
+        assert_eq!(100, num_frames);
+
+        let reader = hound::WavReader::open(&output_path).unwrap();
+        assert_eq!(SAMPLE_RATE, reader.spec().sample_rate);
+        assert_eq!(num_frames * NUM_SAMPLES_PER_FRAME as usize, reader.len() as usize);
+    }
+}
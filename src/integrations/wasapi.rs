@@ -0,0 +1,136 @@
+//! Echo cancellation on Windows, with the echo reference captured from the output device by
+//! WASAPI loopback. Unlike [`render_callback()`](super::cpal::render_callback), this works
+//! whatever application plays the far-end audio.
+//!
+//! Both streams go through cpal, whose WASAPI host opens an input stream on an output device
+//! in loopback mode. Loopback streams come in the shared mode mix format of the device, which
+//! has to be 48 kHz; set it in the advanced sound settings of the device otherwise.
+
+use super::cpal::{capture_callback, initialization_config, loopback_callback, stream_config};
+use crate::{Error, Processor};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::{error, fmt};
+
+pub use super::{cpal::UnsupportedStreamConfig, SAMPLE_RATE};
+
+/// An error while setting up a [`WasapiDuplex`].
+#[derive(Debug)]
+pub enum WasapiDuplexError {
+    /// The mix format of a device couldn't be queried.
+    DefaultStreamConfig(cpal::DefaultStreamConfigError),
+    /// A device isn't running at 48 kHz.
+    UnsupportedStreamConfig(UnsupportedStreamConfig),
+    /// A stream couldn't be built.
+    BuildStream(cpal::BuildStreamError),
+    /// A stream couldn't be started.
+    PlayStream(cpal::PlayStreamError),
+    /// The processor couldn't be created.
+    Processing(Error),
+}
+
+impl fmt::Display for WasapiDuplexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WasapiDuplexError::DefaultStreamConfig(err) => {
+                write!(f, "failed to query the device format: {}", err)
+            },
+            WasapiDuplexError::UnsupportedStreamConfig(err) => write!(f, "{}", err),
+            WasapiDuplexError::BuildStream(err) => write!(f, "failed to build a stream: {}", err),
+            WasapiDuplexError::PlayStream(err) => write!(f, "failed to start a stream: {}", err),
+            WasapiDuplexError::Processing(err) => write!(f, "failed to create processor: {}", err),
+        }
+    }
+}
+
+impl error::Error for WasapiDuplexError {}
+
+impl From<cpal::DefaultStreamConfigError> for WasapiDuplexError {
+    fn from(err: cpal::DefaultStreamConfigError) -> Self {
+        WasapiDuplexError::DefaultStreamConfig(err)
+    }
+}
+
+impl From<UnsupportedStreamConfig> for WasapiDuplexError {
+    fn from(err: UnsupportedStreamConfig) -> Self {
+        WasapiDuplexError::UnsupportedStreamConfig(err)
+    }
+}
+
+impl From<cpal::BuildStreamError> for WasapiDuplexError {
+    fn from(err: cpal::BuildStreamError) -> Self {
+        WasapiDuplexError::BuildStream(err)
+    }
+}
+
+impl From<cpal::PlayStreamError> for WasapiDuplexError {
+    fn from(err: cpal::PlayStreamError) -> Self {
+        WasapiDuplexError::PlayStream(err)
+    }
+}
+
+impl From<Error> for WasapiDuplexError {
+    fn from(err: Error) -> Self {
+        WasapiDuplexError::Processing(err)
+    }
+}
+
+/// A microphone stream and a loopback stream of an output device, both running through the
+/// same [`Processor`]. The streams stop when this is dropped.
+///
+/// The two streams are driven by separate WASAPI events, so the loopback audio reaches the
+/// processor with some jitter relative to the microphone. The microphone side lags by one
+/// frame (10 ms) to absorb it; enable `EchoCancellation::enable_delay_agnostic` to have the
+/// processor estimate the remaining delay.
+pub struct WasapiDuplex {
+    processor: Processor,
+    capture: cpal::Stream,
+    loopback: cpal::Stream,
+}
+
+impl WasapiDuplex {
+    /// Opens `input_device` for capture and `output_device` in loopback mode, each with its
+    /// default number of channels. The processed capture is passed to `on_processed`, like with
+    /// [`capture_callback()`], and stream errors of either stream to `on_error`. Call
+    /// [`play()`](Self::play) to start the streams.
+    pub fn new<F, E>(
+        input_device: &cpal::Device,
+        output_device: &cpal::Device,
+        on_processed: F,
+        on_error: E,
+    ) -> Result<Self, WasapiDuplexError>
+    where
+        F: FnMut(Result<&[f32], Error>) + Send + 'static,
+        E: FnMut(cpal::StreamError) + Clone + Send + 'static,
+    {
+        let capture_config = stream_config(input_device.default_input_config()?.channels());
+        // Loopback streams must use the mix format of the output device.
+        let loopback_config = output_device.default_output_config()?.config();
+
+        let processor = Processor::new(&initialization_config(&capture_config, &loopback_config)?)?;
+        let capture = input_device.build_input_stream(
+            &capture_config,
+            capture_callback(processor.clone(), capture_config.channels, on_processed),
+            on_error.clone(),
+        )?;
+        let loopback = output_device.build_input_stream(
+            &loopback_config,
+            loopback_callback(processor.clone(), loopback_config.channels),
+            on_error,
+        )?;
+
+        Ok(Self { processor, capture, loopback })
+    }
+
+    /// Returns the processor, e.g. to call `set_config()` on it.
+    pub fn processor(&mut self) -> &mut Processor {
+        &mut self.processor
+    }
+
+    /// Starts both streams, the loopback one first so that the echo reference is available
+    /// before the first captured frame.
+    pub fn play(&self) -> Result<(), WasapiDuplexError> {
+        self.loopback.play()?;
+        self.capture.play()?;
+        Ok(())
+    }
+}
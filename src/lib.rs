@@ -5,42 +5,219 @@
 #![warn(clippy::all)]
 #![warn(missing_docs)]
 
+mod audit;
+mod calibration;
+mod chunking;
+mod comfort_noise;
 mod config;
+pub mod conversion;
+mod cpu_load;
+mod denormal;
+mod duplex;
+mod echo_reference;
+mod error;
+#[cfg(feature = "golden")]
+pub mod golden;
+mod high_pass_filter;
+pub mod integrations;
+mod local_processor;
+pub mod metrics;
+mod mic_volume;
+#[cfg(feature = "mock")]
+mod mock;
+mod noise_suppression;
+#[cfg(feature = "offline")]
+pub mod offline;
+#[cfg(feature = "plot")]
+pub mod plot;
+// Threads are only available on emscripten when building with `-pthread`, which needs atomics.
+#[cfg(any(not(target_os = "emscripten"), target_feature = "atomics"))]
+mod pool;
+#[cfg(feature = "profiles")]
+pub mod profile;
+mod render_feeder;
+mod shared_render;
+pub mod signal;
+mod stats_sink;
+mod sync_policy;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+#[cfg(any(not(target_os = "emscripten"), target_feature = "atomics"))]
+mod thread_priority;
+mod voice_gate;
 
-use std::{error, fmt, sync::Arc};
+use audit::ConfigAuditLog;
+use comfort_noise::ComfortNoiseGenerator;
+use conversion::SampleFormat;
+use cpu_load::CpuLoadMeter;
+use echo_reference::EchoReference;
+use high_pass_filter::HighPassFilterState;
+#[cfg(feature = "mock")]
+use mock as ffi;
+use signal::Signal;
+use std::{
+    collections::VecDeque,
+    fmt, mem,
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::Duration,
+};
+use sync_policy::{AtomicCell, AtomicCounter, Local, Lock, Shared, SyncPolicy};
+use voice_gate::VoiceGateState;
+#[cfg(not(feature = "mock"))]
 use webrtc_audio_processing_sys as ffi;
 
+pub use audit::*;
+pub use calibration::*;
+pub use chunking::*;
 pub use config::*;
+pub use cpu_load::*;
+pub use denormal::*;
+pub use duplex::*;
+pub use error::*;
 pub use ffi::NUM_SAMPLES_PER_FRAME;
+pub use local_processor::*;
+pub use mic_volume::*;
+pub use noise_suppression::*;
+#[cfg(any(not(target_os = "emscripten"), target_feature = "atomics"))]
+pub use pool::*;
+pub use render_feeder::*;
+pub use shared_render::*;
+pub use stats_sink::*;
 
-/// Represents an error inside webrtc::AudioProcessing.
-/// See the documentation of [`webrtc::AudioProcessing::Error`](https://cgit.freedesktop.org/pulseaudio/webrtc-audio-processing/tree/webrtc/modules/audio_processing/include/audio_processing.h?id=9def8cf10d3c97640d32f1328535e881288f700f)
-/// for further details.
-#[derive(Debug)]
-pub struct Error {
-    /// webrtc::AudioProcessing::Error
-    code: i32,
+#[cfg(feature = "rt-safe")]
+pub use assert_no_alloc::AllocDisabler;
+
+/// The only sample rate the processor supports, in Hz.
+pub const SAMPLE_RATE: u32 = NUM_SAMPLES_PER_FRAME as u32 * 100;
+
+/// The number of silent frames `Processor::warm_up()` runs through each direction.
+const NUM_WARM_UP_FRAMES: usize = 10;
+
+/// The number of frames (samples per channel) handled per block in `Processor::deinterleave()`
+/// and `Processor::interleave()`. 16 channels of 32 frames take 2 KiB, well within L1 cache.
+const INTERLEAVE_BLOCK_SIZE: usize = 32;
+
+/// The number of noise frames run through the processor in `measure_algorithmic_delay()`.
+const NUM_DELAY_MEASUREMENT_FRAMES: usize = 20;
+
+/// The power of the noise used in `measure_algorithmic_delay()`, -20 dBFS.
+const DELAY_MEASUREMENT_NOISE_POWER: f32 = 0.01;
+
+/// The number of frames run through the processor in `run_self_test()`, 3 s.
+const NUM_SELF_TEST_FRAMES: usize = 300;
+
+/// The number of last frames of `run_self_test()` the ERLE is measured over, once the echo
+/// cancellation has converged.
+const NUM_SELF_TEST_MEASURED_FRAMES: usize = 100;
+
+/// The peak amplitude of the far end in `run_self_test()`.
+const SELF_TEST_FAR_END_AMPLITUDE: f32 = 0.3;
+
+/// The gain of the simulated echo path in `run_self_test()`, -6 dB.
+const SELF_TEST_ECHO_GAIN: f32 = 0.5;
+
+/// The delay of the simulated echo path in `run_self_test()`, unless the config sets one.
+const SELF_TEST_ECHO_DELAY_MS: i32 = 20;
+
+/// The highest `GainControl::target_level_dbfs` the native gain control accepts.
+const MAX_AGC1_TARGET_LEVEL_DBFS: u8 = 31;
+
+/// Marks that no stream delay has been set since the last capture frame.
+const NO_PENDING_STREAM_DELAY: i32 = i32::MIN;
+
+/// Marks that a flag, e.g. the output muting, hasn't changed since the last capture frame.
+/// Otherwise the pending value is `flag as u8`.
+const NO_PENDING_FLAG: u8 = u8::MAX;
+
+/// Marks that no analog level has been read from the mic volume backend since the last capture
+/// frame, or that the gain control hasn't recommended one since the last poll.
+const NO_ANALOG_LEVEL: i32 = i32::MIN;
+
+/// Approximate memory used by a `Processor` on the Rust side, in bytes.
+/// See [`Processor::memory_usage()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The buffer holding a de-interleaved capture frame.
+    pub capture_frame_bytes: usize,
+
+    /// The buffer holding a de-interleaved render frame.
+    pub render_frame_bytes: usize,
+
+    /// Scratch space used to pass frames through the ffi.
+    pub scratch_bytes: usize,
+
+    /// The capture and render channel maps, if set.
+    pub channel_map_bytes: usize,
+
+    /// The `Processor` itself and the handle to the native module.
+    pub handle_bytes: usize,
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ffi::AudioProcessing::Error code: {}", self.code)
+impl MemoryUsage {
+    /// Returns the sum of all the entries.
+    pub fn total_bytes(&self) -> usize {
+        self.capture_frame_bytes
+            + self.render_frame_bytes
+            + self.scratch_bytes
+            + self.channel_map_bytes
+            + self.handle_bytes
     }
 }
 
-impl error::Error for Error {}
+/// The result of [`Processor::run_self_test()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelfTestReport {
+    /// The echo return loss enhancement once converged, in dB.
+    pub erle_db: f32,
+
+    /// Whether `erle_db` reached the threshold passed to `run_self_test()`.
+    pub passed: bool,
+}
+
+/// A snapshot of the state of a processor, e.g. to attach to bug reports.
+/// See [`Processor::inspect()`].
+#[derive(Debug, Clone)]
+pub struct Inspection {
+    /// The config the processor was created with.
+    pub init_config: InitializationConfig,
+
+    /// The last config passed to `set_config()`, if any.
+    pub config: Option<Config>,
+
+    /// The number of capture frames passed to the native module so far, over all clones.
+    pub num_processed_capture_frames: usize,
+
+    /// The number of render frames passed to the native module so far, over all clones.
+    pub num_processed_render_frames: usize,
+
+    /// Whether `close()` has been called.
+    pub closed: bool,
+
+    /// The stats of the last capture frame, as returned by `get_stats()`.
+    pub stats: Stats,
+
+    /// The memory used on the Rust side, as returned by `memory_usage()`.
+    pub memory_usage: MemoryUsage,
+}
 
 /// `Processor` provides an access to webrtc's audio processing e.g. echo
 /// cancellation and automatic gain control. It can be cloned, and cloned
 /// instances share the same underlying processor module. It's the recommended
 /// way to run the `Processor` in multi-threaded application.
+/// See `try_clone_independent()` for a separate processor with the same settings.
 #[derive(Clone)]
 pub struct Processor {
     inner: Arc<AudioProcessing>,
     // TODO: Refactor. It's not necessary to have two frame buffers as
     // `Processor`s are cloned for each thread.
-    deinterleaved_capture_frame: Vec<Vec<f32>>,
-    deinterleaved_render_frame: Vec<Vec<f32>>,
+    buffers: FrameBuffers,
+}
+
+impl fmt::Debug for Processor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Processor").field(&self.inspect()).finish()
+    }
 }
 
 impl Processor {
@@ -50,56 +227,108 @@ impl Processor {
     pub fn new(config: &ffi::InitializationConfig) -> Result<Self, Error> {
         Ok(Self {
             inner: Arc::new(AudioProcessing::new(config)?),
-            deinterleaved_capture_frame: vec![
-                vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
-                config.num_capture_channels as usize
-            ],
-            deinterleaved_render_frame: vec![
-                vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
-                config.num_render_channels as usize
-            ],
+            buffers: FrameBuffers::new(config),
         })
     }
 
     /// Processes and modifies the audio frame from a capture device by applying
     /// signal processing as specified in the config. `frame` should hold an
-    /// interleaved f32 audio frame, with NUM_SAMPLES_PER_FRAME samples.
+    /// interleaved f32 audio frame, with NUM_SAMPLES_PER_FRAME samples, or a
+    /// buffer of several such frames (e.g. 20 or 100 ms), which are processed
+    /// in turn. Returns `Error::ChannelMismatch` or `Error::FrameSizeMismatch`
+    /// otherwise.
     pub fn process_capture_frame(&mut self, frame: &mut [f32]) -> Result<(), Error> {
-        Self::deinterleave(frame, &mut self.deinterleaved_capture_frame);
-        self.inner.process_capture_frame(&mut self.deinterleaved_capture_frame)?;
-        Self::interleave(&self.deinterleaved_capture_frame, frame);
-        Ok(())
+        self.buffers.process_capture_frame(&self.inner, frame)
+    }
+
+    /// Same as `process_capture_frame()`, for interleaved samples encoded as `format` in a raw
+    /// byte buffer, e.g. as received from a network or IPC transport. The samples are decoded
+    /// while de-interleaving and encoded back in place, so no intermediate `f32` buffer is
+    /// needed. Integer formats are rounded and clamped like in `conversion::f32_to_s16()`. A
+    /// buffer holding a partial sample is reported as `Error::FrameSizeMismatch`.
+    pub fn process_capture_bytes(
+        &mut self,
+        frame: &mut [u8],
+        format: SampleFormat,
+    ) -> Result<(), Error> {
+        self.buffers.process_capture_bytes(&self.inner, frame, format)
     }
 
     /// Processes and modifies the audio frame from a capture device by applying
     /// signal processing as specified in the config. `frame` should be a Vec of
     /// length 'num_capture_channels', with each inner Vec representing a channel
-    /// with NUM_SAMPLES_PER_FRAME samples.
+    /// with NUM_SAMPLES_PER_FRAME samples. Returns `Error::ChannelMismatch` or
+    /// `Error::FrameSizeMismatch` otherwise.
     pub fn process_capture_frame_noninterleaved(
         &mut self,
-        frame: &mut Vec<Vec<f32>>,
+        frame: &mut [Vec<f32>],
     ) -> Result<(), Error> {
-        self.inner.process_capture_frame(frame)
+        self.buffers.process_capture_frame_noninterleaved(&self.inner, frame)
     }
 
     /// Processes and optionally modifies the audio frame from a playback device.
     /// `frame` should hold an interleaved `f32` audio frame, with
-    /// `NUM_SAMPLES_PER_FRAME` samples.
+    /// `NUM_SAMPLES_PER_FRAME` samples, or a buffer of several such frames.
+    /// Returns `Error::ChannelMismatch` or `Error::FrameSizeMismatch` otherwise.
     pub fn process_render_frame(&mut self, frame: &mut [f32]) -> Result<(), Error> {
-        Self::deinterleave(frame, &mut self.deinterleaved_render_frame);
-        self.inner.process_render_frame(&mut self.deinterleaved_render_frame)?;
-        Self::interleave(&self.deinterleaved_render_frame, frame);
-        Ok(())
+        self.buffers.process_render_frame(&self.inner, frame)
+    }
+
+    /// Same as `process_capture_bytes()`, for the render stream.
+    pub fn process_render_bytes(
+        &mut self,
+        frame: &mut [u8],
+        format: SampleFormat,
+    ) -> Result<(), Error> {
+        self.buffers.process_render_bytes(&self.inner, frame, format)
     }
 
     /// Processes and optionally modifies the audio frame from a playback device.
     /// `frame` should be a Vec of length 'num_render_channels', with each inner Vec
-    /// representing a channel with NUM_SAMPLES_PER_FRAME samples.
+    /// representing a channel with NUM_SAMPLES_PER_FRAME samples. Returns
+    /// `Error::ChannelMismatch` or `Error::FrameSizeMismatch` otherwise.
     pub fn process_render_frame_noninterleaved(
         &mut self,
-        frame: &mut Vec<Vec<f32>>,
+        frame: &mut [Vec<f32>],
     ) -> Result<(), Error> {
-        self.inner.process_render_frame(frame)
+        self.buffers.process_render_frame_noninterleaved(&self.inner, frame)
+    }
+
+    /// Runs a few silent frames through the render and capture paths, so that the lazy
+    /// allocations and initialization inside the native module happen now rather than on the
+    /// first frames of the stream, where they'd cause a glitch. Call it after `set_config()`,
+    /// since only the enabled components are warmed up, and off the audio thread.
+    pub fn warm_up(&mut self) -> Result<(), Error> {
+        self.buffers.warm_up(&self.inner)
+    }
+
+    /// Creates a new `Processor` with its own native module, initialized with the same
+    /// `InitializationConfig` and configured with the last config passed to `set_config()`.
+    ///
+    /// Unlike `clone()`, which returns another handle to the same module, sharing its state
+    /// (echo canceller filters, gain, statistics) and its config, the returned processor starts
+    /// from a fresh state and is configured independently from then on. The channel maps and
+    /// the echo reference weights are carried over. The external noise suppressor, the mic
+    /// volume backend, and any `set_stream_delay_ms()` override, aren't.
+    pub fn try_clone_independent(&self) -> Result<Self, Error> {
+        let mut processor = Self::new(&self.inner.init_config)?;
+        if let Some(config) = self.inner.config.lock().unwrap().clone() {
+            processor.set_config(config);
+        }
+        if self.inner.echo_control_suspended.load(Ordering::Relaxed) {
+            processor.inner.set_echo_control_suspended(true);
+        }
+        let weights = self
+            .inner
+            .echo_reference
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|reference| reference.weights().to_vec());
+        *processor.inner.echo_reference.lock().unwrap() = weights.map(EchoReference::new);
+        processor.buffers.capture_channel_map = self.buffers.capture_channel_map.clone();
+        processor.buffers.render_channel_map = self.buffers.render_channel_map.clone();
+        Ok(processor)
     }
 
     /// Returns statistics from the last `process_capture_frame()` call.
@@ -107,24 +336,303 @@ impl Processor {
         self.inner.get_stats()
     }
 
-    /// Immediately updates the configurations of the internal signal processor.
-    /// May be called multiple times after the initialization and during
-    /// processing.
+    /// Measures how long the native module delays the capture stream with the current config,
+    /// e.g. through the overlapping blocks of its filters, by running noise through a processor
+    /// of its own and finding the lag of the output. Delays beyond one frame aren't detected.
+    /// It takes a few milliseconds, so call it off the audio thread, and again after
+    /// `set_config()`. Pass the result to `FrameChunker::set_processing_delay()` to account for
+    /// it in `FrameChunker::current_latency()`.
+    ///
+    /// The external noise suppressor isn't included, as the measurement doesn't run through it.
+    pub fn measure_algorithmic_delay(&self) -> Result<Duration, Error> {
+        self.inner.measure_algorithmic_delay()
+    }
+
+    /// Returns the inherent delay of the processing pipeline with the current config, e.g. to
+    /// compensate for it in A/V sync. Multiply by 48 kHz for the delay in samples. It's measured
+    /// with `measure_algorithmic_delay()` on the first call after each `set_config()`, and
+    /// cached, so only that first call is slow.
+    pub fn algorithmic_delay(&self) -> Result<Duration, Error> {
+        self.inner.algorithmic_delay()
+    }
+
+    /// Checks that the echo cancellation works with the current config, e.g. at startup to catch
+    /// a broken build or config before a call does: runs 3 s of speech-like far end through a
+    /// processor of its own, with a simulated echo of it in the capture, and measures the ERLE
+    /// once converged. The echo is 6 dB below the far end, and delayed by the stream delay of
+    /// the config, or by 20 ms. It takes a while, so call it off the audio thread.
+    ///
+    /// Like in `measure_algorithmic_delay()`, the external noise suppressor, the voice gate and
+    /// the comfort noise are left out, so that they don't pass for cancelled echo.
+    pub fn run_self_test(&self, min_erle_db: f32) -> Result<SelfTestReport, Error> {
+        self.inner.run_self_test(min_erle_db)
+    }
+
+    /// Returns the initialization parameters, the applied config, the frame counters and the
+    /// latest stats in one snapshot. The `Debug` output of the `Processor` shows the same.
+    pub fn inspect(&self) -> Inspection {
+        self.inner.inspect(self.memory_usage())
+    }
+
+    /// Releases the native module shared by all the clones of this `Processor`, without waiting
+    /// for the last one to be dropped, e.g. to shut down in a defined order. Waits for the
+    /// processing calls in flight on other threads to return first. From then on, processing
+    /// fails with `Error::Closed` in every clone, `get_stats()` returns empty stats and
+    /// `set_config()` only takes effect in `try_clone_independent()`.
+    pub fn close(&self) {
+        self.inner.close();
+    }
+
+    /// Returns the approximate number of bytes used by the buffers this `Processor` owns on the
+    /// Rust side. Each clone owns its own frame buffers, so the figure is per clone.
+    ///
+    /// The internal allocations of the native module are not included, as
+    /// webrtc::AudioProcessing doesn't expose them.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.buffers.memory_usage(mem::size_of::<Self>() + mem::size_of::<AudioProcessing>())
+    }
+
+    /// Returns a continuously updated estimate of the time it takes to process a frame, per
+    /// direction, as a fraction of the 10 ms of real time it covers. It's averaged over about a
+    /// second of frames, and shared by all the clones of this `Processor`. Loads approaching 1
+    /// on weak hardware mean that frames will soon be late, e.g. to degrade gracefully by
+    /// switching to AECM or a lower noise suppression level.
+    ///
+    /// The time is measured on the thread processing the frames, from wall clock time, so it
+    /// includes the time the thread was preempted.
+    pub fn cpu_load(&self) -> CpuLoad {
+        self.inner.cpu_load()
+    }
+
+    /// Updates the configurations of the internal signal processor, from the next capture frame
+    /// on. May be called multiple times after the initialization and during processing; the
+    /// capture thread never waits for it.
     pub fn set_config(&mut self, config: Config) {
         self.inner.set_config(config);
     }
 
+    /// Changes the level of the built-in noise suppression, without re-applying the rest of the
+    /// config like `set_config()` does, which can reset the adaptation of e.g. the echo
+    /// cancellation. Has no effect unless `Config::noise_suppression` is set, with the
+    /// `NoiseSuppressionBackend::Builtin` backend.
+    pub fn set_noise_suppression_level(&mut self, level: NoiseSuppressionLevel) {
+        self.inner.set_noise_suppression_level(level);
+    }
+
+    /// Changes the target level of the gain control, see `GainControl::target_level_dbfs`,
+    /// without re-applying the rest of the config like `set_config()` does, e.g. to nudge the
+    /// loudness during a call. Fails with `Error::BadParameter` outside of [0, 31]. Has no effect
+    /// unless `Config::gain_control` is set.
+    pub fn set_agc1_target_level_dbfs(&mut self, level: u8) -> Result<(), Error> {
+        self.inner.set_agc1_target_level_dbfs(level)
+    }
+
+    /// Enables or disables the limiter of the gain control, see `GainControl::enable_limiter`,
+    /// without re-applying the rest of the config like `set_config()` does. Has no effect
+    /// unless `Config::gain_control` is set.
+    pub fn set_agc1_enable_limiter(&mut self, enable: bool) {
+        self.inner.set_agc1_enable_limiter(enable);
+    }
+
+    /// Starts recording every change of the config, by `set_config()` and the runtime setters
+    /// like `set_agc1_target_level_dbfs()`, with the time and the fields that changed, e.g. to
+    /// find out after the fact what was changed when the audio got bad. The other calls that
+    /// change the processing, e.g. `set_headphones_connected()`, `set_stream_delay_ms()` or
+    /// `set_capture_channel_map()`, are recorded with their arguments. Keeps the last
+    /// `capacity` changes; 0 stops the recording and forgets them. It's shared by all the
+    /// clones of this `Processor`.
+    pub fn enable_config_audit_log(&self, capacity: usize) {
+        self.inner.set_config_audit_log_capacity(capacity);
+    }
+
+    /// Returns the recorded changes of the config, oldest first. Empty unless
+    /// `enable_config_audit_log()` was called.
+    pub fn config_audit_log(&self) -> Vec<ConfigChange> {
+        self.inner.config_audit_log()
+    }
+
+    /// Signals that the playout device changed, e.g. when the OS switched the default output
+    /// device. The echo path is a different one, so the adapted state of the echo cancellation
+    /// is reset, as it would only hurt; the adaptation of the gain control and noise
+    /// suppression is kept. The delay of the new device likely differs too, so update the
+    /// stream delay with `set_stream_delay_ms()` unless the echo cancellation is delay agnostic.
+    pub fn notify_playout_device_changed(&mut self) {
+        self.inner.reset_echo_control(ConfigChangeSource::NotifyPlayoutDeviceChanged);
+    }
+
+    /// Signals that the capture device changed. Like `notify_playout_device_changed()`, the
+    /// adapted state of the echo cancellation is reset, while that of the gain control and
+    /// noise suppression is kept.
+    pub fn notify_capture_device_changed(&mut self) {
+        self.inner.reset_echo_control(ConfigChangeSource::NotifyCaptureDeviceChanged);
+    }
+
+    /// Signals whether headphones are connected, in which case there's no acoustic echo to
+    /// cancel. The echo cancellation is suspended until they're disconnected, saving its
+    /// processing time, and resumes with the config of the last `set_config()` call, which keeps
+    /// applying meanwhile. The native module resets the adapted state of the echo canceller
+    /// when it resumes, so it converges again like after `notify_playout_device_changed()`.
+    pub fn set_headphones_connected(&mut self, connected: bool) {
+        self.inner.set_echo_control_suspended(connected);
+    }
+
+    /// Sets the external noise suppressor, used while `Config::noise_suppression_backend` is
+    /// `NoiseSuppressionBackend::External`. It's shared by all the clones of this `Processor`.
+    pub fn set_noise_suppressor(&self, backend: Box<dyn NoiseSuppressorBackend>) {
+        self.inner.set_noise_suppressor(backend);
+    }
+
+    /// Sets the volume control of the microphone, driven by the gain control while
+    /// `GainControl::mode` is `GainControlMode::AdaptiveAnalog`. It's shared by all the clones of
+    /// this `Processor`.
+    pub fn set_mic_volume_backend(&self, backend: Box<dyn MicVolumeBackend>) {
+        self.inner.set_mic_volume_backend(backend);
+    }
+
+    /// Drives the volume control set by `set_mic_volume_backend()`: sets the volume recommended
+    /// by the gain control since the last call, if it differs, and reads the current volume for
+    /// the next capture frame. Does nothing unless `GainControl::mode` is
+    /// `GainControlMode::AdaptiveAnalog`.
+    ///
+    /// Mixer calls may block, so call it from a thread other than the audio thread, e.g. every
+    /// 100 ms with a clone of this `Processor`. The gain control assumes that its
+    /// recommendations are applied in between.
+    pub fn poll_mic_volume(&self) {
+        self.inner.poll_mic_volume();
+    }
+
+    /// Sets how much each render channel contributes to the far-end reference of the echo
+    /// cancellation, e.g. `[1.0, 1.0, 0.0, 0.0, 0.0, 0.0]` for a 5.1 stream of which only the
+    /// front left and right are played near the microphone. The weighted channels are mixed
+    /// into a single reference; the render frame itself is left as is, which also means that
+    /// `InitializationConfig::enable_intelligibility_enhancer` has no effect on it while weights
+    /// are set. `None` feeds all the channels as they are, which is the default. Fails with
+    /// `Error::BadParameter` unless there's one finite weight per render channel. It's shared by
+    /// all the clones of this `Processor`.
+    pub fn set_echo_reference_weights(&self, weights: Option<Vec<f32>>) -> Result<(), Error> {
+        self.inner.set_echo_reference_weights(weights)
+    }
+
     /// Signals the AEC and AGC that the audio output will be / is muted.
     /// They may use the hint to improve their parameter adaptation.
+    /// With `Config::enable_comfort_noise`, the capture stream is replaced with comfort noise
+    /// while muted.
+    /// Applied from the next capture frame on, so it's safe to call from any thread.
     pub fn set_output_will_be_muted(&self, muted: bool) {
         self.inner.set_output_will_be_muted(muted);
     }
 
-    /// Signals the AEC and AGC that the next frame will contain key press sound
+    /// Signals the AEC and AGC that the next frame will contain key press sound.
+    /// Applied from the next capture frame on, until changed, so it's safe to call from any
+    /// thread.
     pub fn set_stream_key_pressed(&self, pressed: bool) {
         self.inner.set_stream_key_pressed(pressed);
     }
 
+    /// Overrides `EchoCancellation::stream_delay_ms` from the next capture frame on, until the
+    /// next `set_config()`. Unlike `set_config()`, it's cheap enough to call on every frame, and
+    /// safe to call from the render thread. See `NetworkRenderFeeder` to maintain it
+    /// automatically.
+    pub fn set_stream_delay_ms(&self, delay_ms: i32) {
+        self.inner.set_stream_delay_ms(delay_ms);
+    }
+
+    /// Sets the order of the channels in the interleaved buffers passed to
+    /// `process_capture_frame()`, e.g. for an interface that delivers them in hardware order:
+    /// channel `i` of the processor is read from, and written back to, channel `map[i]` of the
+    /// buffer. The reordering happens while de-interleaving, so it costs no extra pass. `map`
+    /// must hold each channel index once, or `Error::BadParameter` is returned. `None` restores
+    /// the buffer order. The non-interleaved functions aren't affected, and each clone keeps its
+    /// own map.
+    pub fn set_capture_channel_map(&mut self, map: Option<Vec<usize>>) -> Result<(), Error> {
+        self.buffers.set_capture_channel_map(&self.inner, map)
+    }
+
+    /// Same as `set_capture_channel_map()`, for the buffers passed to `process_render_frame()`.
+    pub fn set_render_channel_map(&mut self, map: Option<Vec<usize>>) -> Result<(), Error> {
+        self.buffers.set_render_channel_map(&self.inner, map)
+    }
+
+    /// Runs each 10 ms frame of the interleaved `buffer` through `process_frame`, de-interleaved
+    /// into `deinterleaved` in the order of `channel_map`. Like `FrameChunker::process()`, the
+    /// remaining frames are still processed after an error, and only the first error is
+    /// returned.
+    fn process_interleaved<F>(
+        buffer: &mut [f32],
+        deinterleaved: &mut [Vec<f32>],
+        channel_map: Option<&[usize]>,
+        stream: StreamKind,
+        mut process_frame: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut [Vec<f32>]) -> Result<(), Error>,
+    {
+        check_interleaved_len(buffer.len(), deinterleaved.len(), stream)?;
+        let mut result = Ok(());
+        for frame in buffer.chunks_exact_mut(NUM_SAMPLES_PER_FRAME as usize * deinterleaved.len()) {
+            Self::deinterleave(frame, deinterleaved, channel_map);
+            match process_frame(deinterleaved) {
+                Ok(()) => Self::interleave(deinterleaved, frame, channel_map),
+                Err(err) => result = result.and(Err(err)),
+            }
+        }
+        result
+    }
+
+    /// Same as `process_interleaved()`, for samples encoded as `format` in `buffer`.
+    fn process_interleaved_bytes<F>(
+        buffer: &mut [u8],
+        format: SampleFormat,
+        deinterleaved: &mut [Vec<f32>],
+        channel_map: Option<&[usize]>,
+        stream: StreamKind,
+        mut process_frame: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut [Vec<f32>]) -> Result<(), Error>,
+    {
+        let bytes_per_sample = format.bytes_per_sample();
+        let num_samples = buffer.len() / bytes_per_sample;
+        if num_samples * bytes_per_sample != buffer.len() {
+            let expected = NUM_SAMPLES_PER_FRAME as usize * deinterleaved.len();
+            return Err(Error::FrameSizeMismatch { expected, got: num_samples });
+        }
+        check_interleaved_len(num_samples, deinterleaved.len(), stream)?;
+        let num_channels = deinterleaved.len();
+        let mut result = Ok(());
+        for frame in buffer
+            .chunks_exact_mut(NUM_SAMPLES_PER_FRAME as usize * num_channels * bytes_per_sample)
+        {
+            for (channel_index, channel) in deinterleaved.iter_mut().enumerate() {
+                let channel_index = channel_map.map_or(channel_index, |map| map[channel_index]);
+                let samples = frame.chunks_exact(bytes_per_sample).skip(channel_index);
+                for (sample, bytes) in channel.iter_mut().zip(samples.step_by(num_channels)) {
+                    *sample = format.decode(bytes);
+                }
+            }
+            match process_frame(deinterleaved) {
+                Ok(()) => {
+                    for (channel_index, channel) in deinterleaved.iter().enumerate() {
+                        let channel_index =
+                            channel_map.map_or(channel_index, |map| map[channel_index]);
+                        let samples = frame.chunks_exact_mut(bytes_per_sample).skip(channel_index);
+                        for (sample, bytes) in channel.iter().zip(samples.step_by(num_channels)) {
+                            format.encode(*sample, bytes);
+                        }
+                    }
+                },
+                Err(err) => result = result.and(Err(err)),
+            }
+        }
+        result
+    }
+
+    /// Returns the number of bytes allocated for a de-interleaved frame buffer.
+    fn frame_bytes(frame: &Vec<Vec<f32>>) -> usize {
+        frame.capacity() * mem::size_of::<Vec<f32>>()
+            + frame.iter().map(|v| v.capacity() * mem::size_of::<f32>()).sum::<usize>()
+    }
+
     /// De-interleaves multi-channel frame `src` into `dst`.
     ///
     /// ```text
@@ -142,128 +650,1816 @@ impl Processor {
     /// |R0 |R1 |R2 |
     /// +---+---+---+
     /// ```
-    fn deinterleave<T: AsMut<[f32]>>(src: &[f32], dst: &mut [T]) {
+    ///
+    /// The samples are copied in blocks of `INTERLEAVE_BLOCK_SIZE` frames, so that the part of
+    /// `src` being read stays in the cache while each channel of the block is written out. This
+    /// matters with many channels, where a channel-by-channel pass over the whole frame would
+    /// stride through memory once per channel.
+    ///
+    /// With a `channel_map`, channel `i` of `dst` is taken from channel `channel_map[i]` of
+    /// `src`.
+    fn deinterleave<T: AsMut<[f32]>>(src: &[f32], dst: &mut [T], channel_map: Option<&[usize]>) {
         let num_channels = dst.len();
         let num_samples = dst[0].as_mut().len();
         assert_eq!(src.len(), num_channels * num_samples);
-        for channel_index in 0..num_channels {
-            for sample_index in 0..num_samples {
-                dst[channel_index].as_mut()[sample_index] =
-                    src[num_channels * sample_index + channel_index];
+        for block_start in (0..num_samples).step_by(INTERLEAVE_BLOCK_SIZE) {
+            let block_end = (block_start + INTERLEAVE_BLOCK_SIZE).min(num_samples);
+            let src_block = &src[num_channels * block_start..num_channels * block_end];
+            for (channel_index, channel) in dst.iter_mut().enumerate() {
+                let channel_index = channel_map.map_or(channel_index, |map| map[channel_index]);
+                let dst_block = &mut channel.as_mut()[block_start..block_end];
+                for (sample, src_frame) in dst_block.iter_mut().zip(src_block.chunks(num_channels))
+                {
+                    *sample = src_frame[channel_index];
+                }
+            }
+        }
+    }
+
+    /// Reverts the `deinterleave` operation, with the same blocking and `channel_map`.
+    fn interleave<T: AsRef<[f32]>>(src: &[T], dst: &mut [f32], channel_map: Option<&[usize]>) {
+        let num_channels = src.len();
+        let num_samples = src[0].as_ref().len();
+        assert_eq!(dst.len(), num_channels * num_samples);
+        for block_start in (0..num_samples).step_by(INTERLEAVE_BLOCK_SIZE) {
+            let block_end = (block_start + INTERLEAVE_BLOCK_SIZE).min(num_samples);
+            let dst_block = &mut dst[num_channels * block_start..num_channels * block_end];
+            for (channel_index, channel) in src.iter().enumerate() {
+                let channel_index = channel_map.map_or(channel_index, |map| map[channel_index]);
+                let src_block = &channel.as_ref()[block_start..block_end];
+                for (sample, dst_frame) in src_block.iter().zip(dst_block.chunks_mut(num_channels))
+                {
+                    dst_frame[channel_index] = *sample;
+                }
+            }
+        }
+    }
+}
+
+/// Checks that an interleaved buffer of `len` samples holds a whole number of 10 ms frames of
+/// `num_channels` channels, so that a wrong-sized buffer from an audio callback is reported
+/// rather than panicking. Otherwise, a whole number of 10 ms channels is taken for a channel
+/// count mismatch.
+fn check_interleaved_len(len: usize, num_channels: usize, stream: StreamKind) -> Result<(), Error> {
+    let expected = NUM_SAMPLES_PER_FRAME as usize * num_channels;
+    let num_frames = len / expected;
+    let num_buffer_channels = len / NUM_SAMPLES_PER_FRAME as usize;
+    if num_frames > 0 && num_frames * expected == len {
+        Ok(())
+    } else if num_buffer_channels > 0 && num_buffer_channels * NUM_SAMPLES_PER_FRAME as usize == len
+    {
+        Err(Error::ChannelMismatch { stream, expected: num_channels, got: num_buffer_channels })
+    } else {
+        Err(Error::FrameSizeMismatch { expected, got: len })
+    }
+}
+
+/// Checks that the de-interleaved `frame` holds `num_channels` channels of 10 ms each. The
+/// native module reads that many samples through each channel pointer, so anything shorter
+/// would be read out of bounds.
+fn check_noninterleaved_len(
+    frame: &[Vec<f32>],
+    num_channels: usize,
+    stream: StreamKind,
+) -> Result<(), Error> {
+    if frame.len() != num_channels {
+        return Err(Error::ChannelMismatch { stream, expected: num_channels, got: frame.len() });
+    }
+    match frame.iter().find(|channel| channel.len() != NUM_SAMPLES_PER_FRAME as usize) {
+        Some(_) => Err(Error::FrameSizeMismatch {
+            expected: NUM_SAMPLES_PER_FRAME as usize * num_channels,
+            got: frame.iter().map(Vec::len).sum(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `channel_map` holds each of the `num_channels` channel indices once.
+pub(crate) fn check_channel_map(
+    channel_map: Option<&[usize]>,
+    num_channels: usize,
+) -> Result<(), Error> {
+    let channel_map = match channel_map {
+        Some(channel_map) => channel_map,
+        None => return Ok(()),
+    };
+    let mut seen = vec![false; num_channels];
+    for &channel in channel_map {
+        match seen.get_mut(channel) {
+            Some(seen) if !*seen => *seen = true,
+            _ => return Err(Error::BadParameter),
+        }
+    }
+    if channel_map.len() == num_channels {
+        Ok(())
+    } else {
+        Err(Error::BadParameter)
+    }
+}
+
+/// Runs `func` as a real-time section. With the `rt-safe` feature, any heap allocation made
+/// through the Rust global allocator inside the section aborts the process in debug builds, as
+/// long as [`AllocDisabler`] is installed as the `#[global_allocator]`.
+///
+/// Only allocations are checked. The mutexes taken on the processing path, which are
+/// uncontended unless another thread changes the config at the same time, and blocking syscalls
+/// aren't detected.
+#[cfg(feature = "rt-safe")]
+fn real_time_section<T, F: FnOnce() -> T>(func: F) -> T {
+    assert_no_alloc::assert_no_alloc(func)
+}
+
+#[cfg(not(feature = "rt-safe"))]
+fn real_time_section<T, F: FnOnce() -> T>(func: F) -> T {
+    func()
+}
+
+/// The state a `Processor` or `LocalProcessor` keeps besides its handle to the native module:
+/// the buffers interleaved frames are de-interleaved into, and the channel maps. Both types
+/// process through it, with their `Arc` or owned module respectively.
+#[derive(Clone)]
+struct FrameBuffers {
+    deinterleaved_capture_frame: Vec<Vec<f32>>,
+    deinterleaved_render_frame: Vec<Vec<f32>>,
+    channel_ptrs: ChannelPointers,
+    capture_channel_map: Option<Vec<usize>>,
+    render_channel_map: Option<Vec<usize>>,
+}
+
+impl FrameBuffers {
+    fn new(config: &ffi::InitializationConfig) -> Self {
+        Self {
+            deinterleaved_capture_frame: vec![
+                vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
+                config.num_capture_channels as usize
+            ],
+            deinterleaved_render_frame: vec![
+                vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
+                config.num_render_channels as usize
+            ],
+            channel_ptrs: ChannelPointers::with_capacity(
+                config.num_capture_channels.max(config.num_render_channels) as usize,
+            ),
+            capture_channel_map: None,
+            render_channel_map: None,
+        }
+    }
+
+    fn process_capture_frame<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        frame: &mut [f32],
+    ) -> Result<(), Error> {
+        let Self { deinterleaved_capture_frame, channel_ptrs, capture_channel_map, .. } = self;
+        real_time_section(|| {
+            Processor::process_interleaved(
+                frame,
+                deinterleaved_capture_frame,
+                capture_channel_map.as_deref(),
+                StreamKind::Capture,
+                |deinterleaved| inner.process_capture_frame(deinterleaved, channel_ptrs),
+            )
+        })
+    }
+
+    fn process_capture_bytes<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        frame: &mut [u8],
+        format: SampleFormat,
+    ) -> Result<(), Error> {
+        let Self { deinterleaved_capture_frame, channel_ptrs, capture_channel_map, .. } = self;
+        real_time_section(|| {
+            Processor::process_interleaved_bytes(
+                frame,
+                format,
+                deinterleaved_capture_frame,
+                capture_channel_map.as_deref(),
+                StreamKind::Capture,
+                |deinterleaved| inner.process_capture_frame(deinterleaved, channel_ptrs),
+            )
+        })
+    }
+
+    fn process_capture_frame_noninterleaved<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        frame: &mut [Vec<f32>],
+    ) -> Result<(), Error> {
+        real_time_section(|| inner.process_capture_frame(frame, &mut self.channel_ptrs))
+    }
+
+    fn process_render_frame<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        frame: &mut [f32],
+    ) -> Result<(), Error> {
+        let Self { deinterleaved_render_frame, channel_ptrs, render_channel_map, .. } = self;
+        real_time_section(|| {
+            Processor::process_interleaved(
+                frame,
+                deinterleaved_render_frame,
+                render_channel_map.as_deref(),
+                StreamKind::Render,
+                |deinterleaved| inner.process_render_frame(deinterleaved, channel_ptrs),
+            )
+        })
+    }
+
+    fn process_render_bytes<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        frame: &mut [u8],
+        format: SampleFormat,
+    ) -> Result<(), Error> {
+        let Self { deinterleaved_render_frame, channel_ptrs, render_channel_map, .. } = self;
+        real_time_section(|| {
+            Processor::process_interleaved_bytes(
+                frame,
+                format,
+                deinterleaved_render_frame,
+                render_channel_map.as_deref(),
+                StreamKind::Render,
+                |deinterleaved| inner.process_render_frame(deinterleaved, channel_ptrs),
+            )
+        })
+    }
+
+    fn process_render_frame_noninterleaved<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        frame: &mut [Vec<f32>],
+    ) -> Result<(), Error> {
+        real_time_section(|| inner.process_render_frame(frame, &mut self.channel_ptrs))
+    }
+
+    fn set_capture_channel_map<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        map: Option<Vec<usize>>,
+    ) -> Result<(), Error> {
+        check_channel_map(map.as_deref(), inner.num_capture_channels)?;
+        inner.audit_call(|| ConfigChangeSource::SetCaptureChannelMap(map.clone()));
+        self.capture_channel_map = map;
+        Ok(())
+    }
+
+    fn set_render_channel_map<S: SyncPolicy>(
+        &mut self,
+        inner: &AudioProcessing<S>,
+        map: Option<Vec<usize>>,
+    ) -> Result<(), Error> {
+        check_channel_map(map.as_deref(), inner.num_render_channels)?;
+        inner.audit_call(|| ConfigChangeSource::SetRenderChannelMap(map.clone()));
+        self.render_channel_map = map;
+        Ok(())
+    }
+
+    fn warm_up<S: SyncPolicy>(&mut self, inner: &AudioProcessing<S>) -> Result<(), Error> {
+        inner.warm_up(
+            &mut self.deinterleaved_capture_frame,
+            &mut self.deinterleaved_render_frame,
+            &mut self.channel_ptrs,
+        )
+    }
+
+    /// Returns the memory used by the buffers, plus the given size of the handle.
+    fn memory_usage(&self, handle_bytes: usize) -> MemoryUsage {
+        MemoryUsage {
+            capture_frame_bytes: Processor::frame_bytes(&self.deinterleaved_capture_frame),
+            render_frame_bytes: Processor::frame_bytes(&self.deinterleaved_render_frame),
+            scratch_bytes: self.channel_ptrs.0.capacity() * mem::size_of::<*mut f32>(),
+            channel_map_bytes: [&self.capture_channel_map, &self.render_channel_map]
+                .iter()
+                .filter_map(|map| map.as_ref())
+                .map(|map| map.capacity() * mem::size_of::<usize>())
+                .sum(),
+            handle_bytes,
+        }
+    }
+}
+
+/// Pre-allocated storage for the per-channel pointers passed to the ffi, so that processing a
+/// frame doesn't need to allocate.
+struct ChannelPointers(Vec<*mut f32>);
+
+impl ChannelPointers {
+    fn with_capacity(num_channels: usize) -> Self {
+        Self(Vec::with_capacity(num_channels))
+    }
+
+    /// Fills the storage with pointers to the given channels. It doesn't allocate unless `frame`
+    /// holds more channels than the processor was initialized with.
+    fn fill(&mut self, frame: &mut [Vec<f32>]) -> *mut *mut f32 {
+        self.0.clear();
+        self.0.extend(frame.iter_mut().map(|v| v.as_mut_ptr()));
+        self.0.as_mut_ptr()
+    }
+}
+
+impl Clone for ChannelPointers {
+    fn clone(&self) -> Self {
+        Self::with_capacity(self.0.capacity())
+    }
+}
+
+// The pointers are only valid during a single ffi call, and are refilled before every use.
+unsafe impl Send for ChannelPointers {}
+
+/// Stats snapshot, valid until the next capture frame is processed.
+struct CachedStats {
+    /// The number of processed capture frames when the snapshot was taken.
+    generation: usize,
+    stats: Stats,
+}
+
+/// Marks a call into the native module as in flight until dropped, so that `close()` waits for
+/// it.
+struct NativeCall<'a, C: AtomicCounter<usize>>(&'a C);
+
+impl<C: AtomicCounter<usize>> Drop for NativeCall<'_, C> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Minimal wrapper for safe and synchronized ffi.
+struct AudioProcessing<S: SyncPolicy = Shared> {
+    inner: *mut ffi::AudioProcessing,
+    init_config: ffi::InitializationConfig,
+    // The last config passed to `set_config()`, if any.
+    config: S::Lock<Option<Config>>,
+    // Only ever locked within `config`.
+    config_audit_log: S::Lock<ConfigAuditLog>,
+    // Whether `config_audit_log` is enabled, checked without locking by the setters that may be
+    // called on the audio threads.
+    config_audit_log_enabled: S::AtomicBool,
+    num_capture_channels: usize,
+    num_render_channels: usize,
+    num_processed_capture_frames: S::AtomicUsize,
+    num_processed_render_frames: S::AtomicUsize,
+    capture_load: CpuLoadMeter<S>,
+    render_load: CpuLoadMeter<S>,
+    stats_cache: S::Lock<Option<CachedStats>>,
+    // Bumped by `set_config()`, within `config`.
+    config_generation: S::AtomicUsize,
+    // Measured on demand, with the `config_generation` it was measured at.
+    algorithmic_delay: S::Lock<Option<(usize, Duration)>>,
+    use_external_noise_suppressor: S::AtomicBool,
+    // Applied on the capture path, as the native setters aren't thread safe.
+    pending_stream_delay_ms: S::AtomicI32,
+    pending_output_will_be_muted: S::AtomicU8,
+    pending_analog_level: S::AtomicI32,
+    pending_echo_control_suspended: S::AtomicU8,
+    pending_echo_control_reset: S::AtomicBool,
+    // The native config staged by `set_config()`, applied on the capture path. Never waited for
+    // there, so that staging a config doesn't stall the capture thread.
+    pending_config: S::Lock<Option<ffi::Config>>,
+    // Published on the capture path for `poll_mic_volume()`.
+    recommended_analog_level: S::AtomicI32,
+    output_muted: S::AtomicBool,
+    stream_key_pressed: S::AtomicBool,
+    // Held while the native module reads or writes its unsynchronized stream parameters, i.e.
+    // by the capture path and, briefly, by the runtime setters of the config.
+    stream_parameters: S::Lock<()>,
+    sanitize_non_finite_samples: S::AtomicBool,
+    num_non_finite_samples: S::AtomicU64,
+    deterministic: S::AtomicBool,
+    // Locked on the capture path, including `warm_up()`, and by `set_noise_suppressor()` when
+    // the backend is swapped, which may briefly block a capture frame.
+    external_noise_suppressor: S::Lock<Option<Box<dyn NoiseSuppressorBackend>>>,
+    use_adaptive_analog_gain: S::AtomicBool,
+    echo_control_suspended: S::AtomicBool,
+    // Only ever locked by `poll_mic_volume()` and `set_mic_volume_backend()`, off the capture path.
+    mic_volume_backend: S::Lock<Option<Box<dyn MicVolumeBackend>>>,
+    // Locked on the capture path, and briefly by `set_config()`.
+    high_pass_filter: S::Lock<Option<HighPassFilterState>>,
+    // Locked on the capture path, and briefly by `set_config()` and `get_stats()`.
+    voice_gate: S::Lock<Option<VoiceGateState>>,
+    enable_comfort_noise: S::AtomicBool,
+    // Only ever locked on the capture path.
+    comfort_noise: S::Lock<ComfortNoiseGenerator>,
+    // Locked on the render path, and briefly by `set_echo_reference_weights()`.
+    echo_reference: S::Lock<Option<EchoReference>>,
+    // Set by `close()`, after which `inner` is released and must not be touched.
+    closed: S::AtomicBool,
+    num_native_calls: S::AtomicUsize,
+}
+
+impl<S: SyncPolicy> AudioProcessing<S> {
+    fn new(config: &ffi::InitializationConfig) -> Result<Self, Error> {
+        let mut code = 0;
+        let inner = unsafe { ffi::audio_processing_create(config, &mut code) };
+        if !inner.is_null() {
+            Ok(Self {
+                inner,
+                init_config: *config,
+                config: Lock::new(None),
+                config_audit_log: Lock::new(ConfigAuditLog::default()),
+                config_audit_log_enabled: AtomicCell::new(false),
+                num_capture_channels: config.num_capture_channels as usize,
+                num_render_channels: config.num_render_channels as usize,
+                num_processed_capture_frames: AtomicCell::new(0),
+                num_processed_render_frames: AtomicCell::new(0),
+                capture_load: CpuLoadMeter::default(),
+                render_load: CpuLoadMeter::default(),
+                stats_cache: Lock::new(None),
+                config_generation: AtomicCell::new(0),
+                algorithmic_delay: Lock::new(None),
+                use_external_noise_suppressor: AtomicCell::new(false),
+                pending_stream_delay_ms: AtomicCell::new(NO_PENDING_STREAM_DELAY),
+                pending_output_will_be_muted: AtomicCell::new(NO_PENDING_FLAG),
+                pending_analog_level: AtomicCell::new(NO_ANALOG_LEVEL),
+                pending_echo_control_suspended: AtomicCell::new(NO_PENDING_FLAG),
+                pending_echo_control_reset: AtomicCell::new(false),
+                pending_config: Lock::new(None),
+                recommended_analog_level: AtomicCell::new(NO_ANALOG_LEVEL),
+                output_muted: AtomicCell::new(false),
+                stream_key_pressed: AtomicCell::new(false),
+                stream_parameters: Lock::new(()),
+                sanitize_non_finite_samples: AtomicCell::new(false),
+                num_non_finite_samples: AtomicCell::new(0),
+                deterministic: AtomicCell::new(false),
+                external_noise_suppressor: Lock::new(None),
+                use_adaptive_analog_gain: AtomicCell::new(false),
+                echo_control_suspended: AtomicCell::new(false),
+                mic_volume_backend: Lock::new(None),
+                high_pass_filter: Lock::new(None),
+                voice_gate: Lock::new(None),
+                enable_comfort_noise: AtomicCell::new(false),
+                comfort_noise: Lock::new(ComfortNoiseGenerator::new()),
+                echo_reference: Lock::new(None),
+                closed: AtomicCell::new(false),
+                num_native_calls: AtomicCell::new(0),
+            })
+        } else {
+            Err(Error::from_code(code))
+        }
+    }
+
+    fn process_capture_frame(
+        &self,
+        frame: &mut [Vec<f32>],
+        channel_ptrs: &mut ChannelPointers,
+    ) -> Result<(), Error> {
+        check_noninterleaved_len(frame, self.num_capture_channels, StreamKind::Capture)?;
+        let _native_call = self.begin_native_call()?;
+        let _load_timer = self.capture_load.start();
+        let _denormal_guard = self.deterministic.load(Ordering::Relaxed).then(DenormalGuard::new);
+        self.sanitize(frame);
+        if let Some(filter) = self.high_pass_filter.lock().as_mut() {
+            filter.process_frame(frame);
+        }
+        let code = {
+            let _stream_parameters = self.stream_parameters.lock();
+            self.apply_stream_parameters();
+            let code = unsafe { ffi::process_capture_frame(self.inner, channel_ptrs.fill(frame)) };
+            if self.use_adaptive_analog_gain.load(Ordering::Relaxed) {
+                let recommended_level = unsafe { ffi::stream_analog_level(self.inner) };
+                self.recommended_analog_level.store(recommended_level, Ordering::Relaxed);
+            }
+            code
+        };
+        self.num_processed_capture_frames.fetch_add(1, Ordering::Release);
+        if !unsafe { ffi::is_success(code) } {
+            return Err(Error::from_code(code));
+        }
+
+        if self.use_external_noise_suppressor.load(Ordering::Relaxed) {
+            if let Some(backend) = self.external_noise_suppressor.lock().as_mut() {
+                backend.process_frame(frame);
+            }
+        }
+        if let Some(gate) = self.voice_gate.lock().as_mut() {
+            let has_voice = unsafe { ffi::stream_has_voice(self.inner) };
+            gate.process_frame(frame, has_voice.into());
+        }
+        if self.enable_comfort_noise.load(Ordering::Relaxed) {
+            let mut comfort_noise = self.comfort_noise.lock();
+            if self.output_muted.load(Ordering::Relaxed) {
+                comfort_noise.fill_frame(frame);
+            } else {
+                comfort_noise.analyze_frame(frame);
             }
         }
+        Ok(())
+    }
+
+    fn process_render_frame(
+        &self,
+        frame: &mut [Vec<f32>],
+        channel_ptrs: &mut ChannelPointers,
+    ) -> Result<(), Error> {
+        check_noninterleaved_len(frame, self.num_render_channels, StreamKind::Render)?;
+        let _native_call = self.begin_native_call()?;
+        let _load_timer = self.render_load.start();
+        let _denormal_guard = self.deterministic.load(Ordering::Relaxed).then(DenormalGuard::new);
+        self.sanitize(frame);
+        let mut echo_reference = self.echo_reference.lock();
+        let reference = match echo_reference.as_mut() {
+            Some(echo_reference) => echo_reference.mix(frame),
+            None => frame,
+        };
+        let code = unsafe { ffi::process_render_frame(self.inner, channel_ptrs.fill(reference)) };
+        self.num_processed_render_frames.fetch_add(1, Ordering::Relaxed);
+        if unsafe { ffi::is_success(code) } {
+            Ok(())
+        } else {
+            Err(Error::from_code(code))
+        }
+    }
+
+    fn warm_up(
+        &self,
+        capture_frame: &mut [Vec<f32>],
+        render_frame: &mut [Vec<f32>],
+        channel_ptrs: &mut ChannelPointers,
+    ) -> Result<(), Error> {
+        for _ in 0..NUM_WARM_UP_FRAMES {
+            for channel in render_frame.iter_mut() {
+                channel.iter_mut().for_each(|sample| *sample = 0.0);
+            }
+            self.process_render_frame(render_frame, channel_ptrs)?;
+            for channel in capture_frame.iter_mut() {
+                channel.iter_mut().for_each(|sample| *sample = 0.0);
+            }
+            self.process_capture_frame(capture_frame, channel_ptrs)?;
+        }
+        Ok(())
+    }
+
+    /// Registers a call into the native module, or fails if the processor has been closed.
+    /// The order of the increment and the check pairs with `close()`, which sets the flag before
+    /// waiting for the count to drop to zero, so that no call slips through.
+    fn begin_native_call(&self) -> Result<NativeCall<'_, S::AtomicUsize>, Error> {
+        self.num_native_calls.fetch_add(1, Ordering::SeqCst);
+        let native_call = NativeCall(&self.num_native_calls);
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::Closed);
+        }
+        Ok(native_call)
+    }
+
+    /// Makes further calls fail with `Error::Closed`, waits for the ones in flight to return,
+    /// and releases the native module.
+    fn close(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        while self.num_native_calls.load(Ordering::SeqCst) > 0 {
+            thread::yield_now();
+        }
+        unsafe {
+            ffi::audio_processing_delete(self.inner);
+        }
+    }
+
+    /// Applies the stream parameters set from other threads since the last capture frame. A
+    /// config being staged right now is left for the next frame.
+    fn apply_stream_parameters(&self) {
+        let suspended =
+            self.pending_echo_control_suspended.swap(NO_PENDING_FLAG, Ordering::Relaxed);
+        if suspended != NO_PENDING_FLAG {
+            unsafe {
+                ffi::set_echo_control_suspended(self.inner, suspended != 0);
+            }
+        }
+        if let Some(mut pending_config) = self.pending_config.try_lock() {
+            if let Some(config) = pending_config.take() {
+                unsafe {
+                    ffi::set_config(self.inner, &config);
+                }
+            }
+        }
+        if self.pending_echo_control_reset.swap(false, Ordering::Relaxed) {
+            unsafe {
+                ffi::reset_echo_control(self.inner);
+            }
+        }
+        let delay_ms =
+            self.pending_stream_delay_ms.swap(NO_PENDING_STREAM_DELAY, Ordering::Relaxed);
+        if delay_ms != NO_PENDING_STREAM_DELAY {
+            unsafe {
+                ffi::set_stream_delay_ms(self.inner, delay_ms);
+            }
+        }
+        let muted = self.pending_output_will_be_muted.swap(NO_PENDING_FLAG, Ordering::Relaxed);
+        if muted != NO_PENDING_FLAG {
+            unsafe {
+                ffi::set_output_will_be_muted(self.inner, muted != 0);
+            }
+        }
+        let analog_level = self.pending_analog_level.swap(NO_ANALOG_LEVEL, Ordering::Relaxed);
+        if analog_level != NO_ANALOG_LEVEL {
+            unsafe {
+                ffi::set_stream_analog_level(self.inner, analog_level);
+            }
+        }
+        unsafe {
+            ffi::set_stream_key_pressed(
+                self.inner,
+                self.stream_key_pressed.load(Ordering::Relaxed),
+            );
+        }
+    }
+
+    /// Replaces the non-finite samples of `frame` if enabled in the config, and counts them.
+    fn sanitize(&self, frame: &mut [Vec<f32>]) {
+        if !self.sanitize_non_finite_samples.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut num_replaced = 0;
+        for sample in frame.iter_mut().flat_map(|channel| channel.iter_mut()) {
+            if !sample.is_finite() {
+                *sample = if sample.is_nan() { 0.0 } else { sample.signum() };
+                num_replaced += 1;
+            }
+        }
+        if num_replaced > 0 {
+            self.num_non_finite_samples.fetch_add(num_replaced, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the cached stats if no capture frame has been processed since they were taken,
+    /// so that polling from several places within the same frame stays cheap.
+    fn get_stats(&self) -> Stats {
+        let _native_call = match self.begin_native_call() {
+            Ok(native_call) => native_call,
+            Err(_) => return ffi::Stats::default().into(),
+        };
+        let generation = self.num_processed_capture_frames.load(Ordering::Acquire);
+        let mut cache = self.stats_cache.lock();
+        let mut stats = match cache.as_ref().filter(|cached| cached.generation == generation) {
+            Some(cached) => cached.stats.clone(),
+            None => {
+                let mut stats = ffi::Stats::default();
+                unsafe {
+                    ffi::get_stats(self.inner, &mut stats);
+                }
+                let stats: Stats = stats.into();
+                *cache = Some(CachedStats { generation, stats: stats.clone() });
+                stats
+            },
+        };
+        // Render frames update the count too, so it's never cached.
+        if self.sanitize_non_finite_samples.load(Ordering::Relaxed) {
+            stats.num_non_finite_samples =
+                Some(self.num_non_finite_samples.load(Ordering::Relaxed));
+        }
+        stats.voice_gate_open = self.voice_gate.lock().as_ref().map(|gate| gate.is_open());
+        stats
+    }
+
+    fn algorithmic_delay(&self) -> Result<Duration, Error> {
+        let (generation, config) = {
+            let config = self.config.lock();
+            (self.config_generation.load(Ordering::Relaxed), config.clone())
+        };
+        match *self.algorithmic_delay.lock() {
+            Some((cached_generation, delay)) if cached_generation == generation => {
+                return Ok(delay)
+            },
+            _ => {},
+        }
+        // Measured without holding a lock, so that other callers aren't held up. A result
+        // measured with a config that has been replaced since is returned, but not cached.
+        let delay = self.measure_delay(config)?;
+        let mut algorithmic_delay = self.algorithmic_delay.lock();
+        if self.config_generation.load(Ordering::Relaxed) == generation {
+            *algorithmic_delay = Some((generation, delay));
+        }
+        Ok(delay)
+    }
+
+    fn measure_algorithmic_delay(&self) -> Result<Duration, Error> {
+        self.measure_delay(self.config.lock().clone())
+    }
+
+    /// Measures the algorithmic delay with `config`, see `measure_algorithmic_delay()`.
+    fn measure_delay(&self, config: Option<Config>) -> Result<Duration, Error> {
+        let processor = Self::new(&self.init_config)?;
+        if let Some(config) = config {
+            // The post-stages would hide the noise, and add no delay anyway.
+            processor.set_config(Config {
+                voice_gate: None,
+                enable_comfort_noise: false,
+                ..config
+            });
+        }
+
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize;
+        let mut frame = vec![vec![0f32; frame_len]; self.num_capture_channels];
+        let mut channel_ptrs = ChannelPointers::with_capacity(self.num_capture_channels);
+        let mut noise = ComfortNoiseGenerator::with_noise_floor(DELAY_MEASUREMENT_NOISE_POWER);
+        let mut input = Vec::with_capacity(frame_len * NUM_DELAY_MEASUREMENT_FRAMES);
+        let mut output = Vec::with_capacity(frame_len * NUM_DELAY_MEASUREMENT_FRAMES);
+        for _ in 0..NUM_DELAY_MEASUREMENT_FRAMES {
+            noise.fill_frame(&mut frame);
+            input.extend_from_slice(&frame[0]);
+            processor.process_capture_frame(&mut frame, &mut channel_ptrs)?;
+            output.extend_from_slice(&frame[0]);
+        }
+
+        // The lag with the highest cross-correlation of the first channel, normalized by the
+        // number of overlapping samples so that longer lags aren't penalized.
+        let correlation = |lag: usize| -> f64 {
+            let sum: f64 =
+                input.iter().zip(&output[lag..]).map(|(&x, &y)| x as f64 * y as f64).sum();
+            sum / (output.len() - lag) as f64
+        };
+        let lag = (0..=frame_len)
+            .map(|lag| (lag, correlation(lag)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(0, |(lag, _)| lag);
+        // A frame is 10 ms.
+        Ok(Duration::from_micros(lag as u64 * 10_000 / frame_len as u64))
+    }
+
+    fn run_self_test(&self, min_erle_db: f32) -> Result<SelfTestReport, Error> {
+        let processor = Self::new(&self.init_config)?;
+        let config = self.config.lock().clone();
+        let stream_delay_ms = config.as_ref().and_then(|config| {
+            match (&config.echo_cancellation, &config.echo_control_mobile) {
+                (Some(echo_cancellation), _) => echo_cancellation.stream_delay_ms,
+                (None, Some(echo_control_mobile)) => echo_control_mobile.stream_delay_ms,
+                (None, None) => None,
+            }
+        });
+        if let Some(config) = config {
+            processor.set_config(Config {
+                voice_gate: None,
+                enable_comfort_noise: false,
+                ..config
+            });
+        }
+
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize;
+        let echo_delay =
+            stream_delay_ms.unwrap_or(SELF_TEST_ECHO_DELAY_MS).max(0) as usize * frame_len / 10;
+        let mut render_frame = vec![vec![0f32; frame_len]; self.num_render_channels];
+        let mut capture_frame = vec![vec![0f32; frame_len]; self.num_capture_channels];
+        let mut channel_ptrs =
+            ChannelPointers::with_capacity(self.num_capture_channels.max(self.num_render_channels));
+        let mut far_end = signal::SpeechShapedNoise::new(SELF_TEST_FAR_END_AMPLITUDE, 0);
+        // The far end on its way from the speaker to the microphone.
+        let mut echo_path: VecDeque<f32> = vec![0.0; echo_delay].into();
+        let mut capture = Vec::with_capacity(frame_len * NUM_SELF_TEST_MEASURED_FRAMES);
+        let mut processed = Vec::with_capacity(frame_len * NUM_SELF_TEST_MEASURED_FRAMES);
+        for frame_index in 0..NUM_SELF_TEST_FRAMES {
+            for i in 0..frame_len {
+                let sample = far_end.next_sample();
+                render_frame.iter_mut().for_each(|channel| channel[i] = sample);
+                echo_path.push_back(sample);
+                let echo = echo_path.pop_front().unwrap() * SELF_TEST_ECHO_GAIN;
+                capture_frame.iter_mut().for_each(|channel| channel[i] = echo);
+            }
+            let measured = frame_index >= NUM_SELF_TEST_FRAMES - NUM_SELF_TEST_MEASURED_FRAMES;
+            processor.process_render_frame(&mut render_frame, &mut channel_ptrs)?;
+            if measured {
+                capture.extend_from_slice(&capture_frame[0]);
+            }
+            processor.process_capture_frame(&mut capture_frame, &mut channel_ptrs)?;
+            if measured {
+                processed.extend_from_slice(&capture_frame[0]);
+            }
+        }
+
+        let erle_db = metrics::erle_db(&capture, &processed);
+        Ok(SelfTestReport { erle_db, passed: erle_db >= min_erle_db })
+    }
+
+    fn cpu_load(&self) -> CpuLoad {
+        CpuLoad { capture: self.capture_load.load(), render: self.render_load.load() }
+    }
+
+    fn inspect(&self, memory_usage: MemoryUsage) -> Inspection {
+        Inspection {
+            init_config: self.init_config,
+            config: self.config.lock().clone(),
+            num_processed_capture_frames: self.num_processed_capture_frames.load(Ordering::Acquire),
+            num_processed_render_frames: self.num_processed_render_frames.load(Ordering::Relaxed),
+            closed: self.closed.load(Ordering::SeqCst),
+            stats: self.get_stats(),
+            memory_usage,
+        }
+    }
+
+    fn set_config(&self, config: Config) {
+        self.use_external_noise_suppressor.store(
+            config.noise_suppression_backend == NoiseSuppressionBackend::External,
+            Ordering::Relaxed,
+        );
+        self.pending_stream_delay_ms.store(NO_PENDING_STREAM_DELAY, Ordering::Relaxed);
+        self.sanitize_non_finite_samples
+            .store(config.sanitize_non_finite_samples, Ordering::Relaxed);
+        self.deterministic.store(config.deterministic, Ordering::Relaxed);
+        self.enable_comfort_noise.store(config.enable_comfort_noise, Ordering::Relaxed);
+        self.use_adaptive_analog_gain.store(
+            config.gain_control.as_ref().map(|gain_control| gain_control.mode)
+                == Some(GainControlMode::AdaptiveAnalog),
+            Ordering::Relaxed,
+        );
+        {
+            let mut high_pass_filter = self.high_pass_filter.lock();
+            *high_pass_filter = match (high_pass_filter.take(), &config.high_pass_filter) {
+                (Some(mut filter), Some(filter_config)) => {
+                    filter.set_config(filter_config);
+                    Some(filter)
+                },
+                (None, Some(filter_config)) => {
+                    Some(HighPassFilterState::new(filter_config, self.num_capture_channels))
+                },
+                (_, None) => None,
+            };
+        }
+        {
+            let mut voice_gate = self.voice_gate.lock();
+            *voice_gate = match (voice_gate.take(), config.voice_gate.clone()) {
+                (Some(mut gate), Some(gate_config)) => {
+                    gate.set_config(gate_config);
+                    Some(gate)
+                },
+                (None, Some(gate_config)) => Some(VoiceGateState::new(gate_config)),
+                (_, None) => None,
+            };
+        }
+        {
+            let mut stored_config = self.config.lock();
+            *self.pending_config.lock() = Some(config.clone().into());
+            let previous = stored_config.replace(config);
+            self.config_generation.fetch_add(1, Ordering::Relaxed);
+            self.config_audit_log.lock().record(
+                ConfigChangeSource::SetConfig,
+                previous.as_ref(),
+                stored_config.as_ref().unwrap(),
+            );
+        }
+        // Enabling or disabling components changes which stats are available.
+        self.stats_cache.lock().take();
+    }
+
+    fn set_noise_suppression_level(&self, level: NoiseSuppressionLevel) {
+        let mut config = self.config.lock();
+        let previous = self.config_audit_snapshot(&config);
+        let noise_suppression = match config.as_mut() {
+            Some(Config {
+                noise_suppression: Some(noise_suppression),
+                noise_suppression_backend: NoiseSuppressionBackend::Builtin,
+                ..
+            }) => noise_suppression,
+            _ => return,
+        };
+        noise_suppression.suppression_level = level;
+        self.audit_config_change(ConfigChangeSource::SetNoiseSuppressionLevel, previous, &config);
+        if self.restage_config(&config) {
+            return;
+        }
+        if let Ok(_native_call) = self.begin_native_call() {
+            let _stream_parameters = self.stream_parameters.lock();
+            unsafe {
+                ffi::set_noise_suppression_level(self.inner, level.into());
+            }
+        }
+    }
+
+    fn set_agc1_target_level_dbfs(&self, level: u8) -> Result<(), Error> {
+        if level > MAX_AGC1_TARGET_LEVEL_DBFS {
+            return Err(Error::BadParameter);
+        }
+        let mut config = self.config.lock();
+        let previous = self.config_audit_snapshot(&config);
+        let gain_control = match config.as_mut().and_then(|config| config.gain_control.as_mut()) {
+            Some(gain_control) => gain_control,
+            None => return Ok(()),
+        };
+        gain_control.target_level_dbfs = level as i32;
+        self.audit_config_change(ConfigChangeSource::SetAgc1TargetLevelDbfs, previous, &config);
+        if self.restage_config(&config) {
+            return Ok(());
+        }
+        if let Ok(_native_call) = self.begin_native_call() {
+            let _stream_parameters = self.stream_parameters.lock();
+            let code = unsafe { ffi::set_gain_control_target_level_dbfs(self.inner, level as i32) };
+            if !unsafe { ffi::is_success(code) } {
+                return Err(Error::from_code(code));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_agc1_enable_limiter(&self, enable: bool) {
+        let mut config = self.config.lock();
+        let previous = self.config_audit_snapshot(&config);
+        let gain_control = match config.as_mut().and_then(|config| config.gain_control.as_mut()) {
+            Some(gain_control) => gain_control,
+            None => return,
+        };
+        gain_control.enable_limiter = enable;
+        self.audit_config_change(ConfigChangeSource::SetAgc1EnableLimiter, previous, &config);
+        if self.restage_config(&config) {
+            return;
+        }
+        if let Ok(_native_call) = self.begin_native_call() {
+            let _stream_parameters = self.stream_parameters.lock();
+            unsafe {
+                ffi::set_gain_control_enable_limiter(self.inner, enable);
+            }
+        }
+    }
+
+    /// Replaces the config staged by `set_config()` with the changed `config`, if it hasn't been
+    /// applied yet, as applying the change to the native module would be overwritten by it.
+    /// Returns whether it did. Call it with the `config` lock held across the change.
+    fn restage_config(&self, config: &Option<Config>) -> bool {
+        let mut pending_config = self.pending_config.lock();
+        match (pending_config.as_mut(), config) {
+            (Some(pending_config), Some(config)) => {
+                *pending_config = config.clone().into();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns a copy of `config` to record a runtime change against, if the audit log is
+    /// enabled. Call it with the `config` lock held across the change.
+    fn config_audit_snapshot(&self, config: &Option<Config>) -> Option<Option<Config>> {
+        self.config_audit_log.lock().is_enabled().then(|| config.clone())
+    }
+
+    /// Records the change of `config` from the `previous` snapshot, if any.
+    fn audit_config_change(
+        &self,
+        source: ConfigChangeSource,
+        previous: Option<Option<Config>>,
+        config: &Option<Config>,
+    ) {
+        if let (Some(previous), Some(config)) = (previous, config) {
+            self.config_audit_log.lock().record(source, previous.as_ref(), config);
+        }
+    }
+
+    /// Records a call that doesn't change the config, if the audit log is enabled. `source` is
+    /// only built then.
+    fn audit_call<F: FnOnce() -> ConfigChangeSource>(&self, source: F) {
+        if !self.config_audit_log_enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let config = self.config.lock();
+        let config = config.clone().unwrap_or_default();
+        self.config_audit_log.lock().record_call(source(), &config);
+    }
+
+    fn set_config_audit_log_capacity(&self, capacity: usize) {
+        let _config = self.config.lock();
+        self.config_audit_log.lock().set_capacity(capacity);
+        self.config_audit_log_enabled.store(capacity > 0, Ordering::Relaxed);
+    }
+
+    fn config_audit_log(&self) -> Vec<ConfigChange> {
+        let _config = self.config.lock();
+        self.config_audit_log.lock().entries()
+    }
+
+    fn reset_echo_control(&self, source: ConfigChangeSource) {
+        self.audit_call(|| source);
+        self.pending_echo_control_reset.store(true, Ordering::Relaxed);
+        // The echo metrics start over.
+        self.stats_cache.lock().take();
+    }
+
+    fn set_echo_control_suspended(&self, suspended: bool) {
+        self.audit_call(|| ConfigChangeSource::SetHeadphonesConnected(suspended));
+        self.echo_control_suspended.store(suspended, Ordering::Relaxed);
+        self.pending_echo_control_suspended.store(suspended as u8, Ordering::Relaxed);
+        self.stats_cache.lock().take();
+    }
+
+    fn set_noise_suppressor(&self, backend: Box<dyn NoiseSuppressorBackend>) {
+        *self.external_noise_suppressor.lock() = Some(backend);
+    }
+
+    fn set_mic_volume_backend(&self, backend: Box<dyn MicVolumeBackend>) {
+        *self.mic_volume_backend.lock() = Some(backend);
+    }
+
+    fn poll_mic_volume(&self) {
+        let mut backend = self.mic_volume_backend.lock();
+        let backend = match backend.as_mut() {
+            Some(backend) if self.use_adaptive_analog_gain.load(Ordering::Relaxed) => backend,
+            _ => return,
+        };
+        let mut analog_level = volume_to_analog_level(backend.volume());
+        let recommended_level =
+            self.recommended_analog_level.swap(NO_ANALOG_LEVEL, Ordering::Relaxed);
+        if recommended_level != NO_ANALOG_LEVEL && recommended_level != analog_level {
+            backend.set_volume(analog_level_to_volume(recommended_level));
+            analog_level = recommended_level;
+        }
+        self.pending_analog_level.store(analog_level, Ordering::Relaxed);
+    }
+
+    fn set_echo_reference_weights(&self, weights: Option<Vec<f32>>) -> Result<(), Error> {
+        let echo_reference = match weights {
+            Some(weights) => {
+                if weights.len() != self.num_render_channels
+                    || !weights.iter().all(|weight| weight.is_finite())
+                {
+                    return Err(Error::BadParameter);
+                }
+                Some(EchoReference::new(weights))
+            },
+            None => None,
+        };
+        self.audit_call(|| {
+            let weights = echo_reference.as_ref().map(|reference| reference.weights().to_vec());
+            ConfigChangeSource::SetEchoReferenceWeights(weights)
+        });
+        *self.echo_reference.lock() = echo_reference;
+        Ok(())
+    }
+
+    fn set_output_will_be_muted(&self, muted: bool) {
+        self.pending_output_will_be_muted.store(muted as u8, Ordering::Relaxed);
+        self.output_muted.store(muted, Ordering::Relaxed);
+    }
+
+    fn set_stream_key_pressed(&self, pressed: bool) {
+        self.stream_key_pressed.store(pressed, Ordering::Relaxed);
+    }
+
+    fn set_stream_delay_ms(&self, delay_ms: i32) {
+        self.audit_call(|| ConfigChangeSource::SetStreamDelayMs(delay_ms));
+        self.pending_stream_delay_ms.store(delay_ms, Ordering::Relaxed);
+    }
+}
+
+impl<S: SyncPolicy> Drop for AudioProcessing<S> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// ffi::AudioProcessing provides thread safety with a few exceptions around the concurrent usage
+// of its stream parameter setters e.g. `set_stream_delay_ms()`. Those are only ever called on
+// the capture path, with the values set from other threads passed through atomics, and so is
+// `set_config()`, which writes them too. The remaining setters run under `stream_parameters`.
+// Every call goes through `begin_native_call()`, so `close()` can release the module while clones
+// are still around. The `Local` policy has none of those locks and atomics, so a module using it
+// stays on the thread that created it.
+unsafe impl Sync for AudioProcessing<Shared> {}
+unsafe impl Send for AudioProcessing<Shared> {}
+
+#[cfg(all(test, feature = "rt-safe"))]
+#[global_allocator]
+static ALLOCATOR: AllocDisabler = AllocDisabler;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{collection::vec, prelude::*};
+    use std::{sync::atomic::AtomicUsize, thread, time::Duration};
+
+    #[test]
+    fn test_create_failure() {
+        let config =
+            InitializationConfig { num_capture_channels: 0, ..InitializationConfig::default() };
+        assert!(matches!(Processor::new(&config), Err(Error::BadNumChannels)));
+    }
+
+    #[test]
+    fn test_create_drop() {
+        let config = InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let _p = Processor::new(&config).unwrap();
+    }
+
+    #[test]
+    fn test_frame_size_mismatch() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let num_samples = NUM_SAMPLES_PER_FRAME as usize;
+
+        let mut frame = vec![0f32; num_samples];
+        assert_eq!(
+            Err(Error::ChannelMismatch { stream: StreamKind::Capture, expected: 2, got: 1 }),
+            ap.process_capture_frame(&mut frame)
+        );
+        assert_eq!(
+            Err(Error::FrameSizeMismatch { expected: num_samples * 2, got: num_samples - 1 }),
+            ap.process_capture_frame(&mut frame[1..])
+        );
+        assert_eq!(Ok(()), ap.process_render_frame(&mut frame));
+        frame.push(0.0);
+        assert_eq!(
+            Err(Error::FrameSizeMismatch { expected: num_samples, got: num_samples + 1 }),
+            ap.process_render_frame(&mut frame)
+        );
+
+        let mut frame = vec![vec![0f32; num_samples]];
+        assert_eq!(
+            Err(Error::ChannelMismatch { stream: StreamKind::Capture, expected: 2, got: 1 }),
+            ap.process_capture_frame_noninterleaved(&mut frame)
+        );
+        frame.push(vec![0f32; num_samples - 1]);
+        assert_eq!(
+            Err(Error::FrameSizeMismatch { expected: num_samples * 2, got: num_samples * 2 - 1 }),
+            ap.process_capture_frame_noninterleaved(&mut frame)
+        );
+    }
+
+    #[test]
+    fn test_multiple_frames() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let (render_frame, capture_frame) = sample_stereo_frames();
+        let processor = || {
+            let mut ap = Processor::new(&config).unwrap();
+            ap.set_config(Config {
+                gain_control: Some(GainControl {
+                    mode: GainControlMode::FixedDigital,
+                    target_level_dbfs: 3,
+                    compression_gain_db: 9,
+                    enable_limiter: true,
+                }),
+                noise_suppression: Some(NoiseSuppression {
+                    suppression_level: NoiseSuppressionLevel::High,
+                }),
+                deterministic: true,
+                ..Config::default()
+            });
+            ap
+        };
+
+        let mut ap = processor();
+        let mut expected = Vec::new();
+        for _ in 0..3 {
+            let mut capture_frame = capture_frame.clone();
+            ap.process_capture_frame(&mut capture_frame).unwrap();
+            expected.extend(capture_frame);
+        }
+
+        // 30 ms at once.
+        let mut ap = processor();
+        let mut render_buffer = render_frame.repeat(3);
+        let mut capture_buffer = capture_frame.repeat(3);
+        ap.process_render_frame(&mut render_buffer).unwrap();
+        ap.process_capture_frame(&mut capture_buffer).unwrap();
+        assert_eq!(render_frame.repeat(3), render_buffer);
+        assert_eq!(expected, capture_buffer);
+
+        assert_eq!(
+            Err(Error::FrameSizeMismatch { expected: capture_frame.len(), got: 0 }),
+            ap.process_capture_frame(&mut [])
+        );
+    }
+
+    #[test]
+    fn test_sanitize_non_finite_samples() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let (mut render_frame, mut capture_frame) = sample_stereo_frames();
+        assert_eq!(None, ap.get_stats().num_non_finite_samples);
+
+        ap.set_config(Config { sanitize_non_finite_samples: true, ..Config::default() });
+        render_frame[0] = f32::NAN;
+        capture_frame[1] = f32::INFINITY;
+        capture_frame[2] = f32::NEG_INFINITY;
+        ap.process_render_frame(&mut render_frame).unwrap();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        assert!(render_frame.iter().chain(&capture_frame).all(|sample| sample.is_finite()));
+        assert_eq!(Some(3), ap.get_stats().num_non_finite_samples);
+    }
+
+    #[test]
+    fn test_stream_parameters_applied_on_capture() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let (_, mut capture_frame) = sample_stereo_frames();
+
+        let setter_ap = ap.clone();
+        thread::spawn(move || {
+            setter_ap.set_output_will_be_muted(true);
+            setter_ap.set_stream_key_pressed(true);
+            setter_ap.set_stream_delay_ms(40);
+        })
+        .join()
+        .unwrap();
+        let inner = &ap.inner;
+        assert_eq!(1, inner.pending_output_will_be_muted.load(Ordering::Relaxed));
+        assert_eq!(40, inner.pending_stream_delay_ms.load(Ordering::Relaxed));
+
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        let inner = &ap.inner;
+        assert_eq!(NO_PENDING_FLAG, inner.pending_output_will_be_muted.load(Ordering::Relaxed));
+        assert_eq!(NO_PENDING_STREAM_DELAY, inner.pending_stream_delay_ms.load(Ordering::Relaxed));
+        // Key presses stay on until cleared.
+        assert!(inner.stream_key_pressed.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_set_config_staged_for_capture() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let (_, mut capture_frame) = sample_stereo_frames();
+
+        // Doesn't wait for the capture path, which holds `stream_parameters` while processing.
+        let stream_parameters = ap.inner.stream_parameters.lock().unwrap();
+        let mut setter_ap = ap.clone();
+        thread::spawn(move || {
+            setter_ap.set_config(Config { enable_high_pass_filter: true, ..Config::default() });
+            setter_ap.set_headphones_connected(true);
+        })
+        .join()
+        .unwrap();
+        drop(stream_parameters);
+        assert!(ap.inner.pending_config.lock().unwrap().is_some());
+
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        let inner = &ap.inner;
+        assert!(inner.pending_config.lock().unwrap().is_none());
+        assert_eq!(NO_PENDING_FLAG, inner.pending_echo_control_suspended.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_config_audit_log_calls() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        ap.set_headphones_connected(true);
+        ap.enable_config_audit_log(10);
+        ap.set_headphones_connected(false);
+        ap.notify_playout_device_changed();
+        ap.set_stream_delay_ms(40);
+        ap.set_echo_reference_weights(Some(vec![1.0, 0.0])).unwrap();
+        ap.set_capture_channel_map(Some(vec![1, 0])).unwrap();
+        assert!(ap.set_render_channel_map(Some(vec![0, 0])).is_err());
+
+        let sources: Vec<_> = ap.config_audit_log().into_iter().map(|entry| entry.source).collect();
+        assert_eq!(
+            vec![
+                ConfigChangeSource::SetHeadphonesConnected(false),
+                ConfigChangeSource::NotifyPlayoutDeviceChanged,
+                ConfigChangeSource::SetStreamDelayMs(40),
+                ConfigChangeSource::SetEchoReferenceWeights(Some(vec![1.0, 0.0])),
+                ConfigChangeSource::SetCaptureChannelMap(Some(vec![1, 0])),
+            ],
+            sources
+        );
+    }
+
+    #[test]
+    fn test_switch_echo_control() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let mut render_ap = ap.clone();
+        let echo_cancellation = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                stream_delay_ms: Some(20),
+                enable_delay_agnostic: false,
+                enable_extended_filter: false,
+            }),
+            ..Config::default()
+        };
+        let echo_control_mobile = Config {
+            echo_control_mobile: Some(EchoControlMobile {
+                routing_mode: EchoControlMobileRoutingMode::Speakerphone,
+                enable_comfort_noise: false,
+                stream_delay_ms: Some(20),
+            }),
+            ..Config::default()
+        };
+
+        // Back and forth, with the clone processing the render stream in between.
+        for config in [&echo_cancellation, &echo_control_mobile, &echo_cancellation] {
+            ap.set_config(config.clone());
+            for _ in 0..10 {
+                let (mut render_frame, mut capture_frame) = sample_stereo_frames();
+                render_ap.process_render_frame(&mut render_frame).unwrap();
+                ap.process_capture_frame(&mut capture_frame).unwrap();
+            }
+            // AECM reports no echo.
+            assert_eq!(config.echo_cancellation.is_some(), ap.get_stats().has_echo.is_some());
+        }
+
+        // AEC takes precedence when both are set.
+        ap.set_config(Config {
+            echo_control_mobile: echo_control_mobile.echo_control_mobile,
+            ..echo_cancellation
+        });
+        let (_, mut capture_frame) = sample_stereo_frames();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        assert!(ap.get_stats().has_echo.is_some());
+    }
+
+    #[test]
+    fn test_notify_device_changed() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let config = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                stream_delay_ms: Some(20),
+                enable_delay_agnostic: false,
+                enable_extended_filter: false,
+            }),
+            ..Config::default()
+        };
+        ap.set_config(config.clone());
+        for _ in 0..10 {
+            let (mut render_frame, mut capture_frame) = sample_stereo_frames();
+            ap.process_render_frame(&mut render_frame).unwrap();
+            ap.process_capture_frame(&mut capture_frame).unwrap();
+        }
+
+        // The echo cancellation keeps running, with the same config.
+        ap.notify_playout_device_changed();
+        ap.notify_capture_device_changed();
+        let (mut render_frame, mut capture_frame) = sample_stereo_frames();
+        ap.process_render_frame(&mut render_frame).unwrap();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        assert!(ap.get_stats().has_echo.is_some());
+        assert_eq!(Some(config), ap.inspect().config);
+    }
+
+    #[test]
+    fn test_set_headphones_connected() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let config = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                stream_delay_ms: Some(20),
+                enable_delay_agnostic: false,
+                enable_extended_filter: false,
+            }),
+            ..Config::default()
+        };
+        ap.set_config(config.clone());
+        let process = |ap: &mut Processor| {
+            let (mut render_frame, mut capture_frame) = sample_stereo_frames();
+            ap.process_render_frame(&mut render_frame).unwrap();
+            ap.process_capture_frame(&mut capture_frame).unwrap();
+            ap.get_stats().has_echo
+        };
+        assert!(process(&mut ap).is_some());
+
+        // Suspended, also across config changes and independent clones.
+        ap.set_headphones_connected(true);
+        assert!(process(&mut ap).is_none());
+        ap.set_config(config.clone());
+        assert!(process(&mut ap).is_none());
+        assert!(process(&mut ap.try_clone_independent().unwrap()).is_none());
+
+        ap.set_headphones_connected(false);
+        assert!(process(&mut ap).is_some());
+        assert_eq!(Some(config), ap.inspect().config);
     }
 
-    /// Reverts the `deinterleave` operation.
-    fn interleave<T: AsRef<[f32]>>(src: &[T], dst: &mut [f32]) {
-        let num_channels = src.len();
-        let num_samples = src[0].as_ref().len();
-        assert_eq!(dst.len(), num_channels * num_samples);
-        for channel_index in 0..num_channels {
-            for sample_index in 0..num_samples {
-                dst[num_channels * sample_index + channel_index] =
-                    src[channel_index].as_ref()[sample_index];
-            }
-        }
+    #[test]
+    fn test_set_echo_reference_weights() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        assert!(matches!(ap.set_echo_reference_weights(Some(vec![1.0])), Err(Error::BadParameter)));
+        assert!(matches!(
+            ap.set_echo_reference_weights(Some(vec![1.0, f32::NAN])),
+            Err(Error::BadParameter)
+        ));
+
+        // Only the reference is mixed, the render frame stays as it was.
+        ap.set_echo_reference_weights(Some(vec![1.0, 0.0])).unwrap();
+        let (mut render_frame, _) = sample_stereo_frames();
+        let expected = render_frame.clone();
+        ap.process_render_frame(&mut render_frame).unwrap();
+        assert_eq!(expected, render_frame);
+        ap.set_echo_reference_weights(None).unwrap();
     }
-}
 
-/// Minimal wrapper for safe and synchronized ffi.
-struct AudioProcessing {
-    inner: *mut ffi::AudioProcessing,
-}
+    #[test]
+    fn test_set_noise_suppression_level() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        ap.set_noise_suppression_level(NoiseSuppressionLevel::Low);
+        assert_eq!(None, ap.inspect().config);
 
-impl AudioProcessing {
-    fn new(config: &ffi::InitializationConfig) -> Result<Self, Error> {
-        let mut code = 0;
-        let inner = unsafe { ffi::audio_processing_create(config, &mut code) };
-        if !inner.is_null() {
-            Ok(Self { inner })
-        } else {
-            Err(Error { code })
-        }
+        let config = Config {
+            noise_suppression: Some(NoiseSuppression {
+                suppression_level: NoiseSuppressionLevel::High,
+            }),
+            ..Config::default()
+        };
+        ap.set_config(config.clone());
+        ap.set_noise_suppression_level(NoiseSuppressionLevel::Low);
+        let (_, mut capture_frame) = sample_stereo_frames();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        let expected = Config {
+            noise_suppression: Some(NoiseSuppression {
+                suppression_level: NoiseSuppressionLevel::Low,
+            }),
+            ..config
+        };
+        assert_eq!(Some(expected), ap.inspect().config);
+
+        // Not enabled by it.
+        ap.set_config(Config::default());
+        ap.set_noise_suppression_level(NoiseSuppressionLevel::High);
+        assert_eq!(Some(Config::default()), ap.inspect().config);
     }
 
-    fn process_capture_frame(&self, frame: &mut Vec<Vec<f32>>) -> Result<(), Error> {
-        let mut frame_ptr = frame.iter_mut().map(|v| v.as_mut_ptr()).collect::<Vec<*mut f32>>();
-        unsafe {
-            let code = ffi::process_capture_frame(self.inner, frame_ptr.as_mut_ptr());
-            if ffi::is_success(code) {
-                Ok(())
-            } else {
-                Err(Error { code })
-            }
-        }
+    #[test]
+    fn test_set_agc1_parameters() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let gain_control = GainControl {
+            mode: GainControlMode::FixedDigital,
+            target_level_dbfs: 3,
+            compression_gain_db: 9,
+            enable_limiter: true,
+        };
+        ap.set_config(Config { gain_control: Some(gain_control.clone()), ..Config::default() });
+
+        ap.set_agc1_target_level_dbfs(10).unwrap();
+        ap.set_agc1_enable_limiter(false);
+        assert_eq!(Err(Error::BadParameter), ap.set_agc1_target_level_dbfs(32));
+        let (_, mut capture_frame) = sample_stereo_frames();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        let expected = GainControl { target_level_dbfs: 10, enable_limiter: false, ..gain_control };
+        assert_eq!(Some(expected), ap.inspect().config.and_then(|config| config.gain_control));
+
+        // Not enabled by them.
+        ap.set_config(Config::default());
+        ap.set_agc1_target_level_dbfs(5).unwrap();
+        ap.set_agc1_enable_limiter(true);
+        assert_eq!(Some(Config::default()), ap.inspect().config);
     }
 
-    fn process_render_frame(&self, frame: &mut Vec<Vec<f32>>) -> Result<(), Error> {
-        let mut frame_ptr = frame.iter_mut().map(|v| v.as_mut_ptr()).collect::<Vec<*mut f32>>();
-        unsafe {
-            let code = ffi::process_render_frame(self.inner, frame_ptr.as_mut_ptr());
-            if ffi::is_success(code) {
-                Ok(())
-            } else {
-                Err(Error { code })
+    #[test]
+    fn test_close() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        ap.set_config(Config {
+            voice_detection: Some(VoiceDetection {
+                detection_likelihood: VoiceDetectionLikelihood::Moderate,
+            }),
+            ..Config::default()
+        });
+
+        let mut capture_ap = ap.clone();
+        let capture_thread = thread::spawn(move || {
+            let (_, mut capture_frame) = sample_stereo_frames();
+            loop {
+                match capture_ap.process_capture_frame(&mut capture_frame) {
+                    Ok(()) => {},
+                    Err(error) => return error,
+                }
             }
-        }
+        });
+        thread::sleep(Duration::from_millis(50));
+        ap.close();
+        assert_eq!(Error::Closed, capture_thread.join().unwrap());
+
+        let (mut render_frame, mut capture_frame) = sample_stereo_frames();
+        assert_eq!(Err(Error::Closed), ap.process_render_frame(&mut render_frame));
+        assert_eq!(Err(Error::Closed), ap.process_capture_frame(&mut capture_frame));
+        assert_eq!(None, ap.get_stats().has_voice);
+        ap.set_config(Config::default());
+        ap.close();
+        let independent = ap.try_clone_independent().unwrap();
+        assert_eq!(Some(Config::default()), *independent.inner.config.lock().unwrap());
     }
 
-    fn get_stats(&self) -> Stats {
-        unsafe { ffi::get_stats(self.inner).into() }
+    #[test]
+    fn test_try_clone_independent() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        ap.set_config(Config {
+            voice_detection: Some(VoiceDetection {
+                detection_likelihood: VoiceDetectionLikelihood::Moderate,
+            }),
+            sanitize_non_finite_samples: true,
+            ..Config::default()
+        });
+        ap.set_echo_reference_weights(Some(vec![1.0, 0.5])).unwrap();
+        let mut independent = ap.try_clone_independent().unwrap();
+        let weights = |ap: &Processor| {
+            ap.inner.echo_reference.lock().unwrap().as_ref().map(|r| r.weights().to_vec())
+        };
+        assert_eq!(Some(vec![1.0, 0.5]), weights(&independent));
+
+        let (_, mut capture_frame) = sample_stereo_frames();
+        capture_frame[0] = f32::NAN;
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        assert_eq!(Some(1), ap.get_stats().num_non_finite_samples);
+        assert_eq!(Some(0), independent.get_stats().num_non_finite_samples);
+
+        // The config isn't shared either.
+        ap.set_config(Config::default());
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        independent.process_capture_frame(&mut capture_frame).unwrap();
+        assert!(ap.get_stats().has_voice.is_none());
+        assert!(independent.get_stats().has_voice.is_some());
     }
 
-    fn set_config(&self, config: Config) {
-        unsafe {
-            ffi::set_config(self.inner, &config.into());
-        }
+    #[test]
+    fn test_measure_algorithmic_delay() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        // Nothing enabled passes the audio straight through.
+        ap.set_config(Config::default());
+        assert_eq!(Duration::ZERO, ap.measure_algorithmic_delay().unwrap());
+        assert_eq!(Duration::ZERO, ap.algorithmic_delay().unwrap());
+        let cached = |ap: &Processor| *ap.inner.algorithmic_delay.lock().unwrap();
+        assert_eq!(Some((1, Duration::ZERO)), cached(&ap));
+        // Cached per config.
+        ap.set_config(Config::default());
+        assert_eq!(Some((1, Duration::ZERO)), cached(&ap));
+        assert_eq!(Duration::ZERO, ap.algorithmic_delay().unwrap());
+        assert_eq!(Some((2, Duration::ZERO)), cached(&ap));
+        // The processor itself is left untouched.
+        assert_eq!(0, ap.inspect().num_processed_capture_frames);
     }
 
-    fn set_output_will_be_muted(&self, muted: bool) {
-        unsafe {
-            ffi::set_output_will_be_muted(self.inner, muted);
-        }
+    #[test]
+    #[cfg_attr(feature = "mock", ignore)]
+    fn test_run_self_test_native() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        // Without echo cancellation, the echo goes through as is.
+        ap.set_config(Config::default());
+        let report = ap.run_self_test(6.0).unwrap();
+        assert!(!report.passed, "{:?}", report);
+
+        ap.set_config(Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                stream_delay_ms: Some(30),
+                enable_delay_agnostic: false,
+                enable_extended_filter: false,
+            }),
+            ..Config::default()
+        });
+        let report = ap.run_self_test(6.0).unwrap();
+        assert!(report.passed, "{:?}", report);
     }
 
-    fn set_stream_key_pressed(&self, pressed: bool) {
-        unsafe {
-            ffi::set_stream_key_pressed(self.inner, pressed);
-        }
+    // The mock doesn't cancel anything, see `test_run_self_test_native()` for the real module.
+    #[test]
+    #[cfg_attr(not(feature = "mock"), ignore)]
+    fn test_run_self_test() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        ap.set_config(Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                stream_delay_ms: Some(30),
+                enable_delay_agnostic: false,
+                enable_extended_filter: false,
+            }),
+            ..Config::default()
+        });
+        // The mock doesn't cancel anything.
+        let report = ap.run_self_test(10.0).unwrap();
+        assert!(report.erle_db.abs() < 0.1, "{:?}", report);
+        assert!(!report.passed);
+        assert!(ap.run_self_test(-1.0).unwrap().passed);
+        // The processor itself is left untouched.
+        assert_eq!(0, ap.inspect().num_processed_capture_frames);
     }
-}
 
-impl Drop for AudioProcessing {
-    fn drop(&mut self) {
-        unsafe {
-            ffi::audio_processing_delete(self.inner);
-        }
+    #[test]
+    fn test_deterministic() {
+        let init_config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let config = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::High,
+                stream_delay_ms: None,
+                enable_delay_agnostic: true,
+                enable_extended_filter: true,
+            }),
+            noise_suppression: Some(NoiseSuppression {
+                suppression_level: NoiseSuppressionLevel::High,
+            }),
+            deterministic: true,
+            ..Config::default()
+        };
+        let (render_frame, capture_frame) = sample_stereo_frames();
+        // Decays into denormals.
+        let frames: Vec<(Vec<f32>, Vec<f32>)> = (0..100)
+            .map(|i| {
+                let scale = 0.4f32.powi(i);
+                let scaled = |frame: &[f32]| frame.iter().map(|s| s * scale).collect();
+                (scaled(&render_frame), scaled(&capture_frame))
+            })
+            .collect();
+        let process = move |flush_denormals: bool| {
+            let _denormal_guard = flush_denormals.then(DenormalGuard::new);
+            let mut ap = Processor::new(&init_config).unwrap();
+            ap.set_config(config.clone());
+            let mut output = Vec::new();
+            for (render_frame, capture_frame) in &frames {
+                let mut render_frame = render_frame.clone();
+                let mut capture_frame = capture_frame.clone();
+                ap.process_render_frame(&mut render_frame).unwrap();
+                ap.process_capture_frame(&mut capture_frame).unwrap();
+                output.extend(capture_frame.iter().map(|sample| sample.to_bits()));
+            }
+            output
+        };
+        let flushing = thread::spawn({
+            let process = process.clone();
+            move || process(true)
+        });
+        assert_eq!(process(false), flushing.join().unwrap());
     }
-}
 
-// ffi::AudioProcessing provides thread safety with a few exceptions around
-// the concurrent usage of its getters and setters e.g. `set_stream_delay_ms()`.
-unsafe impl Sync for AudioProcessing {}
-unsafe impl Send for AudioProcessing {}
+    #[test]
+    fn test_deterministic_all_components() {
+        use signal::{Signal, WhiteNoise};
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::{thread, time::Duration};
+        let init_config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let config = Config {
+            gain_control: Some(GainControl {
+                mode: GainControlMode::AdaptiveDigital,
+                target_level_dbfs: 3,
+                compression_gain_db: 9,
+                enable_limiter: true,
+            }),
+            noise_suppression: Some(NoiseSuppression {
+                suppression_level: NoiseSuppressionLevel::High,
+            }),
+            voice_detection: Some(VoiceDetection {
+                detection_likelihood: VoiceDetectionLikelihood::High,
+            }),
+            enable_transient_suppressor: true,
+            enable_high_pass_filter: true,
+            deterministic: true,
+            ..Config::default()
+        };
+        // The two echo cancellers can't run together.
+        let configs = [
+            Config {
+                echo_cancellation: Some(EchoCancellation {
+                    suppression_level: EchoCancellationSuppressionLevel::High,
+                    stream_delay_ms: None,
+                    enable_delay_agnostic: true,
+                    enable_extended_filter: true,
+                }),
+                ..config.clone()
+            },
+            Config {
+                echo_control_mobile: Some(EchoControlMobile {
+                    routing_mode: EchoControlMobileRoutingMode::Speakerphone,
+                    enable_comfort_noise: true,
+                    stream_delay_ms: Some(20),
+                }),
+                ..config
+            },
+        ];
+        for config in &configs {
+            let process = || {
+                let mut ap = Processor::new(&init_config).unwrap();
+                ap.set_config(config.clone());
+                let mut far_end = WhiteNoise::new(0.3, 1);
+                let mut near_end = WhiteNoise::new(0.1, 2);
+                let mut render_frame = vec![0.0; NUM_SAMPLES_PER_FRAME as usize * 2];
+                let mut capture_frame = render_frame.clone();
+                let mut output = Vec::new();
+                for _ in 0..300 {
+                    far_end.fill_frame(&mut render_frame, 2);
+                    near_end.fill_frame(&mut capture_frame, 2);
+                    for (capture, render) in capture_frame.iter_mut().zip(&render_frame) {
+                        *capture += 0.5 * render;
+                    }
+                    ap.process_render_frame(&mut render_frame).unwrap();
+                    ap.process_capture_frame(&mut capture_frame).unwrap();
+                    output.extend(capture_frame.iter().map(|sample| sample.to_bits()));
+                }
+                output
+            };
+            assert!(process() == process(), "{:?}", config);
+        }
+    }
 
     #[test]
-    fn test_create_failure() {
-        let config =
-            InitializationConfig { num_capture_channels: 0, ..InitializationConfig::default() };
-        assert!(Processor::new(&config).is_err());
+    fn test_memory_usage() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let usage = ap.memory_usage();
+        assert!(usage.capture_frame_bytes >= 2 * NUM_SAMPLES_PER_FRAME as usize * 4);
+        assert!(usage.render_frame_bytes >= NUM_SAMPLES_PER_FRAME as usize * 4);
+        assert_eq!(0, usage.channel_map_bytes);
+        assert!(usage.total_bytes() > usage.capture_frame_bytes + usage.render_frame_bytes);
+
+        ap.set_capture_channel_map(Some(vec![1, 0])).unwrap();
+        let usage = ap.memory_usage();
+        assert_eq!(2 * mem::size_of::<usize>(), usage.channel_map_bytes);
     }
 
     #[test]
-    fn test_create_drop() {
+    fn test_stats_cache() {
         let config = InitializationConfig {
-            num_capture_channels: 1,
-            num_render_channels: 1,
+            num_capture_channels: 2,
+            num_render_channels: 2,
             ..InitializationConfig::default()
         };
-        let _p = Processor::new(&config).unwrap();
+        let mut ap = Processor::new(&config).unwrap();
+        let cached_generation =
+            |ap: &Processor| ap.inner.stats_cache.lock().unwrap().as_ref().map(|c| c.generation);
+
+        ap.get_stats();
+        assert_eq!(Some(0), cached_generation(&ap));
+
+        let (_, mut capture_frame) = sample_stereo_frames();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        // Still cached until the stats are requested again.
+        assert_eq!(Some(0), cached_generation(&ap));
+        ap.get_stats();
+        assert_eq!(Some(1), cached_generation(&ap));
+
+        ap.set_config(Config::default());
+        assert_eq!(None, cached_generation(&ap));
     }
 
     #[test]
@@ -273,12 +2469,104 @@ mod tests {
 
         let interleaved = (0..num_channels * num_samples).map(|v| v as f32).collect::<Vec<f32>>();
         let mut deinterleaved = vec![vec![-1f32; num_samples]; num_channels];
-        Processor::deinterleave(&interleaved, &mut deinterleaved);
+        Processor::deinterleave(&interleaved, &mut deinterleaved, None);
         assert_eq!(vec![vec![0f32, 2f32, 4f32], vec![1f32, 3f32, 5f32]], deinterleaved);
 
         let mut interleaved_out = vec![-1f32; num_samples * num_channels];
-        Processor::interleave(&deinterleaved, &mut interleaved_out);
+        Processor::interleave(&deinterleaved, &mut interleaved_out, None);
+        assert_eq!(interleaved, interleaved_out);
+    }
+
+    #[test]
+    fn test_deinterleave_interleave_blocks() {
+        // More than one block, not a multiple of the block size, with many channels.
+        let num_channels = 16usize;
+        let num_samples = INTERLEAVE_BLOCK_SIZE * 3 + 5;
+
+        let interleaved = (0..num_channels * num_samples).map(|v| v as f32).collect::<Vec<f32>>();
+        let mut deinterleaved = vec![vec![-1f32; num_samples]; num_channels];
+        Processor::deinterleave(&interleaved, &mut deinterleaved, None);
+        for (channel_index, channel) in deinterleaved.iter().enumerate() {
+            for (sample_index, sample) in channel.iter().enumerate() {
+                assert_eq!((sample_index * num_channels + channel_index) as f32, *sample);
+            }
+        }
+
+        let mut interleaved_out = vec![-1f32; num_samples * num_channels];
+        Processor::interleave(&deinterleaved, &mut interleaved_out, None);
+        assert_eq!(interleaved, interleaved_out);
+    }
+
+    #[test]
+    fn test_process_capture_bytes() {
+        let mut ap = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        ap.set_config(Config::default());
+        let (render_frame, capture_frame) = sample_stereo_frames();
+        for format in [SampleFormat::S16Le, SampleFormat::S24Le, SampleFormat::F32Le] {
+            let bytes_per_sample = format.bytes_per_sample();
+            let mut render_bytes = vec![0u8; render_frame.len() * bytes_per_sample];
+            conversion::f32_to_bytes(&render_frame, format, &mut render_bytes);
+            let mut capture_bytes = vec![0u8; capture_frame.len() * bytes_per_sample];
+            conversion::f32_to_bytes(&capture_frame, format, &mut capture_bytes);
+
+            // Nothing is enabled, so the samples pass through, whatever their order.
+            let expected = capture_bytes.clone();
+            ap.set_capture_channel_map(Some(vec![1, 0])).unwrap();
+            ap.process_render_bytes(&mut render_bytes, format).unwrap();
+            ap.process_capture_bytes(&mut capture_bytes, format).unwrap();
+            assert_eq!(expected, capture_bytes, "{:?}", format);
+
+            assert!(matches!(
+                ap.process_capture_bytes(&mut capture_bytes[1..], format),
+                Err(Error::FrameSizeMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_channel_map() {
+        let interleaved = vec![0f32, 1f32, 2f32, 3f32, 4f32, 5f32];
+        let mut deinterleaved = vec![vec![-1f32; 2]; 3];
+        Processor::deinterleave(&interleaved, &mut deinterleaved, Some(&[2, 0, 1]));
+        assert_eq!(vec![vec![2f32, 5f32], vec![0f32, 3f32], vec![1f32, 4f32]], deinterleaved);
+
+        let mut interleaved_out = vec![-1f32; 6];
+        Processor::interleave(&deinterleaved, &mut interleaved_out, Some(&[2, 0, 1]));
         assert_eq!(interleaved, interleaved_out);
+
+        assert!(check_channel_map(None, 3).is_ok());
+        assert!(check_channel_map(Some(&[1, 2, 0]), 3).is_ok());
+        assert!(matches!(check_channel_map(Some(&[1, 1, 0]), 3), Err(Error::BadParameter)));
+        assert!(matches!(check_channel_map(Some(&[1, 3, 0]), 3), Err(Error::BadParameter)));
+        assert!(matches!(check_channel_map(Some(&[1, 0]), 3), Err(Error::BadParameter)));
+    }
+
+    proptest! {
+        #[test]
+        fn test_deinterleave_interleave_round_trip(
+            (num_channels, interleaved) in (1..=24usize, 1..=NUM_SAMPLES_PER_FRAME as usize)
+                .prop_flat_map(|(num_channels, num_samples)| {
+                    (Just(num_channels), vec(any::<f32>(), num_channels * num_samples))
+                })
+        ) {
+            let num_samples = interleaved.len() / num_channels;
+            let mut deinterleaved = vec![vec![0f32; num_samples]; num_channels];
+            Processor::deinterleave(&interleaved, &mut deinterleaved, None);
+            for (channel_index, channel) in deinterleaved.iter().enumerate() {
+                let expected = interleaved.iter().skip(channel_index).step_by(num_channels);
+                prop_assert!(channel.iter().map(|s| s.to_bits()).eq(expected.map(|s| s.to_bits())));
+            }
+
+            let mut interleaved_out = vec![0f32; interleaved.len()];
+            Processor::interleave(&deinterleaved, &mut interleaved_out, None);
+            let bits = |samples: &[f32]| samples.iter().map(|s| s.to_bits()).collect::<Vec<_>>();
+            prop_assert_eq!(bits(&interleaved), bits(&interleaved_out));
+        }
     }
 
     fn sample_stereo_frames() -> (Vec<f32>, Vec<f32>) {
@@ -303,6 +2591,8 @@ mod tests {
     }
 
     #[test]
+    // The mock doesn't cancel echo.
+    #[cfg_attr(feature = "mock", ignore)]
     fn test_nominal() {
         let config = InitializationConfig {
             num_capture_channels: 2,
@@ -404,6 +2694,29 @@ mod tests {
         capture_thread.join().unwrap();
     }
 
+    #[test]
+    fn test_warm_up() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        ap.set_config(Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::High,
+                stream_delay_ms: None,
+                enable_delay_agnostic: false,
+                enable_extended_filter: false,
+            }),
+            ..Config::default()
+        });
+        ap.warm_up().unwrap();
+
+        let (_, mut capture_frame) = sample_stereo_frames();
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+    }
+
     #[test]
     fn test_tweak_processor_params() {
         let config = InitializationConfig {
@@ -412,8 +2725,8 @@ mod tests {
             ..InitializationConfig::default()
         };
         let mut ap = Processor::new(&config).unwrap();
-        
-        // tweak params outside of config 
+
+        // tweak params outside of config
         ap.set_output_will_be_muted(true);
         ap.set_stream_key_pressed(true);
 
@@ -428,4 +2741,38 @@ mod tests {
         // it shouldn't crash
     }
 
+    #[test]
+    fn test_external_noise_suppressor() {
+        struct Silencer(Arc<AtomicUsize>);
+
+        impl NoiseSuppressorBackend for Silencer {
+            fn process_frame(&mut self, frame: &mut [Vec<f32>]) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                frame.iter_mut().flatten().for_each(|sample| *sample = 0.0);
+            }
+        }
+
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let mut ap = Processor::new(&config).unwrap();
+        let num_frames = Arc::new(AtomicUsize::new(0));
+        ap.set_noise_suppressor(Box::new(Silencer(num_frames.clone())));
+
+        let (_, capture_frame) = sample_stereo_frames();
+        let mut capture_frame_output = capture_frame.clone();
+        ap.process_capture_frame(&mut capture_frame_output).unwrap();
+        assert_eq!(0, num_frames.load(Ordering::Relaxed));
+
+        ap.set_config(Config {
+            noise_suppression_backend: NoiseSuppressionBackend::External,
+            ..Config::default()
+        });
+        let mut capture_frame_output = capture_frame.clone();
+        ap.process_capture_frame(&mut capture_frame_output).unwrap();
+        assert_eq!(1, num_frames.load(Ordering::Relaxed));
+        assert!(capture_frame_output.iter().all(|&sample| sample == 0.0));
+    }
 }
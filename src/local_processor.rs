@@ -0,0 +1,246 @@
+use crate::{
+    conversion::SampleFormat, AudioProcessing, Config, ConfigChange, ConfigChangeSource, CpuLoad,
+    Error, FrameBuffers, InitializationConfig, Inspection, Local, MemoryUsage, MicVolumeBackend,
+    NoiseSuppressionLevel, NoiseSuppressorBackend, SelfTestReport, Stats,
+};
+use std::{fmt, mem, time::Duration};
+
+// For the links of the docs, which refer to `Processor` for the details.
+#[cfg(doc)]
+use crate::Processor;
+
+/// A `Processor` for single-threaded use e.g. an embedded target driving both directions from
+/// one audio callback. It owns the underlying processor module directly instead of sharing it
+/// through an `Arc`, and keeps the state the module shares between its processing paths and its
+/// setters in plain cells instead of mutexes and atomics, so there's no reference counting,
+/// locking or atomic access on the processing path. Unlike `Processor`, it can be neither cloned
+/// nor sent to another thread.
+pub struct LocalProcessor {
+    inner: AudioProcessing<Local>,
+    buffers: FrameBuffers,
+}
+
+impl fmt::Debug for LocalProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("LocalProcessor").field(&self.inspect()).finish()
+    }
+}
+
+impl LocalProcessor {
+    /// Creates a new `LocalProcessor`. `InitializationConfig` is only used on
+    /// instantiation, however new configs can be be passed to `set_config()`
+    /// at any time during processing.
+    pub fn new(config: &InitializationConfig) -> Result<Self, Error> {
+        Ok(Self { inner: AudioProcessing::new(config)?, buffers: FrameBuffers::new(config) })
+    }
+
+    /// Same as [`Processor::process_capture_frame()`].
+    pub fn process_capture_frame(&mut self, frame: &mut [f32]) -> Result<(), Error> {
+        self.buffers.process_capture_frame(&self.inner, frame)
+    }
+
+    /// Same as [`Processor::process_capture_bytes()`].
+    pub fn process_capture_bytes(
+        &mut self,
+        frame: &mut [u8],
+        format: SampleFormat,
+    ) -> Result<(), Error> {
+        self.buffers.process_capture_bytes(&self.inner, frame, format)
+    }
+
+    /// Same as [`Processor::process_capture_frame_noninterleaved()`].
+    pub fn process_capture_frame_noninterleaved(
+        &mut self,
+        frame: &mut [Vec<f32>],
+    ) -> Result<(), Error> {
+        self.buffers.process_capture_frame_noninterleaved(&self.inner, frame)
+    }
+
+    /// Same as [`Processor::process_render_frame()`].
+    pub fn process_render_frame(&mut self, frame: &mut [f32]) -> Result<(), Error> {
+        self.buffers.process_render_frame(&self.inner, frame)
+    }
+
+    /// Same as [`Processor::process_render_bytes()`].
+    pub fn process_render_bytes(
+        &mut self,
+        frame: &mut [u8],
+        format: SampleFormat,
+    ) -> Result<(), Error> {
+        self.buffers.process_render_bytes(&self.inner, frame, format)
+    }
+
+    /// Same as [`Processor::process_render_frame_noninterleaved()`].
+    pub fn process_render_frame_noninterleaved(
+        &mut self,
+        frame: &mut [Vec<f32>],
+    ) -> Result<(), Error> {
+        self.buffers.process_render_frame_noninterleaved(&self.inner, frame)
+    }
+
+    /// Same as [`Processor::set_capture_channel_map()`].
+    pub fn set_capture_channel_map(&mut self, map: Option<Vec<usize>>) -> Result<(), Error> {
+        self.buffers.set_capture_channel_map(&self.inner, map)
+    }
+
+    /// Same as [`Processor::set_render_channel_map()`].
+    pub fn set_render_channel_map(&mut self, map: Option<Vec<usize>>) -> Result<(), Error> {
+        self.buffers.set_render_channel_map(&self.inner, map)
+    }
+
+    /// Same as [`Processor::warm_up()`].
+    pub fn warm_up(&mut self) -> Result<(), Error> {
+        self.buffers.warm_up(&self.inner)
+    }
+
+    /// Returns statistics from the last `process_capture_frame()` call.
+    pub fn get_stats(&self) -> Stats {
+        self.inner.get_stats()
+    }
+
+    /// Same as [`Processor::memory_usage()`].
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.buffers.memory_usage(mem::size_of::<Self>())
+    }
+
+    /// Same as [`Processor::measure_algorithmic_delay()`].
+    pub fn measure_algorithmic_delay(&self) -> Result<Duration, Error> {
+        self.inner.measure_algorithmic_delay()
+    }
+
+    /// Same as [`Processor::algorithmic_delay()`].
+    pub fn algorithmic_delay(&self) -> Result<Duration, Error> {
+        self.inner.algorithmic_delay()
+    }
+
+    /// Same as [`Processor::run_self_test()`].
+    pub fn run_self_test(&self, min_erle_db: f32) -> Result<SelfTestReport, Error> {
+        self.inner.run_self_test(min_erle_db)
+    }
+
+    /// Same as [`Processor::cpu_load()`].
+    pub fn cpu_load(&self) -> CpuLoad {
+        self.inner.cpu_load()
+    }
+
+    /// Same as [`Processor::inspect()`].
+    pub fn inspect(&self) -> Inspection {
+        self.inner.inspect(self.memory_usage())
+    }
+
+    /// Same as [`Processor::set_config()`].
+    pub fn set_config(&mut self, config: Config) {
+        self.inner.set_config(config);
+    }
+
+    /// Same as [`Processor::set_noise_suppression_level()`].
+    pub fn set_noise_suppression_level(&mut self, level: NoiseSuppressionLevel) {
+        self.inner.set_noise_suppression_level(level);
+    }
+
+    /// Same as [`Processor::set_agc1_target_level_dbfs()`].
+    pub fn set_agc1_target_level_dbfs(&mut self, level: u8) -> Result<(), Error> {
+        self.inner.set_agc1_target_level_dbfs(level)
+    }
+
+    /// Same as [`Processor::set_agc1_enable_limiter()`].
+    pub fn set_agc1_enable_limiter(&mut self, enable: bool) {
+        self.inner.set_agc1_enable_limiter(enable);
+    }
+
+    /// Same as [`Processor::notify_playout_device_changed()`].
+    pub fn notify_playout_device_changed(&mut self) {
+        self.inner.reset_echo_control(ConfigChangeSource::NotifyPlayoutDeviceChanged);
+    }
+
+    /// Same as [`Processor::notify_capture_device_changed()`].
+    pub fn notify_capture_device_changed(&mut self) {
+        self.inner.reset_echo_control(ConfigChangeSource::NotifyCaptureDeviceChanged);
+    }
+
+    /// Same as [`Processor::enable_config_audit_log()`].
+    pub fn enable_config_audit_log(&mut self, capacity: usize) {
+        self.inner.set_config_audit_log_capacity(capacity);
+    }
+
+    /// Same as [`Processor::config_audit_log()`].
+    pub fn config_audit_log(&self) -> Vec<ConfigChange> {
+        self.inner.config_audit_log()
+    }
+
+    /// Same as [`Processor::set_headphones_connected()`].
+    pub fn set_headphones_connected(&mut self, connected: bool) {
+        self.inner.set_echo_control_suspended(connected);
+    }
+
+    /// Same as [`Processor::set_noise_suppressor()`].
+    pub fn set_noise_suppressor(&mut self, backend: Box<dyn NoiseSuppressorBackend>) {
+        self.inner.set_noise_suppressor(backend);
+    }
+
+    /// Same as [`Processor::set_mic_volume_backend()`].
+    pub fn set_mic_volume_backend(&mut self, backend: Box<dyn MicVolumeBackend>) {
+        self.inner.set_mic_volume_backend(backend);
+    }
+
+    /// Same as [`Processor::poll_mic_volume()`], between frames since this processor can't be
+    /// shared with another thread.
+    pub fn poll_mic_volume(&mut self) {
+        self.inner.poll_mic_volume();
+    }
+
+    /// Same as [`Processor::set_echo_reference_weights()`].
+    pub fn set_echo_reference_weights(&mut self, weights: Option<Vec<f32>>) -> Result<(), Error> {
+        self.inner.set_echo_reference_weights(weights)
+    }
+
+    /// Signals the AEC and AGC that the audio output will be / is muted.
+    /// They may use the hint to improve their parameter adaptation.
+    /// With `Config::enable_comfort_noise`, the capture stream is replaced with comfort noise
+    /// while muted.
+    pub fn set_output_will_be_muted(&self, muted: bool) {
+        self.inner.set_output_will_be_muted(muted);
+    }
+
+    /// Signals the AEC and AGC that the next frame will contain key press sound
+    pub fn set_stream_key_pressed(&self, pressed: bool) {
+        self.inner.set_stream_key_pressed(pressed);
+    }
+
+    /// Overrides `EchoCancellation::stream_delay_ms` from the next capture frame on, until the
+    /// next `set_config()`.
+    pub fn set_stream_delay_ms(&self, delay_ms: i32) {
+        self.inner.set_stream_delay_ms(delay_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NUM_SAMPLES_PER_FRAME;
+
+    #[test]
+    fn test_local_processor() {
+        let config = InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let mut ap = LocalProcessor::new(&config).unwrap();
+        ap.set_config(Config::default());
+
+        let mut render_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
+        ap.process_render_frame(&mut render_frame).unwrap();
+        let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * 2];
+        ap.process_capture_frame(&mut capture_frame).unwrap();
+        ap.get_stats();
+
+        let inspection = ap.inspect();
+        assert_eq!(2, inspection.init_config.num_capture_channels);
+        assert_eq!(Some(Config::default()), inspection.config);
+        assert_eq!(1, inspection.num_processed_capture_frames);
+        assert_eq!(1, inspection.num_processed_render_frames);
+        assert!(!inspection.closed);
+        assert!(format!("{:?}", ap).starts_with("LocalProcessor(Inspection { init_config:"));
+    }
+}
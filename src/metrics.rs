@@ -0,0 +1,162 @@
+//! Objective measures of the processing, to compare configurations numerically rather than by
+//! ear, e.g. on recordings run through `offline::process_wav()`.
+//!
+//! All the functions take interleaved samples, and pool the channels together. ERLE and noise
+//! reduction are only meaningful over segments where the far end or the noise alone is
+//! present, which is up to the caller to select.
+
+use crate::NUM_SAMPLES_PER_FRAME;
+use std::time::Duration;
+
+/// The level reported for silence, in dB.
+pub const SILENCE_DB: f32 = -100.0;
+
+/// Returns the mean power of `samples` in dB relative to full scale, or [`SILENCE_DB`] for
+/// silence.
+pub fn power_db(samples: &[f32]) -> f32 {
+    let energy = samples.iter().map(|&sample| sample as f64 * sample as f64).sum::<f64>();
+    mean_power_db(energy, samples.len())
+}
+
+/// Same as [`power_db()`], from the sum of the squared samples, e.g. to measure recordings
+/// that don't fit in memory.
+pub fn mean_power_db(energy: f64, num_samples: usize) -> f32 {
+    if num_samples == 0 {
+        return SILENCE_DB;
+    }
+    let power = energy / num_samples as f64;
+    if power > 0.0 {
+        (10.0 * power.log10() as f32).max(SILENCE_DB)
+    } else {
+        SILENCE_DB
+    }
+}
+
+/// Returns the echo return loss enhancement in dB: how much weaker the processed capture is
+/// than the unprocessed one, over a segment where only the far end talks.
+pub fn erle_db(capture: &[f32], processed: &[f32]) -> f32 {
+    power_db(capture) - power_db(processed)
+}
+
+/// Returns how much weaker the noise is after processing in dB, over a segment where there is
+/// only noise.
+pub fn noise_reduction_db(noisy: &[f32], processed: &[f32]) -> f32 {
+    power_db(noisy) - power_db(processed)
+}
+
+/// Returns the signal-to-noise ratio of `signal` in dB, taking everything that differs from the
+/// clean `reference` as noise (including residual echo). Both must be aligned.
+pub fn snr_db(reference: &[f32], signal: &[f32]) -> f32 {
+    assert_eq!(reference.len(), signal.len(), "signals must be of the same length");
+    let error: Vec<f32> = reference.iter().zip(signal).map(|(&r, &s)| s - r).collect();
+    power_db(reference) - power_db(&error)
+}
+
+/// Returns the ERLE of consecutive windows of `window_frames` 10 ms frames, to follow how the
+/// echo canceller converges and tracks. A last partial window is dropped.
+pub fn erle_over_time(
+    capture: &[f32],
+    processed: &[f32],
+    num_channels: usize,
+    window_frames: usize,
+) -> Vec<f32> {
+    assert_eq!(capture.len(), processed.len(), "signals must be of the same length");
+    let window_len = window_frames * NUM_SAMPLES_PER_FRAME as usize * num_channels;
+    capture
+        .chunks_exact(window_len)
+        .zip(processed.chunks_exact(window_len))
+        .map(|(capture, processed)| erle_db(capture, processed))
+        .collect()
+}
+
+/// Returns how long it takes for the ERLE, as returned by [`erle_over_time()`] with the same
+/// `window_frames`, to reach `threshold_db` and stay there. `None` if it never settles.
+pub fn convergence_time(
+    erle_db: &[f32],
+    window_frames: usize,
+    threshold_db: f32,
+) -> Option<Duration> {
+    let num_unconverged =
+        erle_db.iter().rposition(|&erle| erle < threshold_db).map_or(0, |index| index + 1);
+    if num_unconverged == erle_db.len() {
+        return None;
+    }
+    Some(Duration::from_millis((num_unconverged * window_frames * 10) as u64))
+}
+
+/// A summary of the echo cancellation performance over a far-end-only recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EchoMetrics {
+    /// The ERLE of each window, in dB.
+    pub erle_db: Vec<f32>,
+    /// The ERLE over the whole recording, in dB.
+    pub mean_erle_db: f32,
+    /// The level of the residual echo in the processed capture, in dBFS.
+    pub residual_echo_db: f32,
+    /// How long the ERLE took to reach the threshold and stay there.
+    pub convergence_time: Option<Duration>,
+}
+
+impl EchoMetrics {
+    /// Measures the echo cancellation over `capture` and `processed`, in windows of
+    /// `window_frames` 10 ms frames. The echo canceller counts as converged once the ERLE stays
+    /// above `threshold_db`.
+    pub fn measure(
+        capture: &[f32],
+        processed: &[f32],
+        num_channels: usize,
+        window_frames: usize,
+        threshold_db: f32,
+    ) -> Self {
+        let erle = erle_over_time(capture, processed, num_channels, window_frames);
+        Self {
+            convergence_time: convergence_time(&erle, window_frames, threshold_db),
+            erle_db: erle,
+            mean_erle_db: erle_db(capture, processed),
+            residual_echo_db: power_db(processed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_db() {
+        assert_eq!(0.0, power_db(&[1.0, -1.0, 1.0, -1.0]));
+        assert!((power_db(&[0.5, -0.5]) + 6.0206).abs() < 1e-3);
+        assert_eq!(SILENCE_DB, power_db(&[0.0; 10]));
+        assert_eq!(SILENCE_DB, power_db(&[]));
+    }
+
+    #[test]
+    fn test_erle_and_snr() {
+        let capture = vec![0.5f32; 100];
+        let processed = vec![0.05f32; 100];
+        assert!((erle_db(&capture, &processed) - 20.0).abs() < 1e-3);
+        assert!((noise_reduction_db(&capture, &processed) - 20.0).abs() < 1e-3);
+
+        let signal: Vec<f32> = capture.iter().zip(&processed).map(|(c, p)| c + p).collect();
+        assert!((snr_db(&capture, &signal) - 20.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_echo_metrics() {
+        let window_frames = 10;
+        let window_len = window_frames * NUM_SAMPLES_PER_FRAME as usize;
+        let capture = vec![0.5f32; window_len * 5];
+        // No cancellation in the first two windows, then 20 dB.
+        let processed: Vec<f32> =
+            (0..window_len * 5).map(|i| if i < window_len * 2 { 0.5 } else { 0.05 }).collect();
+
+        let metrics = EchoMetrics::measure(&capture, &processed, 1, window_frames, 15.0);
+        assert_eq!(5, metrics.erle_db.len());
+        assert!(metrics.erle_db[0].abs() < 1e-3);
+        assert!((metrics.erle_db[4] - 20.0).abs() < 1e-3);
+        assert_eq!(Some(Duration::from_millis(200)), metrics.convergence_time);
+
+        assert_eq!(None, convergence_time(&metrics.erle_db, window_frames, 30.0));
+        assert_eq!(Some(Duration::ZERO), convergence_time(&metrics.erle_db, window_frames, -1.0));
+    }
+}
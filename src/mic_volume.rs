@@ -0,0 +1,102 @@
+/// The volume control of the microphone, driven by the gain control while
+/// `GainControl::mode` is `GainControlMode::AdaptiveAnalog`. Pass it to
+/// `Processor::set_mic_volume_backend()`, and call `Processor::poll_mic_volume()` regularly
+/// from a thread other than the audio thread; its methods are only called from there.
+///
+/// The analog gain control works best when it's the only one adjusting the volume, i.e. with
+/// any automatic gain of the platform turned off. An implementation for ALSA lives in
+/// `integrations::alsa`; there are none for CoreAudio or WASAPI yet.
+pub trait MicVolumeBackend: Send {
+    /// Returns the current volume of the microphone, from 0.0 to 1.0. Called on every poll, so
+    /// that changes made by the user are picked up. Return the last known volume if it can't be
+    /// read.
+    fn volume(&mut self) -> f32;
+
+    /// Sets the volume of the microphone, from 0.0 to 1.0. Called on a poll whenever the gain
+    /// control recommended a different volume since the last one.
+    fn set_volume(&mut self, volume: f32);
+}
+
+/// The range of analog levels the native gain control works with.
+const MAX_ANALOG_LEVEL: f32 = 255.0;
+
+pub(crate) fn volume_to_analog_level(volume: f32) -> i32 {
+    (volume.clamp(0.0, 1.0) * MAX_ANALOG_LEVEL).round() as i32
+}
+
+pub(crate) fn analog_level_to_volume(level: i32) -> f32 {
+    level as f32 / MAX_ANALOG_LEVEL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A volume control that records the volumes set.
+    struct FakeMicVolume(Arc<Mutex<Vec<f32>>>);
+
+    impl MicVolumeBackend for FakeMicVolume {
+        fn volume(&mut self) -> f32 {
+            self.0.lock().unwrap().last().copied().unwrap_or(0.5)
+        }
+
+        fn set_volume(&mut self, volume: f32) {
+            self.0.lock().unwrap().push(volume);
+        }
+    }
+
+    #[test]
+    fn test_analog_level_conversion() {
+        assert_eq!(0, volume_to_analog_level(-1.0));
+        assert_eq!(128, volume_to_analog_level(0.5));
+        assert_eq!(255, volume_to_analog_level(2.0));
+        assert_eq!(128, volume_to_analog_level(analog_level_to_volume(128)));
+    }
+
+    // The native gain control takes too long to adapt to check its recommendations here.
+    #[test]
+    #[cfg_attr(not(feature = "mock"), ignore)]
+    fn test_mic_volume_backend() {
+        let mut processor = Processor::new(&InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let volumes = Arc::new(Mutex::new(Vec::new()));
+        processor.set_mic_volume_backend(Box::new(FakeMicVolume(volumes.clone())));
+        let quiet_frame = vec![0.001; NUM_SAMPLES_PER_FRAME as usize];
+
+        // Not driven with a digital mode.
+        let mut gain_control = GainControl {
+            mode: GainControlMode::AdaptiveDigital,
+            target_level_dbfs: 3,
+            compression_gain_db: 0,
+            enable_limiter: false,
+        };
+        processor
+            .set_config(Config { gain_control: Some(gain_control.clone()), ..Config::default() });
+        processor.poll_mic_volume();
+        processor.process_capture_frame(&mut quiet_frame.clone()).unwrap();
+        processor.poll_mic_volume();
+        assert!(volumes.lock().unwrap().is_empty());
+        assert_eq!(None, processor.get_stats().recommended_input_volume);
+
+        gain_control.mode = GainControlMode::AdaptiveAnalog;
+        processor.set_config(Config { gain_control: Some(gain_control), ..Config::default() });
+        for _ in 0..3 {
+            processor.poll_mic_volume();
+            processor.process_capture_frame(&mut quiet_frame.clone()).unwrap();
+        }
+        // Nothing is set on the capture path itself.
+        assert_eq!(2, volumes.lock().unwrap().len());
+        processor.poll_mic_volume();
+        let expected: Vec<_> = (129..=131).map(analog_level_to_volume).collect();
+        assert_eq!(expected, *volumes.lock().unwrap());
+        let stats = processor.get_stats();
+        assert_eq!(Some(130), stats.applied_input_volume);
+        assert_eq!(Some(131), stats.recommended_input_volume);
+    }
+}
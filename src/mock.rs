@@ -0,0 +1,281 @@
+//! A Rust stand-in for the native module, used in place of the ffi functions with the `mock`
+//! feature, so that tests of applications depending on this crate build and run anywhere.
+//!
+//! Capture frames pass through, amplified by `GainControl::compression_gain_db` while gain
+//! control is on, and limited to full scale if `enable_limiter` is set. With the adaptive analog
+//! mode, the recommended analog level moves up by one step per frame while the capture is
+//! quieter than the target level, and down while it's louder. The stats are computed
+//! from the levels of the last frames only, so the same input always gives the same stats:
+//! - `has_voice` is true if the capture is louder than [`MOCK_VOICE_LEVEL_DB`], here and in
+//!   [`stream_has_voice()`].
+//! - `has_echo` is true if the render is louder than [`MOCK_ECHO_LEVEL_DB`]. Like the other echo
+//!   stats, it's left out while the echo control is suspended.
+//! - `speech_probability` is 1 or 0, following `has_voice`.
+//! - `echo_return_loss` is the render level minus the capture level. Nothing is cancelled, so
+//!   the enhancement and `a_nlp` are 0, and the residual echo return loss equals it.
+//! - `delay_median_ms` is the stream delay, or 0 in delay-agnostic mode.
+
+use crate::metrics::{mean_power_db, SILENCE_DB};
+use std::{os::raw::c_int, slice};
+pub use webrtc_audio_processing_sys::*;
+
+/// The capture level above which [`get_stats()`] reports voice, in dBFS.
+pub const MOCK_VOICE_LEVEL_DB: f32 = -40.0;
+
+/// The render level above which [`get_stats()`] reports echo, in dBFS.
+pub const MOCK_ECHO_LEVEL_DB: f32 = -60.0;
+
+const NO_ERROR: c_int = 0;
+const BAD_PARAMETER_ERROR: c_int = -6;
+const BAD_NUM_CHANNELS_ERROR: c_int = -9;
+
+/// Shadows the opaque native type.
+pub struct AudioProcessing {
+    num_capture_channels: usize,
+    num_render_channels: usize,
+    config: Option<Config>,
+    stream_delay_ms: Option<i32>,
+    analog_level: c_int,
+    applied_analog_level: c_int,
+    echo_control_suspended: bool,
+    capture_db: f32,
+    render_db: f32,
+    output_db: f32,
+}
+
+pub unsafe fn audio_processing_create(
+    init_config: *const InitializationConfig,
+    error: *mut c_int,
+) -> *mut AudioProcessing {
+    let init_config = &*init_config;
+    if init_config.num_capture_channels <= 0 || init_config.num_render_channels <= 0 {
+        *error = BAD_NUM_CHANNELS_ERROR;
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(AudioProcessing {
+        num_capture_channels: init_config.num_capture_channels as usize,
+        num_render_channels: init_config.num_render_channels as usize,
+        config: None,
+        stream_delay_ms: None,
+        analog_level: 0,
+        applied_analog_level: 0,
+        echo_control_suspended: false,
+        capture_db: SILENCE_DB,
+        render_db: SILENCE_DB,
+        output_db: SILENCE_DB,
+    }))
+}
+
+/// Returns the level of all the channels of a frame together.
+unsafe fn frame_level_db(channels: *mut *mut f32, num_channels: usize) -> f32 {
+    let energy = slice::from_raw_parts(channels, num_channels)
+        .iter()
+        .flat_map(|&channel| slice::from_raw_parts(channel, NUM_SAMPLES_PER_FRAME as usize))
+        .map(|&sample| sample as f64 * sample as f64)
+        .sum::<f64>();
+    mean_power_db(energy, num_channels * NUM_SAMPLES_PER_FRAME as usize)
+}
+
+pub unsafe fn process_capture_frame(ap: *mut AudioProcessing, channels: *mut *mut f32) -> c_int {
+    let ap = &mut *ap;
+    ap.capture_db = frame_level_db(channels, ap.num_capture_channels);
+    if let Some(gain_control) = ap.config.map(|config| config.gain_control) {
+        if gain_control.enable && gain_control.mode == GainControl_Mode::ADAPTIVE_ANALOG {
+            ap.applied_analog_level = ap.analog_level;
+            let target_db = -gain_control.target_level_dbfs as f32;
+            if ap.capture_db < target_db {
+                ap.analog_level = (ap.analog_level + 1).min(255);
+            } else if ap.capture_db > target_db {
+                ap.analog_level = (ap.analog_level - 1).max(0);
+            }
+        }
+        if gain_control.enable {
+            let gain = 10f32.powf(gain_control.compression_gain_db as f32 / 20.0);
+            for &channel in slice::from_raw_parts(channels, ap.num_capture_channels) {
+                for sample in slice::from_raw_parts_mut(channel, NUM_SAMPLES_PER_FRAME as usize) {
+                    *sample *= gain;
+                    if gain_control.enable_limiter {
+                        *sample = sample.clamp(-1.0, 1.0);
+                    }
+                }
+            }
+        }
+    }
+    ap.output_db = frame_level_db(channels, ap.num_capture_channels);
+    NO_ERROR
+}
+
+pub unsafe fn process_render_frame(ap: *mut AudioProcessing, channels: *mut *mut f32) -> c_int {
+    let ap = &mut *ap;
+    ap.render_db = frame_level_db(channels, ap.num_render_channels);
+    NO_ERROR
+}
+
+pub unsafe fn get_stats(ap: *mut AudioProcessing, stats: *mut Stats) {
+    let ap = &*ap;
+    let stats = &mut *stats;
+    *stats = Stats::default();
+    let config = match ap.config {
+        Some(config) => config,
+        None => return,
+    };
+
+    let has_voice = ap.capture_db > MOCK_VOICE_LEVEL_DB;
+    if config.voice_detection.enable {
+        stats.has_voice = Some(has_voice).into();
+    }
+    if config.noise_suppression.enable {
+        stats.speech_probability = Some(if has_voice { 1.0 } else { 0.0 }).into();
+    }
+    stats.rms_dbfs = Some((-ap.output_db).clamp(0.0, 127.0).round() as i32).into();
+    if config.echo_cancellation.enable && !ap.echo_control_suspended {
+        let echo_return_loss = (ap.render_db - ap.capture_db) as f64;
+        stats.has_echo = Some(ap.render_db > MOCK_ECHO_LEVEL_DB).into();
+        stats.echo_return_loss = Some(echo_return_loss).into();
+        stats.residual_echo_return_loss = Some(echo_return_loss).into();
+        stats.echo_return_loss_enhancement = Some(0.0).into();
+        stats.a_nlp = Some(0.0).into();
+        stats.delay_median_ms = Some(ap.stream_delay_ms.unwrap_or(0)).into();
+        stats.delay_standard_deviation_ms = Some(0).into();
+        stats.delay_fraction_poor_delays = Some(0.0).into();
+    }
+    if config.gain_control.enable && config.gain_control.mode == GainControl_Mode::ADAPTIVE_ANALOG {
+        stats.applied_input_volume = Some(ap.applied_analog_level).into();
+        stats.recommended_input_volume = Some(ap.analog_level).into();
+    }
+}
+
+pub unsafe fn stream_has_voice(ap: *mut AudioProcessing) -> OptionalBool {
+    let ap = &*ap;
+    match ap.config {
+        Some(config) if config.voice_detection.enable => {
+            Some(ap.capture_db > MOCK_VOICE_LEVEL_DB).into()
+        },
+        _ => None.into(),
+    }
+}
+
+pub unsafe fn set_config(ap: *mut AudioProcessing, config: *const Config) {
+    let ap = &mut *ap;
+    let config = *config;
+    if config.echo_cancellation.enable {
+        ap.stream_delay_ms = config.echo_cancellation.stream_delay_ms.into();
+    } else if config.echo_control_mobile.enable {
+        ap.stream_delay_ms = config.echo_control_mobile.stream_delay_ms.into();
+    }
+    ap.config = Some(config);
+}
+
+pub unsafe fn set_noise_suppression_level(
+    ap: *mut AudioProcessing,
+    level: NoiseSuppression_SuppressionLevel,
+) {
+    let ap = &mut *ap;
+    if let Some(config) = &mut ap.config {
+        if config.noise_suppression.enable {
+            config.noise_suppression.suppression_level = level;
+        }
+    }
+}
+
+pub unsafe fn set_gain_control_target_level_dbfs(ap: *mut AudioProcessing, level: c_int) -> c_int {
+    let ap = &mut *ap;
+    if !(0..=31).contains(&level) {
+        return BAD_PARAMETER_ERROR;
+    }
+    if let Some(config) = &mut ap.config {
+        if config.gain_control.enable {
+            config.gain_control.target_level_dbfs = level;
+        }
+    }
+    NO_ERROR
+}
+
+pub unsafe fn set_gain_control_enable_limiter(ap: *mut AudioProcessing, enable: bool) {
+    let ap = &mut *ap;
+    if let Some(config) = &mut ap.config {
+        if config.gain_control.enable {
+            config.gain_control.enable_limiter = enable;
+        }
+    }
+}
+
+pub unsafe fn reset_echo_control(_ap: *mut AudioProcessing) {}
+
+pub unsafe fn set_echo_control_suspended(ap: *mut AudioProcessing, suspended: bool) {
+    (*ap).echo_control_suspended = suspended;
+}
+
+pub unsafe fn set_output_will_be_muted(_ap: *mut AudioProcessing, _muted: bool) {}
+
+pub unsafe fn set_stream_key_pressed(_ap: *mut AudioProcessing, _pressed: bool) {}
+
+pub unsafe fn set_stream_analog_level(ap: *mut AudioProcessing, level: c_int) {
+    (*ap).analog_level = level;
+}
+
+pub unsafe fn stream_analog_level(ap: *mut AudioProcessing) -> c_int {
+    (*ap).analog_level
+}
+
+pub unsafe fn set_stream_delay_ms(ap: *mut AudioProcessing, delay_ms: c_int) {
+    (*ap).stream_delay_ms = Some(delay_ms);
+}
+
+pub unsafe fn audio_processing_delete(ap: *mut AudioProcessing) {
+    drop(Box::from_raw(ap));
+}
+
+pub unsafe fn is_success(code: c_int) -> bool {
+    code == NO_ERROR
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_mock_processing() {
+        let config = InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let mut processor = Processor::new(&config).unwrap();
+        processor.set_config(Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                enable_extended_filter: false,
+                enable_delay_agnostic: false,
+                stream_delay_ms: Some(30),
+            }),
+            gain_control: Some(GainControl {
+                mode: GainControlMode::FixedDigital,
+                target_level_dbfs: 3,
+                compression_gain_db: 6,
+                enable_limiter: true,
+            }),
+            voice_detection: Some(VoiceDetection {
+                detection_likelihood: VoiceDetectionLikelihood::Moderate,
+            }),
+            voice_gate: Some(VoiceGate { attack_ms: 0, ..VoiceGate::default() }),
+            ..Config::default()
+        });
+
+        let mut render_frame = vec![0.1; NUM_SAMPLES_PER_FRAME as usize];
+        let mut capture_frame = vec![0.2; NUM_SAMPLES_PER_FRAME as usize];
+        capture_frame[0] = 0.9;
+        processor.process_render_frame(&mut render_frame).unwrap();
+        processor.process_capture_frame(&mut capture_frame).unwrap();
+        assert!((capture_frame[1] - 0.2 * 10f32.powf(6.0 / 20.0)).abs() < 1e-6);
+        assert_eq!(1.0, capture_frame[0]);
+
+        let stats = processor.get_stats();
+        assert_eq!(Some(true), stats.has_voice);
+        assert_eq!(Some(true), stats.voice_gate_open);
+        assert_eq!(Some(true), stats.has_echo);
+        assert_eq!(Some(30), stats.delay_median_ms);
+        assert_eq!(Some(0.0), stats.echo_return_loss_enhancement);
+        assert_eq!(format!("{:?}", stats), format!("{:?}", processor.get_stats()));
+    }
+}
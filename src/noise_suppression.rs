@@ -0,0 +1,14 @@
+/// An external noise suppressor, e.g. RNNoise or an ONNX model, to use in place of WebRTC's
+/// one. Select it with `Config::noise_suppression_backend` and pass it to
+/// `Processor::set_noise_suppressor()`.
+///
+/// The native module doesn't allow inserting a stage into its capture chain, so the backend runs
+/// after it, i.e. after echo cancellation and gain control. Note that the gain control therefore
+/// adapts to the signal before the noise is suppressed.
+pub trait NoiseSuppressorBackend: Send {
+    /// Suppresses the noise in the capture frame in place. `frame` holds one `Vec` per channel
+    /// of `NUM_SAMPLES_PER_FRAME` samples (10 ms at 48 kHz), which is also the frame size
+    /// RNNoise works on. Samples are in the [-1.0, 1.0] range. Called on the capture thread, so
+    /// it shouldn't block or allocate.
+    fn process_frame(&mut self, frame: &mut [Vec<f32>]);
+}
@@ -0,0 +1,729 @@
+//! Processing of WAV files, e.g. to evaluate configurations against recorded samples.
+//!
+//! Everything is streamed 10 ms at a time, so multi-hour recordings don't need to fit in
+//! memory. The files must be at 48 kHz; see `integrations::symphonia` (behind the `symphonia`
+//! feature) for other formats and sample rates.
+
+use crate::{
+    metrics,
+    signal::{Signal, WhiteNoise},
+    stats_sink::{write_csv_stats, CSV_STATS_HEADER},
+    Config, InitializationConfig, Processor, Stats, NUM_SAMPLES_PER_FRAME, SAMPLE_RATE,
+};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+#[cfg(feature = "derive_serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    error, fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An error while processing WAV files.
+#[derive(Debug)]
+pub enum OfflineError {
+    /// A WAV file couldn't be read or written.
+    Wav(hound::Error),
+    /// A WAV file isn't at 48 kHz.
+    UnsupportedSampleRate(u32),
+    /// The processor failed.
+    Processing(crate::Error),
+}
+
+impl fmt::Display for OfflineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OfflineError::Wav(err) => write!(f, "WAV file error: {}", err),
+            OfflineError::UnsupportedSampleRate(sample_rate) => {
+                write!(f, "unsupported sample rate {} Hz, expected {} Hz", sample_rate, SAMPLE_RATE)
+            },
+            OfflineError::Processing(err) => write!(f, "failed to process: {}", err),
+        }
+    }
+}
+
+impl error::Error for OfflineError {}
+
+impl From<hound::Error> for OfflineError {
+    fn from(err: hound::Error) -> Self {
+        OfflineError::Wav(err)
+    }
+}
+
+impl From<crate::Error> for OfflineError {
+    fn from(err: crate::Error) -> Self {
+        OfflineError::Processing(err)
+    }
+}
+
+/// Reads a 48 kHz WAV file 10 ms at a time, as interleaved `f32` samples. Integer WAV files
+/// are scaled to [-1.0, 1.0).
+pub struct WavFrameReader {
+    samples: Box<dyn Iterator<Item = Result<f32, hound::Error>> + Send>,
+    num_channels: usize,
+}
+
+impl WavFrameReader {
+    /// Opens the WAV file at `path`.
+    pub fn open(path: &Path) -> Result<Self, OfflineError> {
+        let reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        if spec.sample_rate != SAMPLE_RATE {
+            return Err(OfflineError::UnsupportedSampleRate(spec.sample_rate));
+        }
+
+        let samples: Box<dyn Iterator<Item = Result<f32, hound::Error>> + Send> =
+            match spec.sample_format {
+                SampleFormat::Float => Box::new(reader.into_samples::<f32>()),
+                SampleFormat::Int => {
+                    let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                    Box::new(
+                        reader
+                            .into_samples::<i32>()
+                            .map(move |sample| sample.map(|sample| sample as f32 * scale)),
+                    )
+                },
+            };
+        Ok(Self { samples, num_channels: spec.channels as usize })
+    }
+
+    /// Returns the number of channels of the file.
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// Fills `frame` with the next 10 ms of interleaved samples. Returns false once the file
+    /// has been read to the end. A partial frame at the end is padded with silence.
+    pub fn read_frame(&mut self, frame: &mut [f32]) -> Result<bool, OfflineError> {
+        assert_eq!(
+            NUM_SAMPLES_PER_FRAME as usize * self.num_channels,
+            frame.len(),
+            "frame must hold 10 ms of samples"
+        );
+
+        let mut num_samples = 0;
+        for (sample, read) in frame.iter_mut().zip(&mut self.samples) {
+            *sample = read?;
+            num_samples += 1;
+        }
+        for sample in &mut frame[num_samples..] {
+            *sample = 0.0;
+        }
+        Ok(num_samples > 0)
+    }
+}
+
+/// Writes 10 ms frames of interleaved `f32` samples to a 32-bit float WAV file at 48 kHz.
+pub struct WavFrameWriter {
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl WavFrameWriter {
+    /// Creates the WAV file at `path` for `num_channels` channels.
+    pub fn create(path: &Path, num_channels: usize) -> Result<Self, OfflineError> {
+        let writer = WavWriter::create(
+            path,
+            WavSpec {
+                channels: num_channels as u16,
+                sample_rate: SAMPLE_RATE,
+                bits_per_sample: 32,
+                sample_format: SampleFormat::Float,
+            },
+        )?;
+        Ok(Self { writer })
+    }
+
+    /// Appends the interleaved samples in `frame`.
+    pub fn write_frame(&mut self, frame: &[f32]) -> Result<(), OfflineError> {
+        for &sample in frame {
+            self.writer.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the header and flushes the file. Dropping the writer does the same, but ignores
+    /// any error.
+    pub fn finalize(self) -> Result<(), OfflineError> {
+        Ok(self.writer.finalize()?)
+    }
+}
+
+/// The result of [`process_wav()`].
+#[derive(Debug, Clone)]
+pub struct ProcessedWav {
+    /// Where the processed capture has been written.
+    pub output_path: PathBuf,
+    /// The number of 10 ms frames processed.
+    pub num_frames: usize,
+    /// The statistics after the last frame.
+    pub stats: Stats,
+}
+
+/// Runs the capture WAV file at `capture_path` through a processor configured with `config`,
+/// and writes the result next to it, e.g. `capture.wav` to `capture-processed.wav`.
+///
+/// If given, `render_path` is fed as the render (far-end) stream in sync with the capture, for
+/// echo cancellation. It is padded with silence if shorter than the capture, and truncated if
+/// longer.
+pub fn process_wav(
+    capture_path: &Path,
+    render_path: Option<&Path>,
+    config: &Config,
+) -> Result<ProcessedWav, OfflineError> {
+    let mut file_name = capture_path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push("-processed.wav");
+    let output_path = capture_path.with_file_name(file_name);
+    let (num_frames, stats) = process_wav_to(capture_path, render_path, &output_path, config)?;
+    Ok(ProcessedWav { output_path, num_frames, stats })
+}
+
+/// Same as [`process_wav()`], but writes the result to `output_path`. Returns the number of
+/// 10 ms frames processed and the statistics after the last one.
+pub fn process_wav_to(
+    capture_path: &Path,
+    render_path: Option<&Path>,
+    output_path: &Path,
+    config: &Config,
+) -> Result<(usize, Stats), OfflineError> {
+    let mut capture = WavFrameReader::open(capture_path)?;
+    let mut render = render_path.map(WavFrameReader::open).transpose()?;
+
+    let mut processor = Processor::new(&InitializationConfig {
+        num_capture_channels: capture.num_channels() as i32,
+        num_render_channels: render.as_ref().map_or(1, |render| render.num_channels()) as i32,
+        ..InitializationConfig::default()
+    })?;
+    processor.set_config(config.clone());
+
+    let mut writer = WavFrameWriter::create(output_path, capture.num_channels())?;
+    let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * capture.num_channels()];
+    let mut render_frame = render
+        .as_ref()
+        .map(|render| vec![0f32; NUM_SAMPLES_PER_FRAME as usize * render.num_channels()]);
+    let mut num_frames = 0;
+    while capture.read_frame(&mut capture_frame)? {
+        if let (Some(render), Some(render_frame)) = (&mut render, &mut render_frame) {
+            // Padded with silence once the render file ends.
+            render.read_frame(render_frame)?;
+            processor.process_render_frame(render_frame)?;
+        }
+        processor.process_capture_frame(&mut capture_frame)?;
+        writer.write_frame(&capture_frame)?;
+        num_frames += 1;
+    }
+    writer.finalize()?;
+
+    Ok((num_frames, processor.get_stats()))
+}
+
+/// Objective metrics of a processed recording, from [`measure_wav()`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct WavMetrics {
+    /// The number of 10 ms frames processed.
+    pub num_frames: usize,
+    /// The mean power of the processed capture, in dBFS.
+    pub output_power_db: f32,
+    /// How much weaker the processed capture is than the unprocessed one, in dB.
+    pub attenuation_db: f32,
+    /// The share of frames in which voice was detected, if voice detection is enabled.
+    pub voice_ratio: Option<f32>,
+    /// The mean ERLE reported by the echo canceller, in dB, if echo cancellation is enabled.
+    pub mean_erle_db: Option<f32>,
+}
+
+/// Runs the capture WAV file at `capture_path`, with the optional `render_path` as in
+/// [`process_wav()`], through a processor configured with `config`, and measures the output
+/// instead of writing it.
+pub fn measure_wav(
+    capture_path: &Path,
+    render_path: Option<&Path>,
+    config: &Config,
+) -> Result<WavMetrics, OfflineError> {
+    let mut capture = WavFrameReader::open(capture_path)?;
+    let mut render = render_path.map(WavFrameReader::open).transpose()?;
+    let mut processor = Processor::new(&InitializationConfig {
+        num_capture_channels: capture.num_channels() as i32,
+        num_render_channels: render.as_ref().map_or(1, |render| render.num_channels()) as i32,
+        ..InitializationConfig::default()
+    })?;
+    processor.set_config(config.clone());
+
+    let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * capture.num_channels()];
+    let mut render_frame = render
+        .as_ref()
+        .map(|render| vec![0f32; NUM_SAMPLES_PER_FRAME as usize * render.num_channels()]);
+    let energy = |frame: &[f32]| frame.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+    let (mut input_energy, mut output_energy) = (0.0, 0.0);
+    let mut num_frames = 0;
+    let (mut num_voice_frames, mut num_voice_reports) = (0, 0);
+    let (mut erle_sum, mut num_erle_reports) = (0.0, 0);
+    while capture.read_frame(&mut capture_frame)? {
+        if let (Some(render), Some(render_frame)) = (&mut render, &mut render_frame) {
+            render.read_frame(render_frame)?;
+            processor.process_render_frame(render_frame)?;
+        }
+        input_energy += energy(&capture_frame);
+        processor.process_capture_frame(&mut capture_frame)?;
+        output_energy += energy(&capture_frame);
+        num_frames += 1;
+
+        let stats = processor.get_stats();
+        if let Some(has_voice) = stats.has_voice {
+            num_voice_frames += has_voice as usize;
+            num_voice_reports += 1;
+        }
+        if let Some(erle) = stats.echo_return_loss_enhancement {
+            erle_sum += erle;
+            num_erle_reports += 1;
+        }
+    }
+
+    let num_samples = num_frames * capture_frame.len();
+    let output_power_db = metrics::mean_power_db(output_energy, num_samples);
+    Ok(WavMetrics {
+        num_frames,
+        output_power_db,
+        attenuation_db: metrics::mean_power_db(input_energy, num_samples) - output_power_db,
+        voice_ratio: (num_voice_reports > 0)
+            .then(|| num_voice_frames as f32 / num_voice_reports as f32),
+        mean_erle_db: (num_erle_reports > 0).then(|| (erle_sum / num_erle_reports as f64) as f32),
+    })
+}
+
+/// The result of one config in [`compare_configs()`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct ConfigComparison {
+    /// The name the config was given.
+    pub name: String,
+    /// The metrics of the session processed with the config.
+    pub metrics: WavMetrics,
+    /// How long the processing took.
+    pub processing_duration: Duration,
+}
+
+/// Processes the same session under each of the named `configs`, in parallel, for A/B
+/// comparisons while tuning. The results are ranked by attenuation, most attenuated first;
+/// [`comparison_table()`] formats them for display.
+pub fn compare_configs(
+    capture_path: &Path,
+    render_path: Option<&Path>,
+    configs: &[(String, Config)],
+) -> Result<Vec<ConfigComparison>, OfflineError> {
+    let mut results = thread::scope(|scope| {
+        let handles: Vec<_> = configs
+            .iter()
+            .map(|(name, config)| {
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let metrics = measure_wav(capture_path, render_path, config)?;
+                    Ok(ConfigComparison {
+                        name: name.clone(),
+                        metrics,
+                        processing_duration: start.elapsed(),
+                    })
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("config comparison thread panicked"))
+            .collect::<Result<Vec<_>, OfflineError>>()
+    })?;
+    results.sort_by(|a, b| b.metrics.attenuation_db.total_cmp(&a.metrics.attenuation_db));
+    Ok(results)
+}
+
+/// Formats the results of [`compare_configs()`] as a plain text table, one config per row.
+pub fn comparison_table(results: &[ConfigComparison]) -> String {
+    let optional = |value: Option<f32>| value.map_or("-".to_string(), |v| format!("{:.2}", v));
+    let name_width = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max(6);
+    let mut table = format!(
+        "{:<name_width$}  {:>10}  {:>14}  {:>10}  {:>11}  {:>10}\n",
+        "config",
+        "output dB",
+        "attenuation dB",
+        "ERLE dB",
+        "voice ratio",
+        "time s",
+        name_width = name_width
+    );
+    for result in results {
+        table.push_str(&format!(
+            "{:<name_width$}  {:>10.2}  {:>14.2}  {:>10}  {:>11}  {:>10.2}\n",
+            result.name,
+            result.metrics.output_power_db,
+            result.metrics.attenuation_db,
+            optional(result.metrics.mean_erle_db),
+            optional(result.metrics.voice_ratio),
+            result.processing_duration.as_secs_f64(),
+            name_width = name_width
+        ));
+    }
+    table
+}
+
+/// A simulated acoustic path from the speaker to the microphone, to evaluate echo cancellation
+/// configs without a physical room, see [`simulate_echo_wav()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EchoPath {
+    /// The impulse response of the room at 48 kHz, from the speaker to the microphone.
+    pub impulse_response: Vec<f32>,
+    /// The delay in front of the impulse response, e.g. the latency of the audio devices.
+    pub delay: Duration,
+    /// The level of the white noise added to the capture, in dBFS, if any.
+    pub noise_level_db: Option<f32>,
+}
+
+impl EchoPath {
+    /// Creates a path with a synthetic impulse response: a direct sound of `gain`, followed by
+    /// a diffuse tail decaying by 60 dB over `reverberation_time` (RT60). The tail is seeded
+    /// noise, so the same parameters always give the same response.
+    pub fn synthetic(gain: f32, reverberation_time: Duration) -> Self {
+        const TAIL_LEVEL: f32 = 0.5;
+
+        let len = (reverberation_time.as_secs_f64() * SAMPLE_RATE as f64).max(1.0) as usize;
+        let mut tail = WhiteNoise::new(gain * TAIL_LEVEL, 0);
+        let mut impulse_response: Vec<f32> = (0..len)
+            .map(|i| {
+                // -60 dB is 1e-3 in amplitude, i.e. e^-6.91.
+                let decay = (-6.91 * i as f64 / len as f64).exp() as f32;
+                tail.next_sample() * decay
+            })
+            .collect();
+        impulse_response[0] = gain;
+        Self { impulse_response, delay: Duration::ZERO, noise_level_db: None }
+    }
+
+    /// Creates a path with the impulse response recorded in the 48 kHz WAV file at `path`,
+    /// taken from its first channel.
+    pub fn from_wav(path: &Path) -> Result<Self, OfflineError> {
+        let mut reader = WavFrameReader::open(path)?;
+        let num_channels = reader.num_channels();
+        let mut frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * num_channels];
+        let mut impulse_response = Vec::new();
+        while reader.read_frame(&mut frame)? {
+            impulse_response.extend(frame.iter().step_by(num_channels));
+        }
+        // Drop the padding of the last frame.
+        let len = impulse_response.iter().rposition(|&sample| sample != 0.0).map_or(1, |i| i + 1);
+        impulse_response.truncate(len);
+        Ok(Self { impulse_response, delay: Duration::ZERO, noise_level_db: None })
+    }
+}
+
+/// Convolves the render stream with an [`EchoPath`], one frame at a time.
+struct EchoSimulator {
+    impulse_response: Vec<f32>,
+    delay: usize,
+    // The last render samples the echo of the next frame depends on, followed by the frame.
+    history: Vec<f32>,
+    noise: Option<WhiteNoise>,
+}
+
+impl EchoSimulator {
+    fn new(echo_path: &EchoPath) -> Self {
+        let delay = (echo_path.delay.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        let impulse_response = echo_path.impulse_response.clone();
+        let history = vec![0.0; delay + impulse_response.len().max(1) - 1];
+        // Uniform noise of peak `a` has a power of a^2 / 3.
+        let noise = echo_path
+            .noise_level_db
+            .map(|level_db| WhiteNoise::new((3.0 * 10f32.powf(level_db / 10.0)).sqrt(), 0));
+        Self { impulse_response, delay, history, noise }
+    }
+
+    /// Adds the echo of the interleaved `render` frame, mixed down to mono, and the noise to
+    /// every channel of the interleaved `capture` frame.
+    fn process(&mut self, render: &[f32], num_render_channels: usize, capture: &mut [f32]) {
+        let num_history = self.history.len();
+        self.history.extend(
+            render
+                .chunks_exact(num_render_channels)
+                .map(|samples| samples.iter().sum::<f32>() / num_render_channels as f32),
+        );
+        let num_capture_channels = capture.len() / NUM_SAMPLES_PER_FRAME as usize;
+        for (i, samples) in capture.chunks_exact_mut(num_capture_channels).enumerate() {
+            let newest = num_history + i - self.delay;
+            let echo: f32 = self
+                .impulse_response
+                .iter()
+                .enumerate()
+                .map(|(k, &coefficient)| coefficient * self.history[newest - k])
+                .sum();
+            let noise = self.noise.as_mut().map_or(0.0, |noise| noise.next_sample());
+            samples.iter_mut().for_each(|sample| *sample += echo + noise);
+        }
+        self.history.drain(..render.len() / num_render_channels);
+    }
+}
+
+/// Mixes the echo of the render WAV file at `render_path` through `echo_path` into the capture
+/// WAV file at `capture_path`, and writes the result to `output_path`, e.g. to run it through
+/// [`process_wav()`] or [`measure_wav()`] with the same render file afterwards. The render is
+/// mixed down to mono, and its echo added to every capture channel. As in `process_wav()`, the
+/// render is padded with silence or truncated to the length of the capture. Returns the number
+/// of 10 ms frames written.
+///
+/// The convolution is direct, so long impulse responses take a while.
+pub fn simulate_echo_wav(
+    capture_path: &Path,
+    render_path: &Path,
+    echo_path: &EchoPath,
+    output_path: &Path,
+) -> Result<usize, OfflineError> {
+    let mut capture = WavFrameReader::open(capture_path)?;
+    let mut render = WavFrameReader::open(render_path)?;
+    let mut writer = WavFrameWriter::create(output_path, capture.num_channels())?;
+    let mut capture_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * capture.num_channels()];
+    let mut render_frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * render.num_channels()];
+    let mut simulator = EchoSimulator::new(echo_path);
+    let mut num_frames = 0;
+    while capture.read_frame(&mut capture_frame)? {
+        render.read_frame(&mut render_frame)?;
+        simulator.process(&render_frame, render.num_channels(), &mut capture_frame);
+        writer.write_frame(&capture_frame)?;
+        num_frames += 1;
+    }
+    writer.finalize()?;
+    Ok(num_frames)
+}
+
+/// Simulates the echo of `render_path` through `echo_path` in the capture at `capture_path`
+/// with [`simulate_echo_wav()`], and measures the simulated session processed with `config`
+/// with [`measure_wav()`], for automated evaluation of echo cancellation configs. The
+/// simulated capture is written next to the capture, e.g. `capture.wav` to
+/// `capture-echo.wav`.
+pub fn measure_simulated_echo(
+    capture_path: &Path,
+    render_path: &Path,
+    echo_path: &EchoPath,
+    config: &Config,
+) -> Result<WavMetrics, OfflineError> {
+    let mut file_name = capture_path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push("-echo.wav");
+    let simulated_path = capture_path.with_file_name(file_name);
+    simulate_echo_wav(capture_path, render_path, echo_path, &simulated_path)?;
+    measure_wav(&simulated_path, Some(render_path), config)
+}
+
+/// The statistics after one frame, aligned with the levels around the processor, to plot how
+/// a session evolves.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "derive_serde", derive(Serialize, Deserialize))]
+pub struct StatsRow {
+    /// The index of the 10 ms frame.
+    pub frame: usize,
+    /// The level of the capture before processing, in dBFS.
+    pub input_db: f32,
+    /// The level of the capture after processing, in dBFS.
+    pub output_db: f32,
+    /// The statistics after the frame.
+    pub stats: Stats,
+}
+
+impl StatsRow {
+    /// Returns the row for `frame`, from the capture `input` and `output` of the frame.
+    pub fn new(frame: usize, input: &[f32], output: &[f32], stats: Stats) -> Self {
+        Self {
+            frame,
+            input_db: metrics::power_db(input),
+            output_db: metrics::power_db(output),
+            stats,
+        }
+    }
+
+    /// Returns the time of the frame since the start, in seconds.
+    pub fn time_secs(&self) -> f64 {
+        self.frame as f64 / 100.0
+    }
+
+    /// Returns the gain applied to the frame, in dB.
+    pub fn gain_db(&self) -> f32 {
+        self.output_db - self.input_db
+    }
+}
+
+/// Writes `rows` as CSV, one row per frame with a header, for spreadsheets and plotting tools.
+/// Statistics that aren't available, e.g. of disabled components, are left empty.
+pub fn write_stats_csv<W: Write>(rows: &[StatsRow], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "frame,time_s,input_db,output_db,gain_db,{}", CSV_STATS_HEADER)?;
+    for row in rows {
+        write!(
+            writer,
+            "{},{:.2},{:.2},{:.2},{:.2},",
+            row.frame,
+            row.time_secs(),
+            row.input_db,
+            row.output_db,
+            row.gain_db(),
+        )?;
+        write_csv_stats(&mut writer, &row.stats)?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_test_wav(path: &Path, num_channels: u16, num_samples_per_channel: usize) {
+        let spec = WavSpec {
+            channels: num_channels,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for _ in 0..num_samples_per_channel * num_channels as usize {
+            writer.write_sample(i16::MIN).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_wav_frame_reader_pads_last_frame() {
+        let path = std::env::temp_dir().join("webrtc-audio-processing-offline-reader.wav");
+        write_test_wav(&path, 2, NUM_SAMPLES_PER_FRAME as usize + 10);
+
+        let mut reader = WavFrameReader::open(&path).unwrap();
+        assert_eq!(2, reader.num_channels());
+        let mut frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * 2];
+        assert!(reader.read_frame(&mut frame).unwrap());
+        assert!(frame.iter().all(|&sample| sample == -1.0));
+        assert!(reader.read_frame(&mut frame).unwrap());
+        assert!(frame[..20].iter().all(|&sample| sample == -1.0));
+        assert!(frame[20..].iter().all(|&sample| sample == 0.0));
+        assert!(!reader.read_frame(&mut frame).unwrap());
+    }
+
+    #[test]
+    fn test_process_wav() {
+        let dir = std::env::temp_dir();
+        let capture_path = dir.join("webrtc-audio-processing-offline-capture.wav");
+        let render_path = dir.join("webrtc-audio-processing-offline-render.wav");
+        write_test_wav(&capture_path, 1, SAMPLE_RATE as usize);
+        write_test_wav(&render_path, 2, SAMPLE_RATE as usize / 2);
+
+        let processed = process_wav(&capture_path, Some(&render_path), &Config::default()).unwrap();
+        assert_eq!(
+            dir.join("webrtc-audio-processing-offline-capture-processed.wav"),
+            processed.output_path
+        );
+        assert_eq!(100, processed.num_frames);
+        let reader = WavReader::open(&processed.output_path).unwrap();
+        assert_eq!(SAMPLE_RATE as usize, reader.len() as usize);
+    }
+
+    #[test]
+    fn test_simulate_echo_wav() {
+        let dir = std::env::temp_dir();
+        let capture_path = dir.join("webrtc-audio-processing-offline-echo-capture.wav");
+        let render_path = dir.join("webrtc-audio-processing-offline-echo-render.wav");
+        let output_path = dir.join("webrtc-audio-processing-offline-echo-output.wav");
+        let mut writer = WavFrameWriter::create(&capture_path, 1).unwrap();
+        for _ in 0..10 {
+            writer.write_frame(&[0.0; NUM_SAMPLES_PER_FRAME as usize]).unwrap();
+        }
+        writer.finalize().unwrap();
+        // An impulse in both channels.
+        let mut writer = WavFrameWriter::create(&render_path, 2).unwrap();
+        let mut frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * 2];
+        frame[..2].copy_from_slice(&[1.0, 1.0]);
+        writer.write_frame(&frame).unwrap();
+        writer.finalize().unwrap();
+
+        let echo_path = EchoPath {
+            impulse_response: vec![0.5, 0.25],
+            delay: Duration::from_millis(15),
+            noise_level_db: None,
+        };
+        assert_eq!(
+            10,
+            simulate_echo_wav(&capture_path, &render_path, &echo_path, &output_path).unwrap()
+        );
+        let samples: Vec<f32> =
+            WavReader::open(&output_path).unwrap().into_samples().map(Result::unwrap).collect();
+        assert_eq!(NUM_SAMPLES_PER_FRAME as usize * 10, samples.len());
+        // 15 ms is 720 samples.
+        assert_eq!(&[0.0, 0.5, 0.25, 0.0], &samples[719..723]);
+        assert_eq!(0.75, samples.iter().sum::<f32>());
+
+        let metrics =
+            measure_simulated_echo(&capture_path, &render_path, &echo_path, &Config::default())
+                .unwrap();
+        assert_eq!(10, metrics.num_frames);
+
+        let synthetic = EchoPath::synthetic(0.5, Duration::from_millis(300));
+        assert_eq!(14_400, synthetic.impulse_response.len());
+        assert_eq!(0.5, synthetic.impulse_response[0]);
+        let tail = synthetic.impulse_response.last().unwrap().abs();
+        assert!(tail < 0.5 * 0.5 * 2e-3, "{}", tail);
+    }
+
+    #[test]
+    fn test_write_stats_csv() {
+        let stats = Stats {
+            has_voice: Some(true),
+            has_echo: None,
+            rms_dbfs: None,
+            speech_probability: None,
+            residual_echo_return_loss: None,
+            echo_return_loss: None,
+            echo_return_loss_enhancement: Some(12.5),
+            a_nlp: None,
+            delay_median_ms: Some(40),
+            delay_standard_deviation_ms: None,
+            delay_fraction_poor_delays: None,
+            num_non_finite_samples: None,
+            voice_gate_open: None,
+            applied_input_volume: None,
+            recommended_input_volume: None,
+        };
+        let rows = vec![StatsRow::new(150, &[0.5, -0.5], &[0.25, -0.25], stats)];
+
+        let mut csv = Vec::new();
+        write_stats_csv(&rows, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert_eq!("150,1.50,-6.02,-12.04,-6.02,12.5,,,40,,,1,", lines[1]);
+    }
+
+    #[test]
+    fn test_compare_configs() {
+        let capture_path = std::env::temp_dir().join("webrtc-audio-processing-offline-compare.wav");
+        write_test_wav(&capture_path, 1, SAMPLE_RATE as usize);
+
+        let configs = vec![
+            ("passthrough".to_string(), Config::default()),
+            (
+                "gain".to_string(),
+                Config {
+                    gain_control: Some(crate::GainControl {
+                        mode: crate::GainControlMode::FixedDigital,
+                        target_level_dbfs: 3,
+                        compression_gain_db: 20,
+                        enable_limiter: true,
+                    }),
+                    ..Config::default()
+                },
+            ),
+        ];
+        let results = compare_configs(&capture_path, None, &configs).unwrap();
+        assert_eq!(2, results.len());
+        assert!(results.iter().all(|result| result.metrics.num_frames == 100));
+        assert!(results[0].metrics.attenuation_db >= results[1].metrics.attenuation_db);
+        assert_eq!(None, results[0].metrics.voice_ratio);
+
+        let table = comparison_table(&results);
+        assert_eq!(3, table.lines().count());
+        assert!(table.contains("passthrough") && table.contains("gain"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Renders the time series of [`StatsRow`]s collected during offline processing as a PNG, to
+//! review processed sessions visually.
+
+use crate::offline::StatsRow;
+use plotters::{coord::Shift, prelude::*};
+use std::{error, fmt, ops::Range, path::Path};
+
+/// The size of the rendered image, in pixels.
+const IMAGE_SIZE: (u32, u32) = (1600, 1200);
+
+/// A named line of `(time, value)` points and its color.
+type Line<'a> = (&'a str, RGBColor, Vec<(f32, f32)>);
+
+/// An error while rendering a plot.
+#[derive(Debug)]
+pub struct PlotError(String);
+
+impl fmt::Display for PlotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to plot: {}", self.0)
+    }
+}
+
+impl error::Error for PlotError {}
+
+impl<E: error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for PlotError {
+    fn from(err: DrawingAreaErrorKind<E>) -> Self {
+        PlotError(err.to_string())
+    }
+}
+
+/// Renders `rows` to a PNG image at `path`, with one panel each for the levels before and after
+/// processing, the ERLE, the estimated delay and the speech probability, over time.
+pub fn plot_stats_png(rows: &[StatsRow], path: &Path) -> Result<(), PlotError> {
+    let root = BitMapBackend::new(path, IMAGE_SIZE).into_drawing_area();
+    plot_stats(rows, &root)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Draws the panels of [`plot_stats_png()`] on any plotters drawing area.
+pub fn plot_stats<DB: DrawingBackend>(
+    rows: &[StatsRow],
+    area: &DrawingArea<DB, Shift>,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    area.fill(&WHITE)?;
+    let duration = rows.last().map_or(1.0, |row| row.time_secs().max(0.01)) as f32;
+    let panels = area.split_evenly((4, 1));
+
+    let series = |value: fn(&StatsRow) -> Option<f32>| -> Vec<(f32, f32)> {
+        rows.iter()
+            .filter_map(|row| value(row).map(|value| (row.time_secs() as f32, value)))
+            .collect()
+    };
+    draw_panel(
+        &panels[0],
+        "Level (dBFS)",
+        duration,
+        -100.0..0.0,
+        &[
+            ("input", RED, series(|row| Some(row.input_db))),
+            ("output", BLUE, series(|row| Some(row.output_db))),
+        ],
+    )?;
+    draw_panel(
+        &panels[1],
+        "ERLE (dB)",
+        duration,
+        0.0..60.0,
+        &[("ERLE", BLUE, series(|row| row.stats.echo_return_loss_enhancement.map(|v| v as f32)))],
+    )?;
+    draw_panel(
+        &panels[2],
+        "Delay (ms)",
+        duration,
+        0.0..500.0,
+        &[("median", BLUE, series(|row| row.stats.delay_median_ms.map(|v| v as f32)))],
+    )?;
+    draw_panel(
+        &panels[3],
+        "Speech probability",
+        duration,
+        0.0..1.0,
+        &[
+            ("speech", BLUE, series(|row| row.stats.speech_probability.map(|v| v as f32))),
+            ("voice", RED, series(|row| row.stats.has_voice.map(|v| v as u8 as f32))),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Draws `lines` of `(time, value)` points in a panel titled `title`.
+fn draw_panel<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    title: &str,
+    duration: f32,
+    range: Range<f32>,
+    lines: &[Line],
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    let mut chart = ChartBuilder::on(area)
+        .caption(title, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(0.0..duration, range)?;
+    chart.configure_mesh().x_desc("Time (s)").draw()?;
+    for (label, color, points) in lines {
+        let color = *color;
+        chart
+            .draw_series(LineSeries::new(points.iter().copied(), color))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    chart.configure_series_labels().background_style(WHITE.mix(0.8)).border_style(BLACK).draw()?;
+    Ok(())
+}
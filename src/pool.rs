@@ -0,0 +1,317 @@
+use crate::{
+    thread_priority, Config, DenormalGuard, Error, InitializationConfig, Processor, Stats,
+};
+use std::{
+    collections::VecDeque,
+    sync::{mpsc, Arc, Mutex, Weak},
+    thread,
+};
+
+/// Owns many independent `Processor`s and processes them on a fixed set of worker threads.
+/// Intended for server-side use e.g. an SFU processing hundreds of participant streams, where
+/// spawning a thread per stream wastes scheduling and cache resources.
+///
+/// Frames are queued per stream through a [`StreamHandle`], and [`ProcessorPool::tick()`]
+/// processes everything queued so far across the workers. Call it from the application's 10 ms
+/// timer. Each stream is pinned to a single worker so that its processor state stays warm in the
+/// cache of the same thread. The workers flush denormals to zero; see [`DenormalGuard`].
+pub struct ProcessorPool {
+    workers: Vec<Worker>,
+    next_worker_index: usize,
+}
+
+impl ProcessorPool {
+    /// Creates a new pool with `num_workers` normal priority worker threads. `num_workers` must
+    /// be non-zero. Use [`ProcessorPoolBuilder`] for more options.
+    pub fn new(num_workers: usize) -> Self {
+        ProcessorPoolBuilder::new(num_workers).build()
+    }
+
+    /// Creates a new `Processor` for a stream and assigns it to one of the workers. The stream
+    /// is removed from the pool when the returned handle is dropped.
+    pub fn add_stream(&mut self, config: &InitializationConfig) -> Result<StreamHandle, Error> {
+        let state = Arc::new(Mutex::new(StreamState {
+            processor: Processor::new(config)?,
+            render_queue: VecDeque::new(),
+            capture_queue: VecDeque::new(),
+            processed_capture_queue: VecDeque::new(),
+        }));
+
+        let worker = &self.workers[self.next_worker_index];
+        self.next_worker_index = (self.next_worker_index + 1) % self.workers.len();
+        worker.send(Message::AddStream(Arc::downgrade(&state)));
+
+        Ok(StreamHandle { state })
+    }
+
+    /// Processes all the frames queued on every stream, and blocks until all the workers are
+    /// done. For each stream, the queued render frames are processed before the capture frames.
+    pub fn tick(&self) {
+        let (done_tx, done_rx) = mpsc::channel();
+        for worker in &self.workers {
+            worker.send(Message::Tick(done_tx.clone()));
+        }
+        drop(done_tx);
+
+        // Returns once every worker has dropped its sender.
+        while done_rx.recv().is_ok() {}
+    }
+
+    /// Returns the number of worker threads.
+    pub fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Returns which of the requested thread settings could actually be applied, per worker.
+    pub fn worker_thread_status(&self) -> Vec<WorkerThreadStatus> {
+        self.workers.iter().map(|worker| worker.status).collect()
+    }
+}
+
+/// Builds a [`ProcessorPool`] with non-default worker thread settings.
+#[derive(Debug, Clone)]
+pub struct ProcessorPoolBuilder {
+    num_workers: usize,
+    real_time_priority: bool,
+    pin_to_cores: bool,
+}
+
+impl ProcessorPoolBuilder {
+    /// Starts building a pool with `num_workers` worker threads. `num_workers` must be non-zero.
+    pub fn new(num_workers: usize) -> Self {
+        Self { num_workers, real_time_priority: false, pin_to_cores: false }
+    }
+
+    /// Runs the workers with real-time scheduling (SCHED_FIFO), so that they keep meeting the
+    /// 10 ms deadlines when the machine is under load. Only supported on unix platforms. When
+    /// the process lacks the permission, the workers silently fall back to normal priority;
+    /// check [`ProcessorPool::worker_thread_status()`] to find out.
+    pub fn real_time_priority(mut self, enabled: bool) -> Self {
+        self.real_time_priority = enabled;
+        self
+    }
+
+    /// Pins each worker to its own CPU core, wrapping around when there are more workers than
+    /// cores. Only supported on Linux and Android, and falls back to unpinned threads otherwise.
+    pub fn pin_to_cores(mut self, enabled: bool) -> Self {
+        self.pin_to_cores = enabled;
+        self
+    }
+
+    /// Spawns the worker threads and returns the pool.
+    pub fn build(self) -> ProcessorPool {
+        assert!(self.num_workers > 0, "ProcessorPool needs at least one worker.");
+        let num_cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let workers = (0..self.num_workers)
+            .map(|index| {
+                Worker::spawn(
+                    index,
+                    self.real_time_priority,
+                    if self.pin_to_cores { Some(index % num_cores) } else { None },
+                )
+            })
+            .collect();
+        ProcessorPool { workers, next_worker_index: 0 }
+    }
+}
+
+/// The thread settings in effect for a worker of a [`ProcessorPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerThreadStatus {
+    /// True if the worker runs with real-time priority.
+    pub real_time_priority: bool,
+
+    /// The CPU core the worker is pinned to, if any.
+    pub pinned_core: Option<usize>,
+}
+
+/// A handle to a single stream processed by a `ProcessorPool`.
+pub struct StreamHandle {
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl StreamHandle {
+    /// Queues an interleaved capture frame to be processed on the next `tick()`.
+    pub fn push_capture_frame(&self, frame: Vec<f32>) {
+        self.lock().capture_queue.push_back(frame);
+    }
+
+    /// Queues an interleaved render frame to be processed on the next `tick()`.
+    pub fn push_render_frame(&self, frame: Vec<f32>) {
+        self.lock().render_queue.push_back(frame);
+    }
+
+    /// Pops the oldest processed capture frame, or the error it failed with.
+    pub fn pop_processed_capture_frame(&self) -> Option<Result<Vec<f32>, Error>> {
+        self.lock().processed_capture_queue.pop_front()
+    }
+
+    /// Updates the config of the stream's processor. Takes effect from the next `tick()`.
+    pub fn set_config(&self, config: Config) {
+        self.lock().processor.set_config(config);
+    }
+
+    /// Returns statistics from the last processed capture frame of the stream.
+    pub fn get_stats(&self) -> Stats {
+        self.lock().processor.get_stats()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StreamState> {
+        self.state.lock().expect("stream state mutex poisoned")
+    }
+}
+
+struct StreamState {
+    processor: Processor,
+    render_queue: VecDeque<Vec<f32>>,
+    capture_queue: VecDeque<Vec<f32>>,
+    processed_capture_queue: VecDeque<Result<Vec<f32>, Error>>,
+}
+
+impl StreamState {
+    fn process_queued_frames(&mut self) {
+        while let Some(mut frame) = self.render_queue.pop_front() {
+            // Render frames are only used as the echo reference, so there is nobody to report
+            // the error to. The following capture frames are still processed.
+            let _ = self.processor.process_render_frame(&mut frame);
+        }
+        while let Some(mut frame) = self.capture_queue.pop_front() {
+            let result = self.processor.process_capture_frame(&mut frame).map(|_| frame);
+            self.processed_capture_queue.push_back(result);
+        }
+    }
+}
+
+enum Message {
+    AddStream(Weak<Mutex<StreamState>>),
+    Tick(mpsc::Sender<()>),
+}
+
+struct Worker {
+    sender: Option<mpsc::Sender<Message>>,
+    thread: Option<thread::JoinHandle<()>>,
+    status: WorkerThreadStatus,
+}
+
+impl Worker {
+    fn spawn(index: usize, real_time_priority: bool, core_index: Option<usize>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (status_tx, status_rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(format!("processor-pool-{}", index))
+            .spawn(move || {
+                let status = WorkerThreadStatus {
+                    real_time_priority: real_time_priority
+                        && thread_priority::promote_current_thread_to_real_time(),
+                    pinned_core: core_index
+                        .filter(|&core| thread_priority::pin_current_thread_to_core(core)),
+                };
+                let _ = status_tx.send(status);
+                Self::run(receiver)
+            })
+            .expect("failed to spawn a processor pool worker");
+        let status = status_rx.recv().expect("processor pool worker exited unexpectedly");
+        Self { sender: Some(sender), thread: Some(thread), status }
+    }
+
+    fn run(receiver: mpsc::Receiver<Message>) {
+        let _denormal_guard = DenormalGuard::new();
+        let mut streams: Vec<Weak<Mutex<StreamState>>> = Vec::new();
+        // Exits when the pool drops the sender.
+        while let Ok(message) = receiver.recv() {
+            match message {
+                Message::AddStream(stream) => streams.push(stream),
+                Message::Tick(_done) => {
+                    // Streams whose handle has been dropped are removed here.
+                    streams.retain(|stream| match stream.upgrade() {
+                        Some(state) => {
+                            if let Ok(mut state) = state.lock() {
+                                state.process_queued_frames();
+                            }
+                            true
+                        },
+                        None => false,
+                    });
+                },
+            }
+        }
+    }
+
+    fn send(&self, message: Message) {
+        self.sender
+            .as_ref()
+            .expect("worker already shut down")
+            .send(message)
+            .expect("processor pool worker exited unexpectedly");
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NUM_SAMPLES_PER_FRAME;
+
+    #[test]
+    fn test_pool_processes_all_streams() {
+        let config = InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let mut pool = ProcessorPool::new(2);
+        let handles =
+            (0..5).map(|_| pool.add_stream(&config).unwrap()).collect::<Vec<StreamHandle>>();
+
+        let frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize];
+        for handle in &handles {
+            handle.push_render_frame(frame.clone());
+            handle.push_capture_frame(frame.clone());
+            handle.push_capture_frame(frame.clone());
+        }
+        pool.tick();
+
+        for handle in &handles {
+            assert_eq!(frame.len(), handle.pop_processed_capture_frame().unwrap().unwrap().len());
+            assert!(handle.pop_processed_capture_frame().unwrap().is_ok());
+            assert!(handle.pop_processed_capture_frame().is_none());
+        }
+    }
+
+    #[test]
+    fn test_pool_thread_settings_fall_back() {
+        // Whether these are applied depends on the platform and permissions, but building the
+        // pool must succeed either way.
+        let pool = ProcessorPoolBuilder::new(2).real_time_priority(true).pin_to_cores(true).build();
+        assert_eq!(2, pool.worker_thread_status().len());
+
+        let pool = ProcessorPool::new(1);
+        assert_eq!(
+            vec![WorkerThreadStatus { real_time_priority: false, pinned_core: None }],
+            pool.worker_thread_status()
+        );
+    }
+
+    #[test]
+    fn test_pool_drops_removed_streams() {
+        let config = InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        };
+        let mut pool = ProcessorPool::new(1);
+        let handle = pool.add_stream(&config).unwrap();
+        let weak = Arc::downgrade(&handle.state);
+        drop(handle);
+        pool.tick();
+        assert!(weak.upgrade().is_none());
+    }
+}
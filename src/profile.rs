@@ -0,0 +1,337 @@
+//! Named [`Config`] profiles stored on disk, e.g. "Headset", "Speakerphone" and "Studio", to
+//! switch between at runtime.
+//!
+//! Each profile is a JSON file in the store directory. A profile may inherit from another one,
+//! and then only holds the fields it changes; the chain is followed up to a profile without a
+//! parent, whose missing fields come from `Config::default()`.
+//!
+//! ```no_run
+//! use webrtc_audio_processing::{profile::*, *};
+//!
+//! let mut store = ProfileStore::open("profiles").unwrap();
+//! let studio = Config { enable_high_pass_filter: true, ..Config::default() };
+//! store.insert("Studio", &Profile::from_config(&studio)).unwrap();
+//! store
+//!     .insert(
+//!         "Speakerphone",
+//!         &Profile::inheriting("Studio", serde_json::json!({ "enable_comfort_noise": true })),
+//!     )
+//!     .unwrap();
+//!
+//! let mut processor = Processor::new(&InitializationConfig::default()).unwrap();
+//! store.apply("Speakerphone", &mut processor).unwrap();
+//! ```
+
+use crate::{Config, Processor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// An error while loading, storing or resolving profiles.
+#[derive(Debug)]
+pub enum ProfileError {
+    /// A profile file or the store directory couldn't be read or written.
+    Io(PathBuf, io::Error),
+    /// A profile file isn't valid.
+    Json(PathBuf, serde_json::Error),
+    /// The profile, or a parent of it, doesn't exist.
+    NotFound(String),
+    /// A profile name can't be used as a file name.
+    InvalidName(String),
+    /// The inheritance chain of the profile loops, listed from the profile on.
+    Cycle(Vec<String>),
+    /// The merged fields of the chain don't make a valid `Config`.
+    InvalidConfig(String, serde_json::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::Io(path, err) => {
+                write!(f, "failed to access {}: {}", path.display(), err)
+            },
+            ProfileError::Json(path, err) => write!(f, "invalid {}: {}", path.display(), err),
+            ProfileError::NotFound(name) => write!(f, "profile {:?} not found", name),
+            ProfileError::InvalidName(name) => write!(f, "invalid profile name {:?}", name),
+            ProfileError::Cycle(chain) => {
+                write!(f, "profiles inherit from each other: {}", chain.join(" -> "))
+            },
+            ProfileError::InvalidConfig(name, err) => {
+                write!(f, "profile {:?} doesn't resolve to a valid config: {}", name, err)
+            },
+        }
+    }
+}
+
+impl error::Error for ProfileError {}
+
+/// A named config, as stored in its file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// The profile the fields missing from `config` are taken from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+    /// The fields of `Config` this profile sets, in its serde representation, as an object.
+    /// Nested objects are merged field by field too, e.g.
+    /// `{"echo_cancellation": {"stream_delay_ms": 40}}` changes only the delay of the inherited
+    /// echo cancellation. Sections the parent leaves disabled have to be given in full.
+    pub config: Value,
+}
+
+impl Profile {
+    /// Creates a profile holding all of `config`.
+    pub fn from_config(config: &Config) -> Self {
+        // Serializing a `Config` can't fail: it has no maps with non-string keys. Non-finite
+        // floats, which no sensible config has, would come out as `null` and fail to resolve.
+        Self { inherits: None, config: serde_json::to_value(config).unwrap() }
+    }
+
+    /// Creates a profile that changes only the `fields` of the `parent` profile.
+    pub fn inheriting(parent: impl Into<String>, fields: Value) -> Self {
+        Self { inherits: Some(parent.into()), config: fields }
+    }
+}
+
+/// A directory of named [`Profile`]s, loaded into memory when opened and written through on
+/// every change.
+pub struct ProfileStore {
+    dir: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfileStore {
+    /// Opens the store in `dir`, creating the directory if needed, and loads every `.json` file
+    /// in it as a profile named after the file.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, ProfileError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|err| ProfileError::Io(dir.clone(), err))?;
+        let mut profiles = BTreeMap::new();
+        for entry in fs::read_dir(&dir).map_err(|err| ProfileError::Io(dir.clone(), err))? {
+            let path = entry.map_err(|err| ProfileError::Io(dir.clone(), err))?.path();
+            let name = match (path.extension(), path.file_stem().and_then(|stem| stem.to_str())) {
+                (Some(extension), Some(name)) if extension == "json" => name.to_string(),
+                _ => continue,
+            };
+            let json =
+                fs::read_to_string(&path).map_err(|err| ProfileError::Io(path.clone(), err))?;
+            let profile =
+                serde_json::from_str(&json).map_err(|err| ProfileError::Json(path, err))?;
+            profiles.insert(name, profile);
+        }
+        Ok(Self { dir, profiles })
+    }
+
+    /// Returns the names of the profiles, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Returns the profile called `name`, as stored, i.e. without the inherited fields.
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Stores `profile` under `name`, replacing any profile of that name. The file is replaced
+    /// atomically, so that a crash doesn't leave a truncated profile behind.
+    pub fn insert(&mut self, name: &str, profile: &Profile) -> Result<(), ProfileError> {
+        let path = self.path(name)?;
+        let json = serde_json::to_string_pretty(profile)
+            .map_err(|err| ProfileError::Json(path.clone(), err))?;
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, json).map_err(|err| ProfileError::Io(temp_path.clone(), err))?;
+        fs::rename(&temp_path, &path).map_err(|err| ProfileError::Io(path, err))?;
+        self.profiles.insert(name.to_string(), profile.clone());
+        Ok(())
+    }
+
+    /// Removes the profile called `name`, and returns it. Profiles inheriting from it fail to
+    /// resolve until it's replaced.
+    pub fn remove(&mut self, name: &str) -> Result<Profile, ProfileError> {
+        let path = self.path(name)?;
+        if !self.profiles.contains_key(name) {
+            return Err(ProfileError::NotFound(name.to_string()));
+        }
+        fs::remove_file(&path).map_err(|err| ProfileError::Io(path, err))?;
+        Ok(self.profiles.remove(name).unwrap())
+    }
+
+    /// Returns the config of the profile called `name`, with the fields it inherits.
+    pub fn resolve(&self, name: &str) -> Result<Config, ProfileError> {
+        let mut chain = vec![name.to_string()];
+        let mut profile =
+            self.profiles.get(name).ok_or_else(|| ProfileError::NotFound(name.to_string()))?;
+        let mut layers = vec![&profile.config];
+        while let Some(parent) = &profile.inherits {
+            if chain.contains(parent) {
+                chain.push(parent.clone());
+                return Err(ProfileError::Cycle(chain));
+            }
+            profile =
+                self.profiles.get(parent).ok_or_else(|| ProfileError::NotFound(parent.clone()))?;
+            chain.push(parent.clone());
+            layers.push(&profile.config);
+        }
+
+        let mut merged = serde_json::to_value(Config::default()).unwrap();
+        for layer in layers.into_iter().rev() {
+            merge(&mut merged, layer);
+        }
+        serde_json::from_value(merged)
+            .map_err(|err| ProfileError::InvalidConfig(name.to_string(), err))
+    }
+
+    /// Resolves the profile called `name` and applies it to `processor` with
+    /// `Processor::set_config()`, all at once. On error, the processor is left untouched.
+    /// Returns the applied config.
+    pub fn apply(&self, name: &str, processor: &mut Processor) -> Result<Config, ProfileError> {
+        let config = self.resolve(name)?;
+        processor.set_config(config.clone());
+        Ok(config)
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, ProfileError> {
+        let is_file_name = Path::new(name).file_name() == Some(name.as_ref());
+        if !is_file_name || name.starts_with('.') {
+            return Err(ProfileError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+/// Merges the fields of `overrides` into `base`, recursing into objects present in both.
+fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    },
+                }
+            }
+        },
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        EchoCancellation, EchoCancellationSuppressionLevel, EchoControlMobile,
+        EchoControlMobileRoutingMode, GainControl, GainControlMode, HighPassFilter,
+        NoiseSuppression, NoiseSuppressionLevel, VoiceDetection, VoiceDetectionLikelihood,
+        VoiceGate,
+    };
+    use serde_json::json;
+    use std::process;
+
+    /// Returns an empty directory for the store of the test `name`, unique to this run, so that
+    /// concurrent runs don't share it.
+    fn store_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "webrtc-audio-processing-profiles-{}-{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_profile_store() {
+        let dir = store_dir("store");
+        let mut store = ProfileStore::open(&dir).unwrap();
+
+        let headset = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Low,
+                enable_extended_filter: false,
+                enable_delay_agnostic: true,
+                stream_delay_ms: None,
+            }),
+            enable_high_pass_filter: true,
+            ..Config::default()
+        };
+        store.insert("Headset", &Profile::from_config(&headset)).unwrap();
+        let speakerphone = Profile::inheriting(
+            "Headset",
+            json!({ "echo_cancellation": { "suppression_level": "High" } }),
+        );
+        store.insert("Speakerphone", &speakerphone).unwrap();
+
+        // Persisted, and reloaded.
+        let store = ProfileStore::open(&dir).unwrap();
+        assert_eq!(vec!["Headset", "Speakerphone"], store.names().collect::<Vec<_>>());
+        let mut expected = headset.clone();
+        expected.echo_cancellation.as_mut().unwrap().suppression_level =
+            EchoCancellationSuppressionLevel::High;
+        assert_eq!(expected, store.resolve("Speakerphone").unwrap());
+        assert_eq!(headset, store.resolve("Headset").unwrap());
+
+        let mut store = store;
+        store.insert("Headset", &Profile::inheriting("Speakerphone", json!({}))).unwrap();
+        match store.resolve("Speakerphone") {
+            Err(ProfileError::Cycle(chain)) => {
+                assert_eq!(vec!["Speakerphone", "Headset", "Speakerphone"], chain)
+            },
+            result => panic!("unexpected {:?}", result),
+        }
+        store.remove("Headset").unwrap();
+        match store.resolve("Speakerphone") {
+            Err(ProfileError::NotFound(name)) => assert_eq!("Headset", name),
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(matches!(store.insert("../x", &speakerphone), Err(ProfileError::InvalidName(_))));
+
+        let broken =
+            Profile { inherits: None, config: json!({ "gain_control": { "mode": "Loud" } }) };
+        store.insert("Broken", &broken).unwrap();
+        assert!(matches!(store.resolve("Broken"), Err(ProfileError::InvalidConfig(..))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_all_sections() {
+        let dir = store_dir("all-sections");
+        let mut store = ProfileStore::open(&dir).unwrap();
+        let config = Config {
+            echo_cancellation: Some(EchoCancellation {
+                suppression_level: EchoCancellationSuppressionLevel::Moderate,
+                enable_extended_filter: true,
+                enable_delay_agnostic: true,
+                stream_delay_ms: Some(40),
+            }),
+            echo_control_mobile: Some(EchoControlMobile {
+                routing_mode: EchoControlMobileRoutingMode::Earpiece,
+                enable_comfort_noise: false,
+                stream_delay_ms: Some(20),
+            }),
+            gain_control: Some(GainControl {
+                mode: GainControlMode::AdaptiveAnalog,
+                target_level_dbfs: 3,
+                compression_gain_db: 9,
+                enable_limiter: true,
+            }),
+            noise_suppression: Some(NoiseSuppression {
+                suppression_level: NoiseSuppressionLevel::Moderate,
+            }),
+            voice_detection: Some(VoiceDetection {
+                detection_likelihood: VoiceDetectionLikelihood::High,
+            }),
+            high_pass_filter: Some(HighPassFilter::default()),
+            voice_gate: Some(VoiceGate::default()),
+            enable_comfort_noise: true,
+            ..Config::default()
+        };
+        store.insert("Everything", &Profile::from_config(&config)).unwrap();
+        let store = ProfileStore::open(&dir).unwrap();
+        assert_eq!(config, store.resolve("Everything").unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{Error, FrameChunker, Processor, NUM_SAMPLES_PER_FRAME};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How much each new measurement moves the stream delay estimate. Jitter buffers adapt their
+/// depth continuously, and echo cancellation copes better with a slowly drifting delay than
+/// with one that jumps on every packet.
+const DELAY_SMOOTHING: f32 = 0.05;
+
+/// The most audio `RenderPlayoutQueue` keeps released for the playback device, in 10 ms frames.
+/// Beyond that the device isn't keeping up, and the oldest audio is dropped rather than letting
+/// the playout delay grow without bounds.
+const MAX_RELEASED_FRAMES: usize = 20;
+
+/// Feeds far-end audio coming out of a network jitter buffer as the render stream, and keeps
+/// `Processor::set_stream_delay_ms()` up to date.
+///
+/// When the decoded audio is fed to the processor ahead of its playout, the echo shows up in
+/// the capture stream only after the time it spends queued, plus the latency of the audio
+/// devices. The queued time is derived from the playout time of each frame; the device latency
+/// has to be given. Decoded frames may be of any length, e.g. 20 ms Opus frames.
+///
+/// The delay is only used while `EchoCancellation::stream_delay_ms` is set in the config, as
+/// delay-agnostic echo cancellation estimates it on its own otherwise.
+pub struct NetworkRenderFeeder {
+    processor: Processor,
+    chunker: FrameChunker,
+    device_latency: Duration,
+    delay_ms: Option<f32>,
+}
+
+impl NetworkRenderFeeder {
+    /// Creates a new feeder for interleaved frames of `num_channels` channels. `device_latency`
+    /// is the output latency of the playback device plus the input latency of the capture
+    /// device.
+    pub fn new(processor: Processor, num_channels: usize, device_latency: Duration) -> Self {
+        Self { processor, chunker: FrameChunker::new(num_channels), device_latency, delay_ms: None }
+    }
+
+    /// Feeds a decoded frame which is due to be played at `playout_time`. Call it as soon as the
+    /// frame leaves the jitter buffer.
+    pub fn feed(&mut self, frame: &[f32], playout_time: Instant) -> Result<(), Error> {
+        self.feed_at(frame, playout_time, Instant::now())
+    }
+
+    /// Same as [`feed()`](Self::feed), with the playout time derived from the RTP timestamp of
+    /// the frame. Frames fed before `clock` is anchored don't update the stream delay.
+    pub fn feed_rtp(
+        &mut self,
+        frame: &[f32],
+        rtp_timestamp: u32,
+        clock: &RtpPlayoutClock,
+    ) -> Result<(), Error> {
+        match clock.playout_time(rtp_timestamp) {
+            Some(playout_time) => self.feed(frame, playout_time),
+            None => self.feed_frames(frame),
+        }
+    }
+
+    /// Returns the current stream delay estimate in ms, or `None` before the first estimate.
+    pub fn stream_delay_ms(&self) -> Option<i32> {
+        self.delay_ms.map(|delay_ms| delay_ms.round() as i32)
+    }
+
+    /// Updates the output latency of the playback device plus the input latency of the capture
+    /// device, e.g. after switching devices.
+    pub fn set_device_latency(&mut self, device_latency: Duration) {
+        self.device_latency = device_latency;
+    }
+
+    fn feed_at(&mut self, frame: &[f32], playout_time: Instant, now: Instant) -> Result<(), Error> {
+        let queued = playout_time.saturating_duration_since(now);
+        let delay_ms = (queued + self.device_latency).as_secs_f32() * 1000.0;
+        let delay_ms = match self.delay_ms {
+            Some(previous) => previous + (delay_ms - previous) * DELAY_SMOOTHING,
+            None => delay_ms,
+        };
+        self.delay_ms = Some(delay_ms);
+        self.processor.set_stream_delay_ms(delay_ms.round() as i32);
+        self.feed_frames(frame)
+    }
+
+    fn feed_frames(&mut self, frame: &[f32]) -> Result<(), Error> {
+        let processor = &mut self.processor;
+        self.chunker.consume(frame, |frame| processor.process_render_frame(frame))
+    }
+}
+
+/// Queues decoded far-end audio between a network-paced decoder and a device-paced playback
+/// stream, and passes it to `Processor::process_render_frame()` at a steady 10 ms cadence.
+///
+/// Decoders produce bursts of frames of any length, as packets arrive. `push()` queues them,
+/// `release_frame()` processes the next 10 ms as the render stream, and `read()` hands the
+/// released audio to the playback device. Call `release_frame()` every 10 ms, e.g. from a timer
+/// or from the playback callback itself, and `read()` from the playback callback. The echo of
+/// a released frame shows up in the capture stream after the audio released before it has been
+/// read, plus the latency of the devices, which `Processor::set_stream_delay_ms()` is kept up
+/// to date with on every release. As with `NetworkRenderFeeder`, the delay is only used while
+/// `EchoCancellation::stream_delay_ms` is set in the config.
+///
+/// At most 200 ms of released audio is kept. If the playback device reads less than is
+/// released, the oldest audio is dropped, and the release counted as an overrun.
+///
+/// Wrap it in a `Mutex` to call it from several threads.
+pub struct RenderPlayoutQueue {
+    processor: Processor,
+    frame: Vec<f32>,
+    device_latency: Duration,
+    pending: VecDeque<f32>,
+    released: VecDeque<f32>,
+    num_underruns: usize,
+    num_overruns: usize,
+    delay_ms: Option<i32>,
+}
+
+impl RenderPlayoutQueue {
+    /// Creates a new queue for interleaved frames of `num_channels` channels. `device_latency`
+    /// is the output latency of the playback device plus the input latency of the capture
+    /// device.
+    ///
+    /// Panics if `num_channels` is 0.
+    pub fn new(processor: Processor, num_channels: usize, device_latency: Duration) -> Self {
+        assert!(num_channels > 0, "a RenderPlayoutQueue needs at least one channel");
+        Self {
+            processor,
+            frame: vec![0.0; NUM_SAMPLES_PER_FRAME as usize * num_channels],
+            device_latency,
+            pending: VecDeque::new(),
+            released: VecDeque::new(),
+            num_underruns: 0,
+            num_overruns: 0,
+            delay_ms: None,
+        }
+    }
+
+    /// Queues decoded samples ahead of their playout. Returns `Error::BadDataLength`, and
+    /// queues nothing, unless `frame` holds a whole number of samples per channel.
+    pub fn push(&mut self, frame: &[f32]) -> Result<(), Error> {
+        let num_channels = self.frame.len() / NUM_SAMPLES_PER_FRAME as usize;
+        if frame.len() / num_channels * num_channels != frame.len() {
+            return Err(Error::BadDataLength);
+        }
+        self.pending.extend(frame.iter());
+        Ok(())
+    }
+
+    /// Processes the next 10 ms of queued audio as the render stream and releases it for
+    /// playout. If less than 10 ms is queued, the rest of the frame is silence, and it's counted
+    /// as an underrun. Audio keeps being released on errors, so that the cadence holds.
+    pub fn release_frame(&mut self) -> Result<(), Error> {
+        let num_queued = self.pending.len().min(self.frame.len());
+        if num_queued < self.frame.len() {
+            self.num_underruns += 1;
+        }
+        for (sample, queued) in self
+            .frame
+            .iter_mut()
+            .zip(self.pending.drain(..num_queued).chain(std::iter::repeat(0.0)))
+        {
+            *sample = queued;
+        }
+
+        let delay_ms = (self.duration_of(self.released.len()) + self.device_latency).as_millis();
+        let delay_ms = delay_ms.min(i32::MAX as u128) as i32;
+        self.delay_ms = Some(delay_ms);
+        self.processor.set_stream_delay_ms(delay_ms);
+        let result = self.processor.process_render_frame(&mut self.frame);
+        let max_released = MAX_RELEASED_FRAMES * self.frame.len();
+        if self.released.len() + self.frame.len() > max_released {
+            self.num_overruns += 1;
+            self.released.drain(..self.released.len() + self.frame.len() - max_released);
+        }
+        self.released.extend(self.frame.iter());
+        result
+    }
+
+    /// Fills `buffer` with released audio for the playback device, padded with silence if not
+    /// enough has been released.
+    pub fn read(&mut self, buffer: &mut [f32]) {
+        let num_released = self.released.len().min(buffer.len());
+        for (sample, released) in
+            buffer.iter_mut().zip(self.released.drain(..num_released).chain(std::iter::repeat(0.0)))
+        {
+            *sample = released;
+        }
+    }
+
+    /// Returns the duration of the audio pushed but not released yet, i.e. the depth of the
+    /// queue in front of the processor.
+    pub fn queued_duration(&self) -> Duration {
+        self.duration_of(self.pending.len())
+    }
+
+    /// Returns the duration of the audio released but not read by the playback device yet.
+    pub fn released_duration(&self) -> Duration {
+        self.duration_of(self.released.len())
+    }
+
+    /// Returns how many frames have been released with less than 10 ms queued.
+    pub fn num_underruns(&self) -> usize {
+        self.num_underruns
+    }
+
+    /// Returns how many releases dropped older audio because the playback device didn't read it
+    /// in time.
+    pub fn num_overruns(&self) -> usize {
+        self.num_overruns
+    }
+
+    /// Returns the stream delay set on the last release in ms, or `None` before the first one.
+    pub fn stream_delay_ms(&self) -> Option<i32> {
+        self.delay_ms
+    }
+
+    /// Updates the output latency of the playback device plus the input latency of the capture
+    /// device, e.g. after switching devices.
+    pub fn set_device_latency(&mut self, device_latency: Duration) {
+        self.device_latency = device_latency;
+    }
+
+    fn duration_of(&self, num_samples: usize) -> Duration {
+        // A frame is 10 ms.
+        Duration::from_micros(num_samples as u64 * 10_000 / self.frame.len() as u64)
+    }
+}
+
+/// Maps the RTP timestamps of a stream to playout times, once the jitter buffer has reported
+/// when one of them is played out.
+#[derive(Debug, Clone)]
+pub struct RtpPlayoutClock {
+    clock_rate: u32,
+    anchor: Option<(u32, Instant)>,
+}
+
+impl RtpPlayoutClock {
+    /// Creates a new clock for RTP timestamps at `clock_rate` Hz, e.g. 48000 for Opus.
+    pub fn new(clock_rate: u32) -> Self {
+        Self { clock_rate, anchor: None }
+    }
+
+    /// Records that the sample at `rtp_timestamp` is played out at `playout_time`. Call it
+    /// again whenever the jitter buffer changes its depth.
+    pub fn anchor(&mut self, rtp_timestamp: u32, playout_time: Instant) {
+        self.anchor = Some((rtp_timestamp, playout_time));
+    }
+
+    /// Returns when the sample at `rtp_timestamp` is played out, or `None` if the clock hasn't
+    /// been anchored yet. Timestamps wrap around, and may be up to 2^31 ticks either side of the
+    /// anchor.
+    pub fn playout_time(&self, rtp_timestamp: u32) -> Option<Instant> {
+        let (anchor_timestamp, anchor_time) = self.anchor?;
+        let ticks = rtp_timestamp.wrapping_sub(anchor_timestamp) as i32;
+        let offset = Duration::from_nanos(
+            ticks.unsigned_abs() as u64 * 1_000_000_000 / self.clock_rate as u64,
+        );
+        if ticks >= 0 {
+            anchor_time.checked_add(offset)
+        } else {
+            anchor_time.checked_sub(offset)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InitializationConfig, NUM_SAMPLES_PER_FRAME};
+
+    #[test]
+    fn test_rtp_playout_clock() {
+        let mut clock = RtpPlayoutClock::new(48_000);
+        assert_eq!(None, clock.playout_time(0));
+
+        let now = Instant::now();
+        clock.anchor(u32::MAX - 479, now);
+        assert_eq!(Some(now + Duration::from_millis(20)), clock.playout_time(480));
+        assert_eq!(Some(now - Duration::from_millis(10)), clock.playout_time(u32::MAX - 959));
+    }
+
+    #[test]
+    fn test_network_render_feeder_smooths_delay() {
+        let processor = Processor::new(&InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 1,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let mut feeder = NetworkRenderFeeder::new(processor, 1, Duration::from_millis(20));
+        assert_eq!(None, feeder.stream_delay_ms());
+
+        // 20 ms frames.
+        let frame = vec![0f32; NUM_SAMPLES_PER_FRAME as usize * 2];
+        let now = Instant::now();
+        feeder.feed_at(&frame, now + Duration::from_millis(100), now).unwrap();
+        assert_eq!(Some(120), feeder.stream_delay_ms());
+        // The jitter buffer grows by 100 ms.
+        feeder.feed_at(&frame, now + Duration::from_millis(200), now).unwrap();
+        assert_eq!(Some(125), feeder.stream_delay_ms());
+    }
+
+    #[test]
+    fn test_render_playout_queue() {
+        let processor = Processor::new(&InitializationConfig {
+            num_capture_channels: 2,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        })
+        .unwrap();
+        let mut queue = RenderPlayoutQueue::new(processor, 2, Duration::from_millis(20));
+        let frame_len = NUM_SAMPLES_PER_FRAME as usize * 2;
+
+        // A burst of 25 ms.
+        assert_eq!(Err(Error::BadDataLength), queue.push(&[0.5; 3]));
+        queue.push(&vec![0.5; frame_len * 5 / 2]).unwrap();
+        assert_eq!(Duration::from_millis(25), queue.queued_duration());
+        queue.release_frame().unwrap();
+        assert_eq!(Some(20), queue.stream_delay_ms());
+        queue.release_frame().unwrap();
+        assert_eq!(Some(30), queue.stream_delay_ms());
+        assert_eq!(0, queue.num_underruns());
+        queue.release_frame().unwrap();
+        assert_eq!(Some(40), queue.stream_delay_ms());
+        assert_eq!(1, queue.num_underruns());
+        assert_eq!(Duration::ZERO, queue.queued_duration());
+
+        // The playback device reads 25 ms.
+        let mut buffer = vec![0f32; frame_len * 5 / 2];
+        queue.read(&mut buffer);
+        assert_eq!(Duration::from_millis(5), queue.released_duration());
+        queue.release_frame().unwrap();
+        assert_eq!(Some(25), queue.stream_delay_ms());
+        assert_eq!(0, queue.num_overruns());
+
+        // The playback device stops reading.
+        for _ in 0..MAX_RELEASED_FRAMES + 5 {
+            queue.release_frame().unwrap();
+        }
+        assert_eq!(7, queue.num_overruns());
+        assert_eq!(Duration::from_millis(200), queue.released_duration());
+    }
+}
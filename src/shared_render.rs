@@ -0,0 +1,116 @@
+use crate::{
+    check_interleaved_len, real_time_section, ChannelPointers, Error, Processor, StreamKind,
+    NUM_SAMPLES_PER_FRAME,
+};
+use std::sync::Arc;
+
+/// Feeds one render stream to several processors, e.g. in a conference room where a single
+/// playout signal is the echo reference of several independent microphone pipelines.
+///
+/// Each render frame is de-interleaved once, and the same de-interleaved frame is passed to the
+/// native module of every attached processor, instead of one copy and de-interleaving pass per
+/// processor with `Processor::process_render_frame()`. The render channel maps of the attached
+/// processors don't apply, while their echo reference weights do.
+pub struct SharedRenderReference {
+    processors: Vec<Processor>,
+    deinterleaved: Vec<Vec<f32>>,
+    channel_ptrs: ChannelPointers,
+}
+
+impl SharedRenderReference {
+    /// Creates a new reference for interleaved render frames of `num_channels` channels, with
+    /// no processor attached.
+    pub fn new(num_channels: usize) -> Self {
+        Self {
+            processors: Vec::new(),
+            deinterleaved: vec![vec![0f32; NUM_SAMPLES_PER_FRAME as usize]; num_channels],
+            channel_ptrs: ChannelPointers::with_capacity(num_channels),
+        }
+    }
+
+    /// Attaches `processor`, typically a clone of a capture processor, to be fed every render
+    /// frame from now on. Fails with `Error::ChannelMismatch` unless it was initialized with
+    /// the same number of render channels, and with `Error::BadParameter` if it runs the
+    /// intelligibility enhancer, which would modify the shared frame for the next processors.
+    pub fn attach(&mut self, processor: Processor) -> Result<(), Error> {
+        let init_config = &processor.inner.init_config;
+        if init_config.num_render_channels as usize != self.deinterleaved.len() {
+            return Err(Error::ChannelMismatch {
+                stream: StreamKind::Render,
+                expected: self.deinterleaved.len(),
+                got: init_config.num_render_channels as usize,
+            });
+        }
+        if init_config.enable_intelligibility_enhancer {
+            return Err(Error::BadParameter);
+        }
+        self.processors.push(processor);
+        Ok(())
+    }
+
+    /// Detaches the processor sharing its native module with `processor`, if attached.
+    pub fn detach(&mut self, processor: &Processor) {
+        self.processors.retain(|attached| !Arc::ptr_eq(&attached.inner, &processor.inner));
+    }
+
+    /// Returns the number of attached processors.
+    pub fn num_attached(&self) -> usize {
+        self.processors.len()
+    }
+
+    /// Feeds the interleaved render `frame`, or a buffer of several frames, to every attached
+    /// processor. The frame itself is left as is. Like `FrameChunker::process()`, the remaining
+    /// processors and frames are still fed after an error, and only the first error is
+    /// returned.
+    pub fn process_render_frame(&mut self, frame: &[f32]) -> Result<(), Error> {
+        let Self { processors, deinterleaved, channel_ptrs } = self;
+        real_time_section(|| {
+            check_interleaved_len(frame.len(), deinterleaved.len(), StreamKind::Render)?;
+            let mut result = Ok(());
+            for frame in frame.chunks_exact(NUM_SAMPLES_PER_FRAME as usize * deinterleaved.len()) {
+                Processor::deinterleave(frame, deinterleaved, None);
+                for processor in processors.iter() {
+                    let processed =
+                        processor.inner.process_render_frame(deinterleaved, channel_ptrs);
+                    result = result.and(processed);
+                }
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InitializationConfig;
+
+    #[test]
+    fn test_shared_render_reference() {
+        let init_config = InitializationConfig {
+            num_capture_channels: 1,
+            num_render_channels: 2,
+            ..InitializationConfig::default()
+        };
+        let first = Processor::new(&init_config).unwrap();
+        let second = Processor::new(&init_config).unwrap();
+        let mut reference = SharedRenderReference::new(2);
+        reference.attach(first.clone()).unwrap();
+        reference.attach(second.clone()).unwrap();
+        let mono = Processor::new(&InitializationConfig { num_render_channels: 1, ..init_config })
+            .unwrap();
+        assert!(matches!(reference.attach(mono), Err(Error::ChannelMismatch { .. })));
+
+        // 20 ms.
+        let frame = vec![0.5f32; NUM_SAMPLES_PER_FRAME as usize * 2 * 2];
+        reference.process_render_frame(&frame).unwrap();
+        assert_eq!(2, first.inspect().num_processed_render_frames);
+        assert_eq!(2, second.inspect().num_processed_render_frames);
+
+        reference.detach(&second);
+        assert_eq!(1, reference.num_attached());
+        reference.process_render_frame(&frame).unwrap();
+        assert_eq!(4, first.inspect().num_processed_render_frames);
+        assert_eq!(2, second.inspect().num_processed_render_frames);
+    }
+}
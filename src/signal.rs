@@ -0,0 +1,310 @@
+//! Test signals, generated as interleaved 10 ms frames ready for
+//! [`Processor::process_capture_frame()`](crate::Processor::process_capture_frame) and
+//! [`Processor::process_render_frame()`](crate::Processor::process_render_frame), e.g. for
+//! integration tests and benchmarks.
+//!
+//! The noise generators are seeded, so that the same seed always yields the same samples.
+
+use crate::{NUM_SAMPLES_PER_FRAME, SAMPLE_RATE};
+use std::{f64::consts::PI, time::Duration};
+
+/// A mono signal at 48 kHz. The frame methods copy it to every channel.
+pub trait Signal {
+    /// Returns the next sample.
+    fn next_sample(&mut self) -> f32;
+
+    /// Fills the interleaved `frame` of `num_channels` channels.
+    fn fill_frame(&mut self, frame: &mut [f32], num_channels: usize) {
+        for samples in frame.chunks_exact_mut(num_channels) {
+            let sample = self.next_sample();
+            samples.iter_mut().for_each(|s| *s = sample);
+        }
+    }
+
+    /// Returns the next 10 ms frame of `num_channels` interleaved channels.
+    fn frame(&mut self, num_channels: usize) -> Vec<f32> {
+        self.frames(num_channels, 1)
+    }
+
+    /// Returns the next `num_frames` 10 ms frames of `num_channels` interleaved channels, one
+    /// after the other.
+    fn frames(&mut self, num_channels: usize, num_frames: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; NUM_SAMPLES_PER_FRAME as usize * num_channels * num_frames];
+        self.fill_frame(&mut samples, num_channels);
+        samples
+    }
+}
+
+/// A sine wave.
+#[derive(Debug, Clone)]
+pub struct Sine {
+    amplitude: f32,
+    phase: f64,
+    phase_increment: f64,
+}
+
+impl Sine {
+    /// Creates a sine wave of `frequency` Hz and peak `amplitude`.
+    pub fn new(frequency: f32, amplitude: f32) -> Self {
+        Self {
+            amplitude,
+            phase: 0.0,
+            phase_increment: 2.0 * PI * frequency as f64 / SAMPLE_RATE as f64,
+        }
+    }
+}
+
+impl Signal for Sine {
+    fn next_sample(&mut self) -> f32 {
+        let sample = self.phase.sin() as f32 * self.amplitude;
+        self.phase = (self.phase + self.phase_increment) % (2.0 * PI);
+        sample
+    }
+}
+
+/// A sine whose frequency sweeps from a start to an end frequency, then starts over.
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    start_frequency: f64,
+    end_frequency: f64,
+    exponential: bool,
+    amplitude: f32,
+    num_samples: usize,
+    index: usize,
+    phase: f64,
+}
+
+impl Sweep {
+    /// Creates a chirp whose frequency rises linearly over `duration`.
+    pub fn linear(
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: Duration,
+        amplitude: f32,
+    ) -> Self {
+        Self::new(start_frequency, end_frequency, duration, amplitude, false)
+    }
+
+    /// Creates a sine sweep whose frequency rises exponentially over `duration`, spending the
+    /// same time in every octave. Both frequencies must be positive.
+    pub fn exponential(
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: Duration,
+        amplitude: f32,
+    ) -> Self {
+        assert!(start_frequency > 0.0 && end_frequency > 0.0, "frequencies must be positive");
+        Self::new(start_frequency, end_frequency, duration, amplitude, true)
+    }
+
+    fn new(
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: Duration,
+        amplitude: f32,
+        exponential: bool,
+    ) -> Self {
+        Self {
+            start_frequency: start_frequency as f64,
+            end_frequency: end_frequency as f64,
+            exponential,
+            amplitude,
+            num_samples: ((duration.as_secs_f64() * SAMPLE_RATE as f64) as usize).max(1),
+            index: 0,
+            phase: 0.0,
+        }
+    }
+
+    /// Returns the length of one sweep, in samples.
+    pub fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    /// Returns the instantaneous frequency at `index` samples into the sweep.
+    fn frequency(&self, index: usize) -> f64 {
+        let progress = index as f64 / self.num_samples as f64;
+        if self.exponential {
+            self.start_frequency * (self.end_frequency / self.start_frequency).powf(progress)
+        } else {
+            self.start_frequency + (self.end_frequency - self.start_frequency) * progress
+        }
+    }
+}
+
+impl Signal for Sweep {
+    fn next_sample(&mut self) -> f32 {
+        let sample = self.phase.sin() as f32 * self.amplitude;
+        self.phase =
+            (self.phase + 2.0 * PI * self.frequency(self.index) / SAMPLE_RATE as f64) % (2.0 * PI);
+        self.index += 1;
+        if self.index == self.num_samples {
+            self.index = 0;
+            self.phase = 0.0;
+        }
+        sample
+    }
+}
+
+/// White noise, uniformly distributed between `-amplitude` and `amplitude`.
+#[derive(Debug, Clone)]
+pub struct WhiteNoise {
+    amplitude: f32,
+    state: u64,
+}
+
+impl WhiteNoise {
+    /// Creates white noise of peak `amplitude`, from `seed`.
+    pub fn new(amplitude: f32, seed: u64) -> Self {
+        // Xorshift gets stuck at 0.
+        Self { amplitude, state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+}
+
+impl Signal for WhiteNoise {
+    fn next_sample(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        // The top 24 bits, mapped to [-1, 1).
+        let unit = (self.state >> 40) as f32 / (1 << 23) as f32 - 1.0;
+        unit * self.amplitude
+    }
+}
+
+/// Pink noise, falling off by 3 dB per octave like most background noise.
+#[derive(Debug, Clone)]
+pub struct PinkNoise {
+    white: WhiteNoise,
+    state: [f32; 7],
+}
+
+impl PinkNoise {
+    /// Creates pink noise of roughly peak `amplitude`, from `seed`.
+    pub fn new(amplitude: f32, seed: u64) -> Self {
+        // The filter below peaks at about 7.5 times its input.
+        Self { white: WhiteNoise::new(amplitude * 0.13, seed), state: [0.0; 7] }
+    }
+}
+
+impl Signal for PinkNoise {
+    fn next_sample(&mut self) -> f32 {
+        // Paul Kellet's refined pink noise filter.
+        let white = self.white.next_sample();
+        let b = &mut self.state;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153_852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+}
+
+/// Noise with the long-term spectrum of speech, rising to about 500 Hz and falling off above,
+/// and modulated at a syllabic rate. Good enough to trigger voice detection and gain control,
+/// without shipping speech recordings.
+#[derive(Debug, Clone)]
+pub struct SpeechShapedNoise {
+    pink: PinkNoise,
+    high_pass: f32,
+    previous_input: f32,
+    low_pass: f32,
+    envelope: Sine,
+}
+
+impl SpeechShapedNoise {
+    /// Creates speech-shaped noise of roughly peak `amplitude`, from `seed`.
+    pub fn new(amplitude: f32, seed: u64) -> Self {
+        Self {
+            // Makes up for the band-pass filtering.
+            pink: PinkNoise::new(amplitude * 2.5, seed),
+            high_pass: 0.0,
+            previous_input: 0.0,
+            low_pass: 0.0,
+            envelope: Sine::new(4.0, 0.5),
+        }
+    }
+}
+
+impl Signal for SpeechShapedNoise {
+    fn next_sample(&mut self) -> f32 {
+        // First order high-pass at about 150 Hz, then low-pass at about 1 kHz.
+        const HIGH_PASS_COEFFICIENT: f32 = 0.98;
+        const LOW_PASS_COEFFICIENT: f32 = 0.12;
+
+        let input = self.pink.next_sample();
+        self.high_pass = HIGH_PASS_COEFFICIENT * (self.high_pass + input - self.previous_input);
+        self.previous_input = input;
+        self.low_pass += LOW_PASS_COEFFICIENT * (self.high_pass - self.low_pass);
+
+        // Between 0 and 1, four times a second.
+        let envelope = 0.5 + self.envelope.next_sample();
+        self.low_pass * envelope
+    }
+}
+
+/// Returns `near_end` with `echo` mixed in at `echo_gain`, to simulate a capture picking up the
+/// render output. Both must have the same layout.
+pub fn mix_echo(near_end: &[f32], echo: &[f32], echo_gain: f32) -> Vec<f32> {
+    assert_eq!(near_end.len(), echo.len(), "signals must be of the same length");
+    near_end.iter().zip(echo).map(|(&near, &echo)| near + echo * echo_gain).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::power_db;
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[test]
+    fn test_frames() {
+        let frame = Sine::new(1000.0, 0.5).frame(2);
+        assert_eq!(NUM_SAMPLES_PER_FRAME as usize * 2, frame.len());
+        assert!(frame.chunks_exact(2).all(|samples| samples[0] == samples[1]));
+        assert!((peak(&frame) - 0.5).abs() < 1e-3);
+        // A full-scale sine is at -3 dBFS.
+        assert!((power_db(&frame) + 9.03).abs() < 0.1);
+
+        assert_eq!(NUM_SAMPLES_PER_FRAME as usize * 3, WhiteNoise::new(1.0, 0).frames(1, 3).len());
+    }
+
+    #[test]
+    fn test_sweep_restarts() {
+        let mut sweep = Sweep::exponential(100.0, 10_000.0, Duration::from_millis(50), 1.0);
+        let samples = sweep.frames(1, 10);
+        assert_eq!(sweep.num_samples() * 2, samples.len());
+        assert_eq!(samples[..sweep.num_samples()], samples[sweep.num_samples()..]);
+        assert!(peak(&samples) <= 1.0);
+
+        let chirp = Sweep::linear(0.0, 1000.0, Duration::from_secs(1), 0.5).frames(1, 100);
+        assert!((peak(&chirp) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_noise() {
+        let white = WhiteNoise::new(0.5, 1).frames(1, 100);
+        assert_eq!(white, WhiteNoise::new(0.5, 1).frames(1, 100));
+        assert_ne!(white, WhiteNoise::new(0.5, 2).frames(1, 100));
+        assert!(peak(&white) <= 0.5);
+        // Uniform noise is 4.8 dB below its peak.
+        assert!((power_db(&white) + 10.8).abs() < 0.2);
+
+        for noise in
+            [PinkNoise::new(0.5, 1).frames(1, 100), SpeechShapedNoise::new(0.5, 1).frames(1, 100)]
+        {
+            assert!(peak(&noise) <= 1.0);
+            assert!(power_db(&noise) > -40.0);
+        }
+    }
+
+    #[test]
+    fn test_mix_echo() {
+        assert_eq!(vec![1.5, -0.5], mix_echo(&[1.0, 0.0], &[1.0, -1.0], 0.5));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::Stats;
+use std::{
+    fmt,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// A destination of periodic `Stats` reports, e.g. a log, a metrics registry or a telemetry
+/// channel. Sinks are fed by a [`StatsReporter`], so that the application decides where the
+/// statistics go rather than this crate.
+///
+/// Closures taking the time and the stats are sinks too.
+pub trait StatsSink {
+    /// Receives the statistics of the processor at `time`.
+    fn on_stats(&mut self, time: Instant, stats: &Stats);
+}
+
+impl<F: FnMut(Instant, &Stats)> StatsSink for F {
+    fn on_stats(&mut self, time: Instant, stats: &Stats) {
+        self(time, stats)
+    }
+}
+
+/// Feeds the statistics of a processor to [`StatsSink`]s at a fixed interval.
+///
+/// Call `poll()` regularly, e.g. after every capture frame, and the stats are fetched and
+/// passed to every sink once `interval` elapsed since the last report. The sinks run on the
+/// calling thread, so slow ones, e.g. writing to a file, are better polled from a thread of
+/// their own with a clone of the `Processor` than from the audio callback.
+///
+/// ```no_run
+/// # use webrtc_audio_processing::*;
+/// # use std::time::Duration;
+/// # let processor = Processor::new(&InitializationConfig::default()).unwrap();
+/// let mut reporter = StatsReporter::new(Duration::from_secs(1));
+/// reporter.add_sink(CsvStatsSink::new(std::io::sink()));
+/// reporter.add_sink(|_time, stats: &Stats| println!("{:?}", stats));
+/// reporter.poll(|| processor.get_stats());
+/// ```
+pub struct StatsReporter {
+    interval: Duration,
+    sinks: Vec<Box<dyn StatsSink + Send>>,
+    last_report: Option<Instant>,
+}
+
+impl StatsReporter {
+    /// Creates a new reporter without sinks, reporting every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, sinks: Vec::new(), last_report: None }
+    }
+
+    /// Adds `sink` to be fed from the next report on.
+    pub fn add_sink<S: StatsSink + Send + 'static>(&mut self, sink: S) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Reports the stats returned by `get_stats` to every sink, if this is the first call or
+    /// `interval` elapsed since the last report. `get_stats` isn't called otherwise. Returns
+    /// whether the stats were reported.
+    pub fn poll<F: FnOnce() -> Stats>(&mut self, get_stats: F) -> bool {
+        self.poll_at(Instant::now(), get_stats)
+    }
+
+    /// Reports `stats` to every sink right away. The next periodic report is due `interval`
+    /// from now.
+    pub fn report(&mut self, time: Instant, stats: &Stats) {
+        self.last_report = Some(time);
+        for sink in &mut self.sinks {
+            sink.on_stats(time, stats);
+        }
+    }
+
+    fn poll_at<F: FnOnce() -> Stats>(&mut self, now: Instant, get_stats: F) -> bool {
+        let due = match self.last_report {
+            Some(last_report) => now >= last_report + self.interval,
+            None => true,
+        };
+        if due {
+            self.report(now, &get_stats());
+        }
+        due
+    }
+}
+
+/// A [`StatsSink`] writing one CSV row per report, with a header, for spreadsheets and plotting
+/// tools. The columns are the same as those of `offline::write_stats_csv()`, after the time
+/// since the first report. Statistics that aren't available are left empty.
+///
+/// The first write error stops the writing, and is returned by `into_inner()`.
+pub struct CsvStatsSink<W: Write> {
+    writer: W,
+    start: Option<Instant>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> CsvStatsSink<W> {
+    /// Creates a new sink writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer, start: None, error: None }
+    }
+
+    /// Flushes and returns the writer, or the first write error.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        match self.error {
+            Some(err) => Err(err),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+
+    fn write_row(&mut self, time: Instant, stats: &Stats) -> io::Result<()> {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                writeln!(self.writer, "time_s,{}", CSV_STATS_HEADER)?;
+                *self.start.insert(time)
+            },
+        };
+        write!(self.writer, "{:.2},", time.duration_since(start).as_secs_f64())?;
+        write_csv_stats(&mut self.writer, stats)?;
+        writeln!(self.writer)
+    }
+}
+
+impl<W: Write> StatsSink for CsvStatsSink<W> {
+    fn on_stats(&mut self, time: Instant, stats: &Stats) {
+        if self.error.is_none() {
+            self.error = self.write_row(time, stats).err();
+        }
+    }
+}
+
+/// The CSV header of the columns written by `write_csv_stats()`.
+pub(crate) const CSV_STATS_HEADER: &str = "erle_db,erl_db,a_nlp_db,delay_median_ms,delay_std_ms,\
+                                           speech_probability,has_voice,has_echo";
+
+/// Writes the CSV columns of `stats`, without a line break.
+pub(crate) fn write_csv_stats<W: Write>(writer: &mut W, stats: &Stats) -> io::Result<()> {
+    fn optional<T: fmt::Display>(value: Option<T>) -> String {
+        value.map_or_else(String::new, |value| value.to_string())
+    }
+
+    write!(
+        writer,
+        "{},{},{},{},{},{},{},{}",
+        optional(stats.echo_return_loss_enhancement),
+        optional(stats.echo_return_loss),
+        optional(stats.a_nlp),
+        optional(stats.delay_median_ms),
+        optional(stats.delay_standard_deviation_ms),
+        optional(stats.speech_probability),
+        optional(stats.has_voice.map(u8::from)),
+        optional(stats.has_echo.map(u8::from)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn stats(has_voice: Option<bool>, echo_return_loss_enhancement: Option<f64>) -> Stats {
+        Stats {
+            has_voice,
+            has_echo: None,
+            rms_dbfs: None,
+            speech_probability: None,
+            residual_echo_return_loss: None,
+            echo_return_loss: None,
+            echo_return_loss_enhancement,
+            a_nlp: None,
+            delay_median_ms: None,
+            delay_standard_deviation_ms: None,
+            delay_fraction_poor_delays: None,
+            num_non_finite_samples: None,
+            voice_gate_open: None,
+            applied_input_volume: None,
+            recommended_input_volume: None,
+        }
+    }
+
+    #[test]
+    fn test_stats_reporter() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let mut reporter = StatsReporter::new(Duration::from_secs(1));
+        reporter.add_sink({
+            let reports = Arc::clone(&reports);
+            move |time, stats: &Stats| reports.lock().unwrap().push((time, stats.has_voice))
+        });
+
+        let start = Instant::now();
+        let voice = || stats(Some(true), None);
+        assert!(reporter.poll_at(start, voice));
+        assert!(!reporter.poll_at(start + Duration::from_millis(500), || unreachable!()));
+        assert!(reporter.poll_at(start + Duration::from_millis(1200), voice));
+        assert!(!reporter.poll_at(start + Duration::from_millis(2100), voice));
+        assert_eq!(
+            vec![(start, Some(true)), (start + Duration::from_millis(1200), Some(true))],
+            *reports.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_csv_stats_sink() {
+        let mut sink = CsvStatsSink::new(Vec::new());
+        let start = Instant::now();
+        sink.on_stats(start, &stats(Some(true), None));
+        sink.on_stats(start + Duration::from_secs(1), &stats(None, Some(12.5)));
+        let csv = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with("time_s,erle_db,"));
+        assert_eq!("0.00,,,,,,,1,", lines[1]);
+        assert_eq!("1.00,12.5,,,,,,,", lines[2]);
+    }
+}
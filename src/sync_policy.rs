@@ -0,0 +1,191 @@
+use std::{
+    cell::{Cell, RefCell, RefMut},
+    ops::DerefMut,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+/// How the processing module synchronizes the state its processing paths share with its
+/// setters: with mutexes and atomics when it's shared between threads, as by `Processor`, or
+/// not at all when it's owned by a single thread, as by `LocalProcessor`.
+pub(crate) trait SyncPolicy {
+    type Lock<T>: Lock<T>;
+    type AtomicBool: AtomicCell<bool>;
+    type AtomicU8: AtomicCell<u8>;
+    type AtomicI32: AtomicCell<i32>;
+    type AtomicU32: AtomicCell<u32>;
+    type AtomicU64: AtomicCounter<u64>;
+    type AtomicUsize: AtomicCounter<usize>;
+}
+
+/// Mutexes and atomics, for a module shared between threads.
+pub(crate) struct Shared;
+
+impl SyncPolicy for Shared {
+    type Lock<T> = Mutex<T>;
+    type AtomicBool = AtomicBool;
+    type AtomicU8 = AtomicU8;
+    type AtomicI32 = AtomicI32;
+    type AtomicU32 = AtomicU32;
+    type AtomicU64 = AtomicU64;
+    type AtomicUsize = AtomicUsize;
+}
+
+/// `RefCell`s and `Cell`s, for a module owned by a single thread. Neither is `Sync`, so the
+/// module can't be shared by mistake.
+pub(crate) struct Local;
+
+impl SyncPolicy for Local {
+    type Lock<T> = RefCell<T>;
+    type AtomicBool = Cell<bool>;
+    type AtomicU8 = Cell<u8>;
+    type AtomicI32 = Cell<i32>;
+    type AtomicU32 = Cell<u32>;
+    type AtomicU64 = Cell<u64>;
+    type AtomicUsize = Cell<usize>;
+}
+
+/// Exclusive access to a value, by locking a `Mutex` or borrowing a `RefCell`.
+pub(crate) trait Lock<T> {
+    type Guard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn new(value: T) -> Self;
+
+    /// Panics if the mutex is poisoned, or the `RefCell` already borrowed.
+    fn lock(&self) -> Self::Guard<'_>;
+
+    /// Returns `None` rather than waiting if the value is held elsewhere.
+    fn try_lock(&self) -> Option<Self::Guard<'_>>;
+}
+
+impl<T> Lock<T> for Mutex<T> {
+    type Guard<'a>
+        = MutexGuard<'a, T>
+    where
+        Self: 'a;
+
+    fn new(value: T) -> Self {
+        Mutex::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        Mutex::lock(self).unwrap()
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        Mutex::try_lock(self).ok()
+    }
+}
+
+impl<T> Lock<T> for RefCell<T> {
+    type Guard<'a>
+        = RefMut<'a, T>
+    where
+        Self: 'a;
+
+    fn new(value: T) -> Self {
+        RefCell::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        self.borrow_mut()
+    }
+
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.try_borrow_mut().ok()
+    }
+}
+
+/// The operations of the std atomics used by the module. The orderings are ignored by `Cell`.
+pub(crate) trait AtomicCell<T> {
+    fn new(value: T) -> Self;
+    fn load(&self, order: Ordering) -> T;
+    fn store(&self, value: T, order: Ordering);
+    fn swap(&self, value: T, order: Ordering) -> T;
+}
+
+/// The arithmetic operations of the std atomics used by the module, wrapping around on
+/// overflow.
+pub(crate) trait AtomicCounter<T>: AtomicCell<T> {
+    fn fetch_add(&self, value: T, order: Ordering) -> T;
+    fn fetch_sub(&self, value: T, order: Ordering) -> T;
+}
+
+impl<T: Copy> AtomicCell<T> for Cell<T> {
+    fn new(value: T) -> Self {
+        Cell::new(value)
+    }
+
+    fn load(&self, _order: Ordering) -> T {
+        self.get()
+    }
+
+    fn store(&self, value: T, _order: Ordering) {
+        self.set(value);
+    }
+
+    fn swap(&self, value: T, _order: Ordering) -> T {
+        self.replace(value)
+    }
+}
+
+macro_rules! impl_atomic_cell {
+    ($($atomic:ty => $value:ty),* $(,)?) => {$(
+        impl AtomicCell<$value> for $atomic {
+            fn new(value: $value) -> Self {
+                <$atomic>::new(value)
+            }
+
+            fn load(&self, order: Ordering) -> $value {
+                <$atomic>::load(self, order)
+            }
+
+            fn store(&self, value: $value, order: Ordering) {
+                <$atomic>::store(self, value, order);
+            }
+
+            fn swap(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::swap(self, value, order)
+            }
+        }
+    )*};
+}
+
+impl_atomic_cell!(
+    AtomicBool => bool,
+    AtomicU8 => u8,
+    AtomicI32 => i32,
+    AtomicU32 => u32,
+    AtomicU64 => u64,
+    AtomicUsize => usize,
+);
+
+macro_rules! impl_atomic_counter {
+    ($($atomic:ty => $value:ty),* $(,)?) => {$(
+        impl AtomicCounter<$value> for $atomic {
+            fn fetch_add(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_add(self, value, order)
+            }
+
+            fn fetch_sub(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_sub(self, value, order)
+            }
+        }
+
+        impl AtomicCounter<$value> for Cell<$value> {
+            fn fetch_add(&self, value: $value, _order: Ordering) -> $value {
+                self.replace(self.get().wrapping_add(value))
+            }
+
+            fn fetch_sub(&self, value: $value, _order: Ordering) -> $value {
+                self.replace(self.get().wrapping_sub(value))
+            }
+        }
+    )*};
+}
+
+impl_atomic_counter!(AtomicU64 => u64, AtomicUsize => usize);
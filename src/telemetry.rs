@@ -0,0 +1,565 @@
+//! Compact binary encoding of [`Stats`](crate::Stats) and [`Config`](crate::Config) snapshots
+//! as Protocol Buffers, e.g. to report them over a telemetry channel where JSON is too verbose.
+//!
+//! The messages here mirror the crate's types, and convert from them with `From`, and back with
+//! `TryFrom`, which fails on enum values unknown to this version. Encode and decode them with
+//! the [`Message`] trait. The schema is in `src/telemetry.proto`, see [`SCHEMA`], for decoding
+//! outside of Rust.
+//!
+//! ```
+//! use std::convert::TryFrom;
+//! use webrtc_audio_processing::{
+//!     telemetry::{self, Message},
+//!     Config,
+//! };
+//!
+//! let config = Config { enable_high_pass_filter: true, ..Config::default() };
+//! let bytes = telemetry::Config::from(config.clone()).encode_to_vec();
+//! let decoded = Config::try_from(telemetry::Config::decode(&bytes[..]).unwrap()).unwrap();
+//! assert_eq!(config, decoded);
+//! ```
+
+// The fields and variants are documented on the types they mirror.
+#![allow(missing_docs)]
+
+pub use prost::{DecodeError, Message, UnknownEnumValue};
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, Write},
+    time::Instant,
+};
+
+/// The `.proto` schema of the messages.
+pub const SCHEMA: &str = include_str!("telemetry.proto");
+
+/// See [`crate::Stats`].
+#[derive(Clone, PartialEq, Message)]
+pub struct Stats {
+    #[prost(bool, optional, tag = "1")]
+    pub has_voice: Option<bool>,
+    #[prost(bool, optional, tag = "2")]
+    pub has_echo: Option<bool>,
+    #[prost(int32, optional, tag = "3")]
+    pub rms_dbfs: Option<i32>,
+    #[prost(double, optional, tag = "4")]
+    pub speech_probability: Option<f64>,
+    #[prost(double, optional, tag = "5")]
+    pub residual_echo_return_loss: Option<f64>,
+    #[prost(double, optional, tag = "6")]
+    pub echo_return_loss: Option<f64>,
+    #[prost(double, optional, tag = "7")]
+    pub echo_return_loss_enhancement: Option<f64>,
+    #[prost(double, optional, tag = "8")]
+    pub a_nlp: Option<f64>,
+    #[prost(int32, optional, tag = "9")]
+    pub delay_median_ms: Option<i32>,
+    #[prost(int32, optional, tag = "10")]
+    pub delay_standard_deviation_ms: Option<i32>,
+    #[prost(double, optional, tag = "11")]
+    pub delay_fraction_poor_delays: Option<f64>,
+    #[prost(uint64, optional, tag = "12")]
+    pub num_non_finite_samples: Option<u64>,
+    #[prost(bool, optional, tag = "13")]
+    pub voice_gate_open: Option<bool>,
+    #[prost(int32, optional, tag = "14")]
+    pub applied_input_volume: Option<i32>,
+    #[prost(int32, optional, tag = "15")]
+    pub recommended_input_volume: Option<i32>,
+}
+
+impl From<crate::Stats> for Stats {
+    fn from(other: crate::Stats) -> Self {
+        Self {
+            has_voice: other.has_voice,
+            has_echo: other.has_echo,
+            rms_dbfs: other.rms_dbfs,
+            speech_probability: other.speech_probability,
+            residual_echo_return_loss: other.residual_echo_return_loss,
+            echo_return_loss: other.echo_return_loss,
+            echo_return_loss_enhancement: other.echo_return_loss_enhancement,
+            a_nlp: other.a_nlp,
+            delay_median_ms: other.delay_median_ms,
+            delay_standard_deviation_ms: other.delay_standard_deviation_ms,
+            delay_fraction_poor_delays: other.delay_fraction_poor_delays,
+            num_non_finite_samples: other.num_non_finite_samples,
+            voice_gate_open: other.voice_gate_open,
+            applied_input_volume: other.applied_input_volume,
+            recommended_input_volume: other.recommended_input_volume,
+        }
+    }
+}
+
+impl From<Stats> for crate::Stats {
+    fn from(other: Stats) -> Self {
+        Self {
+            has_voice: other.has_voice,
+            has_echo: other.has_echo,
+            rms_dbfs: other.rms_dbfs,
+            speech_probability: other.speech_probability,
+            residual_echo_return_loss: other.residual_echo_return_loss,
+            echo_return_loss: other.echo_return_loss,
+            echo_return_loss_enhancement: other.echo_return_loss_enhancement,
+            a_nlp: other.a_nlp,
+            delay_median_ms: other.delay_median_ms,
+            delay_standard_deviation_ms: other.delay_standard_deviation_ms,
+            delay_fraction_poor_delays: other.delay_fraction_poor_delays,
+            num_non_finite_samples: other.num_non_finite_samples,
+            voice_gate_open: other.voice_gate_open,
+            applied_input_volume: other.applied_input_volume,
+            recommended_input_volume: other.recommended_input_volume,
+        }
+    }
+}
+
+/// A [`StatsSink`](crate::StatsSink) writing each report as a length-delimited [`Stats`]
+/// message, to be read back with `Stats::decode_length_delimited()`. The time of the reports
+/// isn't encoded, the receiver is expected to stamp them.
+///
+/// The first write error stops the writing, and is returned by `into_inner()`.
+pub struct ProtobufStatsSink<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> ProtobufStatsSink<W> {
+    /// Creates a new sink writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer, buffer: Vec::new(), error: None }
+    }
+
+    /// Flushes and returns the writer, or the first write error.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        match self.error {
+            Some(err) => Err(err),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+}
+
+impl<W: Write> crate::StatsSink for ProtobufStatsSink<W> {
+    fn on_stats(&mut self, _time: Instant, stats: &crate::Stats) {
+        if self.error.is_some() {
+            return;
+        }
+        self.buffer.clear();
+        // Writing to a `Vec` can't run out of space.
+        Stats::from(stats.clone()).encode_length_delimited(&mut self.buffer).unwrap();
+        self.error = self.writer.write_all(&self.buffer).err();
+    }
+}
+
+/// Declares a protobuf enum mirroring one of the crate, with conversions both ways.
+macro_rules! mirrored_enum {
+    ($name:ident { $($variant:ident = $value:literal),* $(,)? }) => {
+        #[doc = concat!("See [`crate::", stringify!($name), "`].")]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+        #[repr(i32)]
+        pub enum $name {
+            $($variant = $value,)*
+        }
+
+        impl From<crate::$name> for $name {
+            fn from(other: crate::$name) -> Self {
+                match other {
+                    $(crate::$name::$variant => $name::$variant,)*
+                }
+            }
+        }
+
+        impl From<$name> for crate::$name {
+            fn from(other: $name) -> Self {
+                match other {
+                    $($name::$variant => crate::$name::$variant,)*
+                }
+            }
+        }
+    };
+}
+
+mirrored_enum!(EchoCancellationSuppressionLevel {
+    Lowest = 0,
+    Lower = 1,
+    Low = 2,
+    Moderate = 3,
+    High = 4,
+});
+
+mirrored_enum!(EchoControlMobileRoutingMode {
+    QuietEarpieceOrHeadset = 0,
+    Earpiece = 1,
+    LoudEarpiece = 2,
+    Speakerphone = 3,
+    LoudSpeakerphone = 4,
+});
+
+mirrored_enum!(GainControlMode { AdaptiveAnalog = 0, AdaptiveDigital = 1, FixedDigital = 2 });
+
+mirrored_enum!(NoiseSuppressionLevel { Low = 0, Moderate = 1, High = 2, VeryHigh = 3 });
+
+mirrored_enum!(NoiseSuppressionBackend { Builtin = 0, External = 1 });
+
+mirrored_enum!(VoiceDetectionLikelihood { VeryLow = 0, Low = 1, Moderate = 2, High = 3 });
+
+/// Decodes an enum field, which protobuf keeps as the raw value.
+fn decode_enum<T, U>(value: i32) -> Result<U, UnknownEnumValue>
+where
+    T: TryFrom<i32, Error = UnknownEnumValue> + Into<U>,
+{
+    T::try_from(value).map(Into::into)
+}
+
+/// See [`crate::EchoCancellation`].
+#[derive(Clone, PartialEq, Message)]
+pub struct EchoCancellation {
+    #[prost(enumeration = "EchoCancellationSuppressionLevel", tag = "1")]
+    pub suppression_level: i32,
+    #[prost(bool, tag = "2")]
+    pub enable_extended_filter: bool,
+    #[prost(bool, tag = "3")]
+    pub enable_delay_agnostic: bool,
+    #[prost(int32, optional, tag = "4")]
+    pub stream_delay_ms: Option<i32>,
+}
+
+impl From<crate::EchoCancellation> for EchoCancellation {
+    fn from(other: crate::EchoCancellation) -> Self {
+        Self {
+            suppression_level: EchoCancellationSuppressionLevel::from(other.suppression_level)
+                as i32,
+            enable_extended_filter: other.enable_extended_filter,
+            enable_delay_agnostic: other.enable_delay_agnostic,
+            stream_delay_ms: other.stream_delay_ms,
+        }
+    }
+}
+
+impl TryFrom<EchoCancellation> for crate::EchoCancellation {
+    type Error = UnknownEnumValue;
+
+    fn try_from(other: EchoCancellation) -> Result<Self, Self::Error> {
+        Ok(Self {
+            suppression_level: decode_enum::<EchoCancellationSuppressionLevel, _>(
+                other.suppression_level,
+            )?,
+            enable_extended_filter: other.enable_extended_filter,
+            enable_delay_agnostic: other.enable_delay_agnostic,
+            stream_delay_ms: other.stream_delay_ms,
+        })
+    }
+}
+
+/// See [`crate::EchoControlMobile`].
+#[derive(Clone, PartialEq, Message)]
+pub struct EchoControlMobile {
+    #[prost(enumeration = "EchoControlMobileRoutingMode", tag = "1")]
+    pub routing_mode: i32,
+    #[prost(bool, tag = "2")]
+    pub enable_comfort_noise: bool,
+    #[prost(int32, optional, tag = "3")]
+    pub stream_delay_ms: Option<i32>,
+}
+
+impl From<crate::EchoControlMobile> for EchoControlMobile {
+    fn from(other: crate::EchoControlMobile) -> Self {
+        Self {
+            routing_mode: EchoControlMobileRoutingMode::from(other.routing_mode) as i32,
+            enable_comfort_noise: other.enable_comfort_noise,
+            stream_delay_ms: other.stream_delay_ms,
+        }
+    }
+}
+
+impl TryFrom<EchoControlMobile> for crate::EchoControlMobile {
+    type Error = UnknownEnumValue;
+
+    fn try_from(other: EchoControlMobile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            routing_mode: decode_enum::<EchoControlMobileRoutingMode, _>(other.routing_mode)?,
+            enable_comfort_noise: other.enable_comfort_noise,
+            stream_delay_ms: other.stream_delay_ms,
+        })
+    }
+}
+
+/// See [`crate::GainControl`].
+#[derive(Clone, PartialEq, Message)]
+pub struct GainControl {
+    #[prost(enumeration = "GainControlMode", tag = "1")]
+    pub mode: i32,
+    #[prost(int32, tag = "2")]
+    pub target_level_dbfs: i32,
+    #[prost(int32, tag = "3")]
+    pub compression_gain_db: i32,
+    #[prost(bool, tag = "4")]
+    pub enable_limiter: bool,
+}
+
+impl From<crate::GainControl> for GainControl {
+    fn from(other: crate::GainControl) -> Self {
+        Self {
+            mode: GainControlMode::from(other.mode) as i32,
+            target_level_dbfs: other.target_level_dbfs,
+            compression_gain_db: other.compression_gain_db,
+            enable_limiter: other.enable_limiter,
+        }
+    }
+}
+
+impl TryFrom<GainControl> for crate::GainControl {
+    type Error = UnknownEnumValue;
+
+    fn try_from(other: GainControl) -> Result<Self, Self::Error> {
+        Ok(Self {
+            mode: decode_enum::<GainControlMode, _>(other.mode)?,
+            target_level_dbfs: other.target_level_dbfs,
+            compression_gain_db: other.compression_gain_db,
+            enable_limiter: other.enable_limiter,
+        })
+    }
+}
+
+/// See [`crate::NoiseSuppression`].
+#[derive(Clone, PartialEq, Message)]
+pub struct NoiseSuppression {
+    #[prost(enumeration = "NoiseSuppressionLevel", tag = "1")]
+    pub suppression_level: i32,
+}
+
+impl From<crate::NoiseSuppression> for NoiseSuppression {
+    fn from(other: crate::NoiseSuppression) -> Self {
+        Self { suppression_level: NoiseSuppressionLevel::from(other.suppression_level) as i32 }
+    }
+}
+
+impl TryFrom<NoiseSuppression> for crate::NoiseSuppression {
+    type Error = UnknownEnumValue;
+
+    fn try_from(other: NoiseSuppression) -> Result<Self, Self::Error> {
+        Ok(Self {
+            suppression_level: decode_enum::<NoiseSuppressionLevel, _>(other.suppression_level)?,
+        })
+    }
+}
+
+/// See [`crate::VoiceDetection`].
+#[derive(Clone, PartialEq, Message)]
+pub struct VoiceDetection {
+    #[prost(enumeration = "VoiceDetectionLikelihood", tag = "1")]
+    pub detection_likelihood: i32,
+}
+
+impl From<crate::VoiceDetection> for VoiceDetection {
+    fn from(other: crate::VoiceDetection) -> Self {
+        Self {
+            detection_likelihood: VoiceDetectionLikelihood::from(other.detection_likelihood) as i32,
+        }
+    }
+}
+
+impl TryFrom<VoiceDetection> for crate::VoiceDetection {
+    type Error = UnknownEnumValue;
+
+    fn try_from(other: VoiceDetection) -> Result<Self, Self::Error> {
+        Ok(Self {
+            detection_likelihood: decode_enum::<VoiceDetectionLikelihood, _>(
+                other.detection_likelihood,
+            )?,
+        })
+    }
+}
+
+/// See [`crate::HighPassFilter`].
+#[derive(Clone, PartialEq, Message)]
+pub struct HighPassFilter {
+    #[prost(float, tag = "1")]
+    pub cutoff_hz: f32,
+    #[prost(uint32, tag = "2")]
+    pub order: u32,
+}
+
+impl From<crate::HighPassFilter> for HighPassFilter {
+    fn from(other: crate::HighPassFilter) -> Self {
+        Self { cutoff_hz: other.cutoff_hz, order: other.order }
+    }
+}
+
+impl From<HighPassFilter> for crate::HighPassFilter {
+    fn from(other: HighPassFilter) -> Self {
+        Self { cutoff_hz: other.cutoff_hz, order: other.order }
+    }
+}
+
+/// See [`crate::VoiceGate`].
+#[derive(Clone, PartialEq, Message)]
+pub struct VoiceGate {
+    #[prost(uint32, tag = "1")]
+    pub attack_ms: u32,
+    #[prost(uint32, tag = "2")]
+    pub hold_ms: u32,
+    #[prost(float, optional, tag = "3")]
+    pub attenuation_db: Option<f32>,
+}
+
+impl From<crate::VoiceGate> for VoiceGate {
+    fn from(other: crate::VoiceGate) -> Self {
+        Self {
+            attack_ms: other.attack_ms,
+            hold_ms: other.hold_ms,
+            attenuation_db: other.attenuation_db,
+        }
+    }
+}
+
+impl From<VoiceGate> for crate::VoiceGate {
+    fn from(other: VoiceGate) -> Self {
+        Self {
+            attack_ms: other.attack_ms,
+            hold_ms: other.hold_ms,
+            attenuation_db: other.attenuation_db,
+        }
+    }
+}
+
+/// See [`crate::Config`].
+#[derive(Clone, PartialEq, Message)]
+pub struct Config {
+    #[prost(message, optional, tag = "1")]
+    pub echo_cancellation: Option<EchoCancellation>,
+    #[prost(message, optional, tag = "2")]
+    pub echo_control_mobile: Option<EchoControlMobile>,
+    #[prost(message, optional, tag = "3")]
+    pub gain_control: Option<GainControl>,
+    #[prost(message, optional, tag = "4")]
+    pub noise_suppression: Option<NoiseSuppression>,
+    #[prost(enumeration = "NoiseSuppressionBackend", tag = "5")]
+    pub noise_suppression_backend: i32,
+    #[prost(message, optional, tag = "6")]
+    pub voice_detection: Option<VoiceDetection>,
+    #[prost(bool, tag = "7")]
+    pub enable_transient_suppressor: bool,
+    #[prost(bool, tag = "8")]
+    pub enable_high_pass_filter: bool,
+    #[prost(message, optional, tag = "9")]
+    pub high_pass_filter: Option<HighPassFilter>,
+    #[prost(bool, tag = "10")]
+    pub sanitize_non_finite_samples: bool,
+    #[prost(bool, tag = "11")]
+    pub deterministic: bool,
+    #[prost(message, optional, tag = "12")]
+    pub voice_gate: Option<VoiceGate>,
+    #[prost(bool, tag = "13")]
+    pub enable_comfort_noise: bool,
+}
+
+impl From<crate::Config> for Config {
+    fn from(other: crate::Config) -> Self {
+        Self {
+            echo_cancellation: other.echo_cancellation.map(Into::into),
+            echo_control_mobile: other.echo_control_mobile.map(Into::into),
+            gain_control: other.gain_control.map(Into::into),
+            noise_suppression: other.noise_suppression.map(Into::into),
+            noise_suppression_backend: NoiseSuppressionBackend::from(
+                other.noise_suppression_backend,
+            ) as i32,
+            voice_detection: other.voice_detection.map(Into::into),
+            enable_transient_suppressor: other.enable_transient_suppressor,
+            enable_high_pass_filter: other.enable_high_pass_filter,
+            high_pass_filter: other.high_pass_filter.map(Into::into),
+            sanitize_non_finite_samples: other.sanitize_non_finite_samples,
+            deterministic: other.deterministic,
+            voice_gate: other.voice_gate.map(Into::into),
+            enable_comfort_noise: other.enable_comfort_noise,
+        }
+    }
+}
+
+impl TryFrom<Config> for crate::Config {
+    type Error = UnknownEnumValue;
+
+    fn try_from(other: Config) -> Result<Self, Self::Error> {
+        Ok(Self {
+            echo_cancellation: other.echo_cancellation.map(TryInto::try_into).transpose()?,
+            echo_control_mobile: other.echo_control_mobile.map(TryInto::try_into).transpose()?,
+            gain_control: other.gain_control.map(TryInto::try_into).transpose()?,
+            noise_suppression: other.noise_suppression.map(TryInto::try_into).transpose()?,
+            noise_suppression_backend: decode_enum::<NoiseSuppressionBackend, _>(
+                other.noise_suppression_backend,
+            )?,
+            voice_detection: other.voice_detection.map(TryInto::try_into).transpose()?,
+            enable_transient_suppressor: other.enable_transient_suppressor,
+            enable_high_pass_filter: other.enable_high_pass_filter,
+            high_pass_filter: other.high_pass_filter.map(Into::into),
+            sanitize_non_finite_samples: other.sanitize_non_finite_samples,
+            deterministic: other.deterministic,
+            voice_gate: other.voice_gate.map(Into::into),
+            enable_comfort_noise: other.enable_comfort_noise,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let config = crate::Config {
+            echo_cancellation: Some(crate::EchoCancellation {
+                suppression_level: crate::EchoCancellationSuppressionLevel::High,
+                enable_extended_filter: true,
+                enable_delay_agnostic: false,
+                stream_delay_ms: Some(40),
+            }),
+            echo_control_mobile: Some(crate::EchoControlMobile {
+                routing_mode: crate::EchoControlMobileRoutingMode::LoudSpeakerphone,
+                enable_comfort_noise: true,
+                stream_delay_ms: None,
+            }),
+            gain_control: Some(crate::GainControl {
+                mode: crate::GainControlMode::FixedDigital,
+                target_level_dbfs: 3,
+                compression_gain_db: 9,
+                enable_limiter: true,
+            }),
+            noise_suppression: Some(crate::NoiseSuppression {
+                suppression_level: crate::NoiseSuppressionLevel::VeryHigh,
+            }),
+            noise_suppression_backend: crate::NoiseSuppressionBackend::External,
+            voice_detection: Some(crate::VoiceDetection {
+                detection_likelihood: crate::VoiceDetectionLikelihood::Low,
+            }),
+            enable_transient_suppressor: true,
+            enable_high_pass_filter: true,
+            high_pass_filter: Some(crate::HighPassFilter::default()),
+            sanitize_non_finite_samples: true,
+            deterministic: true,
+            voice_gate: Some(crate::VoiceGate::default()),
+            enable_comfort_noise: true,
+        };
+        let bytes = Config::from(config.clone()).encode_to_vec();
+        let decoded = Config::decode(&bytes[..]).unwrap();
+        assert_eq!(config, decoded.try_into().unwrap());
+
+        // The defaults take no space.
+        assert!(Config::from(crate::Config::default()).encode_to_vec().is_empty());
+
+        let unknown = NoiseSuppression { suppression_level: 7 };
+        assert!(crate::NoiseSuppression::try_from(unknown).is_err());
+    }
+
+    #[test]
+    fn test_protobuf_stats_sink() {
+        use crate::StatsSink;
+
+        let mut sink = ProtobufStatsSink::new(Vec::new());
+        let stats = Stats { has_voice: Some(true), a_nlp: Some(-3.5), ..Stats::default() };
+        sink.on_stats(Instant::now(), &stats.clone().into());
+        sink.on_stats(Instant::now(), &Stats::default().into());
+        let bytes = sink.into_inner().unwrap();
+        let mut bytes = &bytes[..];
+        assert_eq!(stats, Stats::decode_length_delimited(&mut bytes).unwrap());
+        assert_eq!(Stats::default(), Stats::decode_length_delimited(&mut bytes).unwrap());
+        assert!(bytes.is_empty());
+    }
+}
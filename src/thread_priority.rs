@@ -0,0 +1,44 @@
+//! Platform specific helpers to make the current thread suitable for real-time processing. Each
+//! helper returns whether it succeeded, so that callers can fall back to a normal thread when the
+//! platform isn't supported or the process lacks the permissions.
+
+/// The SCHED_FIFO priority requested for real-time threads, clamped to what the platform allows.
+/// It's in the range commonly used by audio servers, below the kernel's own threads.
+#[cfg(unix)]
+const REAL_TIME_PRIORITY: libc::c_int = 80;
+
+/// Switches the current thread to the SCHED_FIFO real-time scheduling policy. Usually requires
+/// `CAP_SYS_NICE` or an `RLIMIT_RTPRIO` grant (e.g. via /etc/security/limits.conf) on Linux.
+#[cfg(unix)]
+pub(crate) fn promote_current_thread_to_real_time() -> bool {
+    unsafe {
+        let max_priority = libc::sched_get_priority_max(libc::SCHED_FIFO);
+        if max_priority < 0 {
+            return false;
+        }
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = REAL_TIME_PRIORITY.min(max_priority);
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) == 0
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn promote_current_thread_to_real_time() -> bool {
+    false
+}
+
+/// Restricts the current thread to run only on the given CPU core.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn pin_current_thread_to_core(core_index: usize) -> bool {
+    unsafe {
+        let mut cpu_set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut cpu_set);
+        libc::CPU_SET(core_index, &mut cpu_set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpu_set) == 0
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn pin_current_thread_to_core(_core_index: usize) -> bool {
+    false
+}
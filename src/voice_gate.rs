@@ -0,0 +1,130 @@
+use crate::{VoiceGate, NUM_SAMPLES_PER_FRAME};
+
+const SAMPLES_PER_MS: u32 = NUM_SAMPLES_PER_FRAME as u32 / 10;
+
+/// The state of the voice gate between capture frames.
+pub(crate) struct VoiceGateState {
+    config: VoiceGate,
+    gain: f32,
+    hold_frames_left: u32,
+    open: bool,
+}
+
+impl VoiceGateState {
+    /// Starts closed, so that nothing passes until voice is detected.
+    pub(crate) fn new(config: VoiceGate) -> Self {
+        let mut state = Self { config, gain: 0.0, hold_frames_left: 0, open: false };
+        state.gain = state.closed_gain();
+        state
+    }
+
+    /// Applies a new config, keeping the current gain and hold time.
+    pub(crate) fn set_config(&mut self, config: VoiceGate) {
+        self.config = config;
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn closed_gain(&self) -> f32 {
+        match self.config.attenuation_db {
+            Some(attenuation_db) => 10f32.powf(-attenuation_db.max(0.0) / 20.0),
+            None => 0.0,
+        }
+    }
+
+    /// Opens or closes the gate depending on `has_voice` of the frame, and applies the gain to
+    /// it. The gain ramps up over the attack time when opening, and down over a frame when
+    /// closing, to avoid clicks.
+    pub(crate) fn process_frame(&mut self, frame: &mut [Vec<f32>], has_voice: Option<bool>) {
+        if has_voice.unwrap_or(true) {
+            self.open = true;
+            self.hold_frames_left =
+                self.config.hold_ms / 10 + u32::from(self.config.hold_ms % 10 != 0);
+        } else if self.hold_frames_left > 0 {
+            self.hold_frames_left -= 1;
+        } else {
+            self.open = false;
+        }
+
+        let closed_gain = self.closed_gain();
+        let (target, ramp_samples) = if self.open {
+            (1.0, self.config.attack_ms.saturating_mul(SAMPLES_PER_MS))
+        } else {
+            (closed_gain, NUM_SAMPLES_PER_FRAME as u32)
+        };
+        if self.gain == 1.0 && target == 1.0 {
+            return;
+        }
+        let step = (1.0 - closed_gain) / ramp_samples.max(1) as f32;
+        for i in 0..NUM_SAMPLES_PER_FRAME as usize {
+            self.gain = if self.gain < target {
+                (self.gain + step).min(target)
+            } else {
+                (self.gain - step).max(target)
+            };
+            for channel in frame.iter_mut() {
+                channel[i] *= self.gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: f32) -> Vec<Vec<f32>> {
+        vec![vec![value; NUM_SAMPLES_PER_FRAME as usize]]
+    }
+
+    #[test]
+    fn test_voice_gate() {
+        let mut gate = VoiceGateState::new(VoiceGate {
+            attack_ms: 5,
+            hold_ms: 20,
+            attenuation_db: Some(20.0),
+        });
+
+        let mut silence = frame(1.0);
+        gate.process_frame(&mut silence, Some(false));
+        assert!(!gate.is_open());
+        assert!(silence[0].iter().all(|&sample| (sample - 0.1).abs() < 1e-6));
+
+        // Ramps up over the first 5 ms.
+        let mut voice = frame(1.0);
+        gate.process_frame(&mut voice, Some(true));
+        assert!(gate.is_open());
+        assert!(voice[0][0] > 0.1 && voice[0][0] < 0.2);
+        assert!(voice[0][SAMPLES_PER_MS as usize * 5 - 2] < 1.0);
+        assert!(voice[0][SAMPLES_PER_MS as usize * 5..].iter().all(|&sample| sample == 1.0));
+
+        // Held open for two frames.
+        for _ in 0..2 {
+            let mut pause = frame(1.0);
+            gate.process_frame(&mut pause, Some(false));
+            assert!(gate.is_open());
+            assert_eq!(frame(1.0), pause);
+        }
+        let mut pause = frame(1.0);
+        gate.process_frame(&mut pause, Some(false));
+        assert!(!gate.is_open());
+        assert!((pause[0][NUM_SAMPLES_PER_FRAME as usize - 1] - 0.1).abs() < 1e-5);
+
+        // Without voice detection, the gate stays open.
+        gate.process_frame(&mut frame(1.0), None);
+        assert!(gate.is_open());
+    }
+
+    #[test]
+    fn test_voice_gate_longest_hold() {
+        let mut gate = VoiceGateState::new(VoiceGate {
+            attack_ms: u32::MAX,
+            hold_ms: u32::MAX,
+            attenuation_db: None,
+        });
+        gate.process_frame(&mut frame(1.0), Some(true));
+        assert_eq!(u32::MAX / 10 + 1, gate.hold_frames_left);
+    }
+}
@@ -9,10 +9,56 @@ use std::{
 
 const DEPLOYMENT_TARGET_VAR: &str = "MACOSX_DEPLOYMENT_TARGET";
 
+/// Extra compiler flags for the native code, separated by whitespace, e.g. `-march=haswell` for
+/// servers, or `-march=x86-64` for the oldest supported CPUs. Otherwise the compiler defaults
+/// for the target apply.
+const CPU_FLAGS_VAR: &str = "WAP_CPU_FLAGS";
+
+/// The bindings to `wrapper.hpp`, checked in for the builds that don't run bindgen.
+const PREGENERATED_BINDINGS_PATH: &str = "src/bindings.rs";
+
 fn out_dir() -> PathBuf {
     std::env::var("OUT_DIR").expect("OUT_DIR environment var not set.").into()
 }
 
+/// The OS being built for. Unlike `cfg!(target_os = ...)`, which reflects the host the build
+/// script runs on, this is correct when cross compiling.
+fn target_os() -> String {
+    env::var("CARGO_CFG_TARGET_OS").expect("CARGO_CFG_TARGET_OS environment var not set.")
+}
+
+/// The ABI being built for, e.g. "gnu", "musl" or "msvc".
+fn target_env() -> String {
+    env::var("CARGO_CFG_TARGET_ENV").expect("CARGO_CFG_TARGET_ENV environment var not set.")
+}
+
+fn cpu_flags() -> Vec<String> {
+    env::var(CPU_FLAGS_VAR).unwrap_or_default().split_whitespace().map(String::from).collect()
+}
+
+fn is_emscripten() -> bool {
+    target_os() == "emscripten"
+}
+
+/// The environment variables pointing to the Android NDK, as set by `cargo ndk` or Android
+/// Studio.
+const ANDROID_NDK_VARS: &[&str] = &["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT", "NDK_HOME"];
+
+/// Returns the directory of the NDK's prebuilt LLVM toolchain for this host, if the NDK can be
+/// found.
+fn android_ndk_toolchain() -> Option<PathBuf> {
+    let ndk = ANDROID_NDK_VARS.iter().find_map(env::var_os)?;
+    // The build script runs on the host, so `cfg!` is right here.
+    let host_tag = if cfg!(target_os = "macos") {
+        "darwin-x86_64"
+    } else if cfg!(windows) {
+        "windows-x86_64"
+    } else {
+        "linux-x86_64"
+    };
+    Some(Path::new(&ndk).join("toolchains/llvm/prebuilt").join(host_tag))
+}
+
 #[cfg(not(feature = "bundled"))]
 mod webrtc {
     use super::*;
@@ -21,16 +67,23 @@ mod webrtc {
     const LIB_NAME: &str = "webrtc-audio-processing";
 
     pub(super) fn get_build_paths() -> Result<(PathBuf, PathBuf), Error> {
-        let (pkgconfig_include_path, pkgconfig_lib_path) = find_pkgconfig_paths()?;
+        if is_emscripten() {
+            eprintln!("There is no system {} to link with for emscripten.", LIB_NAME);
+            eprintln!("Use the 'bundled' feature to compile it to WebAssembly.");
+            bail!("Aborting compilation because the target is emscripten.");
+        }
 
-        let include_path = std::env::var("WEBRTC_AUDIO_PROCESSING_INCLUDE")
-            .ok()
-            .map(|x| x.into())
-            .or(pkgconfig_include_path);
-        let lib_path = std::env::var("WEBRTC_AUDIO_PROCESSING_LIB")
-            .ok()
-            .map(|x| x.into())
-            .or(pkgconfig_lib_path);
+        let include_path = target_env_var("WEBRTC_AUDIO_PROCESSING_INCLUDE").map(PathBuf::from);
+        let lib_path = target_env_var("WEBRTC_AUDIO_PROCESSING_LIB").map(PathBuf::from);
+        // Only probe when needed, as pkg-config refuses to when cross compiling without a
+        // sysroot or target specific search path set up.
+        let (include_path, lib_path) = match (include_path, lib_path) {
+            (Some(include_path), Some(lib_path)) => (Some(include_path), Some(lib_path)),
+            (include_path, lib_path) => {
+                let (pkgconfig_include_path, pkgconfig_lib_path) = find_pkgconfig_paths()?;
+                (include_path.or(pkgconfig_include_path), lib_path.or(pkgconfig_lib_path))
+            },
+        };
 
         println!("{:?}, {:?}", include_path, lib_path);
 
@@ -44,8 +97,20 @@ mod webrtc {
         }
     }
 
-    pub(super) fn build_if_necessary() -> Result<(), Error> {
-        Ok(())
+    /// Reads `var`, preferring its variants for the target, e.g. `VAR_aarch64-unknown-linux-gnu`
+    /// or `VAR_aarch64_unknown_linux_gnu`, like `cc` and `pkg-config` do. Otherwise paths meant
+    /// for the host leak into cross builds.
+    fn target_env_var(var: &str) -> Option<String> {
+        let target = env::var("TARGET").expect("TARGET environment var not set.");
+        let names = [
+            format!("{}_{}", var, target),
+            format!("{}_{}", var, target.replace('-', "_")),
+            var.into(),
+        ];
+        for name in &names {
+            println!("cargo:rerun-if-env-changed={}", name);
+        }
+        names.iter().find_map(|name| env::var(name).ok())
     }
 
     fn find_pkgconfig_paths() -> Result<(Option<PathBuf>, Option<PathBuf>), Error> {
@@ -59,15 +124,90 @@ mod webrtc {
 mod webrtc {
     use super::*;
     use failure::bail;
+    use std::{
+        collections::hash_map::DefaultHasher,
+        fs,
+        hash::{Hash, Hasher},
+        process,
+        time::SystemTime,
+    };
 
     const BUNDLED_SOURCE_PATH: &str = "./webrtc-audio-processing";
 
+    const LIB_FILE_NAME: &str = "libwebrtc_audio_processing.a";
+
+    /// An opt-in directory outside of `OUT_DIR`, e.g. `~/.cache/webrtc-audio-processing`, where
+    /// the bundled library is kept once built. Builds with the same sources, target, compiler
+    /// and flags share it, whichever workspace, target directory or profile they're for.
+    const BUILD_CACHE_VAR: &str = "WEBRTC_AUDIO_PROCESSING_BUILD_CACHE";
+
     pub(super) fn get_build_paths() -> Result<(PathBuf, PathBuf), Error> {
+        // Hashes the whole source tree, so it's only worked out once.
+        let cache_entry = build_cache_entry()?;
+        build_if_necessary(cache_entry.as_deref())?;
+        if let Some(cache_entry) = cache_entry {
+            // The headers are used as they are in the source tree, whether or not it was built
+            // in this `OUT_DIR`.
+            return Ok((Path::new(BUNDLED_SOURCE_PATH).canonicalize()?, cache_entry));
+        }
+
         let include_path = out_dir().join(BUNDLED_SOURCE_PATH);
         let lib_path = out_dir().join("lib");
         Ok((include_path, lib_path))
     }
 
+    /// Returns the directory of the shared build cache holding the library for this build, if
+    /// the cache is enabled.
+    fn build_cache_entry() -> Result<Option<PathBuf>, Error> {
+        println!("cargo:rerun-if-env-changed={}", BUILD_CACHE_VAR);
+        let cache_dir = match env::var_os(BUILD_CACHE_VAR) {
+            Some(cache_dir) => PathBuf::from(cache_dir),
+            None => return Ok(None),
+        };
+
+        let mut hasher = DefaultHasher::new();
+        env::var("TARGET")?.hash(&mut hasher);
+        cpu_flags().hash(&mut hasher);
+        // Covers the optimization level and debug info of the profile, and CFLAGS/CXXFLAGS.
+        for cpp in &[false, true] {
+            let compiler = cc::Build::new().cpp(*cpp).get_compiler();
+            compiler.path().hash(&mut hasher);
+            compiler.cflags_env().hash(&mut hasher);
+        }
+        hash_tree(Path::new(BUNDLED_SOURCE_PATH), &mut hasher)?;
+        Ok(Some(cache_dir.join(format!("{:016x}", hasher.finish()))))
+    }
+
+    /// Hashes the relative paths and the contents of the files under `path`.
+    fn hash_tree(path: &Path, hasher: &mut DefaultHasher) -> Result<(), Error> {
+        path.hash(hasher);
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.sort();
+            for entry in entries {
+                // The `.git` link of the submodule differs between checkouts.
+                if entry.file_name() != Some(".git".as_ref()) {
+                    hash_tree(&entry, hasher)?;
+                }
+            }
+        } else {
+            fs::read(path)?.hash(hasher);
+        }
+        Ok(())
+    }
+
+    /// Copies the built library into the shared cache. It's renamed into place, so that
+    /// concurrent builds never see a partial file.
+    fn store_in_cache(lib_path: &Path, cache_entry: &Path) -> Result<(), Error> {
+        fs::create_dir_all(cache_entry)?;
+        let partial = cache_entry.join(format!("{}.{}", LIB_FILE_NAME, process::id()));
+        fs::copy(lib_path, &partial)?;
+        fs::rename(&partial, cache_entry.join(LIB_FILE_NAME))?;
+        Ok(())
+    }
+
     fn copy_source_to_out_dir() -> Result<PathBuf, Error> {
         use fs_extra::dir::CopyOptions;
 
@@ -87,9 +227,35 @@ mod webrtc {
         Ok(out_dir.join(BUNDLED_SOURCE_PATH))
     }
 
-    pub(super) fn build_if_necessary() -> Result<(), Error> {
+    fn build_if_necessary(cache_entry: Option<&Path>) -> Result<(), Error> {
+        if target_env() == "msvc" {
+            eprintln!("The bundled webrtc-audio-processing is built with autotools, which can't");
+            eprintln!("drive the MSVC toolchain. Without the 'bundled' feature, point");
+            eprintln!("WEBRTC_AUDIO_PROCESSING_INCLUDE and WEBRTC_AUDIO_PROCESSING_LIB to a copy");
+            eprintln!("built with MSVC, or target x86_64-pc-windows-gnu instead.");
+            bail!("Aborting compilation because the bundled build doesn't support MSVC.");
+        }
+
+        println!("cargo:rerun-if-changed={}", BUNDLED_SOURCE_PATH);
+        if cache_entry.iter().any(|entry| entry.join(LIB_FILE_NAME).exists()) {
+            return Ok(());
+        }
+
+        let fingerprint = build_fingerprint()?;
+        let fingerprint_path = out_dir().join("webrtc-audio-processing.fingerprint");
+        let installed = out_dir().join("lib").join(LIB_FILE_NAME);
+        if installed.exists()
+            && fs::read_to_string(&fingerprint_path).ok().as_deref() == Some(&*fingerprint)
+        {
+            if let Some(cache_entry) = cache_entry {
+                store_in_cache(&installed, cache_entry)?;
+            }
+            return Ok(());
+        }
+
         let build_dir = copy_source_to_out_dir()?;
 
+        // These run on the host, so the host OS is what matters here.
         if cfg!(target_os = "macos") {
             run_command(&build_dir, "glibtoolize", None)?;
         } else {
@@ -100,12 +266,96 @@ mod webrtc {
         run_command(&build_dir, "automake", Some(&["--add-missing", "--copy"]))?;
         run_command(&build_dir, "autoconf", None)?;
 
-        autotools::Config::new(build_dir)
-            .cflag("-fPIC")
-            .cxxflag("-fPIC")
-            .disable_shared()
-            .enable_static()
-            .build();
+        let mut config = autotools::Config::new(build_dir);
+        config.disable_shared().enable_static();
+        for flag in cpu_flags() {
+            config.cflag(&flag).cxxflag(&flag);
+        }
+        if target_os() != "windows" {
+            // All code is position independent on Windows, where MinGW GCC warns about it.
+            config.cflag("-fPIC").cxxflag("-fPIC");
+        }
+        if target_os() == "android" {
+            configure_android(&mut config)?;
+        }
+        if is_emscripten() {
+            // What `emconfigure` would set up. The host triple is passed on from $TARGET.
+            config
+                .env("CC", "emcc")
+                .env("CXX", "em++")
+                .env("AR", "emar")
+                .env("RANLIB", "emranlib")
+                .env("PKG_CONFIG", "false");
+        }
+        config.build();
+
+        fs::write(&fingerprint_path, fingerprint)?;
+        if let Some(cache_entry) = cache_entry {
+            store_in_cache(&installed, cache_entry)?;
+        }
+        Ok(())
+    }
+
+    /// Describes what the installed library is built from, so that it's only rebuilt when that
+    /// changes, and not e.g. when the build script reruns for a change in `wrapper.cpp`.
+    fn build_fingerprint() -> Result<String, Error> {
+        Ok(format!(
+            "{} {:?} {:?}",
+            env::var("TARGET")?,
+            cpu_flags(),
+            newest_modification(Path::new(BUNDLED_SOURCE_PATH))?,
+        ))
+    }
+
+    fn newest_modification(path: &Path) -> Result<SystemTime, Error> {
+        let metadata = fs::symlink_metadata(path)?;
+        let mut newest = metadata.modified()?;
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path)? {
+                newest = newest.max(newest_modification(&entry?.path())?);
+            }
+        }
+        Ok(newest)
+    }
+
+    fn configure_android(config: &mut autotools::Config) -> Result<(), Error> {
+        // The NDK compilers are named after the API level too, e.g.
+        // `aarch64-linux-android21-clang`, so autotools can't derive the host from them.
+        let target = env::var("TARGET")?;
+        let (host, neon) = match target.as_str() {
+            "aarch64-linux-android" => ("aarch64-linux-android", true),
+            // NEON is mandatory for armeabi-v7a since NDK r21.
+            "armv7-linux-androideabi" | "thumbv7neon-linux-androideabi" => {
+                ("armv7a-linux-androideabi", true)
+            },
+            "arm-linux-androideabi" => ("arm-linux-androideabi", false),
+            "i686-linux-android" => ("i686-linux-android", false),
+            "x86_64-linux-android" => ("x86_64-linux-android", false),
+            _ => bail!("Unsupported Android target {}.", target),
+        };
+        config.config_option("host", Some(host));
+        if neon {
+            config.enable("neon", Some("yes"));
+            if host.starts_with("armv7") {
+                config.cflag("-mfpu=neon").cxxflag("-mfpu=neon");
+            }
+        } else {
+            config.disable("neon", None);
+        }
+
+        // Recent NDKs only ship the LLVM binutils, which libtool doesn't look for.
+        for (var, tool) in &[("AR", "llvm-ar"), ("RANLIB", "llvm-ranlib")] {
+            let underscored_target = target.replace('-', "_");
+            let from_env =
+                [format!("{}_{}", var, target), format!("{}_{}", var, underscored_target)]
+                    .iter()
+                    .find_map(env::var_os);
+            if let Some(path) = from_env {
+                config.env(var, path);
+            } else if let Some(toolchain) = android_ndk_toolchain() {
+                config.env(var, toolchain.join("bin").join(tool));
+            }
+        }
 
         Ok(())
     }
@@ -154,14 +404,123 @@ fn derive_serde(binding_file: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Exports the paths of the headers and static libraries as `DEP_WEBRTC_AUDIO_PROCESSING_*`
+/// variables, for the build scripts of crates depending on this one.
+fn export_paths(webrtc_include: &Path, webrtc_lib: &Path) -> Result<(), Error> {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    // The webrtc headers, e.g. `webrtc/modules/audio_processing/include/audio_processing.h`.
+    println!("cargo:include={}", webrtc_include.display());
+    // The directory of `libwebrtc_audio_processing`, in bundled builds.
+    println!("cargo:lib={}", webrtc_lib.display());
+    // `wrapper.hpp`, and the directory of `libwebrtc_audio_processing_wrapper.a`.
+    println!("cargo:wrapper_include={}", manifest_dir.join("src").display());
+    println!("cargo:wrapper_lib={}", out_dir().display());
+    Ok(())
+}
+
+/// Links the C++ standard library the wrapper and the bundled library are built against.
+fn link_cxx_runtime() -> Result<(), Error> {
+    // Fully static musl binaries can't load a shared C++ runtime.
+    let static_cxx = cfg!(feature = "static-cxx") || target_env() == "musl";
+    // emcc links its own C++ runtime, and so does the MSVC linker.
+    match (target_os().as_str(), target_env().as_str()) {
+        // Apple doesn't ship a static libc++.
+        ("macos", _) => println!("cargo:rustc-link-lib=dylib=c++"),
+        // The NDK only ships LLVM's libc++. Ship libc++_shared.so with the app unless it's
+        // linked statically.
+        ("android", _) if static_cxx => println!("cargo:rustc-link-lib=static=c++_static"),
+        ("android", _) => println!("cargo:rustc-link-lib=dylib=c++_shared"),
+        ("emscripten", _) | (_, "msvc") => {},
+        _ if static_cxx => {
+            // rustc looks for static libraries itself, so tell it where the compiler keeps
+            // libstdc++.a.
+            let output = cc::Build::new()
+                .cpp(true)
+                .get_compiler()
+                .to_command()
+                .arg("-print-file-name=libstdc++.a")
+                .output()?;
+            let path = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+            if let Some(dir) = path.parent().filter(|_| path.is_absolute()) {
+                println!("cargo:rustc-link-search=native={}", dir.display());
+            }
+            println!("cargo:rustc-link-lib=static=stdc++");
+        },
+        _ => println!("cargo:rustc-link-lib=dylib=stdc++"),
+    }
+    Ok(())
+}
+
+/// Generates the Rust bindings of `wrapper.hpp`. It doesn't include any webrtc header, so
+/// `webrtc_include` is optional.
+fn generate_bindings(webrtc_include: &Path) {
+    let binding_file = out_dir().join("bindings.rs");
+    let mut bindgen_builder = bindgen::Builder::default();
+    if is_emscripten() {
+        // Let clang find the C++ standard library headers of emscripten, as set up by emsdk_env.
+        if let Ok(emsdk) = env::var("EMSDK") {
+            let sysroot = Path::new(&emsdk).join("upstream/emscripten/cache/sysroot");
+            bindgen_builder = bindgen_builder.clang_arg(format!("--sysroot={}", sysroot.display()));
+        }
+    }
+    if target_os() == "android" {
+        // Let clang find the headers of the NDK.
+        if let Some(toolchain) = android_ndk_toolchain() {
+            let sysroot = toolchain.join("sysroot");
+            bindgen_builder = bindgen_builder.clang_arg(format!("--sysroot={}", sysroot.display()));
+        }
+    }
+    println!("cargo:rerun-if-env-changed=EMSDK");
+    for var in ANDROID_NDK_VARS {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+    bindgen_builder
+        .header("src/wrapper.hpp")
+        .generate_comments(true)
+        .rustified_enum(".*")
+        .derive_debug(true)
+        .derive_default(true)
+        .derive_partialeq(true)
+        .clang_arg(format!("-I{}", webrtc_include.display()))
+        .disable_name_namespacing()
+        .generate()
+        .expect("Unable to generate bindings")
+        .write_to_file(&binding_file)
+        .expect("Couldn't write bindings!");
+
+    if cfg!(feature = "derive_serde") {
+        derive_serde(&binding_file).expect("Failed to modify derive macros");
+    }
+}
+
+/// Copies the checked-in bindings to where `generate_bindings()` writes them. Unlike bindgen,
+/// it doesn't need libclang.
+fn copy_pregenerated_bindings() -> Result<(), Error> {
+    println!("cargo:rerun-if-changed={}", PREGENERATED_BINDINGS_PATH);
+    let binding_file = out_dir().join("bindings.rs");
+    std::fs::copy(PREGENERATED_BINDINGS_PATH, &binding_file)?;
+    if cfg!(feature = "derive_serde") {
+        derive_serde(&binding_file)?;
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Error> {
-    webrtc::build_if_necessary()?;
+    // docs.rs has neither network access nor the autotools toolchain, and rustdoc doesn't link
+    // anything. Only the bindings are needed there, so that the documentation is complete. The
+    // `mock` feature of the wrapper crate only needs the types too, as it implements the
+    // functions in Rust. Both use the checked-in bindings, so that they build without libclang.
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    if env::var_os("DOCS_RS").is_some() || cfg!(feature = "mock") {
+        return copy_pregenerated_bindings();
+    }
+
     let (webrtc_include, webrtc_lib) = webrtc::get_build_paths()?;
 
     let mut cc_build = cc::Build::new();
 
     // set mac minimum version
-    if cfg!(target_os = "macos") {
+    if target_os() == "macos" {
         let min_version = match env::var(DEPLOYMENT_TARGET_VAR) {
             Ok(ver) => ver,
             Err(_) => {
@@ -179,51 +538,47 @@ fn main() -> Result<(), Error> {
         cc_build.flag(&format!("-mmacos-version-min={}", min_version));
     }
 
+    for flag in cpu_flags() {
+        cc_build.flag(&flag);
+    }
+
     cc_build
         .cpp(true)
         .file("src/wrapper.cpp")
         .include(&webrtc_include)
-        .flag("-Wno-unused-parameter")
-        .flag("-Wno-deprecated-declarations")
-        .flag("-std=c++11")
+        // MSVC doesn't know these, and defaults to a later standard anyway.
+        .flag_if_supported("-Wno-unused-parameter")
+        .flag_if_supported("-Wno-deprecated-declarations")
+        .flag_if_supported("-std=c++11")
         .out_dir(&out_dir())
         .compile("webrtc_audio_processing_wrapper");
 
     println!("cargo:rustc-link-search=native={}", webrtc_lib.display());
+    export_paths(&webrtc_include, &webrtc_lib)?;
     println!("cargo:rustc-link-lib=static=webrtc_audio_processing_wrapper");
 
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/wrapper.cpp");
+    println!("cargo:rerun-if-changed=src/wrapper.hpp");
     println!("cargo:rerun-if-env-changed={}", DEPLOYMENT_TARGET_VAR);
+    println!("cargo:rerun-if-env-changed={}", CPU_FLAGS_VAR);
 
     if cfg!(feature = "bundled") {
         println!("cargo:rustc-link-lib=static=webrtc_audio_processing");
+        match target_os().as_str() {
+            // For timeGetTime() in system_wrappers.
+            "windows" => println!("cargo:rustc-link-lib=dylib=winmm"),
+            // For the logging in system_wrappers.
+            "android" => println!("cargo:rustc-link-lib=dylib=log"),
+            _ => {},
+        }
     } else {
         println!("cargo:rustc-link-lib=dylib=webrtc_audio_processing");
     }
 
-    if cfg!(target_os = "macos") {
-        println!("cargo:rustc-link-lib=dylib=c++");
-    } else {
-        println!("cargo:rustc-link-lib=dylib=stdc++");
-    }
-
-    let binding_file = out_dir().join("bindings.rs");
-    bindgen::Builder::default()
-        .header("src/wrapper.hpp")
-        .generate_comments(true)
-        .rustified_enum(".*")
-        .derive_debug(true)
-        .derive_default(true)
-        .derive_partialeq(true)
-        .clang_arg(&format!("-I{}", &webrtc_include.display()))
-        .disable_name_namespacing()
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(&binding_file)
-        .expect("Couldn't write bindings!");
+    link_cxx_runtime()?;
 
-    if cfg!(feature = "derive_serde") {
-        derive_serde(&binding_file).expect("Failed to modify derive macros");
-    }
+    generate_bindings(&webrtc_include);
 
     Ok(())
 }
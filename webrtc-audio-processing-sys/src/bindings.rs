@@ -0,0 +1,355 @@
+// The bindings to `wrapper.hpp`, laid out as bindgen generates them, for the builds that don't
+// run bindgen: docs.rs and the `mock` feature. Neither links the native functions, so only the
+// types have to match the header. Keep them in sync with `wrapper.hpp`.
+
+/// The number of expected samples per frame.
+pub const NUM_SAMPLES_PER_FRAME: ::std::os::raw::c_int = 480;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AudioProcessing {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct OptionalDouble {
+    pub has_value: bool,
+    pub value: f64,
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct OptionalInt {
+    pub has_value: bool,
+    pub value: ::std::os::raw::c_int,
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct OptionalBool {
+    pub has_value: bool,
+    pub value: bool,
+}
+/// A configuration used only when initializing a Processor.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct InitializationConfig {
+    pub num_capture_channels: ::std::os::raw::c_int,
+    pub num_render_channels: ::std::os::raw::c_int,
+    pub enable_experimental_agc: bool,
+    pub enable_intelligibility_enhancer: bool,
+}
+/// Echo cancellation configuration.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EchoCancellation {
+    /// Whether to use echo cancellation.
+    pub enable: bool,
+    /// Determines the aggressiveness of the suppressor. A higher level trades off
+    /// double-talk performance for increased echo suppression.
+    pub suppression_level: EchoCancellation_SuppressionLevel,
+    /// Use to enable the extended filter mode in the AEC, along with robustness
+    /// measures around the reported system delays. It comes with a significant
+    /// increase in AEC complexity, but is much more robust to unreliable reported
+    /// delays.
+    pub enable_extended_filter: bool,
+    /// Enables delay-agnostic echo cancellation. This feature relies on internally
+    /// estimated delays between the process and reverse streams, thus not relying
+    /// on reported system delays.
+    pub enable_delay_agnostic: bool,
+    /// Sets the delay in ms between process_render_frame() receiving a far-end
+    /// frame and process_capture_frame() receiving a near-end frame containing
+    /// the corresponding echo. You should set this only if you are certain that
+    /// the delay will be stable and constant. enable_delay_agnostic will be
+    /// ignored when this option is set.
+    pub stream_delay_ms: OptionalInt,
+}
+/// A level of echo suppression.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum EchoCancellation_SuppressionLevel {
+    LOWEST = 0,
+    LOWER = 1,
+    LOW = 2,
+    MODERATE = 3,
+    HIGH = 4,
+}
+impl Default for EchoCancellation {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+/// Mobile echo control (AECM) configuration.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EchoControlMobile {
+    /// Whether to use mobile echo control. It can't run along with
+    /// EchoCancellation, which has to be disabled.
+    pub enable: bool,
+    /// Sets the echo path the suppression is tuned for. Louder routes suppress
+    /// more.
+    pub routing_mode: EchoControlMobile_RoutingMode,
+    /// Fills the suppressed parts of the capture stream with comfort noise.
+    pub enable_comfort_noise: bool,
+    /// Sets the delay in ms between process_render_frame() receiving a far-end
+    /// frame and process_capture_frame() receiving a near-end frame containing
+    /// the corresponding echo. AECM doesn't estimate the delay on its own, so 0
+    /// is used if unset.
+    pub stream_delay_ms: OptionalInt,
+}
+/// The audio routing of the device.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum EchoControlMobile_RoutingMode {
+    QUIET_EARPIECE_OR_HEADSET = 0,
+    EARPIECE = 1,
+    LOUD_EARPIECE = 2,
+    SPEAKERPHONE = 3,
+    LOUD_SPEAKERPHONE = 4,
+}
+impl Default for EchoControlMobile {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+/// Gain control configuration.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GainControl {
+    /// Whether to use gain control.
+    pub enable: bool,
+    /// Determines what type of gain control is applied.
+    pub mode: GainControl_Mode,
+    /// Sets the target peak level (or envelope) of the AGC in dBFs (decibels from
+    /// digital full-scale). The convention is to use positive values.
+    /// For instance, passing in a value of 3 corresponds to -3 dBFs, or a target
+    /// level 3 dB below full-scale. Limited to [0, 31].
+    pub target_level_dbfs: ::std::os::raw::c_int,
+    /// Sets the maximum gain the digital compression stage may apply, in dB. A
+    /// higher number corresponds to greater compression, while a value of 0 will
+    /// leave the signal uncompressed. Limited to [0, 90].
+    pub compression_gain_db: ::std::os::raw::c_int,
+    /// When enabled, the compression stage will hard limit the signal to the
+    /// target level. Otherwise, the signal will be compressed but not limited
+    /// above the target level.
+    pub enable_limiter: bool,
+}
+/// Mode of gain control.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum GainControl_Mode {
+    /// Adapts the analog volume of the microphone, in addition to the
+    /// digital gain. The volume is passed in with set_stream_analog_level()
+    /// before each capture frame, and the recommended one read back with
+    /// stream_analog_level() after it.
+    ADAPTIVE_ANALOG = 0,
+    /// Bring the signal to an appropriate range by applying an adaptive gain
+    /// control. The volume is dynamically amplified with a microphone with
+    /// small pickup and vice versa.
+    ADAPTIVE_DIGITAL = 1,
+    /// Unlike ADAPTIVE_DIGITAL, it only compresses (i.e. gradually reduces
+    /// gain with increasing level) the input signal when at higher levels.
+    /// Use this where the capture signal level is predictable, so that a
+    /// known gain can be applied.
+    FIXED_DIGITAL = 2,
+}
+impl Default for GainControl {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+/// Noise suppression configuration.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NoiseSuppression {
+    /// Whether to use noise supression.
+    pub enable: bool,
+    /// Determines the aggressiveness of the suppression. Increasing the level will
+    /// reduce the noise level at the expense of a higher speech distortion.
+    pub suppression_level: NoiseSuppression_SuppressionLevel,
+}
+/// A level of noise suppression.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum NoiseSuppression_SuppressionLevel {
+    LOW = 0,
+    MODERATE = 1,
+    HIGH = 2,
+    VERY_HIGH = 3,
+}
+impl Default for NoiseSuppression {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+/// Voice detection configuration.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoiceDetection {
+    /// Whether to use voice detection.
+    pub enable: bool,
+    /// Specifies the likelihood that a frame will be declared to contain voice. A
+    /// higher value makes it more likely that speech will not be clipped, at the
+    /// expense of more noise being detected as voice.
+    pub detection_likelihood: VoiceDetection_DetectionLikelihood,
+}
+/// The sensitivity of the noise detector.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum VoiceDetection_DetectionLikelihood {
+    VERY_LOW = 0,
+    LOW = 1,
+    MODERATE = 2,
+    HIGH = 3,
+}
+impl Default for VoiceDetection {
+    fn default() -> Self {
+        let mut s = ::std::mem::MaybeUninit::<Self>::uninit();
+        unsafe {
+            ::std::ptr::write_bytes(s.as_mut_ptr(), 0, 1);
+            s.assume_init()
+        }
+    }
+}
+/// Config that can be used mid-processing.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Config {
+    pub echo_cancellation: EchoCancellation,
+    pub echo_control_mobile: EchoControlMobile,
+    pub gain_control: GainControl,
+    pub noise_suppression: NoiseSuppression,
+    pub voice_detection: VoiceDetection,
+    /// Use to enable experimental transient noise suppression.
+    pub enable_transient_suppressor: bool,
+    /// Use to enable a filtering component which removes DC offset and
+    /// low-frequency noise.
+    pub enable_high_pass_filter: bool,
+}
+/// Statistics about the processor state.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Stats {
+    /// True if voice is detected in the current frame.
+    pub has_voice: OptionalBool,
+    /// False if the current frame almost certainly contains no echo and true if it
+    /// _might_ contain echo.
+    pub has_echo: OptionalBool,
+    /// Root mean square (RMS) level in dBFs (decibels from digital full-scale), or
+    /// alternately dBov. It is computed over all primary stream frames since the
+    /// last call to |get_stats()|. The returned value is constrained to [-127, 0],
+    /// where -127 indicates muted.
+    pub rms_dbfs: OptionalInt,
+    /// Prior speech probability of the current frame averaged over output
+    /// channels, internally computed by noise suppressor.
+    pub speech_probability: OptionalDouble,
+    /// RERL = ERL + ERLE
+    pub residual_echo_return_loss: OptionalDouble,
+    /// ERL = 10log_10(P_far / P_echo)
+    pub echo_return_loss: OptionalDouble,
+    /// ERLE = 10log_10(P_echo / P_out)
+    pub echo_return_loss_enhancement: OptionalDouble,
+    /// (Pre non-linear processing suppression) A_NLP = 10log_10(P_echo / P_a)
+    pub a_nlp: OptionalDouble,
+    /// Median of the measured delay in ms. The values are aggregated until the
+    /// first call to |get_stats()| and afterwards aggregated and updated every
+    /// second.
+    pub delay_median_ms: OptionalInt,
+    /// Standard deviation of the measured delay in ms. The values are aggregated
+    /// until the first call to |get_stats()| and afterwards aggregated and updated
+    /// every second.
+    pub delay_standard_deviation_ms: OptionalInt,
+    /// The fraction of delay estimates that can make the echo cancellation perform
+    /// poorly.
+    pub delay_fraction_poor_delays: OptionalDouble,
+    /// The analog level in [0, 255] passed to the adaptive analog gain control
+    /// for the current frame, i.e. the microphone volume actually applied.
+    pub applied_input_volume: OptionalInt,
+    /// The analog level in [0, 255] recommended by the adaptive analog gain
+    /// control after the current frame, to apply to the microphone before the
+    /// next one.
+    pub recommended_input_volume: OptionalInt,
+}
+extern "C" {
+    pub fn audio_processing_create(
+        init_config: *const InitializationConfig,
+        error: *mut ::std::os::raw::c_int,
+    ) -> *mut AudioProcessing;
+}
+extern "C" {
+    pub fn process_capture_frame(
+        ap: *mut AudioProcessing,
+        channels: *mut *mut f32,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn process_render_frame(
+        ap: *mut AudioProcessing,
+        channel3: *mut *mut f32,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn get_stats(ap: *mut AudioProcessing, stats: *mut Stats);
+}
+extern "C" {
+    pub fn stream_has_voice(ap: *mut AudioProcessing) -> OptionalBool;
+}
+extern "C" {
+    pub fn set_config(ap: *mut AudioProcessing, config: *const Config);
+}
+extern "C" {
+    pub fn set_noise_suppression_level(
+        ap: *mut AudioProcessing,
+        level: NoiseSuppression_SuppressionLevel,
+    );
+}
+extern "C" {
+    pub fn set_gain_control_target_level_dbfs(
+        ap: *mut AudioProcessing,
+        level: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn set_gain_control_enable_limiter(ap: *mut AudioProcessing, enable: bool);
+}
+extern "C" {
+    pub fn reset_echo_control(ap: *mut AudioProcessing);
+}
+extern "C" {
+    pub fn set_echo_control_suspended(ap: *mut AudioProcessing, suspended: bool);
+}
+extern "C" {
+    pub fn set_output_will_be_muted(ap: *mut AudioProcessing, muted: bool);
+}
+extern "C" {
+    /// Signals the AEC and AGC that the next frame will contain key press sound
+    pub fn set_stream_key_pressed(ap: *mut AudioProcessing, pressed: bool);
+}
+extern "C" {
+    pub fn set_stream_delay_ms(ap: *mut AudioProcessing, delay_ms: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn set_stream_analog_level(ap: *mut AudioProcessing, level: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn stream_analog_level(ap: *mut AudioProcessing) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn audio_processing_delete(ap: *mut AudioProcessing);
+}
+extern "C" {
+    pub fn is_success(code: ::std::os::raw::c_int) -> bool;
+}
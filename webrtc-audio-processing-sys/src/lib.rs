@@ -64,7 +64,8 @@ impl From<Option<f64>> for OptionalDouble {
     }
 }
 
-#[cfg(test)]
+// The functions aren't linked with the `mock` feature.
+#[cfg(all(test, not(feature = "mock")))]
 mod tests {
     use super::*;
 
@@ -182,7 +183,8 @@ mod tests {
             assert!(!ap.is_null());
             assert!(is_success(error));
 
-            let stats = get_stats(ap);
+            let mut stats = Stats::default();
+            get_stats(ap, &mut stats);
             println!("Stats:\n{:#?}", stats);
             assert!(!stats.has_voice.has_value);
             assert!(!stats.has_echo.has_value);
@@ -195,6 +197,8 @@ mod tests {
             assert!(!stats.delay_median_ms.has_value);
             assert!(!stats.delay_standard_deviation_ms.has_value);
             assert!(!stats.delay_fraction_poor_delays.has_value);
+            assert!(!stats.applied_input_volume.has_value);
+            assert!(!stats.recommended_input_volume.has_value);
 
             audio_processing_delete(ap);
         }
@@ -215,7 +219,8 @@ mod tests {
             let mut frame_ptr = frame.iter_mut().map(|v| v.as_mut_ptr()).collect::<Vec<*mut f32>>();
             assert!(is_success(process_render_frame(ap, frame_ptr.as_mut_ptr())));
             assert!(is_success(process_capture_frame(ap, frame_ptr.as_mut_ptr())));
-            let stats = get_stats(ap);
+            let mut stats = Stats::default();
+            get_stats(ap, &mut stats);
             println!("Stats:\n{:#?}", stats);
             assert!(stats.has_voice.has_value);
             assert!(stats.has_echo.has_value);
@@ -5,7 +5,11 @@
 #include <algorithm>
 #include <memory>
 
+#if defined(_WIN32)
+#define WEBRTC_WIN
+#else
 #define WEBRTC_POSIX
+#endif
 #define WEBRTC_AUDIO_PROCESSING_ONLY_BUILD
 
 #include <webrtc/modules/audio_processing/include/audio_processing.h>
@@ -45,6 +49,16 @@ struct AudioProcessing {
   webrtc::StreamConfig capture_stream_config;
   webrtc::StreamConfig render_stream_config;
   OptionalInt stream_delay_ms;
+  int analog_level = 0;
+  // The |analog_level| passed in with the last capture frame.
+  int applied_analog_level = 0;
+  // Whether AEC and AECM are enabled by the config, even while suspended.
+  bool echo_cancellation_enabled = false;
+  bool echo_control_mobile_enabled = false;
+  bool echo_control_suspended = false;
+  // Set when AEC and AECM were switched while suspended, in which case the
+  // processing rate is only picked up on resuming.
+  bool initialize_on_resume = false;
 };
 
 AudioProcessing* audio_processing_create(
@@ -89,13 +103,26 @@ AudioProcessing* audio_processing_create(
 int process_capture_frame(AudioProcessing* ap, float** channels) {
   auto* p = ap->processor.get();
 
-  if (p->echo_cancellation()->is_enabled()) {
+  if (p->echo_cancellation()->is_enabled() ||
+      p->echo_control_mobile()->is_enabled()) {
     p->set_stream_delay_ms(
         ap->stream_delay_ms.has_value ? ap->stream_delay_ms.value : 0);
   }
 
-  return p->ProcessStream(
+  const bool adaptive_analog = p->gain_control()->is_enabled() &&
+      p->gain_control()->mode() == webrtc::GainControl::kAdaptiveAnalog;
+  if (adaptive_analog) {
+    p->gain_control()->set_stream_analog_level(ap->analog_level);
+    ap->applied_analog_level = ap->analog_level;
+  }
+
+  const int code = p->ProcessStream(
       channels, ap->capture_stream_config, ap->capture_stream_config, channels);
+
+  if (adaptive_analog) {
+    ap->analog_level = p->gain_control()->stream_analog_level();
+  }
+  return code;
 }
 
 int process_render_frame(AudioProcessing* ap, float** channels) {
@@ -103,25 +130,25 @@ int process_render_frame(AudioProcessing* ap, float** channels) {
       channels, ap->render_stream_config, ap->render_stream_config, channels);
 }
 
-Stats get_stats(AudioProcessing* ap) {
+void get_stats(AudioProcessing* ap, Stats* stats) {
   auto* p = ap->processor.get();
 
-  Stats stats;
+  *stats = Stats();
   if (p->voice_detection()->is_enabled()) {
-    stats.has_voice =
+    stats->has_voice =
         make_optional_bool(p->voice_detection()->stream_has_voice());
   }
   if (p->echo_cancellation()->is_enabled()) {
-    stats.has_echo =
+    stats->has_echo =
         make_optional_bool(p->echo_cancellation()->stream_has_echo());
   }
   if (p->level_estimator()->is_enabled()) {
-    stats.rms_dbfs = make_optional_int(-1 * p->level_estimator()->RMS());
+    stats->rms_dbfs = make_optional_int(-1 * p->level_estimator()->RMS());
   }
   if (p->noise_suppression()->is_enabled()) {
     if (p->noise_suppression()->speech_probability()
         != webrtc::AudioProcessing::kUnsupportedFunctionError) {
-      stats.speech_probability =
+      stats->speech_probability =
           make_optional_double(p->noise_suppression()->speech_probability());
     }
     // TODO(ryo): NoiseSuppression supports NoiseEstimate function in the latest
@@ -134,13 +161,13 @@ Stats get_stats(AudioProcessing* ap) {
     webrtc::EchoCancellation::Metrics metrics;
     if (p->echo_cancellation()->GetMetrics(&metrics)
         == webrtc::AudioProcessing::kNoError) {
-      stats.residual_echo_return_loss =
+      stats->residual_echo_return_loss =
           make_optional_double(metrics.residual_echo_return_loss.instant);
-      stats.echo_return_loss =
+      stats->echo_return_loss =
           make_optional_double(metrics.echo_return_loss.instant);
-      stats.echo_return_loss_enhancement =
+      stats->echo_return_loss_enhancement =
           make_optional_double(metrics.echo_return_loss_enhancement.instant);
-      stats.a_nlp = make_optional_double(metrics.a_nlp.instant);
+      stats->a_nlp = make_optional_double(metrics.a_nlp.instant);
     }
 
     int delay_median_ms = -1;
@@ -149,14 +176,26 @@ Stats get_stats(AudioProcessing* ap) {
     if (p->echo_cancellation()->GetDelayMetrics(
             &delay_median_ms, &delay_stddev_ms, &fraction_poor_delays)
         == webrtc::AudioProcessing::kNoError) {
-      stats.delay_median_ms = make_optional_int(delay_median_ms);
-      stats.delay_standard_deviation_ms = make_optional_int(delay_stddev_ms);
-      stats.delay_fraction_poor_delays =
+      stats->delay_median_ms = make_optional_int(delay_median_ms);
+      stats->delay_standard_deviation_ms = make_optional_int(delay_stddev_ms);
+      stats->delay_fraction_poor_delays =
           make_optional_double(fraction_poor_delays);
     }
   }
 
-  return stats;
+  if (p->gain_control()->is_enabled() &&
+      p->gain_control()->mode() == webrtc::GainControl::kAdaptiveAnalog) {
+    stats->applied_input_volume = make_optional_int(ap->applied_analog_level);
+    stats->recommended_input_volume = make_optional_int(ap->analog_level);
+  }
+}
+
+OptionalBool stream_has_voice(AudioProcessing* ap) {
+  auto* p = ap->processor.get();
+  if (!p->voice_detection()->is_enabled()) {
+    return OptionalBool();
+  }
+  return make_optional_bool(p->voice_detection()->stream_has_voice());
 }
 
 void set_config(AudioProcessing* ap, const Config& config) {
@@ -175,6 +214,19 @@ void set_config(AudioProcessing* ap, const Config& config) {
   // TODO(ryo): There is a new RefinedAdaptiveFilter in the latest master.
   p->SetExtraOptions(extra_config);
 
+  // AEC and AECM refuse to be enabled while the other one is, so the one being
+  // switched off goes first.
+  const bool switch_echo_control =
+      config.echo_control_mobile.enable != ap->echo_control_mobile_enabled;
+  ap->echo_cancellation_enabled = config.echo_cancellation.enable;
+  ap->echo_control_mobile_enabled = config.echo_control_mobile.enable;
+  if (!config.echo_cancellation.enable) {
+    p->echo_cancellation()->Enable(false);
+  }
+  if (!config.echo_control_mobile.enable) {
+    p->echo_control_mobile()->Enable(false);
+  }
+
   // TODO(ryo): Look into EchoCanceller3.
   if (config.echo_cancellation.enable) {
     ap->stream_delay_ms = config.echo_cancellation.stream_delay_ms;
@@ -187,9 +239,28 @@ void set_config(AudioProcessing* ap, const Config& config) {
     p->echo_cancellation()->set_suppression_level(
         static_cast<webrtc::EchoCancellation::SuppressionLevel>(
             config.echo_cancellation.suppression_level));
-    p->echo_cancellation()->Enable(true);
-  } else {
-    p->echo_cancellation()->Enable(false);
+    p->echo_cancellation()->Enable(!ap->echo_control_suspended);
+  }
+
+  if (config.echo_control_mobile.enable) {
+    ap->stream_delay_ms = config.echo_control_mobile.stream_delay_ms;
+    p->echo_control_mobile()->set_routing_mode(
+        static_cast<webrtc::EchoControlMobile::RoutingMode>(
+            config.echo_control_mobile.routing_mode));
+    p->echo_control_mobile()->enable_comfort_noise(
+        config.echo_control_mobile.enable_comfort_noise);
+    p->echo_control_mobile()->Enable(!ap->echo_control_suspended);
+  }
+
+  // AECM runs at a lower processing rate than AEC, which is only picked up on
+  // initialization. The configuration of the components is kept, but their
+  // states, e.g. the gain of the AGC, are reset.
+  if (switch_echo_control) {
+    if (ap->echo_control_suspended) {
+      ap->initialize_on_resume = true;
+    } else {
+      p->Initialize();
+    }
   }
 
   if (config.gain_control.enable) {
@@ -229,6 +300,65 @@ void set_config(AudioProcessing* ap, const Config& config) {
   p->level_estimator()->Enable(true);
 }
 
+void set_noise_suppression_level(
+    AudioProcessing* ap, NoiseSuppression::SuppressionLevel level) {
+  webrtc::NoiseSuppression* noise_suppression =
+      ap->processor->noise_suppression();
+  if (noise_suppression->is_enabled()) {
+    noise_suppression->set_level(
+        static_cast<webrtc::NoiseSuppression::Level>(level));
+  }
+}
+
+int set_gain_control_target_level_dbfs(AudioProcessing* ap, int level) {
+  webrtc::GainControl* gain_control = ap->processor->gain_control();
+  if (!gain_control->is_enabled()) {
+    return webrtc::AudioProcessing::kNoError;
+  }
+  return gain_control->set_target_level_dbfs(level);
+}
+
+void set_gain_control_enable_limiter(AudioProcessing* ap, bool enable) {
+  webrtc::GainControl* gain_control = ap->processor->gain_control();
+  if (gain_control->is_enabled()) {
+    gain_control->enable_limiter(enable);
+  }
+}
+
+void reset_echo_control(AudioProcessing* ap) {
+  // Components are reinitialized when they're enabled again, which doesn't
+  // touch their configuration.
+  webrtc::EchoCancellation* echo_cancellation =
+      ap->processor->echo_cancellation();
+  if (echo_cancellation->is_enabled()) {
+    echo_cancellation->Enable(false);
+    echo_cancellation->Enable(true);
+  }
+  webrtc::EchoControlMobile* echo_control_mobile =
+      ap->processor->echo_control_mobile();
+  if (echo_control_mobile->is_enabled()) {
+    echo_control_mobile->Enable(false);
+    echo_control_mobile->Enable(true);
+  }
+}
+
+void set_echo_control_suspended(AudioProcessing* ap, bool suspended) {
+  auto* p = ap->processor.get();
+  ap->echo_control_suspended = suspended;
+  // The one being switched off goes first, see |set_config()|.
+  if (suspended) {
+    p->echo_cancellation()->Enable(false);
+    p->echo_control_mobile()->Enable(false);
+    return;
+  }
+  p->echo_cancellation()->Enable(ap->echo_cancellation_enabled);
+  p->echo_control_mobile()->Enable(ap->echo_control_mobile_enabled);
+  if (ap->initialize_on_resume) {
+    ap->initialize_on_resume = false;
+    p->Initialize();
+  }
+}
+
 void set_output_will_be_muted(AudioProcessing* ap, bool muted) {
   ap->processor->set_output_will_be_muted(muted);
 }
@@ -237,6 +367,18 @@ void set_stream_key_pressed(AudioProcessing* ap, bool pressed) {
   ap->processor->set_stream_key_pressed(pressed);
 }
 
+void set_stream_analog_level(AudioProcessing* ap, int level) {
+  ap->analog_level = level;
+}
+
+int stream_analog_level(AudioProcessing* ap) {
+  return ap->analog_level;
+}
+
+void set_stream_delay_ms(AudioProcessing* ap, int delay_ms) {
+  ap->stream_delay_ms = make_optional_int(delay_ms);
+}
+
 void audio_processing_delete(AudioProcessing* ap) {
   delete ap;
 }
@@ -89,6 +89,43 @@ struct EchoCancellation {
   OptionalInt stream_delay_ms;
 };
 
+/// <div rustbindgen>Mobile echo control (AECM) configuration.</div>
+struct EchoControlMobile {
+  /// <div rustbindgen>
+  /// Whether to use mobile echo control. It can't run along with
+  /// EchoCancellation, which has to be disabled.
+  /// </div>
+  bool enable;
+
+  /// <div rustbindgen>The audio routing of the device.</div>
+  enum RoutingMode {
+      QUIET_EARPIECE_OR_HEADSET,
+      EARPIECE,
+      LOUD_EARPIECE,
+      SPEAKERPHONE,
+      LOUD_SPEAKERPHONE,
+  };
+
+  /// <div rustbindgen>
+  /// Sets the echo path the suppression is tuned for. Louder routes suppress
+  /// more.
+  /// </div>
+  RoutingMode routing_mode;
+
+  /// <div rustbindgen>
+  /// Fills the suppressed parts of the capture stream with comfort noise.
+  /// </div>
+  bool enable_comfort_noise;
+
+  /// <div rustbindgen>
+  /// Sets the delay in ms between process_render_frame() receiving a far-end
+  /// frame and process_capture_frame() receiving a near-end frame containing
+  /// the corresponding echo. AECM doesn't estimate the delay on its own, so 0
+  /// is used if unset.
+  /// </div>
+  OptionalInt stream_delay_ms;
+};
+
 /// <div rustbindgen>Gain control configuration.</div>
 struct GainControl {
   /// <div rustbindgen>Whether to use gain control.</div>
@@ -96,9 +133,12 @@ struct GainControl {
 
   /// <div rustbindgen>Mode of gain control.</div>
   enum Mode {
-      /// <div rustbindgen>Not supported yet.</div>
-      /// TODO(skywhale): Expose set_stream_analog_level() and
-      /// stream_analog_level().
+      /// <div rustbindgen>
+      /// Adapts the analog volume of the microphone, in addition to the
+      /// digital gain. The volume is passed in with set_stream_analog_level()
+      /// before each capture frame, and the recommended one read back with
+      /// stream_analog_level() after it.
+      /// </div>
       ADAPTIVE_ANALOG,
 
       /// <div rustbindgen>
@@ -187,6 +227,7 @@ struct VoiceDetection {
 /// <div rustbindgen>Config that can be used mid-processing.</div>
 struct Config {
   EchoCancellation echo_cancellation;
+  EchoControlMobile echo_control_mobile;
   GainControl gain_control;
   NoiseSuppression noise_suppression;
   VoiceDetection voice_detection;
@@ -269,6 +310,19 @@ struct Stats {
   /// poorly.
   /// </div>
   OptionalDouble delay_fraction_poor_delays;
+
+  /// <div rustbindgen>
+  /// The analog level in [0, 255] passed to the adaptive analog gain control
+  /// for the current frame, i.e. the microphone volume actually applied.
+  /// </div>
+  OptionalInt applied_input_volume;
+
+  /// <div rustbindgen>
+  /// The analog level in [0, 255] recommended by the adaptive analog gain
+  /// control after the current frame, to apply to the microphone before the
+  /// next one.
+  /// </div>
+  OptionalInt recommended_input_volume;
 };
 
 // Creates a new instance of the signal processor.
@@ -284,13 +338,45 @@ int process_capture_frame(AudioProcessing* ap, float** channels);
 // frame of 10 ms length. Returns an error code or |kNoError|.
 int process_render_frame(AudioProcessing* ap, float** channel3);
 
-// Returns statistics from the last |process_capture_frame()| call.
-Stats get_stats(AudioProcessing* ap);
+// Writes statistics from the last |process_capture_frame()| call into |stats|.
+// Every field of |stats| is overwritten, so the same struct can be reused
+// across calls.
+void get_stats(AudioProcessing* ap, Stats* stats);
+
+// Returns whether voice was detected in the last |process_capture_frame()|
+// call, or nothing if voice detection is disabled. Unlike |get_stats()|, it
+// computes no metrics, so it's cheap enough to call on every frame.
+OptionalBool stream_has_voice(AudioProcessing* ap);
 
 // Immediately updates the configurations of the signal processor.
 // May be called multiple times after the initialization and during processing.
 void set_config(AudioProcessing* ap, const Config& config);
 
+// Changes the level of the noise suppression, if it's enabled, without touching
+// the other components like |set_config()| does.
+void set_noise_suppression_level(
+    AudioProcessing* ap, NoiseSuppression::SuppressionLevel level);
+
+// Changes the target level of the gain control, if it's enabled, without
+// touching the other components like |set_config()| does. Returns the error
+// code.
+int set_gain_control_target_level_dbfs(AudioProcessing* ap, int level);
+
+// Enables or disables the limiter of the gain control, if it's enabled,
+// without touching the other components like |set_config()| does.
+void set_gain_control_enable_limiter(AudioProcessing* ap, bool enable);
+
+// Resets the adapted state of the enabled echo canceller, i.e. its filter and
+// delay estimate, while keeping its configuration and the state of the other
+// components, e.g. the gain of the AGC.
+void reset_echo_control(AudioProcessing* ap);
+
+// Disables the AEC or AECM enabled by the config while |suspended|, e.g. while
+// headphones are connected, and enables it again otherwise. The configuration
+// is kept, including that of later |set_config()| calls, but the adapted state
+// is reset when the echo canceller is enabled again.
+void set_echo_control_suspended(AudioProcessing* ap, bool suspended);
+
 // Signals the AEC and AGC that the audio output will be / is muted.
 // They may use the hint to improve their parameter adaptation.
 void set_output_will_be_muted(AudioProcessing* ap, bool muted);
@@ -298,6 +384,22 @@ void set_output_will_be_muted(AudioProcessing* ap, bool muted);
 /// Signals the AEC and AGC that the next frame will contain key press sound
 void set_stream_key_pressed(AudioProcessing* ap, bool pressed);
 
+// Overrides |EchoCancellation::stream_delay_ms| of the last |set_config()|
+// call, until the next one. Not thread safe; call it from the thread calling
+// |process_capture_frame()|.
+void set_stream_delay_ms(AudioProcessing* ap, int delay_ms);
+
+// Sets the current analog volume of the microphone, in [0, 255], for the
+// adaptive analog gain control. It's kept for the following frames, updated
+// with the recommended volume after each. Not thread safe; call it from the
+// thread calling |process_capture_frame()|.
+void set_stream_analog_level(AudioProcessing* ap, int level);
+
+// Returns the analog volume the adaptive analog gain control recommends after
+// the last |process_capture_frame()|, in [0, 255]. Not thread safe; call it
+// from the thread calling |process_capture_frame()|.
+int stream_analog_level(AudioProcessing* ap);
+
 // Every processor created by |audio_processing_create()| needs to destroyed by
 // this function.
 void audio_processing_delete(AudioProcessing* ap);